@@ -0,0 +1,283 @@
+#!/usr/bin/env python3
+# Small two-pass 6502/65C02 assembler for the test programs in this
+# directory. It writes a binary starting at the first `org` and a listing
+# laid out like the as65 ones, so the test harness can look up labels and
+# trap addresses the same way as for the functional test.
+#
+#   python3 asm.py 6502_decimal_test.a65
+#
+# Syntax is a subset of as65: labels start in column 0 (a trailing colon is
+# optional), `name = expr`, `org expr`, `db` and `dw`, comments after ';'.
+# Expressions are numbers ($hex, %binary, decimal, 'c'), labels, '*', '+',
+# '-', and '<'/'>' for the low and high byte. The opcode tables are kept
+# here on purpose instead of being read from the emulator sources, so a
+# wrong entry there can't hide in both.
+import re
+import sys
+
+NMOS = {
+    "adc": dict(imm=0x69, zp=0x65, zpx=0x75, abs=0x6D, absx=0x7D, absy=0x79, indx=0x61, indy=0x71),
+    "and": dict(imm=0x29, zp=0x25, zpx=0x35, abs=0x2D, absx=0x3D, absy=0x39, indx=0x21, indy=0x31),
+    "asl": dict(acc=0x0A, zp=0x06, zpx=0x16, abs=0x0E, absx=0x1E),
+    "bcc": dict(rel=0x90), "bcs": dict(rel=0xB0), "beq": dict(rel=0xF0), "bmi": dict(rel=0x30),
+    "bne": dict(rel=0xD0), "bpl": dict(rel=0x10), "bvc": dict(rel=0x50), "bvs": dict(rel=0x70),
+    "bit": dict(zp=0x24, abs=0x2C),
+    "brk": dict(imp=0x00),
+    "clc": dict(imp=0x18), "cld": dict(imp=0xD8), "cli": dict(imp=0x58), "clv": dict(imp=0xB8),
+    "cmp": dict(imm=0xC9, zp=0xC5, zpx=0xD5, abs=0xCD, absx=0xDD, absy=0xD9, indx=0xC1, indy=0xD1),
+    "cpx": dict(imm=0xE0, zp=0xE4, abs=0xEC),
+    "cpy": dict(imm=0xC0, zp=0xC4, abs=0xCC),
+    "dec": dict(zp=0xC6, zpx=0xD6, abs=0xCE, absx=0xDE),
+    "dex": dict(imp=0xCA), "dey": dict(imp=0x88),
+    "eor": dict(imm=0x49, zp=0x45, zpx=0x55, abs=0x4D, absx=0x5D, absy=0x59, indx=0x41, indy=0x51),
+    "inc": dict(zp=0xE6, zpx=0xF6, abs=0xEE, absx=0xFE),
+    "inx": dict(imp=0xE8), "iny": dict(imp=0xC8),
+    "jmp": dict(abs=0x4C, ind=0x6C),
+    "jsr": dict(abs=0x20),
+    "lda": dict(imm=0xA9, zp=0xA5, zpx=0xB5, abs=0xAD, absx=0xBD, absy=0xB9, indx=0xA1, indy=0xB1),
+    "ldx": dict(imm=0xA2, zp=0xA6, zpy=0xB6, abs=0xAE, absy=0xBE),
+    "ldy": dict(imm=0xA0, zp=0xA4, zpx=0xB4, abs=0xAC, absx=0xBC),
+    "lsr": dict(acc=0x4A, zp=0x46, zpx=0x56, abs=0x4E, absx=0x5E),
+    "nop": dict(imp=0xEA),
+    "ora": dict(imm=0x09, zp=0x05, zpx=0x15, abs=0x0D, absx=0x1D, absy=0x19, indx=0x01, indy=0x11),
+    "pha": dict(imp=0x48), "php": dict(imp=0x08), "pla": dict(imp=0x68), "plp": dict(imp=0x28),
+    "rol": dict(acc=0x2A, zp=0x26, zpx=0x36, abs=0x2E, absx=0x3E),
+    "ror": dict(acc=0x6A, zp=0x66, zpx=0x76, abs=0x6E, absx=0x7E),
+    "rti": dict(imp=0x40), "rts": dict(imp=0x60),
+    "sbc": dict(imm=0xE9, zp=0xE5, zpx=0xF5, abs=0xED, absx=0xFD, absy=0xF9, indx=0xE1, indy=0xF1),
+    "sec": dict(imp=0x38), "sed": dict(imp=0xF8), "sei": dict(imp=0x78),
+    "sta": dict(zp=0x85, zpx=0x95, abs=0x8D, absx=0x9D, absy=0x99, indx=0x81, indy=0x91),
+    "stx": dict(zp=0x86, zpy=0x96, abs=0x8E),
+    "sty": dict(zp=0x84, zpx=0x94, abs=0x8C),
+    "tax": dict(imp=0xAA), "tay": dict(imp=0xA8), "tsx": dict(imp=0xBA),
+    "txa": dict(imp=0x8A), "txs": dict(imp=0x9A), "tya": dict(imp=0x98),
+}
+
+CMOS = {
+    "bra": dict(rel=0x80),
+    "phx": dict(imp=0xDA), "phy": dict(imp=0x5A), "plx": dict(imp=0xFA), "ply": dict(imp=0x7A),
+    "stz": dict(zp=0x64, zpx=0x74, abs=0x9C, absx=0x9E),
+    "trb": dict(zp=0x14, abs=0x1C),
+    "tsb": dict(zp=0x04, abs=0x0C),
+    "wai": dict(imp=0xCB), "stp": dict(imp=0xDB),
+}
+# (zp) for the eight ALU instructions
+for name, opcode in [("ora", 0x12), ("and", 0x32), ("eor", 0x52), ("adc", 0x72),
+                     ("sta", 0x92), ("lda", 0xB2), ("cmp", 0xD2), ("sbc", 0xF2)]:
+    CMOS[name] = dict(zpi=opcode)
+CMOS["bit"] = dict(imm=0x89, zpx=0x34, absx=0x3C)
+CMOS["inc"] = dict(acc=0x1A)
+CMOS["dec"] = dict(acc=0x3A)
+CMOS["jmp"] = dict(aix=0x7C)
+for bit in range(8):
+    CMOS["rmb%d" % bit] = dict(zp=0x07 + bit * 16)
+    CMOS["smb%d" % bit] = dict(zp=0x87 + bit * 16)
+    CMOS["bbr%d" % bit] = dict(zpr=0x0F + bit * 16)
+    CMOS["bbs%d" % bit] = dict(zpr=0x8F + bit * 16)
+
+SIZE = dict(imp=1, acc=1, imm=2, zp=2, zpx=2, zpy=2, indx=2, indy=2, zpi=2, rel=2,
+            abs=3, absx=3, absy=3, ind=3, aix=3, zpr=3)
+
+
+class Error(Exception):
+    pass
+
+
+def opcodes(cpu):
+    table = {name: dict(modes) for name, modes in NMOS.items()}
+    if cpu == "65c02":
+        for name, modes in CMOS.items():
+            table.setdefault(name, {}).update(modes)
+    return table
+
+
+class Assembler:
+    def __init__(self, cpu):
+        self.table = opcodes(cpu)
+        self.labels = {}
+        self.sizes = {}  # Line number to the size chosen in the first pass
+
+    def eval(self, expr, pc, final):
+        expr = expr.strip()
+        if not expr:
+            raise Error("missing expression")
+        if expr[0] == "<":
+            value = self.eval(expr[1:], pc, final)
+            return None if value is None else value & 0xFF
+        if expr[0] == ">":
+            value = self.eval(expr[1:], pc, final)
+            return None if value is None else (value >> 8) & 0xFF
+        total, sign = 0, 1
+        for token in re.findall(r"'.'|[^+\-\s]+|[+\-]", expr):
+            if token == "+":
+                sign = 1
+                continue
+            if token == "-":
+                sign = -sign
+                continue
+            if token == "*":
+                value = pc
+            elif token.startswith("$"):
+                value = int(token[1:], 16)
+            elif token.startswith("%"):
+                value = int(token[1:], 2)
+            elif token.startswith("'"):
+                value = ord(token[1])
+            elif token[0].isdigit():
+                value = int(token)
+            elif token.lower() in self.labels:
+                value = self.labels[token.lower()]
+            elif final:
+                raise Error("undefined label " + token)
+            else:
+                return None
+            total += sign * value
+            sign = 1
+        return total
+
+    def mode(self, mnemonic, operand, pc, final, line):
+        modes = self.table.get(mnemonic)
+        if modes is None:
+            raise Error("unknown instruction " + mnemonic)
+        op = operand.replace(" ", "")
+        low = op.lower()
+        if not op:
+            return ("acc" if "acc" in modes else "imp"), []
+        if low == "a" and "acc" in modes:
+            return "acc", []
+        if op.startswith("#"):
+            return "imm", [op[1:]]
+        if "zpr" in modes:
+            return "zpr", op.split(",")
+        if "rel" in modes:
+            return "rel", [op]
+        if low.startswith("(") and low.endswith(",x)"):
+            return ("aix" if "aix" in modes and "indx" not in modes else "indx"), [op[1:-3]]
+        if low.startswith("(") and low.endswith("),y"):
+            return "indy", [op[1:-3]]
+        if op.startswith("(") and op.endswith(")"):
+            return ("ind" if "ind" in modes else "zpi"), [op[1:-1]]
+        index = ""
+        if low.endswith(",x") or low.endswith(",y"):
+            index, op = low[-1], op[:-2]
+        short, long = "zp" + index, ("abs" + index)
+        if line in self.sizes:
+            chosen = self.sizes[line]
+        else:
+            value = self.eval(op, pc, False)
+            chosen = short if value is not None and value < 0x100 and short in modes else long
+            if chosen not in modes:
+                chosen = short
+        return chosen, [op]
+
+    def encode(self, mnemonic, operand, pc, final, line):
+        mode, args = self.mode(mnemonic, operand, pc, final, line)
+        modes = self.table[mnemonic]
+        if mode not in modes:
+            raise Error("%s has no %s mode" % (mnemonic, mode))
+        self.sizes[line] = mode
+        size = SIZE[mode]
+        if not final:
+            return [0] * size
+        values = [self.eval(arg, pc, True) for arg in args]
+        data = [modes[mode]]
+        if mode == "rel":
+            offset = values[0] - (pc + 2)
+            if not -128 <= offset < 128:
+                raise Error("branch out of range")
+            data.append(offset & 0xFF)
+        elif mode == "zpr":
+            offset = values[1] - (pc + 3)
+            if not -128 <= offset < 128:
+                raise Error("branch out of range")
+            data += [values[0] & 0xFF, offset & 0xFF]
+        elif size == 2:
+            if not 0 <= values[0] < 0x100 and mode != "imm":
+                raise Error("operand out of range")
+            data.append(values[0] & 0xFF)
+        elif size == 3:
+            data += [values[0] & 0xFF, (values[0] >> 8) & 0xFF]
+        return data
+
+    def assemble(self, source, final):
+        pc, origin = 0, None
+        image, listing = {}, []
+        for number, text in enumerate(source.splitlines(), 1):
+            text = text.rstrip()
+            code = text.split(";")[0].rstrip()
+            try:
+                label, rest = None, code
+                if code and not code[0].isspace():
+                    label, _, rest = code.partition(" ")
+                    label = label.rstrip(":")
+                rest = rest.strip()
+                words = rest.split(None, 1)
+                directive = words[0].lower() if words else ""
+                argument = words[1] if len(words) > 1 else ""
+                if directive == "=":
+                    value = self.eval(argument, pc, final)
+                    if value is not None:
+                        self.labels[label.lower()] = value
+                    listing.append("%04x =                  %s" % ((value or 0) & 0xFFFF, text))
+                    continue
+                if label:
+                    self.labels[label.lower()] = pc
+                if directive == "org":
+                    pc = self.eval(argument, pc, True)
+                    if origin is None:
+                        origin = pc
+                    listing.append(" " * 24 + text)
+                    continue
+                if directive == "db":
+                    data = [self.eval(arg, pc, final) or 0 for arg in argument.split(",")]
+                    data = [value & 0xFF for value in data]
+                elif directive == "dw":
+                    data = []
+                    for arg in argument.split(","):
+                        value = self.eval(arg, pc, final) or 0
+                        data += [value & 0xFF, (value >> 8) & 0xFF]
+                elif directive:
+                    data = self.encode(directive, argument, pc, final, number)
+                else:
+                    data = []
+            except (Error, ValueError) as e:
+                raise Error("line %d: %s: %s" % (number, e, text))
+            if data:
+                for i, byte in enumerate(data):
+                    image[pc + i] = byte
+                listing.append("%04x : %-17s%s" % (pc, "".join("%02x" % b for b in data), text))
+            elif label:
+                listing.append("%04x : %-17s%s" % (pc, "", text))
+            else:
+                listing.append(" " * 24 + text)
+            pc += len(data)
+        return origin, image, listing
+
+
+def main():
+    cpu = "6502"
+    args = sys.argv[1:]
+    if args and args[0] == "--65c02":
+        cpu, args = "65c02", args[1:]
+    if len(args) != 1:
+        sys.exit("usage: asm.py [--65c02] source.a65")
+    path = args[0]
+    with open(path) as f:
+        source = f.read()
+    assembler = Assembler(cpu)
+    try:
+        assembler.assemble(source, False)
+        origin, image, listing = assembler.assemble(source, True)
+    except Error as e:
+        sys.exit("%s: %s" % (path, e))
+    end = max(image) + 1
+    binary = bytes(image.get(addr, 0xFF) for addr in range(origin, end))
+    base = path.rsplit(".", 1)[0]
+    with open(base + ".bin", "wb") as f:
+        f.write(binary)
+    with open(base + ".lst", "w") as f:
+        f.write("\n".join(listing) + "\n")
+
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,446 @@
+#!/usr/bin/env python3
+# Generates the per-opcode tests in this directory, in the JSON format of
+# https://github.com/SingleStepTests/65x02 (6502 directory), from a
+# cycle by cycle model of the NMOS 6502 kept here on purpose instead of
+# being derived from the emulator sources.
+#
+#   python3 generate.py [tests per opcode]
+#
+# Every test starts from random registers and random memory, runs one
+# instruction and records the final state and the address, value and
+# direction of the bus access made in each cycle. The seed is fixed, so
+# running it again gives the same files. The JAM opcodes are left out,
+# they never finish.
+import json
+import random
+import sys
+
+# Mnemonic and addressing mode of each opcode, row by high nibble
+TABLE = """
+brk imp|ora izx|jam imp|slo izx|nop zp |ora zp |asl zp |slo zp |php imp|ora imm|asl acc|anc imm|nop abs|ora abs|asl abs|slo abs
+bpl rel|ora izy|jam imp|slo izy|nop zpx|ora zpx|asl zpx|slo zpx|clc imp|ora aby|nop imp|slo aby|nop abx|ora abx|asl abx|slo abx
+jsr abs|and izx|jam imp|rla izx|bit zp |and zp |rol zp |rla zp |plp imp|and imm|rol acc|anc imm|bit abs|and abs|rol abs|rla abs
+bmi rel|and izy|jam imp|rla izy|nop zpx|and zpx|rol zpx|rla zpx|sec imp|and aby|nop imp|rla aby|nop abx|and abx|rol abx|rla abx
+rti imp|eor izx|jam imp|sre izx|nop zp |eor zp |lsr zp |sre zp |pha imp|eor imm|lsr acc|alr imm|jmp abs|eor abs|lsr abs|sre abs
+bvc rel|eor izy|jam imp|sre izy|nop zpx|eor zpx|lsr zpx|sre zpx|cli imp|eor aby|nop imp|sre aby|nop abx|eor abx|lsr abx|sre abx
+rts imp|adc izx|jam imp|rra izx|nop zp |adc zp |ror zp |rra zp |pla imp|adc imm|ror acc|arr imm|jmp ind|adc abs|ror abs|rra abs
+bvs rel|adc izy|jam imp|rra izy|nop zpx|adc zpx|ror zpx|rra zpx|sei imp|adc aby|nop imp|rra aby|nop abx|adc abx|ror abx|rra abx
+nop imm|sta izx|nop imm|sax izx|sty zp |sta zp |stx zp |sax zp |dey imp|nop imm|txa imp|ane imm|sty abs|sta abs|stx abs|sax abs
+bcc rel|sta izy|jam imp|sha izy|sty zpx|sta zpx|stx zpy|sax zpy|tya imp|sta aby|txs imp|tas aby|shy abx|sta abx|shx aby|sha aby
+ldy imm|lda izx|ldx imm|lax izx|ldy zp |lda zp |ldx zp |lax zp |tay imp|lda imm|tax imp|lxa imm|ldy abs|lda abs|ldx abs|lax abs
+bcs rel|lda izy|jam imp|lax izy|ldy zpx|lda zpx|ldx zpy|lax zpy|clv imp|lda aby|tsx imp|las aby|ldy abx|lda abx|ldx aby|lax aby
+cpy imm|cmp izx|nop imm|dcp izx|cpy zp |cmp zp |dec zp |dcp zp |iny imp|cmp imm|dex imp|sbx imm|cpy abs|cmp abs|dec abs|dcp abs
+bne rel|cmp izy|jam imp|dcp izy|nop zpx|cmp zpx|dec zpx|dcp zpx|cld imp|cmp aby|nop imp|dcp aby|nop abx|cmp abx|dec abx|dcp abx
+cpx imm|sbc izx|nop imm|isc izx|cpx zp |sbc zp |inc zp |isc zp |inx imp|sbc imm|nop imp|sbc imm|cpx abs|sbc abs|inc abs|isc abs
+beq rel|sbc izy|jam imp|isc izy|nop zpx|sbc zpx|inc zpx|isc zpx|sed imp|sbc aby|nop imp|isc aby|nop abx|sbc abx|inc abx|isc abx
+"""
+OPCODES = [cell.split() for row in TABLE.strip().split("\n")
+           for cell in row.split("|")]
+
+WRITES = {"sta", "stx", "sty", "sax", "sha", "shx", "shy", "tas"}
+MODIFIES = {"asl", "lsr", "rol", "ror", "inc", "dec",
+            "slo", "rla", "sre", "rra", "dcp", "isc"}
+BRANCHES = {"bpl": (0x80, False), "bmi": (0x80, True),
+            "bvc": (0x40, False), "bvs": (0x40, True),
+            "bcc": (0x01, False), "bcs": (0x01, True),
+            "bne": (0x02, False), "beq": (0x02, True)}
+
+C, Z, I, D, B, U, V, N = (1 << bit for bit in range(8))
+MAGIC = 0xEE  # ANE and LXA constant
+
+
+class Cpu:
+    def __init__(self, rng, regs):
+        self.rng = rng
+        self.pc, self.s, self.a, self.x, self.y, self.p = regs
+        self.memory = {}   # address -> value, filled in on first use
+        self.initial = {}  # address -> value before the instruction
+        self.cycles = []
+
+    def touch(self, addr):
+        if addr not in self.memory:
+            self.memory[addr] = self.initial[addr] = self.rng.randrange(256)
+
+    def read(self, addr):
+        addr &= 0xFFFF
+        self.touch(addr)
+        value = self.memory[addr]
+        self.cycles.append([addr, value, "read"])
+        return value
+
+    def write(self, addr, value):
+        addr &= 0xFFFF
+        self.touch(addr)
+        self.memory[addr] = value & 0xFF
+        self.cycles.append([addr, value & 0xFF, "write"])
+
+    def fetch(self):
+        value = self.read(self.pc)
+        self.pc = (self.pc + 1) & 0xFFFF
+        return value
+
+    def push(self, value):
+        self.write(0x100 | self.s, value)
+        self.s = (self.s - 1) & 0xFF
+
+    def pull(self):
+        self.s = (self.s + 1) & 0xFF
+        return self.read(0x100 | self.s)
+
+    def flag(self, mask, on):
+        self.p = (self.p | mask) if on else (self.p & ~mask & 0xFF)
+
+    def nz(self, value):
+        self.flag(Z, value & 0xFF == 0)
+        self.flag(N, value & 0x80)
+        return value & 0xFF
+
+    # Effective address, cycle by cycle. Returns the address and, for the
+    # SH* stores, the high byte of the unindexed base address.
+    def address(self, mode, kind):
+        if mode == "zp":
+            return self.fetch(), None
+        if mode in ("zpx", "zpy"):
+            base = self.fetch()
+            self.read(base)
+            index = self.x if mode == "zpx" else self.y
+            return (base + index) & 0xFF, None
+        if mode == "abs":
+            lo = self.fetch()
+            return lo | self.fetch() << 8, None
+        if mode in ("abx", "aby"):
+            lo = self.fetch()
+            hi = self.fetch()
+            index = self.x if mode == "abx" else self.y
+            return self.indexed(lo, hi, index, kind)
+        if mode == "izx":
+            pointer = self.fetch()
+            self.read(pointer)
+            pointer = (pointer + self.x) & 0xFF
+            lo = self.read(pointer)
+            return lo | self.read((pointer + 1) & 0xFF) << 8, None
+        if mode == "izy":
+            pointer = self.fetch()
+            lo = self.read(pointer)
+            hi = self.read((pointer + 1) & 0xFF)
+            return self.indexed(lo, hi, self.y, kind)
+        raise ValueError(mode)
+
+    # Adding the index to the low byte takes a cycle in which the CPU reads
+    # from the address with the high byte not fixed up yet. Reads are done
+    # when no fixup is needed, writes and read-modify-writes always wait.
+    def indexed(self, lo, hi, index, kind):
+        addr = ((hi << 8) | lo) + index & 0xFFFF
+        crossed = lo + index > 0xFF
+        if crossed or kind != "read":
+            self.read((hi << 8) | (lo + index) & 0xFF)
+        return addr, hi
+
+    def adc(self, value):
+        carry = self.p & C
+        if self.p & D:
+            lo = (self.a & 0x0F) + (value & 0x0F) + carry
+            if lo > 9:
+                lo += 6
+            sum = (self.a & 0xF0) + (value & 0xF0) + (lo & 0x0F)
+            if lo > 0x0F:
+                sum += 0x10
+            self.flag(Z, (self.a + value + carry) & 0xFF == 0)
+            self.flag(N, sum & 0x80)
+            self.flag(V, (self.a ^ sum) & 0x80 and not (self.a ^ value) & 0x80)
+            if sum & 0x1F0 > 0x90:
+                sum += 0x60
+            self.flag(C, sum & 0xFF0 > 0xF0)
+            self.a = sum & 0xFF
+            return
+        sum = self.a + value + carry
+        self.flag(V, ~(self.a ^ value) & (self.a ^ sum) & 0x80)
+        self.flag(C, sum > 0xFF)
+        self.a = self.nz(sum)
+
+    def sbc(self, value):
+        borrow = 1 - (self.p & C)
+        diff = self.a - value - borrow
+        self.flag(V, (self.a ^ diff) & (self.a ^ value) & 0x80)
+        self.flag(C, diff >= 0)
+        self.nz(diff)
+        if self.p & D:
+            lo = (self.a & 0x0F) - (value & 0x0F) - borrow
+            if lo & 0x10:
+                diff = ((lo - 6) & 0x0F) | ((self.a & 0xF0) - (value & 0xF0) - 0x10)
+            else:
+                diff = (lo & 0x0F) | ((self.a & 0xF0) - (value & 0xF0))
+            if diff & 0x100:
+                diff -= 0x60
+        self.a = diff & 0xFF
+
+    def compare(self, register, value):
+        self.flag(C, register >= value)
+        self.nz(register - value)
+
+    def modify(self, name, value):
+        if name in ("asl", "slo"):
+            self.flag(C, value & 0x80)
+            value = value << 1 & 0xFF
+        elif name in ("lsr", "sre"):
+            self.flag(C, value & 1)
+            value >>= 1
+        elif name in ("rol", "rla"):
+            value, carry = (value << 1 | self.p & C) & 0xFF, value & 0x80
+            self.flag(C, carry)
+        elif name in ("ror", "rra"):
+            value, carry = value >> 1 | (self.p & C) << 7, value & 1
+            self.flag(C, carry)
+        elif name in ("inc", "isc"):
+            value = (value + 1) & 0xFF
+        else:
+            value = (value - 1) & 0xFF
+        if name in ("asl", "lsr", "rol", "ror", "inc", "dec"):
+            self.nz(value)
+        elif name == "slo":
+            self.a = self.nz(self.a | value)
+        elif name == "rla":
+            self.a = self.nz(self.a & value)
+        elif name == "sre":
+            self.a = self.nz(self.a ^ value)
+        elif name == "rra":
+            self.adc(value)
+        elif name == "dcp":
+            self.compare(self.a, value)
+        elif name == "isc":
+            self.sbc(value)
+        return value
+
+    def operate(self, name, value):
+        if name == "lda":
+            self.a = self.nz(value)
+        elif name == "ldx":
+            self.x = self.nz(value)
+        elif name == "ldy":
+            self.y = self.nz(value)
+        elif name in ("lax", "lxa"):
+            if name == "lxa":
+                value = (self.a | MAGIC) & value
+            self.a = self.x = self.nz(value)
+        elif name == "ora":
+            self.a = self.nz(self.a | value)
+        elif name == "and":
+            self.a = self.nz(self.a & value)
+        elif name == "eor":
+            self.a = self.nz(self.a ^ value)
+        elif name == "adc":
+            self.adc(value)
+        elif name == "sbc":
+            self.sbc(value)
+        elif name == "cmp":
+            self.compare(self.a, value)
+        elif name == "cpx":
+            self.compare(self.x, value)
+        elif name == "cpy":
+            self.compare(self.y, value)
+        elif name == "bit":
+            self.flag(Z, self.a & value == 0)
+            self.flag(N, value & 0x80)
+            self.flag(V, value & 0x40)
+        elif name == "anc":
+            self.a = self.nz(self.a & value)
+            self.flag(C, self.a & 0x80)
+        elif name == "alr":
+            value &= self.a
+            self.flag(C, value & 1)
+            self.a = self.nz(value >> 1)
+        elif name == "arr":
+            self.arr(value)
+        elif name == "ane":
+            self.a = self.nz((self.a | MAGIC) & self.x & value)
+        elif name == "sbx":
+            value = (self.a & self.x) - value
+            self.flag(C, value >= 0)
+            self.x = self.nz(value)
+        elif name == "las":
+            self.a = self.x = self.s = self.nz(value & self.s)
+        elif name != "nop":
+            raise ValueError(name)
+
+    def arr(self, value):
+        anded = self.a & value
+        result = anded >> 1 | (self.p & C) << 7
+        if not self.p & D:
+            self.a = self.nz(result)
+            self.flag(C, result & 0x40)
+            self.flag(V, (result >> 6 ^ result >> 5) & 1)
+            return
+        self.flag(N, self.p & C)
+        self.flag(Z, result == 0)
+        self.flag(V, (result ^ anded) & 0x40)
+        if (anded & 0x0F) + (anded & 0x01) > 5:
+            result = (result & 0xF0) | ((result + 6) & 0x0F)
+        self.flag(C, (anded & 0xF0) + (anded & 0x10) > 0x50)
+        if self.p & C:
+            result += 0x60
+        self.a = result & 0xFF
+
+    def stored(self, name):
+        return {"sta": self.a, "stx": self.x, "sty": self.y,
+                "sax": self.a & self.x, "sha": self.a & self.x,
+                "shx": self.x, "shy": self.y, "tas": self.a & self.x}[name]
+
+    def step(self):
+        name, mode = OPCODES[self.fetch()]
+        kind = "write" if name in WRITES else \
+            "modify" if name in MODIFIES and mode != "acc" else "read"
+
+        if name == "brk":
+            self.fetch()
+            self.push(self.pc >> 8)
+            self.push(self.pc)
+            self.push(self.p | B | U)
+            self.p |= I
+            lo = self.read(0xFFFE)
+            self.pc = lo | self.read(0xFFFF) << 8
+        elif name == "jsr":
+            lo = self.fetch()
+            self.read(0x100 | self.s)
+            self.push(self.pc >> 8)
+            self.push(self.pc)
+            self.pc = lo | self.read(self.pc) << 8
+        elif name == "rts":
+            self.read(self.pc)
+            self.read(0x100 | self.s)
+            lo = self.pull()
+            self.pc = lo | self.pull() << 8
+            self.fetch()
+        elif name == "rti":
+            self.read(self.pc)
+            self.read(0x100 | self.s)
+            self.p = self.pull() & ~B & 0xFF | U
+            lo = self.pull()
+            self.pc = lo | self.pull() << 8
+        elif name in ("pha", "php"):
+            self.read(self.pc)
+            self.push(self.a if name == "pha" else self.p | B | U)
+        elif name in ("pla", "plp"):
+            self.read(self.pc)
+            self.read(0x100 | self.s)
+            value = self.pull()
+            if name == "pla":
+                self.a = self.nz(value)
+            else:
+                self.p = value & ~B & 0xFF | U
+        elif name == "jmp" and mode == "abs":
+            lo = self.fetch()
+            self.pc = lo | self.fetch() << 8
+        elif name == "jmp":
+            lo = self.fetch()
+            hi = self.fetch()
+            target = self.read(hi << 8 | lo)
+            # The pointer's high byte comes from the same page
+            self.pc = target | self.read(hi << 8 | (lo + 1) & 0xFF) << 8
+        elif name in BRANCHES:
+            offset = self.fetch()
+            mask, taken_if = BRANCHES[name]
+            if bool(self.p & mask) == taken_if:
+                self.read(self.pc)
+                target = (self.pc + offset - (offset & 0x80) * 2) & 0xFFFF
+                if target >> 8 != self.pc >> 8:
+                    self.read(self.pc & 0xFF00 | target & 0xFF)
+                self.pc = target
+        elif mode in ("imp", "acc"):
+            self.read(self.pc)
+            self.implied(name)
+        elif mode == "imm":
+            self.operate(name, self.fetch())
+        else:
+            addr, base_hi = self.address(mode, kind)
+            if kind == "read":
+                self.operate(name, self.read(addr))
+            elif kind == "modify":
+                value = self.read(addr)
+                self.write(addr, value)
+                self.write(addr, self.modify(name, value))
+            elif name in ("sha", "shx", "shy", "tas"):
+                if name == "tas":
+                    self.s = self.a & self.x
+                value = self.stored(name) & (base_hi + 1)
+                if addr >> 8 != base_hi:
+                    # Crossing a page puts the value on the high address lines
+                    addr = value << 8 | addr & 0xFF
+                self.write(addr, value)
+            else:
+                self.write(addr, self.stored(name))
+
+    def implied(self, name):
+        if name in ("asl", "lsr", "rol", "ror"):
+            self.a = self.nz(self.modify(name, self.a))
+        elif name == "tax":
+            self.x = self.nz(self.a)
+        elif name == "tay":
+            self.y = self.nz(self.a)
+        elif name == "txa":
+            self.a = self.nz(self.x)
+        elif name == "tya":
+            self.a = self.nz(self.y)
+        elif name == "tsx":
+            self.x = self.nz(self.s)
+        elif name == "txs":
+            self.s = self.x
+        elif name == "inx":
+            self.x = self.nz(self.x + 1)
+        elif name == "iny":
+            self.y = self.nz(self.y + 1)
+        elif name == "dex":
+            self.x = self.nz(self.x - 1)
+        elif name == "dey":
+            self.y = self.nz(self.y - 1)
+        elif name in ("clc", "sec"):
+            self.flag(C, name == "sec")
+        elif name in ("cli", "sei"):
+            self.flag(I, name == "sei")
+        elif name in ("cld", "sed"):
+            self.flag(D, name == "sed")
+        elif name == "clv":
+            self.flag(V, False)
+        elif name != "nop":
+            raise ValueError(name)
+
+
+def state(cpu, ram):
+    return {"pc": cpu.pc, "s": cpu.s, "a": cpu.a, "x": cpu.x, "y": cpu.y,
+            "p": cpu.p, "ram": [[addr, ram[addr]] for addr in sorted(ram)]}
+
+
+def generate(rng, opcode):
+    regs = [rng.randrange(0x10000)] + [rng.randrange(256) for _ in range(4)]
+    regs.append(rng.randrange(256) & ~B | U)
+    cpu = Cpu(rng, regs)
+    cpu.memory[cpu.pc] = cpu.initial[cpu.pc] = opcode
+    initial = state(cpu, {})
+    pc = cpu.pc
+    cpu.step()
+    # Named after the bytes of the instruction, like the upstream tests
+    size = {"imp": 1, "acc": 1, "imm": 2, "zp": 2, "zpx": 2, "zpy": 2,
+            "rel": 2, "izx": 2, "izy": 2}.get(OPCODES[opcode][1], 3)
+    name = " ".join("%02x" % cpu.initial[(pc + i) & 0xFFFF]
+                    for i in range(size))
+    initial["ram"] = [[addr, cpu.initial[addr]] for addr in sorted(cpu.initial)]
+    final = state(cpu, {addr: cpu.memory[addr] for addr in cpu.initial})
+    return {"name": name, "initial": initial, "final": final,
+            "cycles": cpu.cycles}
+
+
+def main():
+    count = int(sys.argv[1]) if len(sys.argv) > 1 else 20
+    rng = random.Random(6502)
+    for opcode, (name, _) in enumerate(OPCODES):
+        if name == "jam":
+            continue
+        tests = [generate(rng, opcode) for _ in range(count)]
+        with open("%02x.json" % opcode, "w") as f:
+            f.write("[\n")
+            f.write(",\n".join(json.dumps(test, separators=(",", ":"))
+                               for test in tests))
+            f.write("\n]\n")
+
+
+if __name__ == "__main__":
+    main()
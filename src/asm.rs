@@ -0,0 +1,760 @@
+// 6502 assembler built on the opcode metadata tables. It works in one pass:
+// operands referring to labels that aren't defined yet get the size of an
+// absolute address and are patched once the label is known, so lines can be
+// fed one at a time as in the debugger.
+//
+// Source files use a subset of ACME and KickAssembler syntax:
+//
+//   *= $c000            set the address, also !org/.org or .pc =
+//   name = expr         define a constant
+//   main:               a label, the colon is optional at the start of a line
+//   .loop               a local label, scoped to the last global one
+//   !byte 1, 2, <main   bytes, also .byte/!by
+//   !word main          little endian words, also .word/!wo
+//   !text "HI", 13      characters as they are plus bytes, also .text/!tx
+//   !fill 8, $ff        a count of bytes, also .fill
+//
+// Comments start with ';' or '//'.
+use crate::opcode::{AddrMode, Mnemonic, OPCODES};
+use std::collections::{BTreeMap, HashMap};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum FixupKind {
+    Byte,
+    Word,
+    Branch { next: u16 }, // Offset relative to the following instruction
+}
+
+// Operand waiting for a label to be defined
+struct Fixup {
+    addr: u16,
+    pc: u16, // Address of the instruction, the value of '*'
+    kind: FixupKind,
+    expr: String,
+    scope: String, // Global label local labels in `expr` belong to
+    line: usize,
+}
+
+pub struct Assembler {
+    pc: u16,
+    line: usize,
+    scope: String,
+    labels: HashMap<String, u16>,
+    fixups: Vec<Fixup>,
+    image: BTreeMap<u16, u8>,
+    written: Vec<(u16, u8)>,
+    lines: BTreeMap<u16, usize>, // Source line of each assembled line's first byte
+}
+
+impl Assembler {
+    pub fn new(origin: u16) -> Self {
+        Assembler {
+            pc: origin,
+            line: 0,
+            scope: String::new(),
+            labels: HashMap::new(),
+            fixups: Vec::new(),
+            image: BTreeMap::new(),
+            written: Vec::new(),
+            lines: BTreeMap::new(),
+        }
+    }
+
+    /// Address the next instruction is assembled to
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    pub fn set_pc(&mut self, pc: u16) {
+        self.pc = pc;
+    }
+
+    pub fn labels(&self) -> &HashMap<String, u16> {
+        &self.labels
+    }
+
+    /// Every byte assembled so far by address
+    pub fn image(&self) -> &BTreeMap<u16, u8> {
+        &self.image
+    }
+
+    /// Address of the first byte of every source line that assembled to
+    /// something, with its line number. Only assemble numbers lines.
+    pub fn lines(&self) -> &BTreeMap<u16, usize> {
+        &self.lines
+    }
+
+    /// Bytes written since the last call, including patched forward
+    /// references, for copying into memory
+    pub fn take_written(&mut self) -> Vec<(u16, u8)> {
+        std::mem::take(&mut self.written)
+    }
+
+    /// Labels used but not defined yet
+    pub fn unresolved(&self) -> Vec<String> {
+        let mut names: Vec<String> = Vec::new();
+        for fixup in &self.fixups {
+            for name in identifiers(&fixup.expr) {
+                let name = qualify(&fixup.scope, &name);
+                if !self.labels.contains_key(&name) && !names.contains(&name) {
+                    names.push(name);
+                }
+            }
+        }
+        names
+    }
+
+    /// Assembles a whole source text, errors carry the line number
+    pub fn assemble(&mut self, source: &str) -> Result<(), String> {
+        for (number, text) in source.lines().enumerate() {
+            self.line = number + 1;
+            self.assemble_line(text)
+                .map_err(|e| format!("line {}: {}", number + 1, e))?;
+        }
+        self.finish()
+    }
+
+    /// Fails if any label used is still undefined
+    pub fn finish(&self) -> Result<(), String> {
+        let unresolved = self.unresolved();
+        if unresolved.is_empty() {
+            Ok(())
+        } else {
+            Err(format!("undefined labels: {}", unresolved.join(", ")))
+        }
+    }
+
+    /// Assembles one line: an optional label, then an instruction or a
+    /// directive, with an optional comment
+    pub fn assemble_line(&mut self, text: &str) -> Result<(), String> {
+        let mut rest = strip_comment(text).trim();
+        if rest.is_empty() {
+            return Ok(());
+        }
+
+        // "*= addr" and "name = value" assign rather than emit
+        if let Some((name, expr)) = rest.split_once('=') {
+            let name = name.trim();
+            if name == "*" || name.eq_ignore_ascii_case(".pc") {
+                self.pc = self.value(expr, 0..0x10000)? as u16;
+                return Ok(());
+            }
+            if is_identifier(name) && !name.contains(char::is_whitespace) {
+                let value = self.value(expr, -0x8000..0x10000)?;
+                return self.assign(name, value as u16);
+            }
+        }
+
+        // "name:" or a first word that isn't a mnemonic or directive
+        // defines a label
+        let first = rest.split_whitespace().next().unwrap_or_default();
+        let name = first.strip_suffix(':').unwrap_or(first);
+        let mut label = None;
+        if first.ends_with(':') || (find_mnemonic(first).is_none() && !is_directive(first)) {
+            if !is_identifier(name) {
+                return Err(format!("unknown instruction '{}'", first));
+            }
+            let qualified = qualify(&self.scope, name);
+            if self.labels.contains_key(&qualified) {
+                return Err(format!("label '{}' is already defined", name));
+            }
+            label = Some((name, self.pc));
+            rest = rest[first.len()..].trim();
+        }
+
+        // The label is defined once the instruction assembled, so a line
+        // with an error leaves no trace and can be entered again
+        if !rest.is_empty() {
+            let (word, operand) = match rest.split_once(char::is_whitespace) {
+                Some((word, operand)) => (word, operand.trim()),
+                None => (rest, ""),
+            };
+            let (start, written) = (self.pc, self.written.len());
+            if is_directive(word) {
+                self.directive(word, operand)?;
+            } else {
+                self.instruction(word, operand)?;
+            }
+            if self.line > 0 && self.written.len() != written {
+                self.lines.insert(start, self.line);
+            }
+        }
+        match label {
+            Some((name, addr)) => self.define(name, addr),
+            None => Ok(()),
+        }
+    }
+
+    /// Defines a label, names starting with '.' are local to the last
+    /// global label
+    pub fn define(&mut self, name: &str, value: u16) -> Result<(), String> {
+        self.assign(name, value)?;
+        if !name.starts_with('.') {
+            self.scope = name.to_string();
+        }
+        Ok(())
+    }
+
+    // Defines a name without starting a new scope for local labels, as
+    // "name = value" does
+    fn assign(&mut self, name: &str, value: u16) -> Result<(), String> {
+        let qualified = qualify(&self.scope, name);
+        if self.labels.contains_key(&qualified) {
+            return Err(format!("label '{}' is already defined", name));
+        }
+        self.labels.insert(qualified, value);
+        self.resolve_fixups()
+    }
+
+    // An expression that must be known right away, such as an origin
+    fn value(&self, expr: &str, range: std::ops::Range<i32>) -> Result<i32, String> {
+        let value = self
+            .eval(expr)?
+            .ok_or_else(|| format!("'{}' must not use labels defined later", expr.trim()))?;
+        if !range.contains(&value) {
+            return Err(format!("value ${:X} is out of range", value));
+        }
+        Ok(value)
+    }
+
+    fn directive(&mut self, name: &str, args: &str) -> Result<(), String> {
+        let args = split_args(args);
+        match &name[1..] {
+            "org" => {
+                let [addr] = args[..] else {
+                    return Err(format!("{} takes an address", name));
+                };
+                self.pc = self.value(addr, 0..0x10000)? as u16;
+            }
+            "byte" | "by" => {
+                for arg in args {
+                    self.data(FixupKind::Byte, arg)?;
+                }
+            }
+            "word" | "wo" => {
+                for arg in args {
+                    self.data(FixupKind::Word, arg)?;
+                }
+            }
+            "text" | "tx" => {
+                for arg in args {
+                    match arg.strip_prefix('"').and_then(|a| a.strip_suffix('"')) {
+                        Some(text) => text.bytes().for_each(|b| self.emit(b)),
+                        None => self.data(FixupKind::Byte, arg)?,
+                    }
+                }
+            }
+            "fill" => {
+                let (count, value) = match args[..] {
+                    [count] => (count, None),
+                    [count, value] => (count, Some(value)),
+                    _ => return Err(format!("{} takes a count and a value", name)),
+                };
+                let count = self.value(count, 0..0x10000)?;
+                let value = match value {
+                    Some(value) => self.value(value, -128..0x100)? as u8,
+                    None => 0,
+                };
+                (0..count).for_each(|_| self.emit(value));
+            }
+            _ => return Err(format!("unknown directive '{}'", name)),
+        }
+        Ok(())
+    }
+
+    // One value of a data directive
+    fn data(&mut self, kind: FixupKind, expr: &str) -> Result<(), String> {
+        let pc = self.pc;
+        match self.eval_at(expr, pc)? {
+            Some(value) => encode(kind, value)?.into_iter().for_each(|b| self.emit(b)),
+            None => self.reserve(kind, expr, pc),
+        }
+        Ok(())
+    }
+
+    // Leaves room for an operand using a label that isn't defined yet
+    fn reserve(&mut self, kind: FixupKind, expr: &str, pc: u16) {
+        self.fixups.push(Fixup {
+            addr: self.pc,
+            pc,
+            kind,
+            expr: expr.to_string(),
+            scope: self.scope.clone(),
+            line: self.line,
+        });
+        let size = if kind == FixupKind::Word { 2 } else { 1 };
+        (0..size).for_each(|_| self.emit(0));
+    }
+
+    /// The assembled bytes as a PRG: the load address followed by the bytes
+    /// from the lowest to the highest address written, gaps are zero
+    pub fn prg(&self) -> Result<Vec<u8>, String> {
+        let (Some((&first, _)), Some((&last, _))) =
+            (self.image.first_key_value(), self.image.last_key_value())
+        else {
+            return Err("nothing was assembled".to_string());
+        };
+        let mut prg = first.to_le_bytes().to_vec();
+        prg.extend((first..=last).map(|addr| self.image.get(&addr).copied().unwrap_or(0)));
+        Ok(prg)
+    }
+
+    /// Labels in the VICE monitor format ("al C:c000 .main"), sorted by
+    /// address
+    pub fn vice_labels(&self) -> String {
+        let mut labels: Vec<(&String, &u16)> = self.labels.iter().collect();
+        labels.sort_by_key(|&(name, &addr)| (addr, name.clone()));
+        labels
+            .iter()
+            .map(|(name, addr)| format!("al C:{:04x} .{}\n", addr, name))
+            .collect()
+    }
+
+    fn instruction(&mut self, name: &str, operand: &str) -> Result<(), String> {
+        let mnemonic =
+            find_mnemonic(name).ok_or_else(|| format!("unknown instruction '{}'", name))?;
+        let has = |mode| opcode_for(mnemonic, mode).is_some();
+        let upper = operand.to_ascii_uppercase();
+
+        let (mode, expr) = if operand.is_empty() {
+            let mode = if has(AddrMode::Implied) {
+                AddrMode::Implied
+            } else {
+                AddrMode::Accumulator
+            };
+            (mode, "")
+        } else if upper == "A" && has(AddrMode::Accumulator) {
+            (AddrMode::Accumulator, "")
+        } else if let Some(expr) = operand.strip_prefix('#') {
+            (AddrMode::Immediate, expr)
+        } else if is_indirect(&upper) {
+            let inner = &upper[1..];
+            let expr = &operand[1..];
+            if inner.ends_with(",X)") {
+                let mode = if has(AddrMode::IndirectX) {
+                    AddrMode::IndirectX
+                } else {
+                    AddrMode::AbsoluteIndexedIndirect
+                };
+                (mode, &expr[..expr.len() - 3])
+            } else if inner.ends_with("),Y") {
+                (AddrMode::IndirectY, &expr[..expr.len() - 3])
+            } else if let Some(expr) = expr.strip_suffix(')') {
+                let mode = if has(AddrMode::Indirect) {
+                    AddrMode::Indirect
+                } else {
+                    AddrMode::ZeroPageIndirect
+                };
+                (mode, expr)
+            } else {
+                return Err(format!("invalid operand '{}'", operand));
+            }
+        } else if has(AddrMode::Relative) {
+            (AddrMode::Relative, operand)
+        } else if upper.ends_with(",X") {
+            let expr = &operand[..operand.len() - 2];
+            (
+                self.size_mode(expr, AddrMode::ZeroPageX, AddrMode::AbsoluteX, has)?,
+                expr,
+            )
+        } else if upper.ends_with(",Y") {
+            let expr = &operand[..operand.len() - 2];
+            (
+                self.size_mode(expr, AddrMode::ZeroPageY, AddrMode::AbsoluteY, has)?,
+                expr,
+            )
+        } else {
+            (
+                self.size_mode(operand, AddrMode::ZeroPage, AddrMode::Absolute, has)?,
+                operand,
+            )
+        };
+
+        let opcode = opcode_for(mnemonic, mode).ok_or_else(|| {
+            format!(
+                "{} doesn't support the addressing mode of '{}'",
+                name, operand
+            )
+        })?;
+        let pc = self.pc;
+        let next = pc.wrapping_add(1 + mode.operand_len() as u16);
+        let kind = match mode.operand_len() {
+            0 => {
+                self.emit(opcode);
+                return Ok(());
+            }
+            1 if mode == AddrMode::Relative => FixupKind::Branch { next },
+            1 => FixupKind::Byte,
+            _ => FixupKind::Word,
+        };
+
+        // Check the operand before emitting anything
+        let expr = expr.trim();
+        let bytes = match self.eval_at(expr, pc)? {
+            Some(value) => Some(encode(kind, value)?),
+            None => None,
+        };
+        self.emit(opcode);
+        match bytes {
+            Some(bytes) => bytes.into_iter().for_each(|b| self.emit(b)),
+            None => self.reserve(kind, expr, pc),
+        }
+        Ok(())
+    }
+
+    // Picks the zero page form when the address is known to fit, unknown
+    // labels are assumed to be absolute
+    fn size_mode(
+        &self,
+        expr: &str,
+        zero_page: AddrMode,
+        absolute: AddrMode,
+        has: impl Fn(AddrMode) -> bool,
+    ) -> Result<AddrMode, String> {
+        let fits = matches!(self.eval(expr)?, Some(value) if (0..0x100).contains(&value));
+        if (fits || !has(absolute)) && has(zero_page) {
+            Ok(zero_page)
+        } else {
+            Ok(absolute)
+        }
+    }
+
+    fn emit(&mut self, value: u8) {
+        self.store(self.pc, value);
+        self.pc = self.pc.wrapping_add(1);
+    }
+
+    fn store(&mut self, addr: u16, value: u8) {
+        self.image.insert(addr, value);
+        self.written.push((addr, value));
+    }
+
+    fn resolve_fixups(&mut self) -> Result<(), String> {
+        let mut pending = Vec::new();
+        let mut error = None;
+        for fixup in std::mem::take(&mut self.fixups) {
+            let value = match self.eval_scoped(&fixup.expr, fixup.pc, &fixup.scope) {
+                Ok(Some(value)) => value,
+                Ok(None) => {
+                    pending.push(fixup);
+                    continue;
+                }
+                Err(e) => {
+                    error.get_or_insert(e);
+                    continue;
+                }
+            };
+            match encode(fixup.kind, value) {
+                Ok(bytes) => {
+                    for (i, &b) in bytes.iter().enumerate() {
+                        self.store(fixup.addr.wrapping_add(i as u16), b);
+                    }
+                }
+                Err(e) if fixup.line > 0 => {
+                    error.get_or_insert(format!("{} (from line {})", e, fixup.line));
+                }
+                Err(e) => {
+                    error.get_or_insert(e);
+                }
+            }
+        }
+        self.fixups = pending;
+        error.map_or(Ok(()), Err)
+    }
+
+    /// Evaluates an expression, None while it uses undefined labels
+    pub fn eval(&self, expr: &str) -> Result<Option<i32>, String> {
+        self.eval_at(expr, self.pc)
+    }
+
+    fn eval_at(&self, expr: &str, pc: u16) -> Result<Option<i32>, String> {
+        self.eval_scoped(expr, pc, &self.scope)
+    }
+
+    fn eval_scoped(&self, expr: &str, pc: u16, scope: &str) -> Result<Option<i32>, String> {
+        let mut parser = Expr {
+            text: expr.as_bytes(),
+            pos: 0,
+            pc,
+            scope,
+            labels: &self.labels,
+        };
+        let value = parser.expr()?;
+        parser.skip_spaces();
+        if parser.pos != parser.text.len() {
+            return Err(format!("invalid expression '{}'", expr.trim()));
+        }
+        Ok(value)
+    }
+}
+
+// Operand bytes for a value, checking it fits
+fn encode(kind: FixupKind, value: i32) -> Result<Vec<u8>, String> {
+    match kind {
+        FixupKind::Byte if (-128..0x100).contains(&value) => Ok(vec![value as u8]),
+        FixupKind::Byte => Err(format!("value ${:X} doesn't fit in a byte", value)),
+        FixupKind::Word if (-0x8000..0x10000).contains(&value) => {
+            Ok(vec![value as u8, (value >> 8) as u8])
+        }
+        FixupKind::Word => Err(format!("value ${:X} doesn't fit in a word", value)),
+        FixupKind::Branch { next } => {
+            let offset = value - next as i32;
+            if (-128..128).contains(&offset) {
+                Ok(vec![offset as u8])
+            } else {
+                Err(format!("branch out of range ({} bytes)", offset))
+            }
+        }
+    }
+}
+
+fn find_mnemonic(name: &str) -> Option<Mnemonic> {
+    let name = name.to_ascii_uppercase();
+    OPCODES
+        .iter()
+        .map(|op| op.mnemonic)
+        .find(|m| m.name() == name)
+}
+
+// Documented opcodes win over undocumented ones with the same meaning,
+// such as SBC #imm over $EB
+fn opcode_for(mnemonic: Mnemonic, mode: AddrMode) -> Option<u8> {
+    let matching = |illegal: bool| {
+        OPCODES
+            .iter()
+            .position(|op| op.mnemonic == mnemonic && op.mode == mode && op.illegal == illegal)
+    };
+    matching(false).or_else(|| matching(true)).map(|i| i as u8)
+}
+
+fn is_identifier(name: &str) -> bool {
+    let name = name.strip_prefix('.').unwrap_or(name);
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
+
+fn is_directive(word: &str) -> bool {
+    const NAMES: [&str; 8] = ["org", "byte", "by", "word", "wo", "text", "tx", "fill"];
+    let name = word.strip_prefix('!').or_else(|| word.strip_prefix('.'));
+    name.is_some_and(|name| NAMES.contains(&name))
+}
+
+// Whether an operand in parentheses is indirect rather than an expression
+// such as "(1+2)*3": the parenthesis opening it has to close at its end or
+// before ",Y"
+fn is_indirect(operand: &str) -> bool {
+    if !operand.starts_with('(') {
+        return false;
+    }
+    let mut depth = 0;
+    for (i, c) in operand.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            _ => {}
+        }
+        if depth == 0 {
+            let rest = &operand[i + 1..];
+            return rest.is_empty() || rest == ",Y";
+        }
+    }
+    false
+}
+
+// Local labels are stored as "global.local"
+fn qualify(scope: &str, name: &str) -> String {
+    if name.starts_with('.') {
+        format!("{}{}", scope, name)
+    } else {
+        name.to_string()
+    }
+}
+
+fn strip_comment(text: &str) -> &str {
+    let mut quoted = false;
+    for (i, c) in text.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ';' if !quoted => return &text[..i],
+            '/' if !quoted && text[i..].starts_with("//") => return &text[..i],
+            _ => {}
+        }
+    }
+    text
+}
+
+// Splits directive arguments at commas outside of quotes
+fn split_args(args: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut quoted = false;
+    let mut start = 0;
+    for (i, c) in args.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ',' if !quoted => {
+                parts.push(args[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    if !args[start..].trim().is_empty() {
+        parts.push(args[start..].trim());
+    }
+    parts
+}
+
+// Label names referred to by an expression, skipping $hex, %binary and
+// 'c' character values
+fn identifiers(expr: &str) -> Vec<String> {
+    let mut names = Vec::new();
+    let mut word = String::new();
+    let mut number = false;
+    for c in expr.chars().chain(std::iter::once(' ')) {
+        if c.is_ascii_alphanumeric() || c == '_' || c == '.' {
+            word.push(c);
+            continue;
+        }
+        if !number && is_identifier(&word) {
+            names.push(std::mem::take(&mut word));
+        }
+        word.clear();
+        number = c == '$' || c == '%' || c == '\'';
+    }
+    names
+}
+
+// Recursive descent parser for expressions:
+//   expr    = term (('+' | '-') term)*
+//   term    = unary (('*' | '/') unary)*
+//   unary   = ('-' | '<' | '>') unary | primary
+//   primary = number | label | '*' | '(' expr ')'
+// Numbers are decimal, $hex, %binary or a 'c' character. '*' is the
+// address of the current instruction, '<' and '>' take the low and high
+// byte.
+struct Expr<'a> {
+    text: &'a [u8],
+    pos: usize,
+    pc: u16,
+    scope: &'a str,
+    labels: &'a HashMap<String, u16>,
+}
+
+impl<'a> Expr<'a> {
+    fn skip_spaces(&mut self) {
+        while self.text.get(self.pos) == Some(&b' ') || self.text.get(self.pos) == Some(&b'\t') {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.skip_spaces();
+        self.text.get(self.pos).copied()
+    }
+
+    fn expr(&mut self) -> Result<Option<i32>, String> {
+        let mut value = self.term()?;
+        while let Some(op @ (b'+' | b'-')) = self.peek() {
+            self.pos += 1;
+            let rhs = self.term()?;
+            value = match (value, rhs) {
+                (Some(a), Some(b)) if op == b'+' => Some(a.wrapping_add(b)),
+                (Some(a), Some(b)) => Some(a.wrapping_sub(b)),
+                _ => None,
+            };
+        }
+        Ok(value)
+    }
+
+    fn term(&mut self) -> Result<Option<i32>, String> {
+        let mut value = self.unary()?;
+        while let Some(op @ (b'*' | b'/')) = self.peek() {
+            self.pos += 1;
+            let rhs = self.unary()?;
+            value = match (value, rhs) {
+                (Some(_), Some(0)) if op == b'/' => return Err("division by zero".to_string()),
+                (Some(a), Some(b)) if op == b'*' => Some(a.wrapping_mul(b)),
+                (Some(a), Some(b)) => Some(a / b),
+                _ => None,
+            };
+        }
+        Ok(value)
+    }
+
+    fn unary(&mut self) -> Result<Option<i32>, String> {
+        match self.peek() {
+            Some(b'-') => {
+                self.pos += 1;
+                Ok(self.unary()?.map(|v| -v))
+            }
+            Some(b'<') => {
+                self.pos += 1;
+                Ok(self.unary()?.map(|v| v & 0xff))
+            }
+            Some(b'>') => {
+                self.pos += 1;
+                Ok(self.unary()?.map(|v| (v >> 8) & 0xff))
+            }
+            _ => self.primary(),
+        }
+    }
+
+    fn primary(&mut self) -> Result<Option<i32>, String> {
+        let start = self.pos;
+        let take = |this: &mut Self, f: fn(u8) -> bool| {
+            let from = this.pos;
+            while this.text.get(this.pos).is_some_and(|&c| f(c)) {
+                this.pos += 1;
+            }
+            String::from_utf8_lossy(&this.text[from..this.pos]).to_string()
+        };
+        let number = |digits: String, radix| {
+            i32::from_str_radix(&digits, radix)
+                .map(Some)
+                .map_err(|_| "invalid number".to_string())
+        };
+        match self.peek() {
+            Some(b'$') => {
+                self.pos += 1;
+                number(take(self, |c| c.is_ascii_hexdigit()), 16)
+            }
+            Some(b'%') => {
+                self.pos += 1;
+                number(take(self, |c| c == b'0' || c == b'1'), 2)
+            }
+            Some(c) if c.is_ascii_digit() => number(take(self, |c| c.is_ascii_digit()), 10),
+            Some(b'\'') if self.text.get(self.pos + 2) == Some(&b'\'') => {
+                self.pos += 3;
+                Ok(Some(self.text[self.pos - 2] as i32))
+            }
+            Some(b'*') => {
+                self.pos += 1;
+                Ok(Some(self.pc as i32))
+            }
+            Some(b'(') => {
+                self.pos += 1;
+                let value = self.expr()?;
+                if self.peek() != Some(b')') {
+                    return Err("missing ')'".to_string());
+                }
+                self.pos += 1;
+                Ok(value)
+            }
+            Some(c) if c.is_ascii_alphabetic() || c == b'_' || c == b'.' => {
+                let name = take(self, |c| {
+                    c.is_ascii_alphanumeric() || c == b'_' || c == b'.'
+                });
+                let name = qualify(self.scope, &name);
+                Ok(self.labels.get(&name).map(|&v| v as i32))
+            }
+            _ => {
+                self.pos = start;
+                Err(format!(
+                    "expected a value in '{}'",
+                    String::from_utf8_lossy(self.text).trim()
+                ))
+            }
+        }
+    }
+}
@@ -0,0 +1,78 @@
+// Starts a program the way typing LOAD and RUN would, once the KERNAL has
+// booted to the READY prompt
+use c64_rs::cpu::Cpu;
+use c64_rs::memory::Memory;
+use std::cell::RefCell;
+
+pub const ADDR_READY: u16 = 0xe5cd; // screen editor waiting for a key
+const ADDR_VARTAB: u16 = 0x002d; // end of the BASIC program
+const ADDR_LOAD_END: u16 = 0x00ae;
+const ADDR_KEYBOARD_BUFFER: u16 = 0x0277;
+const ADDR_KEYBOARD_COUNT: u16 = 0x00c6;
+const KEYBOARD_BUFFER_SIZE: usize = 10;
+
+// Booting takes a few million cycles, give it plenty of slack
+const BOOT_CYCLES: u64 = 10_000_000;
+
+// Resets the machine and runs the KERNAL until BASIC is ready for input.
+// `step` steps the whole machine, it returns false when the machine stops.
+pub fn boot(cpu: &RefCell<Cpu<Memory>>, mut step: impl FnMut() -> bool) -> Result<(), String> {
+    {
+        let mut cpu = cpu.borrow_mut();
+        // The 6510 port floats high on reset, banking in the ROMs
+        cpu.bus.write_byte(Memory::ADDR_MEMORY_LAYOUT, 0x37);
+        cpu.reset();
+    }
+    let mut elapsed = 0;
+    let mut last_cycles = cpu.borrow().cycles();
+    while cpu.borrow().pc != ADDR_READY {
+        if elapsed > BOOT_CYCLES || !step() {
+            return Err(format!(
+                "machine did not boot, stuck at ${:04X}",
+                cpu.borrow().pc
+            ));
+        }
+        let cycles = cpu.borrow().cycles();
+        elapsed += cycles.wrapping_sub(last_cycles) as u64;
+        last_cycles = cycles;
+    }
+    Ok(())
+}
+
+// Places the program at its load address like LOAD does and types RUN,
+// or a SYS for machine code that doesn't load to the BASIC start
+pub fn autostart(cpu: &mut Cpu<Memory>, program: &[u8]) {
+    let addr = load(cpu, program);
+    if addr == 0x0801 {
+        type_keys(cpu, b"RUN\r");
+    } else {
+        type_keys(cpu, format!("SYS{}\r", addr).as_bytes());
+    }
+}
+
+// Places the program at its load address like LOAD does, returns the
+// address
+pub fn load(cpu: &mut Cpu<Memory>, program: &[u8]) -> u16 {
+    let addr = u16::from_le_bytes([program[0], program[1]]);
+    let end = addr.wrapping_add((program.len() - 2) as u16);
+    for (i, &byte) in program[2..].iter().enumerate() {
+        cpu.bus.write_byte(addr.wrapping_add(i as u16), byte);
+    }
+    for ptr in [ADDR_VARTAB, ADDR_LOAD_END] {
+        cpu.bus.write_byte(ptr, end as u8);
+        cpu.bus.write_byte(ptr + 1, (end >> 8) as u8);
+    }
+    addr
+}
+
+// Puts keys into the KERNAL keyboard buffer if it is empty
+pub fn type_keys(cpu: &mut Cpu<Memory>, keys: &[u8]) {
+    if cpu.peek_memory(ADDR_KEYBOARD_COUNT) != 0 {
+        return;
+    }
+    let keys = &keys[..keys.len().min(KEYBOARD_BUFFER_SIZE)];
+    for (i, &key) in keys.iter().enumerate() {
+        cpu.bus.write_byte(ADDR_KEYBOARD_BUFFER + i as u16, key);
+    }
+    cpu.bus.write_byte(ADDR_KEYBOARD_COUNT, keys.len() as u8);
+}
@@ -0,0 +1,711 @@
+// VICE binary monitor protocol server, so IDE plugins and test harnesses
+// written for VICE can drive the emulated machine over a local TCP port.
+//
+// A request is STX, the API version, the body length (32 bits), a request
+// id (32 bits), the command and its body. A response is STX, the API
+// version, the body length, the response type, an error code, the request
+// id and its body. Numbers are little endian. Like VICE the machine runs
+// until a command comes in and then stays stopped until the exit command.
+// What the machine does by itself is reported as events, with request id
+// $FFFFFFFF. Checkpoints are the breakpoints of the internal debugger.
+//
+// https://vice-emu.sourceforge.io/vice_13.html
+use crate::breakpoint::{Breakpoint, Condition, Hit, Kind};
+use crate::cpu::{Cpu, Processor};
+use crate::display::Frame;
+use crate::flags::Flags;
+use crate::gdb::Session;
+use crate::memory::Memory;
+use crate::target::{Step, Target};
+use std::collections::HashSet;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+const STX: u8 = 0x02;
+const API_VERSION: u8 = 0x02;
+const EVENT_ID: u32 = 0xffff_ffff;
+
+// Commands, answered by a response of the same type
+const MEMORY_GET: u8 = 0x01;
+const MEMORY_SET: u8 = 0x02;
+const CHECKPOINT_GET: u8 = 0x11;
+const CHECKPOINT_SET: u8 = 0x12;
+const CHECKPOINT_DELETE: u8 = 0x13;
+const CHECKPOINT_LIST: u8 = 0x14;
+const CHECKPOINT_TOGGLE: u8 = 0x15;
+const CONDITION_SET: u8 = 0x22;
+const REGISTERS_GET: u8 = 0x31;
+const REGISTERS_SET: u8 = 0x32;
+const ADVANCE_INSTRUCTIONS: u8 = 0x71;
+const EXECUTE_UNTIL_RETURN: u8 = 0x73;
+const PING: u8 = 0x81;
+const BANKS_AVAILABLE: u8 = 0x82;
+const REGISTERS_AVAILABLE: u8 = 0x83;
+const DISPLAY_GET: u8 = 0x84;
+const EXIT: u8 = 0xaa;
+const QUIT: u8 = 0xbb;
+const RESET: u8 = 0xcc;
+const AUTOSTART: u8 = 0xdd;
+
+// Events
+const JAM: u8 = 0x61;
+const STOPPED: u8 = 0x62;
+const RESUMED: u8 = 0x63;
+
+// Error codes
+const OK: u8 = 0x00;
+const NOT_FOUND: u8 = 0x01;
+const INVALID_MEMSPACE: u8 = 0x02;
+const BAD_LENGTH: u8 = 0x80;
+const INVALID_PARAMETER: u8 = 0x81;
+const BAD_API_VERSION: u8 = 0x82;
+const UNKNOWN_COMMAND: u8 = 0x83;
+const FAILED: u8 = 0x8f;
+
+// Longest request body, setting all of memory
+const MAX_BODY: u32 = 8 + 0x10000;
+
+// Checkpoint operations
+const OP_LOAD: u8 = 0x01;
+const OP_STORE: u8 = 0x02;
+const OP_EXEC: u8 = 0x04;
+
+// VICE's numbers for the 6502 registers, with their size in bits
+const REGISTERS: [(u8, u8, &str); 10] = [
+    (0x00, 8, "A"),
+    (0x01, 8, "X"),
+    (0x02, 8, "Y"),
+    (0x03, 16, "PC"),
+    (0x04, 8, "SP"),
+    (0x05, 8, "FL"),
+    (0x35, 16, "LIN"),
+    (0x36, 16, "CYC"),
+    (0x37, 8, "00"),
+    (0x38, 8, "01"),
+];
+
+// Memory banks: what the CPU sees, and the RAM underneath
+const BANK_CPU: u16 = 0;
+const BANK_RAM: u16 = 1;
+const BANKS: [(u16, &str); 2] = [(BANK_CPU, "cpu"), (BANK_RAM, "ram")];
+
+// Instructions run between checks for a request from the client
+const POLL_INTERVAL: u32 = 1000;
+
+struct Request {
+    id: u32,
+    command: u8,
+    version: u8,
+    body: Vec<u8>,
+}
+
+// Why the machine stopped running
+enum Stop {
+    Step(Step),
+    /// The client sent a request
+    Request,
+    HungUp,
+}
+
+// What the server does after a request
+enum Next {
+    Wait,
+    Stopped(Stop),
+    Quit,
+}
+
+pub struct BinaryMonitor<T: Target> {
+    target: T,
+    temporary: HashSet<u32>, // Checkpoints deleted once hit
+}
+
+impl<T: Target> BinaryMonitor<T> {
+    pub fn new(target: T) -> Self {
+        BinaryMonitor {
+            target,
+            temporary: HashSet::new(),
+        }
+    }
+
+    pub fn into_target(self) -> T {
+        self.target
+    }
+
+    /// Waits for a client to connect to `addr`, e.g. "127.0.0.1:6502",
+    /// and serves it
+    pub fn listen(&mut self, addr: &str) -> Result<Session, String> {
+        let listener = TcpListener::bind(addr).map_err(|e| format!("{}: {}", addr, e))?;
+        println!("Waiting for a binary monitor client on {}", addr);
+        let (stream, peer) = listener.accept().map_err(|e| format!("{}: {}", addr, e))?;
+        println!("Binary monitor client connected from {}", peer);
+        self.serve(stream)
+    }
+
+    /// Serves one connection until the client quits or hangs up
+    pub fn serve(&mut self, mut stream: TcpStream) -> Result<Session, String> {
+        let mut stop = self.run_until(&mut stream, |_| false)?;
+        loop {
+            match stop {
+                Stop::HungUp => return Ok(Session::Detached),
+                Stop::Step(step) => self.stopped(&mut stream, step)?,
+                Stop::Request => self.stopped(&mut stream, Step::Done)?,
+            }
+            stop = loop {
+                let Some(request) = read_request(&mut stream)? else {
+                    return Ok(Session::Detached);
+                };
+                match self.handle(&mut stream, &request)? {
+                    Next::Wait => {}
+                    Next::Stopped(stop) => break stop,
+                    Next::Quit => return Ok(Session::Killed),
+                }
+            };
+        }
+    }
+
+    fn handle(&mut self, stream: &mut TcpStream, request: &Request) -> Result<Next, String> {
+        if request.version == 0 || request.version > API_VERSION {
+            send(stream, request.command, BAD_API_VERSION, request.id, &[])?;
+            return Ok(Next::Wait);
+        }
+        let mut body = Body {
+            data: &request.body,
+        };
+        let reply = match request.command {
+            ADVANCE_INSTRUCTIONS | EXECUTE_UNTIL_RETURN | EXIT => {
+                return self.resume(stream, request, &mut body)
+            }
+            QUIT => {
+                send(stream, QUIT, OK, request.id, &[])?;
+                return Ok(Next::Quit);
+            }
+            CHECKPOINT_LIST => {
+                self.list_checkpoints(stream, request.id)?;
+                return Ok(Next::Wait);
+            }
+            _ => self.command(request.command, &mut body),
+        };
+        match reply {
+            Ok((kind, reply)) => send(stream, kind, OK, request.id, &reply)?,
+            Err(error) => send(stream, request.command, error, request.id, &[])?,
+        }
+        Ok(Next::Wait)
+    }
+
+    // Commands that leave the machine stopped, giving the response type and
+    // body or an error code
+    fn command(&mut self, command: u8, body: &mut Body) -> Result<(u8, Vec<u8>), u8> {
+        let cpu = self.target.cpu();
+        let reply = match command {
+            MEMORY_GET => {
+                let side_effects = body.u8()? != 0;
+                let (start, end, bank) = memory_range(body)?;
+                let cpu = cpu.borrow();
+                // A length of 0 stands for all 64K
+                let mut reply = end
+                    .wrapping_sub(start)
+                    .wrapping_add(1)
+                    .to_le_bytes()
+                    .to_vec();
+                for addr in start..=end {
+                    reply.push(match bank {
+                        BANK_RAM => cpu.bus.read_byte_no_io(addr),
+                        _ if side_effects => cpu.bus.read_byte(addr),
+                        _ => cpu.peek_memory(addr),
+                    });
+                }
+                (MEMORY_GET, reply)
+            }
+            MEMORY_SET => {
+                body.u8()?; // side effects, writes always have them
+                let (start, end, bank) = memory_range(body)?;
+                let data = body.bytes(end as usize - start as usize + 1)?;
+                // Like the monitor, writes don't trigger watchpoints
+                let mut cpu = cpu.borrow_mut();
+                for (addr, &value) in (start..=end).zip(data) {
+                    match bank {
+                        BANK_RAM => cpu.bus.write_byte_no_io(addr, value),
+                        _ => cpu.bus.write_byte(addr, value),
+                    }
+                }
+                (MEMORY_SET, Vec::new())
+            }
+            CHECKPOINT_GET => {
+                let id = body.u32()?;
+                (CHECKPOINT_GET, self.checkpoint_info(id, false)?)
+            }
+            CHECKPOINT_SET => {
+                let start = body.u16()?;
+                let end = body.u16()?;
+                let stop = body.u8()? != 0;
+                let enabled = body.u8()? != 0;
+                let kind = match body.u8()? {
+                    OP_LOAD => Kind::Load,
+                    OP_STORE => Kind::Store,
+                    op if op == OP_LOAD | OP_STORE => Kind::Access,
+                    OP_EXEC => Kind::Exec,
+                    _ => return Err(INVALID_PARAMETER),
+                };
+                let temporary = body.u8()? != 0;
+                // Trace points that only count hits aren't supported
+                if !stop || end < start {
+                    return Err(INVALID_PARAMETER);
+                }
+                let breakpoints = self.target.breakpoints();
+                let id = breakpoints.add(kind, start..=end, None);
+                breakpoints.get_mut(id).map_err(|_| FAILED)?.enabled = enabled;
+                if temporary {
+                    self.temporary.insert(id);
+                }
+                self.target.sync_breakpoints();
+                (CHECKPOINT_GET, self.checkpoint_info(id, false)?)
+            }
+            CHECKPOINT_DELETE => {
+                let id = body.u32()?;
+                self.target
+                    .breakpoints()
+                    .remove(id)
+                    .map_err(|_| NOT_FOUND)?;
+                self.temporary.remove(&id);
+                self.target.sync_breakpoints();
+                (CHECKPOINT_DELETE, Vec::new())
+            }
+            CHECKPOINT_TOGGLE => {
+                let id = body.u32()?;
+                let enabled = body.u8()? != 0;
+                self.target
+                    .breakpoints()
+                    .get_mut(id)
+                    .map_err(|_| NOT_FOUND)?
+                    .enabled = enabled;
+                self.target.sync_breakpoints();
+                (CHECKPOINT_TOGGLE, Vec::new())
+            }
+            CONDITION_SET => {
+                let id = body.u32()?;
+                let len = body.u8()? as usize;
+                let text = String::from_utf8_lossy(body.bytes(len)?).to_lowercase();
+                let condition = Condition::parse(&text).map_err(|_| INVALID_PARAMETER)?;
+                self.target
+                    .breakpoints()
+                    .get_mut(id)
+                    .map_err(|_| NOT_FOUND)?
+                    .condition = Some(condition);
+                (CONDITION_SET, Vec::new())
+            }
+            REGISTERS_GET => {
+                memspace(body)?;
+                (REGISTERS_GET, self.registers())
+            }
+            REGISTERS_SET => {
+                memspace(body)?;
+                let count = body.u16()?;
+                let mut items = Vec::new();
+                for _ in 0..count {
+                    let size = body.u8()? as usize;
+                    let mut item = Body {
+                        data: body.bytes(size)?,
+                    };
+                    items.push((item.u8()?, item.u16()?));
+                }
+                // Check them all before changing any
+                if items
+                    .iter()
+                    .any(|(id, _)| !REGISTERS.iter().any(|r| r.0 == *id))
+                {
+                    return Err(INVALID_PARAMETER);
+                }
+                for (id, value) in items {
+                    set_register(&mut cpu.borrow_mut(), id, value);
+                }
+                (REGISTERS_GET, self.registers())
+            }
+            PING => (PING, Vec::new()),
+            BANKS_AVAILABLE => {
+                let mut reply = (BANKS.len() as u16).to_le_bytes().to_vec();
+                for (id, name) in BANKS {
+                    reply.push(3 + name.len() as u8);
+                    reply.extend(id.to_le_bytes());
+                    reply.push(name.len() as u8);
+                    reply.extend(name.as_bytes());
+                }
+                (BANKS_AVAILABLE, reply)
+            }
+            REGISTERS_AVAILABLE => {
+                memspace(body)?;
+                let mut reply = (REGISTERS.len() as u16).to_le_bytes().to_vec();
+                for (id, bits, name) in REGISTERS {
+                    reply.extend([3 + name.len() as u8, id, bits, name.len() as u8]);
+                    reply.extend(name.as_bytes());
+                }
+                (REGISTERS_AVAILABLE, reply)
+            }
+            DISPLAY_GET => {
+                body.u8()?; // VIC-II or VDC, there is only the VIC
+                if body.u8()? != 0 {
+                    // Only 8 bit indexed pixels
+                    return Err(INVALID_PARAMETER);
+                }
+                let frame = Frame::capture(&cpu.borrow().bus);
+                let mut reply = 13u32.to_le_bytes().to_vec();
+                for value in [
+                    frame.width,
+                    frame.height,
+                    frame.inner_x,
+                    frame.inner_y,
+                    frame.inner_width,
+                    frame.inner_height,
+                ] {
+                    reply.extend(value.to_le_bytes());
+                }
+                reply.push(8);
+                reply.extend((frame.pixels.len() as u32).to_le_bytes());
+                reply.extend(frame.pixels);
+                (DISPLAY_GET, reply)
+            }
+            RESET => {
+                body.u8()?; // soft or hard, they are the same here
+                cpu.borrow_mut().reset();
+                (RESET, Vec::new())
+            }
+            AUTOSTART => {
+                let run = body.u8()? != 0;
+                let index = body.u16()?;
+                let len = body.u8()? as usize;
+                let path = String::from_utf8_lossy(body.bytes(len)?).into_owned();
+                if let Err(e) = self.target.autostart(&path, index, run) {
+                    eprintln!("Autostart failed: {}", e);
+                    return Err(FAILED);
+                }
+                (AUTOSTART, Vec::new())
+            }
+            _ => return Err(UNKNOWN_COMMAND),
+        };
+        Ok(reply)
+    }
+
+    // Sends every checkpoint as a response to the request, then their count
+    fn list_checkpoints(&mut self, stream: &mut TcpStream, id: u32) -> Result<(), String> {
+        let temporary = &self.temporary;
+        let infos: Vec<_> = self
+            .target
+            .breakpoints()
+            .iter()
+            .map(|b| encode_checkpoint(b, temporary.contains(&b.id), false))
+            .collect();
+        for info in &infos {
+            send(stream, CHECKPOINT_GET, OK, id, info)?;
+        }
+        send(
+            stream,
+            CHECKPOINT_LIST,
+            OK,
+            id,
+            &(infos.len() as u32).to_le_bytes(),
+        )
+    }
+
+    // Advancing, returning and exiting answer right away and run the machine
+    // afterwards
+    fn resume(
+        &mut self,
+        stream: &mut TcpStream,
+        request: &Request,
+        body: &mut Body,
+    ) -> Result<Next, String> {
+        let cpu = self.target.cpu();
+        let stop = match request.command {
+            ADVANCE_INSTRUCTIONS => {
+                let (Ok(over), Ok(count)) = (body.u8(), body.u16()) else {
+                    send(stream, request.command, BAD_LENGTH, request.id, &[])?;
+                    return Ok(Next::Wait);
+                };
+                send(stream, ADVANCE_INSTRUCTIONS, OK, request.id, &[])?;
+                let mut stop = Stop::Step(Step::Done);
+                for _ in 0..count {
+                    let (opcode, next, sp) = {
+                        let cpu = cpu.borrow();
+                        (cpu.peek_memory(cpu.pc), cpu.pc.wrapping_add(3), cpu.sp)
+                    };
+                    stop = if over != 0 && opcode == 0x20 {
+                        self.run_until(stream, |cpu| cpu.pc == next && cpu.sp >= sp)?
+                    } else {
+                        Stop::Step(self.target.step_checked())
+                    };
+                    if !matches!(stop, Stop::Step(Step::Done)) {
+                        break;
+                    }
+                }
+                stop
+            }
+            EXECUTE_UNTIL_RETURN => {
+                send(stream, EXECUTE_UNTIL_RETURN, OK, request.id, &[])?;
+                // The routine has returned once the stack is above where it
+                // started
+                let sp = cpu.borrow().sp;
+                self.run_until(stream, |cpu| cpu.sp > sp)?
+            }
+            _ => {
+                send(stream, EXIT, OK, request.id, &[])?;
+                let pc = cpu.borrow().pc;
+                send(stream, RESUMED, OK, EVENT_ID, &pc.to_le_bytes())?;
+                self.run_until(stream, |_| false)?
+            }
+        };
+        Ok(Next::Stopped(stop))
+    }
+
+    // Runs until `done` holds, the machine stops or the client sends
+    // something
+    fn run_until(
+        &mut self,
+        stream: &mut TcpStream,
+        mut done: impl FnMut(&Cpu<Memory>) -> bool,
+    ) -> Result<Stop, String> {
+        let cpu = self.target.cpu();
+        let mut steps = 0u32;
+        loop {
+            let step = self.target.step_checked();
+            if step != Step::Done || done(&cpu.borrow()) {
+                return Ok(Stop::Step(step));
+            }
+            steps += 1;
+            if steps.is_multiple_of(POLL_INTERVAL) {
+                if let Some(stop) = poll(stream)? {
+                    return Ok(stop);
+                }
+            }
+        }
+    }
+
+    // Tells the client where and why the machine stopped
+    fn stopped(&mut self, stream: &mut TcpStream, step: Step) -> Result<(), String> {
+        let pc = self.target.cpu().borrow().pc;
+        match step {
+            Step::Hit(Hit::Break { id, .. } | Hit::Watch { id, .. }) => {
+                if let Ok(info) = self.checkpoint_info(id, true) {
+                    send(stream, CHECKPOINT_GET, OK, EVENT_ID, &info)?;
+                }
+                if self.temporary.remove(&id) {
+                    self.target.breakpoints().remove(id)?;
+                    self.target.sync_breakpoints();
+                }
+            }
+            Step::Halted => send(stream, JAM, OK, EVENT_ID, &pc.to_le_bytes())?,
+            _ => {}
+        }
+        send(stream, STOPPED, OK, EVENT_ID, &pc.to_le_bytes())
+    }
+
+    fn checkpoint_info(&mut self, id: u32, hit: bool) -> Result<Vec<u8>, u8> {
+        let temporary = self.temporary.contains(&id);
+        let breakpoint = self
+            .target
+            .breakpoints()
+            .iter()
+            .find(|b| b.id == id)
+            .ok_or(NOT_FOUND)?;
+        Ok(encode_checkpoint(breakpoint, temporary, hit))
+    }
+
+    fn registers(&self) -> Vec<u8> {
+        let cpu = self.target.cpu();
+        let cpu = cpu.borrow();
+        let registers = cpu.registers();
+        let (line, cycle) = self.target.raster();
+        let mut reply = (REGISTERS.len() as u16).to_le_bytes().to_vec();
+        for (id, _, _) in REGISTERS {
+            let value = match id {
+                0x00 => registers.a as u16,
+                0x01 => registers.x as u16,
+                0x02 => registers.y as u16,
+                0x03 => registers.pc,
+                0x04 => registers.sp as u16,
+                0x05 => registers.p.bits() as u16,
+                0x35 => line,
+                0x36 => cycle,
+                0x37 => cpu.peek_memory(Memory::ADDR_DATA_DIRECTION) as u16,
+                _ => cpu.peek_memory(Memory::ADDR_MEMORY_LAYOUT) as u16,
+            };
+            reply.extend([3, id]);
+            reply.extend(value.to_le_bytes());
+        }
+        reply
+    }
+}
+
+// Checkpoint info as in responses and events
+fn encode_checkpoint(breakpoint: &Breakpoint, temporary: bool, hit: bool) -> Vec<u8> {
+    let op = match breakpoint.kind {
+        Kind::Exec => OP_EXEC,
+        Kind::Load => OP_LOAD,
+        Kind::Store | Kind::Io => OP_STORE,
+        Kind::Access => OP_LOAD | OP_STORE,
+    };
+    let mut info = breakpoint.id.to_le_bytes().to_vec();
+    info.push(hit as u8);
+    info.extend(breakpoint.range.start().to_le_bytes());
+    info.extend(breakpoint.range.end().to_le_bytes());
+    info.extend([
+        1, // stops when hit
+        breakpoint.enabled as u8,
+        op,
+        temporary as u8,
+    ]);
+    info.extend(breakpoint.hits.to_le_bytes());
+    info.extend(0u32.to_le_bytes()); // ignore count
+    info.extend([breakpoint.condition.is_some() as u8, 0]);
+    info
+}
+
+// Raster position can't be set, it follows the cycle count
+fn set_register(cpu: &mut Cpu<Memory>, id: u8, value: u16) {
+    let mut registers = cpu.registers();
+    match id {
+        0x00 => registers.a = value as u8,
+        0x01 => registers.x = value as u8,
+        0x02 => registers.y = value as u8,
+        0x03 => registers.pc = value,
+        0x04 => registers.sp = value as u8,
+        0x05 => registers.p = Flags::from_stack(value as u8),
+        0x37 => cpu.bus.write_byte(Memory::ADDR_DATA_DIRECTION, value as u8),
+        0x38 => cpu.bus.write_byte(Memory::ADDR_MEMORY_LAYOUT, value as u8),
+        _ => {}
+    }
+    cpu.set_registers(&registers);
+}
+
+// Little endian fields of a request body
+struct Body<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Body<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], u8> {
+        if self.data.len() < len {
+            return Err(BAD_LENGTH);
+        }
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, u8> {
+        let bytes = self.bytes(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, u8> {
+        let bytes = self.bytes(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+}
+
+// Only the main computer has memory, not the drives
+fn memspace(body: &mut Body) -> Result<(), u8> {
+    match body.u8()? {
+        0 => Ok(()),
+        _ => Err(INVALID_MEMSPACE),
+    }
+}
+
+// Start and end address, memspace and bank of the memory commands
+fn memory_range(body: &mut Body) -> Result<(u16, u16, u16), u8> {
+    let start = body.u16()?;
+    let end = body.u16()?;
+    memspace(body)?;
+    let bank = body.u16()?;
+    if end < start || !BANKS.iter().any(|b| b.0 == bank) {
+        return Err(INVALID_PARAMETER);
+    }
+    Ok((start, end, bank))
+}
+
+// Reads the next request, None once the client hung up
+fn read_request(stream: &mut TcpStream) -> Result<Option<Request>, String> {
+    let mut header = [0; 11];
+    loop {
+        if !read_exact(stream, &mut header)? {
+            return Ok(None);
+        }
+        if header[0] != STX {
+            return Err(format!(
+                "binary monitor: bad request start ${:02X}",
+                header[0]
+            ));
+        }
+        let len = u32::from_le_bytes([header[2], header[3], header[4], header[5]]);
+        let id = u32::from_le_bytes([header[6], header[7], header[8], header[9]]);
+        if len > MAX_BODY {
+            // Skip the body rather than allocate whatever the client claims
+            let skipped = io::copy(&mut (&mut *stream).take(len as u64), &mut io::sink())
+                .map_err(|e| format!("binary monitor connection: {}", e))?;
+            if skipped < len as u64 {
+                return Ok(None);
+            }
+            send(stream, header[10], BAD_LENGTH, id, &[])?;
+            continue;
+        }
+        let mut body = vec![0; len as usize];
+        if !read_exact(stream, &mut body)? {
+            return Ok(None);
+        }
+        return Ok(Some(Request {
+            version: header[1],
+            id,
+            command: header[10],
+            body,
+        }));
+    }
+}
+
+fn send(stream: &mut TcpStream, kind: u8, error: u8, id: u32, body: &[u8]) -> Result<(), String> {
+    let mut response = Vec::with_capacity(body.len() + 12);
+    response.extend([STX, API_VERSION]);
+    response.extend((body.len() as u32).to_le_bytes());
+    response.extend([kind, error]);
+    response.extend(id.to_le_bytes());
+    response.extend(body);
+    stream
+        .write_all(&response)
+        .map_err(|e| format!("binary monitor connection: {}", e))
+}
+
+// False when the client hung up
+fn read_exact(stream: &mut TcpStream, buf: &mut [u8]) -> Result<bool, String> {
+    match stream.read_exact(buf) {
+        Ok(()) => Ok(true),
+        Err(e)
+            if matches!(
+                e.kind(),
+                ErrorKind::UnexpectedEof | ErrorKind::ConnectionReset
+            ) =>
+        {
+            Ok(false)
+        }
+        Err(e) => Err(format!("binary monitor connection: {}", e)),
+    }
+}
+
+// Whether the client sent a request or hung up while the machine was
+// running, leaving the request to be read
+fn poll(stream: &mut TcpStream) -> Result<Option<Stop>, String> {
+    stream
+        .set_nonblocking(true)
+        .map_err(|e| format!("binary monitor connection: {}", e))?;
+    let mut byte = [0];
+    let result = stream.peek(&mut byte);
+    stream
+        .set_nonblocking(false)
+        .map_err(|e| format!("binary monitor connection: {}", e))?;
+    match result {
+        Ok(0) => Ok(Some(Stop::HungUp)),
+        Ok(_) => Ok(Some(Stop::Request)),
+        Err(e) if e.kind() == ErrorKind::WouldBlock => Ok(None),
+        Err(e) => Err(format!("binary monitor connection: {}", e)),
+    }
+}
@@ -0,0 +1,1078 @@
+// Breakpoints, watchpoints and the conditions attached to them.
+//
+// Execution breakpoints are checked between instructions. Watchpoints rely
+// on Memory recording CPU reads and writes of watched addresses, which are
+// checked once the instruction doing them has finished. Traps stop on
+// events rather than addresses: interrupts, BRK, undocumented opcodes, JAM
+// and changes of the memory configuration.
+//
+// Conditions are expressions over the registers, memory and cycle counters,
+// e.g. "a == $ff && @d012 > $80". Numbers are written like in the monitor,
+// hex with an optional '$', '+' for decimal and '%' for binary. '@' reads
+// memory without side effects.
+use crate::bus::Bus;
+use crate::callstack::Imbalance;
+use crate::cpu::{Cpu, Interrupt};
+use crate::disasm::Instruction;
+use crate::memory::{Access, Memory};
+use crate::opcode::{Mnemonic, OPCODES};
+use std::collections::HashSet;
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+
+/// What a breakpoint reacts to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+    Exec,
+    Load,
+    Store,
+    Access, // Loads and stores
+    Io,     // Stores to a chip register or any of its mirrors
+}
+
+impl Kind {
+    fn matches(self, access: &Access) -> bool {
+        match self {
+            Kind::Exec => false,
+            Kind::Load => !access.write,
+            Kind::Store | Kind::Io => access.write,
+            Kind::Access => true,
+        }
+    }
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Kind::Exec => "exec",
+            Kind::Load => "load",
+            Kind::Store => "store",
+            Kind::Access => "load/store",
+            Kind::Io => "io",
+        };
+        f.pad(name)
+    }
+}
+
+// Chip registers repeat all over their area, the VIC-II every $40 bytes
+// and the SID every $20 in $D000-$D7FF, the CIAs every $10 in their page
+fn io_mirror_mask(addr: u16) -> Option<u16> {
+    match addr {
+        0xd000..=0xd3ff => Some(0x3f),
+        0xd400..=0xd7ff => Some(0x1f),
+        0xdc00..=0xddff => Some(0x0f),
+        _ => None,
+    }
+}
+
+// Every address a register can be written through, for "any $D011 write"
+fn io_mirrors(addr: u16) -> Vec<u16> {
+    match io_mirror_mask(addr) {
+        Some(mask) => {
+            let area = match addr {
+                0xd000..=0xd3ff => 0xd000..=0xd3ff,
+                0xd400..=0xd7ff => 0xd400..=0xd7ff,
+                _ => (addr & 0xff00)..=(addr | 0x00ff),
+            };
+            area.filter(|a| a & mask == addr & mask).collect()
+        }
+        None => vec![addr],
+    }
+}
+
+pub struct Breakpoint {
+    pub id: u32,
+    pub kind: Kind,
+    pub range: RangeInclusive<u16>,
+    pub condition: Option<Condition>,
+    pub enabled: bool,
+    pub hits: u32,
+}
+
+impl Breakpoint {
+    fn covers(&self, addr: u16) -> bool {
+        match (self.kind, io_mirror_mask(addr)) {
+            (Kind::Io, Some(mask)) => self
+                .range
+                .clone()
+                .any(|r| io_mirror_mask(r) == Some(mask) && io_mirrors(r).contains(&addr)),
+            _ => self.range.contains(&addr),
+        }
+    }
+
+    fn condition_holds(&self, cpu: &Cpu<Memory>, raster: (u16, u16)) -> bool {
+        self.condition
+            .as_ref()
+            .is_none_or(|c| c.eval(cpu, raster) != 0)
+    }
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "#{:<3} {:<10} ${:04X}",
+            self.id,
+            self.kind,
+            self.range.start()
+        )?;
+        if self.range.end() != self.range.start() {
+            write!(f, "-${:04X}", self.range.end())?;
+        }
+        if let Some(condition) = &self.condition {
+            write!(f, " if {}", condition)?;
+        }
+        if !self.enabled {
+            write!(f, " (disabled)")?;
+        }
+        write!(f, ", {} hits", self.hits)
+    }
+}
+
+/// Events that stop execution without a breakpoint at an address
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Trap {
+    Irq,
+    Nmi,
+    Brk,
+    Illegal, // Undocumented opcodes
+    Jam,
+    Banking, // Change of the $01 memory configuration
+    Stack,   // Returns and stack tricks the shadow call stack can't follow
+}
+
+impl Trap {
+    pub const ALL: [Trap; 7] = [
+        Trap::Irq,
+        Trap::Nmi,
+        Trap::Brk,
+        Trap::Illegal,
+        Trap::Jam,
+        Trap::Banking,
+        Trap::Stack,
+    ];
+}
+
+impl FromStr for Trap {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Trap::ALL
+            .into_iter()
+            .find(|trap| trap.to_string().eq_ignore_ascii_case(s))
+            .ok_or(format!("unknown trap: {}", s))
+    }
+}
+
+impl fmt::Display for Trap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Trap::Irq => "irq",
+            Trap::Nmi => "nmi",
+            Trap::Brk => "brk",
+            Trap::Illegal => "illegal",
+            Trap::Jam => "jam",
+            Trap::Banking => "banking",
+            Trap::Stack => "stack",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Why execution stopped
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Hit {
+    Break {
+        id: u32,
+        pc: u16,
+    },
+    /// `pc` is the instruction that did the access
+    Watch {
+        id: u32,
+        pc: u16,
+        access: Access,
+    },
+    /// `pc` is the instruction about to run, or the one an interrupt
+    /// interrupted
+    Trap {
+        trap: Trap,
+        pc: u16,
+    },
+    Banking {
+        pc: u16,
+        old: u8,
+        new: u8,
+    },
+    Stack {
+        pc: u16,
+        imbalance: Imbalance,
+    },
+}
+
+impl fmt::Display for Hit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Hit::Break { id, pc } => write!(f, "Breakpoint #{} at ${:04X}", id, pc),
+            Hit::Watch { id, pc, access } => write!(
+                f,
+                "Watchpoint #{}: {} ${:04X} = ${:02X} by ${:04X}",
+                id,
+                if access.write { "store" } else { "load" },
+                access.addr,
+                access.value,
+                pc
+            ),
+            Hit::Trap { trap, pc } => match trap {
+                Trap::Irq | Trap::Nmi => {
+                    write!(f, "{} at ${:04X}", trap.to_string().to_uppercase(), pc)
+                }
+                _ => write!(f, "Stopped on {} at ${:04X}", trap, pc),
+            },
+            Hit::Banking { pc, old, new } => write!(
+                f,
+                "Banking changed from %{:03b} to %{:03b} by ${:04X}",
+                old, new, pc
+            ),
+            Hit::Stack { imbalance, .. } => write!(f, "Call stack unbalanced: {}", imbalance),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Breakpoints {
+    list: Vec<Breakpoint>,
+    next_id: u32,
+    traps: HashSet<Trap>,
+}
+
+impl Breakpoints {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Breakpoint> {
+        self.list.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty() && self.traps.is_empty()
+    }
+
+    /// Adds a breakpoint and returns its number. Call sync afterwards for
+    /// watchpoints to take effect.
+    pub fn add(
+        &mut self,
+        kind: Kind,
+        range: RangeInclusive<u16>,
+        condition: Option<Condition>,
+    ) -> u32 {
+        self.next_id += 1;
+        self.list.push(Breakpoint {
+            id: self.next_id,
+            kind,
+            range,
+            condition,
+            enabled: true,
+            hits: 0,
+        });
+        self.next_id
+    }
+
+    pub fn get_mut(&mut self, id: u32) -> Result<&mut Breakpoint, String> {
+        self.list
+            .iter_mut()
+            .find(|b| b.id == id)
+            .ok_or(format!("no breakpoint #{}", id))
+    }
+
+    pub fn remove(&mut self, id: u32) -> Result<(), String> {
+        self.get_mut(id)?;
+        self.list.retain(|b| b.id != id);
+        Ok(())
+    }
+
+    pub fn clear(&mut self) {
+        self.list.clear();
+    }
+
+    pub fn traps(&self) -> &HashSet<Trap> {
+        &self.traps
+    }
+
+    pub fn set_trap(&mut self, trap: Trap, enabled: bool) {
+        if enabled {
+            self.traps.insert(trap);
+        } else {
+            self.traps.remove(&trap);
+        }
+    }
+
+    /// Tells memory which addresses the enabled watchpoints cover
+    pub fn sync(&self, memory: &mut Memory) {
+        memory.clear_watches();
+        for b in self.list.iter().filter(|b| b.enabled) {
+            match b.kind {
+                Kind::Exec => {}
+                Kind::Load => memory.watch(b.range.clone(), true, false),
+                Kind::Store => memory.watch(b.range.clone(), false, true),
+                Kind::Access => memory.watch(b.range.clone(), true, true),
+                Kind::Io => {
+                    for addr in b.range.clone().flat_map(io_mirrors) {
+                        memory.watch(addr..=addr, false, true);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Checks the instruction at `from` that has just run for watched
+    /// accesses, interrupts, banking changes and call stack imbalances,
+    /// then the next instruction for execution breakpoints and traps.
+    /// `raster` is the raster line and cycle for conditions.
+    pub fn check(&mut self, cpu: &mut Cpu<Memory>, from: u16, raster: (u16, u16)) -> Option<Hit> {
+        let accesses = cpu.bus.take_accesses();
+        let banking = cpu.bus.take_banking_change();
+        let imbalance = cpu.calls_mut().take_imbalance();
+        if self.is_empty() {
+            return None;
+        }
+
+        let interrupt = match cpu.interrupted() {
+            Some(Interrupt::Irq) => Some(Trap::Irq),
+            Some(Interrupt::Nmi) => Some(Trap::Nmi),
+            None => None,
+        };
+        if let Some(trap) = interrupt.filter(|trap| self.traps.contains(trap)) {
+            return Some(Hit::Trap { trap, pc: from });
+        }
+        if let Some((old, new)) = banking.filter(|_| self.traps.contains(&Trap::Banking)) {
+            return Some(Hit::Banking { pc: from, old, new });
+        }
+        if let Some(imbalance) = imbalance.filter(|_| self.traps.contains(&Trap::Stack)) {
+            return Some(Hit::Stack {
+                pc: from,
+                imbalance,
+            });
+        }
+
+        // Fetching the instruction itself isn't a load
+        let fetched = Instruction::decode(from, |a| cpu.peek_memory(a));
+        let fetch = from..=fetched.next().wrapping_sub(1);
+        for access in accesses {
+            if !access.write && fetch.contains(&access.addr) {
+                continue;
+            }
+            let hit = self.list.iter_mut().find(|b| {
+                b.enabled
+                    && b.kind.matches(&access)
+                    && b.covers(access.addr)
+                    && b.condition_holds(cpu, raster)
+            });
+            if let Some(b) = hit {
+                b.hits += 1;
+                return Some(Hit::Watch {
+                    id: b.id,
+                    pc: from,
+                    access,
+                });
+            }
+        }
+
+        if let Some(hit) = self.check_exec(cpu, raster) {
+            return Some(hit);
+        }
+
+        // Stop in front of the instruction, so it can still be looked at
+        let pc = cpu.pc;
+        let next = &OPCODES[cpu.peek_memory(pc) as usize];
+        let trap = if next.mnemonic == Mnemonic::Jam {
+            Trap::Jam
+        } else if next.mnemonic == Mnemonic::Brk {
+            Trap::Brk
+        } else if next.illegal {
+            Trap::Illegal
+        } else {
+            return None;
+        };
+        self.traps.contains(&trap).then_some(Hit::Trap { trap, pc })
+    }
+
+    /// Checks the execution breakpoints on the next instruction only, e.g.
+    /// after stepping backwards
+    pub fn check_exec(&mut self, cpu: &Cpu<Memory>, raster: (u16, u16)) -> Option<Hit> {
+        let pc = cpu.pc;
+        let hit = self.list.iter_mut().find(|b| {
+            b.enabled
+                && b.kind == Kind::Exec
+                && b.range.contains(&pc)
+                && b.condition_holds(cpu, raster)
+        })?;
+        hit.hits += 1;
+        Some(Hit::Break { id: hit.id, pc })
+    }
+}
+
+/// Values a condition can refer to by name
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Name {
+    A,
+    X,
+    Y,
+    Sp,
+    Pc,
+    P,
+    Cycles,
+    RasterLine,
+    RasterCycle,
+}
+
+impl Name {
+    fn parse(word: &str) -> Option<Self> {
+        Some(match word.to_lowercase().as_str() {
+            "a" => Name::A,
+            "x" => Name::X,
+            "y" => Name::Y,
+            "sp" => Name::Sp,
+            "pc" => Name::Pc,
+            "p" => Name::P,
+            "cycles" => Name::Cycles,
+            "rl" => Name::RasterLine,
+            "cy" => Name::RasterCycle,
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Op {
+    Or,
+    And,
+    BitOr,
+    BitXor,
+    BitAnd,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+impl Op {
+    // Binding strength, higher binds tighter
+    fn precedence(self) -> u8 {
+        match self {
+            Op::Or => 1,
+            Op::And => 2,
+            Op::BitOr => 3,
+            Op::BitXor => 4,
+            Op::BitAnd => 5,
+            Op::Eq | Op::Ne => 6,
+            Op::Lt | Op::Le | Op::Gt | Op::Ge => 7,
+            Op::Add | Op::Sub => 8,
+            Op::Mul | Op::Div => 9,
+        }
+    }
+
+    fn apply(self, l: i64, r: i64) -> i64 {
+        match self {
+            Op::Or => (l != 0 || r != 0) as i64,
+            Op::And => (l != 0 && r != 0) as i64,
+            Op::BitOr => l | r,
+            Op::BitXor => l ^ r,
+            Op::BitAnd => l & r,
+            Op::Eq => (l == r) as i64,
+            Op::Ne => (l != r) as i64,
+            Op::Lt => (l < r) as i64,
+            Op::Le => (l <= r) as i64,
+            Op::Gt => (l > r) as i64,
+            Op::Ge => (l >= r) as i64,
+            Op::Add => l.wrapping_add(r),
+            Op::Sub => l.wrapping_sub(r),
+            Op::Mul => l.wrapping_mul(r),
+            // Dividing by zero gives zero rather than stopping the machine
+            Op::Div => l.checked_div(r).unwrap_or(0),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Expr {
+    Number(i64),
+    Name(Name),
+    Memory(Box<Expr>),
+    Neg(Box<Expr>),
+    Not(Box<Expr>),
+    Binary(Op, Box<Expr>, Box<Expr>),
+}
+
+/// A parsed condition, shown as it was written
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Condition {
+    text: String,
+    expr: Expr,
+}
+
+impl Condition {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut parser = Parser {
+            text: text.as_bytes(),
+            pos: 0,
+        };
+        let expr = parser.expr(0)?;
+        parser.skip_spaces();
+        if parser.pos < text.len() {
+            return Err(format!("unexpected '{}' in condition", &text[parser.pos..]));
+        }
+        Ok(Condition {
+            text: text.trim().to_string(),
+            expr,
+        })
+    }
+
+    /// Value of the condition, non-zero counts as true
+    pub fn eval<B: Bus>(&self, cpu: &Cpu<B>, raster: (u16, u16)) -> i64 {
+        eval(&self.expr, cpu, raster)
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+fn eval<B: Bus>(expr: &Expr, cpu: &Cpu<B>, raster: (u16, u16)) -> i64 {
+    match expr {
+        Expr::Number(n) => *n,
+        Expr::Name(name) => match name {
+            Name::A => cpu.a as i64,
+            Name::X => cpu.x as i64,
+            Name::Y => cpu.y as i64,
+            Name::Sp => cpu.sp as i64,
+            Name::Pc => cpu.pc as i64,
+            Name::P => cpu.p.bits() as i64,
+            Name::Cycles => cpu.cycles() as i64,
+            Name::RasterLine => raster.0 as i64,
+            Name::RasterCycle => raster.1 as i64,
+        },
+        Expr::Memory(addr) => cpu.peek_memory(eval(addr, cpu, raster) as u16) as i64,
+        Expr::Neg(e) => eval(e, cpu, raster).wrapping_neg(),
+        Expr::Not(e) => (eval(e, cpu, raster) == 0) as i64,
+        Expr::Binary(op, l, r) => op.apply(eval(l, cpu, raster), eval(r, cpu, raster)),
+    }
+}
+
+// Precedence climbing parser
+struct Parser<'a> {
+    text: &'a [u8],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn skip_spaces(&mut self) {
+        while self
+            .text
+            .get(self.pos)
+            .is_some_and(|c| c.is_ascii_whitespace())
+        {
+            self.pos += 1;
+        }
+    }
+
+    fn expr(&mut self, min_precedence: u8) -> Result<Expr, String> {
+        let mut left = self.unary()?;
+        while let Some((op, len)) = self.peek_op() {
+            if op.precedence() <= min_precedence {
+                break;
+            }
+            self.pos += len;
+            let right = self.expr(op.precedence())?;
+            left = Expr::Binary(op, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn peek_op(&mut self) -> Option<(Op, usize)> {
+        self.skip_spaces();
+        let rest = &self.text[self.pos..];
+        let ops: [(&[u8], Op); 15] = [
+            (b"||", Op::Or),
+            (b"&&", Op::And),
+            (b"==", Op::Eq),
+            (b"!=", Op::Ne),
+            (b"<=", Op::Le),
+            (b">=", Op::Ge),
+            (b"<", Op::Lt),
+            (b">", Op::Gt),
+            (b"|", Op::BitOr),
+            (b"^", Op::BitXor),
+            (b"&", Op::BitAnd),
+            (b"+", Op::Add),
+            (b"-", Op::Sub),
+            (b"*", Op::Mul),
+            (b"/", Op::Div),
+        ];
+        ops.iter()
+            .find(|(token, _)| rest.starts_with(token))
+            .map(|&(token, op)| (op, token.len()))
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        self.skip_spaces();
+        let Some(&c) = self.text.get(self.pos) else {
+            return Err("condition ends early".to_string());
+        };
+        match c {
+            b'-' | b'!' | b'@' => {
+                self.pos += 1;
+                let e = Box::new(self.unary()?);
+                Ok(match c {
+                    b'-' => Expr::Neg(e),
+                    b'!' => Expr::Not(e),
+                    _ => Expr::Memory(e),
+                })
+            }
+            b'(' => {
+                self.pos += 1;
+                let e = self.expr(0)?;
+                self.skip_spaces();
+                if self.text.get(self.pos) != Some(&b')') {
+                    return Err("missing ')' in condition".to_string());
+                }
+                self.pos += 1;
+                Ok(e)
+            }
+            b'$' | b'%' | b'+' => {
+                self.pos += 1;
+                let word = self.word();
+                let radix = match c {
+                    b'$' => 16,
+                    b'%' => 2,
+                    _ => 10,
+                };
+                i64::from_str_radix(word, radix)
+                    .map(Expr::Number)
+                    .map_err(|_| format!("bad number in condition: {}{}", c as char, word))
+            }
+            _ => {
+                // Names win over hex numbers, "$a" is the number
+                let word = self.word();
+                if word.is_empty() {
+                    return Err(format!("unexpected '{}' in condition", c as char));
+                }
+                match Name::parse(word) {
+                    Some(name) => Ok(Expr::Name(name)),
+                    None => i64::from_str_radix(word, 16)
+                        .map(Expr::Number)
+                        .map_err(|_| format!("unknown name in condition: {}", word)),
+                }
+            }
+        }
+    }
+
+    fn word(&mut self) -> &str {
+        let start = self.pos;
+        while self
+            .text
+            .get(self.pos)
+            .is_some_and(|c| c.is_ascii_alphanumeric())
+        {
+            self.pos += 1;
+        }
+        // Only ASCII was consumed, so this is valid UTF-8
+        std::str::from_utf8(&self.text[start..self.pos]).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::FlatRam;
+    use crate::cpu::{IrqSource, NmiSource};
+    use crate::flags::Flags;
+
+    fn cpu() -> Cpu<FlatRam> {
+        let mut cpu = Cpu::new(FlatRam::new());
+        cpu.a = 0xff;
+        cpu.x = 0x01;
+        cpu.y = 0x02;
+        cpu.sp = 0xf0;
+        cpu.pc = 0xc000;
+        cpu.bus.load(0xd012, &[0x90]);
+        cpu.bus.load(0x00fb, &[0x34, 0x12]);
+        cpu.bus.load(0x1234, &[0x56]);
+        cpu
+    }
+
+    fn eval(text: &str) -> i64 {
+        let condition = Condition::parse(text).unwrap_or_else(|e| panic!("{}: {}", text, e));
+        condition.eval(&cpu(), (100, 20))
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(eval("1 + 2 * 3"), 7);
+        assert_eq!(eval("(1 + 2) * 3"), 9);
+        assert_eq!(eval("2 * 3 == 6"), 1);
+        assert_eq!(eval("4 | 2 == 2"), 5);
+        assert_eq!(eval("6 & 3 ^ 1"), 3);
+        assert_eq!(eval("1 + 1 < 3"), 1);
+        assert_eq!(eval("-1 + 2"), 1);
+        // Operators of the same level group to the left
+        assert_eq!(eval("+10 - 2 - 3"), 5);
+        assert_eq!(eval("+12 / 2 / 3"), 2);
+    }
+
+    #[test]
+    fn logical_operators() {
+        // && binds tighter than ||
+        assert_eq!(eval("1 || 1 && 0"), 1);
+        assert_eq!(eval("1 && 0 || 1"), 1);
+        assert_eq!(eval("0 || 0"), 0);
+        assert_eq!(eval("2 && 3"), 1);
+        assert_eq!(eval("!0"), 1);
+        assert_eq!(eval("!5"), 0);
+        assert_eq!(eval("a == $ff && x == 1 || y == 3"), 1);
+        assert_eq!(eval("a == $fe && x == 1 || y == 3"), 0);
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(eval("$10"), 0x10);
+        assert_eq!(eval("10"), 0x10);
+        assert_eq!(eval("+10"), 10);
+        assert_eq!(eval("%101"), 5);
+        // "a" is the register, "$a" the number
+        assert_eq!(eval("a"), 0xff);
+        assert_eq!(eval("$a"), 0x0a);
+        assert_eq!(eval("5 / 0"), 0);
+    }
+
+    #[test]
+    fn registers_and_counters() {
+        assert_eq!(eval("A"), 0xff);
+        assert_eq!(eval("x"), 0x01);
+        assert_eq!(eval("y"), 0x02);
+        assert_eq!(eval("sp"), 0xf0);
+        assert_eq!(eval("pc"), 0xc000);
+        assert_eq!(eval("p"), 0x24);
+        assert_eq!(eval("cycles"), 0);
+        assert_eq!(eval("rl"), 100);
+        assert_eq!(eval("cy"), 20);
+        assert_eq!(eval("rl > +99 && cy < +21"), 1);
+    }
+
+    #[test]
+    fn memory_reads() {
+        assert_eq!(eval("@d012"), 0x90);
+        assert_eq!(eval("@d012 > $80"), 1);
+        assert_eq!(eval("@(d011 + x)"), 0x90);
+        // Little endian pointer at $FB
+        assert_eq!(eval("@(@fb + @fc * $100)"), 0x56);
+        assert_eq!(eval("@@fb"), 0x00);
+    }
+
+    #[test]
+    fn shown_as_written() {
+        let condition = Condition::parse("  a == $ff  ").unwrap();
+        assert_eq!(condition.to_string(), "a == $ff");
+    }
+
+    #[test]
+    fn parse_errors() {
+        let error = |text| Condition::parse(text).unwrap_err();
+        assert_eq!(error(""), "condition ends early");
+        assert_eq!(error("a =="), "condition ends early");
+        assert_eq!(error("(a == 1"), "missing ')' in condition");
+        assert_eq!(error("a == 1)"), "unexpected ')' in condition");
+        assert_eq!(error("a = 1"), "unexpected '= 1' in condition");
+        assert_eq!(error("#1"), "unexpected '#' in condition");
+        assert_eq!(error("$g1"), "bad number in condition: $g1");
+        assert_eq!(error("%12"), "bad number in condition: %12");
+        assert_eq!(error("foo == 1"), "unknown name in condition: foo");
+    }
+
+    // `code` at $C000 on the C64 memory map
+    fn machine_with(code: &[u8]) -> Cpu<Memory> {
+        let mut memory = Memory::new().unwrap();
+        for (addr, &value) in (0xc000..).zip(code) {
+            memory.write_byte(addr, value);
+        }
+        let mut cpu = Cpu::new(memory);
+        cpu.pc = 0xc000;
+        cpu
+    }
+
+    // LDA $1000, STA $1001, INC $1002
+    fn machine() -> Cpu<Memory> {
+        machine_with(&[0xad, 0x00, 0x10, 0x8d, 0x01, 0x10, 0xee, 0x02, 0x10])
+    }
+
+    // Runs the next instruction and checks it
+    fn step(cpu: &mut Cpu<Memory>, breakpoints: &mut Breakpoints) -> Option<Hit> {
+        let from = cpu.pc;
+        cpu.step();
+        breakpoints.check(cpu, from, (0, 0))
+    }
+
+    fn watched(hit: Option<Hit>) -> Option<(u32, u16, bool)> {
+        match hit? {
+            Hit::Watch { id, access, .. } => Some((id, access.addr, access.write)),
+            hit => panic!("unexpected {:?}", hit),
+        }
+    }
+
+    #[test]
+    fn load_watchpoint_ignores_stores() {
+        let mut cpu = machine();
+        let mut breakpoints = Breakpoints::new();
+        let id = breakpoints.add(Kind::Load, 0x1000..=0x10ff, None);
+        breakpoints.sync(&mut cpu.bus);
+
+        assert_eq!(
+            watched(step(&mut cpu, &mut breakpoints)),
+            Some((id, 0x1000, false))
+        );
+        assert_eq!(watched(step(&mut cpu, &mut breakpoints)), None);
+        assert_eq!(
+            watched(step(&mut cpu, &mut breakpoints)),
+            Some((id, 0x1002, false))
+        );
+        assert_eq!(breakpoints.get_mut(id).unwrap().hits, 2);
+    }
+
+    #[test]
+    fn store_watchpoint_ignores_loads() {
+        let mut cpu = machine();
+        let mut breakpoints = Breakpoints::new();
+        let id = breakpoints.add(Kind::Store, 0x1000..=0x10ff, None);
+        breakpoints.sync(&mut cpu.bus);
+
+        assert_eq!(watched(step(&mut cpu, &mut breakpoints)), None);
+        assert_eq!(
+            watched(step(&mut cpu, &mut breakpoints)),
+            Some((id, 0x1001, true))
+        );
+        assert_eq!(
+            watched(step(&mut cpu, &mut breakpoints)),
+            Some((id, 0x1002, true))
+        );
+    }
+
+    #[test]
+    fn watchpoint_range_and_condition() {
+        let mut cpu = machine();
+        let mut breakpoints = Breakpoints::new();
+        // Only the end of the range is hit, and only with X set
+        let id = breakpoints.add(
+            Kind::Access,
+            0x1001..=0x1002,
+            Some(Condition::parse("x == 1").unwrap()),
+        );
+        breakpoints.sync(&mut cpu.bus);
+
+        assert_eq!(watched(step(&mut cpu, &mut breakpoints)), None);
+        assert_eq!(watched(step(&mut cpu, &mut breakpoints)), None);
+        cpu.x = 1;
+        assert_eq!(
+            watched(step(&mut cpu, &mut breakpoints)),
+            Some((id, 0x1002, false))
+        );
+    }
+
+    #[test]
+    fn fetching_code_is_not_a_load() {
+        let mut cpu = machine();
+        let mut breakpoints = Breakpoints::new();
+        breakpoints.add(Kind::Load, 0xc000..=0xc0ff, None);
+        breakpoints.sync(&mut cpu.bus);
+        for _ in 0..3 {
+            assert_eq!(watched(step(&mut cpu, &mut breakpoints)), None);
+        }
+    }
+
+    #[test]
+    fn disabled_watchpoint_is_not_hit() {
+        let mut cpu = machine();
+        let mut breakpoints = Breakpoints::new();
+        let id = breakpoints.add(Kind::Load, 0x1000..=0x1000, None);
+        breakpoints.get_mut(id).unwrap().enabled = false;
+        breakpoints.sync(&mut cpu.bus);
+        assert_eq!(watched(step(&mut cpu, &mut breakpoints)), None);
+    }
+
+    fn vector(cpu: &Cpu<Memory>, addr: u16) -> u16 {
+        u16::from_le_bytes([cpu.peek_memory(addr), cpu.peek_memory(addr + 1)])
+    }
+
+    fn trapping(trap: Trap) -> Breakpoints {
+        let mut breakpoints = Breakpoints::new();
+        breakpoints.set_trap(trap, true);
+        breakpoints
+    }
+
+    #[test]
+    fn irq_entry() {
+        // NOP, NOP
+        let mut cpu = machine_with(&[0xea, 0xea]);
+        cpu.p.remove(Flags::INTERRUPT_DISABLE);
+        let mut breakpoints = trapping(Trap::Irq);
+        cpu.set_irq(IrqSource::Cia1, true);
+
+        // The first NOP runs while the line settles
+        assert_eq!(step(&mut cpu, &mut breakpoints), None);
+        let hit = step(&mut cpu, &mut breakpoints);
+        assert_eq!(
+            hit,
+            Some(Hit::Trap {
+                trap: Trap::Irq,
+                pc: 0xc001
+            })
+        );
+        assert_eq!(hit.unwrap().to_string(), "IRQ at $C001");
+        assert_eq!(cpu.pc, vector(&cpu, Memory::ADDR_IRQ_VECTOR));
+    }
+
+    #[test]
+    fn masked_or_untrapped_irq_does_not_stop() {
+        let mut cpu = machine_with(&[0xea, 0xea, 0xea]);
+        let mut breakpoints = trapping(Trap::Irq);
+        cpu.set_irq(IrqSource::Vic, true);
+        for _ in 0..3 {
+            assert_eq!(step(&mut cpu, &mut breakpoints), None);
+        }
+
+        let mut cpu = machine_with(&[0xea, 0xea]);
+        cpu.p.remove(Flags::INTERRUPT_DISABLE);
+        let mut breakpoints = trapping(Trap::Nmi);
+        cpu.set_irq(IrqSource::Vic, true);
+        assert_eq!(step(&mut cpu, &mut breakpoints), None);
+        assert_eq!(step(&mut cpu, &mut breakpoints), None);
+        assert_eq!(cpu.interrupted(), Some(Interrupt::Irq));
+    }
+
+    #[test]
+    fn nmi_entry_ignores_the_i_flag() {
+        let mut cpu = machine_with(&[0xea, 0xea]);
+        let mut breakpoints = trapping(Trap::Nmi);
+        cpu.set_nmi(NmiSource::Restore, true);
+
+        assert_eq!(step(&mut cpu, &mut breakpoints), None);
+        assert_eq!(
+            step(&mut cpu, &mut breakpoints),
+            Some(Hit::Trap {
+                trap: Trap::Nmi,
+                pc: 0xc001
+            })
+        );
+        assert_eq!(cpu.pc, vector(&cpu, Memory::ADDR_NMI_VECTOR));
+    }
+
+    // Stops in front of the opcode at $C001, which hasn't run yet
+    fn stops_in_front(opcode: u8, trap: Trap) {
+        let mut cpu = machine_with(&[0xea, opcode, 0x12]);
+        let mut breakpoints = trapping(trap);
+        let hit = step(&mut cpu, &mut breakpoints);
+        assert_eq!(hit, Some(Hit::Trap { trap, pc: 0xc001 }), "{:02X}", opcode);
+        assert_eq!(cpu.pc, 0xc001);
+
+        // Not with the trap off
+        let mut cpu = machine_with(&[0xea, opcode, 0x12]);
+        let mut breakpoints = trapping(Trap::Stack);
+        assert_eq!(step(&mut cpu, &mut breakpoints), None);
+    }
+
+    #[test]
+    fn brk_trap() {
+        stops_in_front(0x00, Trap::Brk);
+    }
+
+    #[test]
+    fn illegal_opcode_trap() {
+        // LAX $12
+        stops_in_front(0xa7, Trap::Illegal);
+        // The JAMs are illegal too but have their own trap
+        let mut cpu = machine_with(&[0xea, 0x02]);
+        let mut breakpoints = trapping(Trap::Illegal);
+        assert_eq!(step(&mut cpu, &mut breakpoints), None);
+    }
+
+    #[test]
+    fn jam_trap() {
+        stops_in_front(0x02, Trap::Jam);
+        stops_in_front(0xf2, Trap::Jam);
+    }
+
+    #[test]
+    fn io_watch_covers_the_mirrors() {
+        // STA $D040, STA $D001, STA $DC1D
+        let code = [0x8d, 0x40, 0xd0, 0x8d, 0x01, 0xd0, 0x8d, 0x1d, 0xdc];
+
+        // $D040 is $D000 again
+        let mut cpu = machine_with(&code);
+        let mut breakpoints = Breakpoints::new();
+        let id = breakpoints.add(Kind::Io, 0xd000..=0xd000, None);
+        breakpoints.add(Kind::Io, 0xdc0d..=0xdc0d, None);
+        breakpoints.sync(&mut cpu.bus);
+        assert_eq!(
+            watched(step(&mut cpu, &mut breakpoints)),
+            Some((id, 0xd040, true))
+        );
+        assert_eq!(watched(step(&mut cpu, &mut breakpoints)), None);
+        assert_eq!(
+            watched(step(&mut cpu, &mut breakpoints)),
+            Some((id + 1, 0xdc1d, true))
+        );
+
+        // And a watch set on a mirror catches the register itself
+        let mut cpu = machine_with(&[0x8d, 0x00, 0xd0]);
+        let mut breakpoints = Breakpoints::new();
+        let id = breakpoints.add(Kind::Io, 0xd040..=0xd040, None);
+        breakpoints.sync(&mut cpu.bus);
+        assert_eq!(
+            watched(step(&mut cpu, &mut breakpoints)),
+            Some((id, 0xd000, true))
+        );
+    }
+
+    #[test]
+    fn io_watch_ignores_reads() {
+        // LDA $D012
+        let mut cpu = machine_with(&[0xad, 0x12, 0xd0]);
+        let mut breakpoints = Breakpoints::new();
+        breakpoints.add(Kind::Io, 0xd012..=0xd012, None);
+        breakpoints.sync(&mut cpu.bus);
+        assert_eq!(watched(step(&mut cpu, &mut breakpoints)), None);
+    }
+
+    #[test]
+    fn banking_trap() {
+        // LDA #$36, STA $01, STA $01
+        let mut cpu = machine_with(&[0xa9, 0x36, 0x85, 0x01, 0x85, 0x01]);
+        cpu.bus.write_byte(0x0001, 0x37);
+        let mut breakpoints = trapping(Trap::Banking);
+
+        assert_eq!(step(&mut cpu, &mut breakpoints), None);
+        let hit = step(&mut cpu, &mut breakpoints);
+        assert_eq!(
+            hit,
+            Some(Hit::Banking {
+                pc: 0xc002,
+                old: 0b111,
+                new: 0b110
+            })
+        );
+        assert_eq!(
+            hit.unwrap().to_string(),
+            "Banking changed from %111 to %110 by $C002"
+        );
+        // Writing the same configuration again changes nothing
+        assert_eq!(step(&mut cpu, &mut breakpoints), None);
+    }
+}
@@ -0,0 +1,70 @@
+// The address and data bus a 6502 core is attached to. The C64 memory map
+// is one implementation; a flat RAM harness, a disk drive or a mock in tests
+// can provide others.
+pub trait Bus {
+    fn read(&mut self, addr: u16) -> u8;
+    fn write(&mut self, addr: u16, value: u8);
+
+    /// Reads without side effects, e.g. without acknowledging interrupts
+    /// when reading an I/O register. Used by debuggers and disassemblers.
+    fn peek(&self, addr: u16) -> u8;
+
+    /// Writes without side effects. Buses without I/O can rely on the
+    /// default, which is a plain write.
+    fn poke(&mut self, addr: u16, value: u8) {
+        self.write(addr, value);
+    }
+}
+
+/// 64K of RAM and nothing else, for running CPU tests
+pub struct FlatRam {
+    ram: Vec<u8>,
+}
+
+impl FlatRam {
+    pub const SIZE: usize = 0x10000;
+
+    pub fn new() -> Self {
+        FlatRam {
+            ram: vec![0; Self::SIZE],
+        }
+    }
+
+    /// Copies `data` into RAM starting at `addr`, wrapping at $FFFF
+    pub fn load(&mut self, addr: u16, data: &[u8]) {
+        for (i, &byte) in data.iter().enumerate() {
+            self.ram[addr.wrapping_add(i as u16) as usize] = byte;
+        }
+    }
+}
+
+impl Default for FlatRam {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Bus for FlatRam {
+    fn read(&mut self, addr: u16) -> u8 {
+        self.ram[addr as usize]
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        self.ram[addr as usize] = value;
+    }
+
+    fn peek(&self, addr: u16) -> u8 {
+        self.ram[addr as usize]
+    }
+}
+
+/// Chip with registers mapped into the I/O area, e.g. the VIC-II or a CIA.
+/// `r` is the register number within the chip.
+pub trait IoDevice {
+    fn read_register(&mut self, r: u8) -> u8;
+    fn write_register(&mut self, r: u8, v: u8);
+
+    /// Register contents without side effects of the read, such as
+    /// acknowledging interrupts by reading the ICR
+    fn peek_register(&self, r: u8) -> u8;
+}
@@ -0,0 +1,145 @@
+// Shadow call stack the CPU keeps from JSR/RTS, BRK/RTI and interrupt
+// entry, for backtraces and for tools that need the call structure.
+//
+// Each frame remembers the stack pointer from before its return address
+// was pushed, and a return that brings the stack pointer back there closes
+// it. Returns that close no frame, like pushing an address and using RTS
+// as a jump, and frames the stack moved past without returning, e.g. after
+// resetting it with TXS, unbalance the stack and are reported.
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum FrameKind {
+    Call,
+    Brk,
+    Irq,
+    Nmi,
+}
+
+impl fmt::Display for FrameKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            FrameKind::Call => "JSR",
+            FrameKind::Brk => "BRK",
+            FrameKind::Irq => "IRQ",
+            FrameKind::Nmi => "NMI",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Frame {
+    pub kind: FrameKind,
+    pub caller: u16, // JSR or BRK, or the instruction an interrupt came before
+    pub target: u16, // Subroutine or handler entered
+    pub sp: u8,      // Stack pointer before the return address was pushed
+}
+
+/// How the shadow stack went out of balance
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Imbalance {
+    /// The RTS or RTI at `pc` to `to` closed no frame
+    Return { pc: u16, to: u16 },
+    /// `frames` frames were left without returning from them, by the
+    /// instruction at `pc`
+    Abandoned { pc: u16, frames: usize },
+}
+
+impl fmt::Display for Imbalance {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Imbalance::Return { pc, to } => {
+                write!(f, "return at ${:04X} to ${:04X} ends no call", pc, to)
+            }
+            Imbalance::Abandoned { pc, frames } => write!(
+                f,
+                "${:04X} left {} call{} without returning",
+                pc,
+                frames,
+                if frames == 1 { "" } else { "s" }
+            ),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct CallStack {
+    frames: Vec<Frame>, // Outermost first
+    imbalance: Option<Imbalance>,
+}
+
+impl CallStack {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Open frames, outermost first
+    pub fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
+
+    pub fn depth(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn clear(&mut self) {
+        self.frames.clear();
+        self.imbalance = None;
+    }
+
+    /// The last imbalance since the previous call
+    pub fn take_imbalance(&mut self) -> Option<Imbalance> {
+        self.imbalance.take()
+    }
+
+    /// Where the CPU is in each frame, innermost first: `pc` in the
+    /// newest frame, then where each frame was entered from. The last
+    /// entry, without a frame, is the code no frame covers.
+    pub fn backtrace(&self, pc: u16) -> Vec<(u16, Option<Frame>)> {
+        let mut trace = Vec::with_capacity(self.frames.len() + 1);
+        let mut pc = pc;
+        for frame in self.frames.iter().rev() {
+            trace.push((pc, Some(*frame)));
+            pc = frame.caller;
+        }
+        trace.push((pc, None));
+        trace
+    }
+
+    /// A frame begins, `sp` is the stack pointer before pushing anything
+    pub(crate) fn enter(&mut self, kind: FrameKind, caller: u16, target: u16, sp: u8) {
+        // Frames starting at or below this stack pointer can't be
+        // returned from any more
+        self.abandon(caller, |frame| frame.sp <= sp);
+        self.frames.push(Frame {
+            kind,
+            caller,
+            target,
+            sp,
+        });
+    }
+
+    /// The RTS or RTI at `pc` returned to `to`, leaving the stack at `sp`
+    pub(crate) fn leave(&mut self, pc: u16, to: u16, sp: u8) {
+        match self.frames.iter().rposition(|frame| frame.sp == sp) {
+            Some(closed) => {
+                self.abandon(pc, |frame| frame.sp < sp);
+                self.frames.truncate(closed);
+            }
+            None => {
+                self.abandon(pc, |frame| frame.sp < sp);
+                self.imbalance = Some(Imbalance::Return { pc, to });
+            }
+        }
+    }
+
+    fn abandon(&mut self, pc: u16, stale: impl Fn(&Frame) -> bool) {
+        let kept = self.frames.iter().take_while(|frame| !stale(frame)).count();
+        let frames = self.frames.len() - kept;
+        if frames > 0 {
+            self.frames.truncate(kept);
+            self.imbalance = Some(Imbalance::Abandoned { pc, frames });
+        }
+    }
+}
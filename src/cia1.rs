@@ -1,16 +1,18 @@
-use super::common::{InputMode, RunMode};
-use super::cpu::Cpu;
+use super::common::{CiaState, InputMode, RunMode, TimerState};
 use super::io::IO;
+use c64_rs::bus::IoDevice;
+use c64_rs::cpu::{Cpu, IrqSource};
+use c64_rs::memory::Memory;
 use std::cell::RefCell;
 use std::rc::Rc;
 
-pub struct Cia1<'a> {
-    cpu: Rc<RefCell<Cpu<'a>>>,
-    io: Rc<RefCell<IO<'a>>>,
+pub struct Cia1 {
+    cpu: Rc<RefCell<Cpu<Memory>>>,
+    io: Rc<RefCell<IO>>,
     timer_a_latch: u16,
     timer_b_latch: u16,
-    timer_a_counter: i16,
-    timer_b_counter: i16,
+    timer_a_counter: i32,
+    timer_b_counter: i32,
     timer_a_enabled: bool,
     timer_b_enabled: bool,
     timer_a_irq_enabled: bool,
@@ -26,8 +28,8 @@ pub struct Cia1<'a> {
     prb: u8,
 }
 
-impl<'a> Cia1<'a> {
-    pub fn new(cpu: Rc<RefCell<Cpu<'a>>>, io: Rc<RefCell<IO<'a>>>) -> Self {
+impl Cia1 {
+    pub fn new(cpu: Rc<RefCell<Cpu<Memory>>>, io: Rc<RefCell<IO>>) -> Self {
         Cia1 {
             cpu,
             io,
@@ -111,7 +113,7 @@ impl<'a> Cia1<'a> {
                 self.timer_a_input_mode = InputMode::from((v & (1 << 5)) >> 5);
                 // load latch requested
                 if (v & (1 << 4)) != 0 {
-                    self.timer_a_counter = self.timer_a_latch as i16;
+                    self.timer_a_counter = self.timer_a_latch as i32;
                 }
             }
             // control timer b
@@ -120,14 +122,26 @@ impl<'a> Cia1<'a> {
                 self.timer_b_input_mode = InputMode::from((v & (1 << 5)) >> 5);
                 // load latch requested
                 if (v & (1 << 4)) != 0 {
-                    self.timer_b_counter = self.timer_b_latch as i16;
+                    self.timer_b_counter = self.timer_b_latch as i32;
                 }
             }
             _ => {}
         }
     }
 
-    pub fn read_register(&self, r: u8) -> u8 {
+    pub fn read_register(&mut self, r: u8) -> u8 {
+        let retval = self.peek_register(r);
+        // reading the ICR acknowledges the interrupt, the IRQ line is released
+        // on the next step
+        if r == 0xd {
+            self.timer_a_irq_triggered = false;
+            self.timer_b_irq_triggered = false;
+        }
+        retval
+    }
+
+    // Register contents without acknowledging interrupts
+    pub fn peek_register(&self, r: u8) -> u8 {
         let mut retval = 0;
         match r {
             // data port a (PRA), keyboard matrix cols and joystick #2
@@ -182,15 +196,13 @@ impl<'a> Cia1<'a> {
             // shift serial
             0xc => {}
             // timer control and status
-            0xd => {
-                if self.timer_a_irq_triggered || self.timer_b_irq_triggered {
-                    retval |= 1 << 7; // IRQ occured
-                    if self.timer_a_irq_triggered {
-                        retval |= 1 << 0;
-                    }
-                    if self.timer_b_irq_triggered {
-                        retval |= 1 << 1;
-                    }
+            0xd if self.timer_a_irq_triggered || self.timer_b_irq_triggered => {
+                retval |= 1 << 7; // IRQ occured
+                if self.timer_a_irq_triggered {
+                    retval |= 1 << 0;
+                }
+                if self.timer_b_irq_triggered {
+                    retval |= 1 << 1;
                 }
             }
             // control timer a
@@ -202,10 +214,35 @@ impl<'a> Cia1<'a> {
         retval
     }
 
+    // Decoded state for the monitor, reading it changes nothing
+    pub fn state(&self) -> CiaState {
+        let bits = |a: bool, b: bool| a as u8 | (b as u8) << 1;
+        CiaState {
+            timer_a: TimerState {
+                counter: self.timer_a_counter as u16,
+                latch: self.timer_a_latch,
+                running: self.timer_a_enabled,
+                run_mode: self.timer_a_run_mode,
+                input_mode: self.timer_a_input_mode,
+            },
+            timer_b: TimerState {
+                counter: self.timer_b_counter as u16,
+                latch: self.timer_b_latch,
+                running: self.timer_b_enabled,
+                run_mode: self.timer_b_run_mode,
+                input_mode: self.timer_b_input_mode,
+            },
+            icr_mask: bits(self.timer_a_irq_enabled, self.timer_b_irq_enabled),
+            icr_pending: bits(self.timer_a_irq_triggered, self.timer_b_irq_triggered),
+            pra: self.peek_register(0x0),
+            prb: self.peek_register(0x1),
+        }
+    }
+
     pub fn reset_timer_a(&mut self) {
         match self.timer_a_run_mode {
             RunMode::Restart => {
-                self.timer_a_counter = self.timer_a_latch as i16;
+                self.timer_a_counter = self.timer_a_latch as i32;
             }
             RunMode::OneTime => {
                 self.timer_a_enabled = false;
@@ -216,7 +253,7 @@ impl<'a> Cia1<'a> {
     pub fn reset_timer_b(&mut self) {
         match self.timer_b_run_mode {
             RunMode::Restart => {
-                self.timer_b_counter = self.timer_b_latch as i16;
+                self.timer_b_counter = self.timer_b_latch as i32;
             }
             RunMode::OneTime => {
                 self.timer_b_enabled = false;
@@ -225,15 +262,19 @@ impl<'a> Cia1<'a> {
     }
 
     pub fn step(&mut self) -> bool {
+        let cycles = self.cpu.borrow().cycles();
+        let elapsed = cycles.wrapping_sub(self.prev_cpu_cycles) as i32;
+        // Cycles that passed since the earliest underflow raising the IRQ,
+        // a counter at or below zero went past it by that many
+        let mut late = 0;
         if self.timer_a_enabled {
             match self.timer_a_input_mode {
                 InputMode::Processor => {
-                    self.timer_a_counter -=
-                        (self.cpu.borrow().cycles() - self.prev_cpu_cycles) as i16;
+                    self.timer_a_counter -= elapsed;
                     if self.timer_a_counter <= 0 {
                         if self.timer_a_irq_enabled {
                             self.timer_a_irq_triggered = true;
-                            self.cpu.borrow_mut().irq();
+                            late = late.max(self.timer_a_counter.unsigned_abs());
                         }
                         self.reset_timer_a();
                     }
@@ -246,12 +287,11 @@ impl<'a> Cia1<'a> {
         if self.timer_b_enabled {
             match self.timer_b_input_mode {
                 InputMode::Processor => {
-                    self.timer_b_counter -=
-                        (self.cpu.borrow().cycles() - self.prev_cpu_cycles) as i16;
+                    self.timer_b_counter -= elapsed;
                     if self.timer_b_counter <= 0 {
                         if self.timer_b_irq_enabled {
                             self.timer_b_irq_triggered = true;
-                            self.cpu.borrow_mut().irq();
+                            late = late.max(self.timer_b_counter.unsigned_abs());
                         }
                         self.reset_timer_b();
                     }
@@ -261,7 +301,26 @@ impl<'a> Cia1<'a> {
                 InputMode::TimerACNT => {}
             }
         }
-        self.prev_cpu_cycles = self.cpu.borrow().cycles();
+        // IRQ is level triggered, hold the line until the ICR is read
+        let irq = self.timer_a_irq_triggered || self.timer_b_irq_triggered;
+        self.cpu
+            .borrow_mut()
+            .set_irq_at(IrqSource::Cia1, irq, cycles.saturating_sub(late));
+        self.prev_cpu_cycles = cycles;
         true
     }
 }
+
+impl IoDevice for Cia1 {
+    fn read_register(&mut self, r: u8) -> u8 {
+        Cia1::read_register(self, r)
+    }
+
+    fn write_register(&mut self, r: u8, v: u8) {
+        Cia1::write_register(self, r, v);
+    }
+
+    fn peek_register(&self, r: u8) -> u8 {
+        Cia1::peek_register(self, r)
+    }
+}
@@ -1,31 +1,33 @@
-use super::common::{InputMode, RunMode};
-use super::cpu::Cpu;
+use super::common::{CiaState, InputMode, RunMode, TimerState};
+use c64_rs::bus::IoDevice;
+use c64_rs::cpu::{Cpu, NmiSource};
+use c64_rs::memory::Memory;
 use std::cell::RefCell;
 use std::rc::Rc;
 
-pub struct Cia2<'a> {
-    cpu: Rc<RefCell<Cpu<'a>>>,
+pub struct Cia2 {
+    cpu: Rc<RefCell<Cpu<Memory>>>,
     timer_a_latch: u16,
     timer_b_latch: u16,
-    timer_a_counter: i16,
-    timer_b_counter: i16,
+    timer_a_counter: i32,
+    timer_b_counter: i32,
     timer_a_enabled: bool,
     timer_b_enabled: bool,
     timer_a_irq_enabled: bool,
     timer_b_irq_enabled: bool,
     timer_a_irq_triggered: bool,
     timer_b_irq_triggered: bool,
-    timer_a_run_mode: u8,
-    timer_b_run_mode: u8,
-    timer_a_input_mode: u8,
-    timer_b_input_mode: u8,
+    timer_a_run_mode: RunMode,
+    timer_b_run_mode: RunMode,
+    timer_a_input_mode: InputMode,
+    timer_b_input_mode: InputMode,
     prev_cpu_cycles: u32,
     pra: u8,
     prb: u8,
 }
 
-impl<'a> Cia2<'a> {
-    pub fn new(cpu: Rc<RefCell<Cpu<'a>>>) -> Self {
+impl Cia2 {
+    pub fn new(cpu: Rc<RefCell<Cpu<Memory>>>) -> Self {
         Cia2 {
             cpu,
             timer_a_latch: 0,
@@ -38,10 +40,10 @@ impl<'a> Cia2<'a> {
             timer_b_irq_enabled: false,
             timer_a_irq_triggered: false,
             timer_b_irq_triggered: false,
-            timer_a_run_mode: RunMode::Restart.as_u8(), // Assuming ModeRestart is the default mode
-            timer_b_run_mode: RunMode::Restart.as_u8(), // Assuming ModeRestart is the default mode
-            timer_a_input_mode: InputMode::Processor.as_u8(), // Assuming ModeProcessor is the default mode
-            timer_b_input_mode: InputMode::Processor.as_u8(), // Assuming ModeProcessor is the default mode
+            timer_a_run_mode: RunMode::Restart,
+            timer_b_run_mode: RunMode::Restart,
+            timer_a_input_mode: InputMode::Processor,
+            timer_b_input_mode: InputMode::Processor,
             prev_cpu_cycles: 0,
             pra: 0xff, // Default value as per cia2.cpp
             prb: 0xff, // Default value as per cia2.cpp
@@ -89,23 +91,35 @@ impl<'a> Cia2<'a> {
             }
             0xe => {
                 self.timer_a_enabled = (v & 1) != 0;
-                self.timer_a_input_mode = (v & 0x20) >> 5;
+                self.timer_a_input_mode = InputMode::from((v & 0x20) >> 5);
                 if (v & 0x10) != 0 {
-                    self.timer_a_counter = self.timer_a_latch as i16;
+                    self.timer_a_counter = self.timer_a_latch as i32;
                 }
             }
             0xf => {
                 self.timer_b_enabled = (v & 1) != 0;
-                self.timer_b_input_mode = (v & 0x20) | (v & 0x40) >> 5;
+                self.timer_b_input_mode = InputMode::from((v & 0x60) >> 5);
                 if (v & 0x10) != 0 {
-                    self.timer_b_counter = self.timer_b_latch as i16;
+                    self.timer_b_counter = self.timer_b_latch as i32;
                 }
             }
             _ => (),
         }
     }
 
-    pub fn read_register(&self, r: u8) -> u8 {
+    pub fn read_register(&mut self, r: u8) -> u8 {
+        let retval = self.peek_register(r);
+        // reading the ICR acknowledges the interrupt, the NMI line is released
+        // on the next step
+        if r == 0xd {
+            self.timer_a_irq_triggered = false;
+            self.timer_b_irq_triggered = false;
+        }
+        retval
+    }
+
+    // Register contents without acknowledging interrupts
+    pub fn peek_register(&self, r: u8) -> u8 {
         let mut retval = 0;
         match r {
             0x0 => self.pra,
@@ -139,19 +153,42 @@ impl<'a> Cia2<'a> {
         }
     }
 
+    // Decoded state for the monitor, reading it changes nothing
+    pub fn state(&self) -> CiaState {
+        let bits = |a: bool, b: bool| a as u8 | (b as u8) << 1;
+        CiaState {
+            timer_a: TimerState {
+                counter: self.timer_a_counter as u16,
+                latch: self.timer_a_latch,
+                running: self.timer_a_enabled,
+                run_mode: self.timer_a_run_mode,
+                input_mode: self.timer_a_input_mode,
+            },
+            timer_b: TimerState {
+                counter: self.timer_b_counter as u16,
+                latch: self.timer_b_latch,
+                running: self.timer_b_enabled,
+                run_mode: self.timer_b_run_mode,
+                input_mode: self.timer_b_input_mode,
+            },
+            icr_mask: bits(self.timer_a_irq_enabled, self.timer_b_irq_enabled),
+            icr_pending: bits(self.timer_a_irq_triggered, self.timer_b_irq_triggered),
+            pra: self.peek_register(0x0),
+            prb: self.peek_register(0x1),
+        }
+    }
+
     pub fn reset_timer_a(&mut self) {
         match self.timer_a_run_mode {
-            kModeRestart => self.timer_a_counter = self.timer_a_latch as i16,
-            kModeOneTime => self.timer_a_enabled = false,
-            _ => {}
+            RunMode::Restart => self.timer_a_counter = self.timer_a_latch as i32,
+            RunMode::OneTime => self.timer_a_enabled = false,
         }
     }
 
     pub fn reset_timer_b(&mut self) {
         match self.timer_b_run_mode {
-            kModeRestart => self.timer_b_counter = self.timer_b_latch as i16,
-            kModeOneTime => self.timer_b_enabled = false,
-            _ => {}
+            RunMode::Restart => self.timer_b_counter = self.timer_b_latch as i32,
+            RunMode::OneTime => self.timer_b_enabled = false,
         }
     }
 
@@ -160,46 +197,146 @@ impl<'a> Cia2<'a> {
     }
 
     pub fn step(&mut self) -> bool {
+        let cycles = self.cpu.borrow().cycles();
+        let elapsed = cycles.wrapping_sub(self.prev_cpu_cycles) as i32;
+        // Cycles that passed since the earliest underflow raising the NMI,
+        // a counter at or below zero went past it by that many
+        let mut late = 0;
+
         // Timer A
         if self.timer_a_enabled {
             match self.timer_a_input_mode {
-                kModeProcessor => {
-                    self.timer_a_counter -=
-                        (self.cpu.borrow().cycles() - self.prev_cpu_cycles) as i16;
+                InputMode::Processor => {
+                    self.timer_a_counter -= elapsed;
                     if self.timer_a_counter <= 0 {
                         if self.timer_a_irq_enabled {
                             self.timer_a_irq_triggered = true;
-                            self.cpu.borrow_mut().nmi();
+                            late = late.max(self.timer_a_counter.unsigned_abs());
                         }
                         self.reset_timer_a();
                     }
                 }
-                kModeCNT => {}
+                InputMode::CNT => {}
+                InputMode::TimerA => {}
+                InputMode::TimerACNT => {}
             }
         }
 
         // Timer B
         if self.timer_b_enabled {
             match self.timer_b_input_mode {
-                kModeProcessor => {
-                    self.timer_b_counter -=
-                        (self.cpu.borrow().cycles() - self.prev_cpu_cycles) as i16;
+                InputMode::Processor => {
+                    self.timer_b_counter -= elapsed;
                     if self.timer_b_counter <= 0 {
                         if self.timer_b_irq_enabled {
                             self.timer_b_irq_triggered = true;
-                            self.cpu.borrow_mut().nmi();
+                            late = late.max(self.timer_b_counter.unsigned_abs());
                         }
                         self.reset_timer_b();
                     }
                 }
-                kModeCNT => {}
-                kModeTimerA => {}
-                kModeTimerACNT => {}
+                InputMode::CNT => {}
+                InputMode::TimerA => {}
+                InputMode::TimerACNT => {}
             }
         }
 
-        self.prev_cpu_cycles = self.cpu.borrow().cycles();
+        // NMI is edge triggered, the line stays active until the ICR is
+        // read so a pending interrupt can't retrigger on every step
+        let nmi = self.timer_a_irq_triggered || self.timer_b_irq_triggered;
+        self.cpu
+            .borrow_mut()
+            .set_nmi_at(NmiSource::Cia2, nmi, cycles.saturating_sub(late));
+
+        self.prev_cpu_cycles = cycles;
 
         true
     }
 }
+
+impl IoDevice for Cia2 {
+    fn read_register(&mut self, r: u8) -> u8 {
+        Cia2::read_register(self, r)
+    }
+
+    fn write_register(&mut self, r: u8, v: u8) {
+        Cia2::write_register(self, r, v);
+    }
+
+    fn peek_register(&self, r: u8) -> u8 {
+        Cia2::peek_register(self, r)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use c64_rs::cpu::Interrupt;
+
+    // A CPU running NOPs from $C000, two cycles each
+    fn cpu() -> Rc<RefCell<Cpu<Memory>>> {
+        let mut memory = Memory::new().unwrap();
+        for addr in 0xc000..0xc100 {
+            memory.write_byte(addr, 0xea);
+        }
+        let mut cpu = Cpu::new(memory);
+        cpu.pc = 0xc000;
+        Rc::new(RefCell::new(cpu))
+    }
+
+    fn start_timer_a(cia: &mut Cia2, latch: u16) {
+        cia.write_register(0x4, latch as u8);
+        cia.write_register(0x5, (latch >> 8) as u8);
+        cia.write_register(0xd, 0x81);
+        cia.write_register(0xe, 0x11);
+    }
+
+    fn step(cpu: &Rc<RefCell<Cpu<Memory>>>, cia: &mut Cia2) {
+        cpu.borrow_mut().step();
+        cia.step();
+    }
+
+    #[test]
+    fn large_latch_counts_down() {
+        let cpu = cpu();
+        let mut cia = Cia2::new(cpu.clone());
+        start_timer_a(&mut cia, 0xffff);
+        for _ in 0..10 {
+            step(&cpu, &mut cia);
+        }
+        assert_eq!(cia.state().timer_a.counter, 0xffff - 20);
+        assert_eq!(cpu.borrow().interrupt_due(), None);
+    }
+
+    #[test]
+    fn nmi_asserted_at_the_underflow() {
+        let cpu = cpu();
+        // LDA $C000 takes four cycles, the timer underflows in its second
+        for (addr, value) in (0xc000..).zip([0xad, 0x00, 0xc0]) {
+            cpu.borrow_mut().write_memory(addr, value);
+        }
+        let mut cia = Cia2::new(cpu.clone());
+        start_timer_a(&mut cia, 1);
+        step(&cpu, &mut cia);
+        assert_eq!(cia.peek_register(0xd), 0x81);
+        // Early enough to be serviced after the LDA
+        step(&cpu, &mut cia);
+        assert_eq!(cpu.borrow().interrupted(), Some(Interrupt::Nmi));
+    }
+
+    #[test]
+    fn inspecting_keeps_the_interrupt_pending() {
+        let cpu = cpu();
+        let mut cia = Cia2::new(cpu.clone());
+        start_timer_a(&mut cia, 1);
+        step(&cpu, &mut cia);
+        for _ in 0..2 {
+            assert_eq!(cia.state().icr_pending, 0x01);
+            assert_eq!(cia.peek_register(0xd), 0x81);
+        }
+        // Only a real read acknowledges it
+        assert_eq!(cia.read_register(0xd), 0x81);
+        assert_eq!(cia.state().icr_pending, 0x00);
+        assert_eq!(cia.peek_register(0xd), 0x00);
+    }
+}
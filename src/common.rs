@@ -1,3 +1,6 @@
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum InputMode {
     Processor,
     CNT,
@@ -23,6 +26,7 @@ impl From<u8> for InputMode {
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum RunMode {
     Restart,
     OneTime,
@@ -41,3 +45,89 @@ impl RunMode {
         }
     }
 }
+
+impl fmt::Display for InputMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            InputMode::Processor => "counts cycles",
+            InputMode::CNT => "counts CNT",
+            InputMode::TimerA => "counts timer A",
+            InputMode::TimerACNT => "counts timer A with CNT",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl fmt::Display for RunMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            RunMode::Restart => "continuous",
+            RunMode::OneTime => "one-shot",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// Timer as the monitor shows it
+pub struct TimerState {
+    pub counter: u16,
+    pub latch: u16,
+    pub running: bool,
+    pub run_mode: RunMode,
+    pub input_mode: InputMode,
+}
+
+impl fmt::Display for TimerState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "${:04X} latch ${:04X}  {}, {}, {}",
+            self.counter,
+            self.latch,
+            if self.running { "running" } else { "stopped" },
+            self.run_mode,
+            self.input_mode
+        )
+    }
+}
+
+// CIA state read without acknowledging interrupts. The ICR bits are those
+// of $DC0D/$DD0D, bit 0 timer A and bit 1 timer B.
+pub struct CiaState {
+    pub timer_a: TimerState,
+    pub timer_b: TimerState,
+    pub icr_mask: u8,
+    pub icr_pending: u8,
+    pub pra: u8,
+    pub prb: u8,
+}
+
+impl CiaState {
+    fn sources(bits: u8) -> String {
+        let names: Vec<&str> = ["timer A", "timer B", "TOD alarm", "serial", "FLAG"]
+            .iter()
+            .enumerate()
+            .filter(|(bit, _)| bits & (1 << bit) != 0)
+            .map(|(_, name)| *name)
+            .collect();
+        if names.is_empty() {
+            "none".to_string()
+        } else {
+            names.join(", ")
+        }
+    }
+}
+
+impl fmt::Display for CiaState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Timer A  {}", self.timer_a)?;
+        writeln!(f, "Timer B  {}", self.timer_b)?;
+        writeln!(
+            f,
+            "ICR      enabled: {}  pending: {}",
+            Self::sources(self.icr_mask),
+            Self::sources(self.icr_pending)
+        )?;
+        write!(f, "Ports    A ${:02X}  B ${:02X}", self.pra, self.prb)
+    }
+}
@@ -0,0 +1,76 @@
+// Where the monitor and the run loop print to. That's stdout, except while
+// stdout carries a debugger protocol: then everything goes to stderr so it
+// doesn't end up between the messages.
+#[cfg(test)]
+use std::cell::RefCell;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static STDERR: AtomicBool = AtomicBool::new(false);
+
+/// Prints to stderr from now on
+pub fn use_stderr() {
+    STDERR.store(true, Ordering::Relaxed);
+}
+
+pub fn stderr() -> bool {
+    STDERR.load(Ordering::Relaxed)
+}
+
+/// Writes to the console, see out! and outln!
+pub fn print(args: fmt::Arguments) {
+    if captured(args) {
+        return;
+    }
+    if stderr() {
+        eprint!("{}", args)
+    } else {
+        print!("{}", args)
+    }
+}
+
+#[cfg(test)]
+thread_local! {
+    static CAPTURED: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Runs `f` and returns what it wrote to the console instead of printing it
+#[cfg(test)]
+pub fn capture(f: impl FnOnce()) -> String {
+    CAPTURED.with(|c| *c.borrow_mut() = Some(String::new()));
+    f();
+    CAPTURED.with(|c| c.borrow_mut().take()).unwrap_or_default()
+}
+
+#[cfg(test)]
+fn captured(args: fmt::Arguments) -> bool {
+    CAPTURED.with(|c| match c.borrow_mut().as_mut() {
+        Some(output) => {
+            output.push_str(&args.to_string());
+            true
+        }
+        None => false,
+    })
+}
+
+#[cfg(not(test))]
+fn captured(_: fmt::Arguments) -> bool {
+    false
+}
+
+/// print! to the console
+macro_rules! out {
+    ($($arg:tt)*) => {
+        $crate::console::print(format_args!($($arg)*))
+    };
+}
+
+/// println! to the console
+macro_rules! outln {
+    () => {
+        $crate::console::print(format_args!("\n"))
+    };
+    ($($arg:tt)*) => {
+        $crate::console::print(format_args!("{}\n", format_args!($($arg)*)))
+    };
+}
@@ -1,43 +1,134 @@
+use crate::bus::Bus;
+use crate::callstack::{CallStack, FrameKind};
+use crate::flags::Flags;
 use crate::memory::Memory;
+use crate::opcode::{AddrMode, Mnemonic, OPCODES};
+
+/// Devices that can pull the shared /IRQ line low. The line is a wired-OR,
+/// so it stays active for as long as any source holds it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IrqSource {
+    Cia1 = 1 << 0,
+    Vic = 1 << 1,
+    Cartridge = 1 << 2,
+}
+
+/// Devices wired to the /NMI line. Only the falling edge of the combined
+/// line triggers an interrupt.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NmiSource {
+    Cia2 = 1 << 0,
+    Restore = 1 << 1,
+    Cartridge = 1 << 2,
+}
+
+/// Interrupt serviced instead of an instruction
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Interrupt {
+    Irq,
+    Nmi,
+}
+
+/// What an instruction does with its operand, which decides the dummy
+/// cycles of the indexed addressing modes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Access {
+    Read,
+    Write,
+    Modify,
+    // JSR pushes the return address between fetching its two operand bytes
+    Call,
+}
+
+impl Access {
+    pub(crate) fn of(mnemonic: Mnemonic) -> Self {
+        use Mnemonic::*;
+        match mnemonic {
+            Sta | Stx | Sty | Stz | Sax | Sha | Shx | Shy | Tas => Access::Write,
+            Asl | Lsr | Rol | Ror | Inc | Dec | Slo | Rla | Sre | Rra | Dcp | Isc | Tsb | Trb
+            | Rmb(_) | Smb(_) => Access::Modify,
+            Jsr => Access::Call,
+            _ => Access::Read,
+        }
+    }
+}
 
-pub struct Cpu<'a> {
-    pub pc: u16,                // Program Counter
-    pub sp: u8,                 // Stack Pointer
-    pub a: u8,                  // Accumulator
-    pub x: u8,                  // X register
-    pub y: u8,                  // Y register
-    status: u8,                 // Processor Status
-    pub memory: &'a mut Memory, // Reference to the memory
-    cycles: u32,                // CPU cycles
-
-    // Flags
-    carry: bool,
-    zero: bool,
-    interrupt_disable: bool,
-    decimal: bool,
-    break_command: bool,
-    overflow: bool,
-    negative: bool,
+/// Register file as seen from outside the CPU, used by debuggers and to save
+/// and restore CPU state
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Registers {
+    pub pc: u16,
+    pub sp: u8,
+    pub a: u8,
+    pub x: u8,
+    pub y: u8,
+    pub p: Flags,
 }
 
-impl<'a> Cpu<'a> {
-    pub fn new(memory: &'a mut Memory) -> Self {
+/// Common interface of the 6502 family cores, so machines, test harnesses
+/// and debuggers can drive any of them. The 65816 only runs in emulation
+/// mode, where its registers fit Registers.
+pub trait Processor {
+    fn reset(&mut self);
+
+    /// Executes one instruction or services a pending interrupt. Returns
+    /// false when the CPU can't continue.
+    fn step(&mut self) -> bool;
+
+    fn cycles(&self) -> u32;
+    fn registers(&self) -> Registers;
+    fn set_registers(&mut self, registers: &Registers);
+    fn set_irq(&mut self, source: IrqSource, active: bool);
+    fn set_nmi(&mut self, source: NmiSource, active: bool);
+    fn read_memory(&mut self, addr: u16) -> u8;
+    fn write_memory(&mut self, addr: u16, value: u8);
+
+    /// Reads memory without side effects on I/O registers
+    fn peek_memory(&self, addr: u16) -> u8;
+}
+
+/// NMOS 6510 as found in the C64
+pub struct Cpu<B: Bus> {
+    pub pc: u16, // Program Counter
+    pub sp: u8,  // Stack Pointer
+    pub a: u8,   // Accumulator
+    pub x: u8,   // X register
+    pub y: u8,   // Y register
+    pub bus: B,  // Bus the CPU is attached to
+    cycles: u32, // CPU cycles
+
+    pub p: Flags, // Processor status
+
+    // Interrupt lines
+    irq_lines: u8,                  // IRQ sources currently holding the line
+    irq_asserted_at: u32,           // Cycle the IRQ line went active
+    nmi_lines: u8,                  // NMI sources currently holding the line
+    nmi_pending: bool,              // NMI edge seen but not serviced yet
+    nmi_edge_at: u32,               // Cycle of the last NMI edge
+    irq_poll_disable: Option<bool>, // I flag seen by the poll after CLI/SEI/PLP
+    interrupted: Option<Interrupt>, // Interrupt entered by the last step
+    calls: CallStack,
+}
+
+impl<B: Bus> Cpu<B> {
+    pub fn new(bus: B) -> Self {
         Cpu {
             pc: 0,
             sp: 0xFF, // Stack starts at 0xFF
             a: 0,
             x: 0,
             y: 0,
-            status: 0,
-            memory,
+            bus,
             cycles: 0,
-            carry: false,
-            zero: false,
-            interrupt_disable: true,
-            decimal: false,
-            break_command: false,
-            overflow: false,
-            negative: false,
+            p: Flags::default(),
+            irq_lines: 0,
+            irq_asserted_at: 0,
+            nmi_lines: 0,
+            nmi_pending: false,
+            nmi_edge_at: 0,
+            irq_poll_disable: None,
+            interrupted: None,
+            calls: CallStack::new(),
         }
     }
 
@@ -46,577 +137,247 @@ impl<'a> Cpu<'a> {
         self.x = 0;
         self.y = 0;
         self.sp = 0xFF;
-        self.carry = false;
-        self.zero = false;
-        self.interrupt_disable = true;
-        self.decimal = false;
-        self.break_command = false;
-        self.overflow = false;
-        self.negative = false;
-        self.pc = self.memory.read_word(0xFFFC); // Read reset vector
+        self.p = Flags::default();
+        self.nmi_pending = false;
+        self.irq_poll_disable = None;
+        self.pc = self.read_word(0xFFFC); // Read reset vector
         self.cycles = 6;
+        self.calls.clear();
     }
 
     pub fn step(&mut self) -> bool {
-        let mut retval: bool = true;
-        let opcode = self.memory.read_byte(self.pc);
-        self.pc += 1; // Increment PC after fetching the opcode
-
-        match opcode {
-            0x00 => self.op_brk(),
-            0x01 => {
-                let addr = self.addr_indx();
-                let value = self.load_byte(addr);
-                self.op_ora(value, 6)
-            }
-            0x05 => {
-                let addr = self.addr_zero();
-                let value = self.load_byte(addr);
-                self.op_ora(value, 3)
-            }
-            0x06 => {
-                let addr = self.addr_zero();
-                self.op_asl(addr, 5)
-            }
-            0x08 => self.op_php(),
-            0x09 => {
-                let addr = self.fetch_op();
-                self.op_ora(addr.into(), 2);
-            }
-            0x0A => self.op_asl_a(),
-            0x0D => {
-                let addr = self.addr_abs();
-                let value = self.load_byte(addr);
-                self.op_ora(value, 4);
-            }
-            0x0E => {
-                let addr = self.addr_abs();
-                self.op_asl(addr, 6);
-            }
-            0x10 => self.op_bpl(),
-            0x11 => {
-                let addr = self.addr_indy();
-                let value = self.load_byte(addr);
-                self.op_ora(value, 5);
-            }
-            0x15 => {
-                let addr = self.addr_zeroy();
-                let value = self.load_byte(addr);
-                self.op_ora(value, 4);
-            }
-            0x16 => {
-                let addr = self.addr_zerox();
-                self.op_asl(addr, 6);
-            }
-            0x18 => self.op_clc(),
-            0x19 => {
-                let addr = self.addr_absy();
-                let value = self.load_byte(addr);
-                self.op_ora(value, 4);
-            }
-            0x1D => {
-                let addr = self.addr_absx();
-                let value = self.load_byte(addr);
-                self.op_ora(value, 4);
-            }
-            0x1E => {
-                let addr = self.addr_absx();
-                self.op_asl(addr, 7);
-            }
-            0x20 => {
-                let addr = self.fetch_opw();
-                self.op_jsr(addr);
-            }
-            0x21 => {
-                let addr = self.addr_indx();
-                let value = self.load_byte(addr);
-                self.op_and(value, 6);
-            }
-            0x24 => {
-                let addr = self.addr_zero();
-                self.op_bit(addr, 3);
-            }
-            0x25 => {
-                let addr = self.addr_zero();
-                let value = self.load_byte(addr);
-                self.op_and(value, 3);
-            }
-            0x26 => {
-                let addr = self.addr_zero();
-                self.op_rol(addr, 5);
-            }
-            0x28 => self.op_plp(),
-            0x29 => {
-                let addr = self.fetch_op();
-                self.op_and(addr.into(), 2);
-            }
-            0x2A => self.op_rol_a(),
-            0x2C => {
-                let addr = self.addr_abs();
-                self.op_bit(addr, 4);
-            }
-            0x2D => {
-                let addr = self.addr_abs();
-                let value = self.load_byte(addr);
-                self.op_and(value, 4);
-            }
-            0x2E => {
-                let addr = self.addr_abs();
-                self.op_rol(addr, 6);
-            }
-            0x30 => self.op_bmi(),
-            0x31 => {
-                let addr = self.addr_indy();
-                let value = self.load_byte(addr);
-                self.op_and(value, 5);
-            }
-            0x35 => {
-                let addr = self.addr_zeroy();
-                let value = self.load_byte(addr);
-                self.op_and(value, 4);
-            }
-            0x36 => {
-                let addr = self.addr_zeroy();
-                self.op_rol(addr, 6);
-            }
-            0x38 => self.op_sec(),
-            0x39 => {
-                let addr = self.addr_absy();
-                let value = self.load_byte(addr);
-                self.op_and(value, 4);
-            }
-            0x3D => {
-                let addr = self.addr_absx();
-                let value = self.load_byte(addr);
-                self.op_and(value, 4);
-            }
-            0x3E => {
-                let addr = self.addr_absx();
-                self.op_rol(addr, 7);
-            }
-            0x40 => self.op_rti(),
-            0x41 => {
-                let addr = self.addr_indx();
-                let value = self.load_byte(addr);
-                self.op_eor(value, 6);
-            }
-            0x45 => {
-                let addr = self.addr_zero();
-                let value = self.load_byte(addr);
-                self.op_eor(value, 3);
-            }
-            0x46 => {
-                let addr = self.addr_zero();
-                self.op_lsr(addr, 5);
-            }
-            0x48 => self.op_pha(),
-            0x49 => {
-                let addr = self.fetch_op();
-                self.op_eor(addr, 2);
-            }
-            0x4A => self.op_lsr_a(),
-            0x4C => self.op_jmp(),
-            0x4D => {
-                let addr = self.addr_abs();
-                let value = self.load_byte(addr);
-                self.op_eor(value, 4);
-            }
-            0x4E => {
-                let addr = self.addr_abs();
-                self.op_lsr(addr, 6);
-            }
-            0x50 => self.op_bvc(),
-            0x51 => {
-                let addr = self.addr_indy();
-                let value = self.load_byte(addr);
-                self.op_eor(value, 5);
-            }
-            0x55 => {
-                let addr = self.addr_zerox();
-                let value = self.load_byte(addr);
-                self.op_eor(value, 4);
-            }
-            0x56 => {
-                let addr = self.addr_zerox();
-                self.op_lsr(addr, 6);
-            }
-            0x58 => self.op_cli(),
-            0x59 => {
-                let addr = self.addr_absy();
-                let value = self.load_byte(addr);
-                self.op_eor(value, 4);
-            }
-            0x5D => {
-                let addr = self.addr_absx();
-                let value = self.load_byte(addr);
-                self.op_eor(value, 4);
-            }
-            0x5E => {
-                let addr = self.addr_absx();
-                self.op_lsr(addr, 7);
-            }
-            0x60 => self.op_rts(),
-            0x61 => {
-                let addr = self.addr_indx();
-                let value = self.load_byte(addr);
-                self.op_adc(value, 6);
-            }
-            0x65 => {
-                let addr = self.addr_zero();
-                let value = self.load_byte(addr);
-                self.op_adc(value, 3);
-            }
-            0x66 => {
-                let addr = self.addr_zero();
-                self.op_ror(addr, 5);
-            }
-            0x68 => self.op_pla(),
-            0x69 => {
-                let addr = self.fetch_op();
-                self.op_adc(addr, 2);
-            }
-            0x6A => self.op_ror_a(),
-            0x6C => self.op_jmp_ind(),
-            0x6D => {
-                let addr = self.addr_abs();
-                let value = self.load_byte(addr);
-                self.op_adc(value, 4);
-            }
-            0x6E => {
-                let addr = self.addr_abs();
-                self.op_ror(addr, 6);
-            }
-            0x70 => self.op_bvs(),
-            0x71 => {
-                let addr = self.addr_indy();
-                let value = self.load_byte(addr);
-                self.op_adc(value, 5);
-            }
-            0x75 => {
-                let addr = self.addr_zerox();
-                let value = self.load_byte(addr);
-                self.op_adc(value, 4);
-            }
-            0x76 => {
-                let addr = self.addr_zerox();
-                self.op_ror(addr, 6);
-            }
-            0x78 => self.op_sei(),
-            0x79 => {
-                let addr = self.addr_absy();
-                let value = self.load_byte(addr);
-                self.op_adc(value, 4);
-            }
-            0x7D => {
-                let addr = self.addr_absx();
-                let value = self.load_byte(addr);
-                self.op_adc(value, 4);
-            }
-            0x7E => {
-                let addr = self.addr_absx();
-                self.op_ror(addr, 7);
-            }
-            0x81 => {
-                let addr = self.addr_indx();
-                self.op_sta(addr, 6);
-            }
-            0x84 => {
-                let addr = self.addr_zero();
-                self.op_sty(addr, 3);
-            }
-            0x85 => {
-                let addr = self.addr_zero();
-                self.op_sta(addr, 3);
-            }
-            0x86 => {
-                let addr = self.addr_zero();
-                self.op_stx(addr, 3);
-            }
-            0x88 => self.op_dey(),
-            0x8A => self.op_txa(),
-            0x8C => {
-                let addr = self.addr_abs();
-                self.op_sty(addr, 4);
-            }
-            0x8D => {
-                let addr = self.addr_abs();
-                self.op_sta(addr, 4);
-            }
-            0x8E => {
-                let addr = self.addr_abs();
-                self.op_stx(addr, 4);
-            }
-            0x90 => self.op_bcc(),
-            0x91 => {
-                let addr = self.addr_indy();
-                self.op_sta(addr, 6);
-            }
-            0x94 => {
-                let addr = self.addr_zerox();
-                self.op_sty(addr, 4);
-            }
-            0x95 => {
-                let addr = self.addr_zerox();
-                self.op_sta(addr, 4);
-            }
-            0x96 => {
-                let addr = self.addr_zeroy();
-                self.op_stx(addr, 4);
-            }
-            0x98 => self.op_tya(),
-            0x99 => {
-                let addr = self.addr_absy();
-                self.op_sta(addr, 5);
-            }
-            0x9A => self.op_txs(),
-            0x9D => {
-                let addr = self.addr_absx();
-                self.op_sta(addr, 5);
-            }
-            0xA0 => {
-                let addr = self.fetch_op();
-                self.op_ldy(addr, 2);
-            }
-            0xA1 => {
-                let addr = self.addr_indx();
-                let value = self.load_byte(addr);
-                self.op_lda(value, 6);
-            }
-            0xA2 => {
-                let addr = self.fetch_op();
-                self.op_ldx(addr, 2);
-            }
-            0xA4 => {
-                let addr = self.addr_zero();
-                let value = self.load_byte(addr);
-                self.op_ldy(value, 3);
-            }
-            0xA5 => {
-                let addr = self.addr_zero();
-                let value = self.load_byte(addr);
-                self.op_lda(value, 3);
-            }
-            0xA6 => {
-                let addr = self.addr_zero();
-                let value = self.load_byte(addr);
-                self.op_ldx(value, 3);
-            }
-            0xA8 => self.op_tay(),
-            0xA9 => {
+        if self.poll_interrupts() {
+            return true;
+        }
+
+        let opcode = self.fetch_op();
+        let op = &OPCODES[opcode as usize];
+        if op.mnemonic == Mnemonic::Jam {
+            // The CPU locks up until the next reset
+            self.pc = self.pc.wrapping_sub(1);
+            return false;
+        }
+
+        let (addr, page_crossed) = self.operand_addr(op.mode, Access::of(op.mnemonic));
+        self.tick(op.cycles as u32);
+        if page_crossed && op.page_penalty {
+            self.tick(1);
+        }
+        self.execute(op.mnemonic, op.mode, addr);
+        true
+    }
+
+    // Resolves the effective address of the operand and whether indexing
+    // crossed a page. Immediate operands resolve to the address of the
+    // operand byte so every instruction can read its value the same way.
+    // The bus sees the same reads as on the hardware, including the dummy
+    // ones, up to the access of the operand itself.
+    pub(crate) fn operand_addr(&mut self, mode: AddrMode, access: Access) -> (u16, bool) {
+        match mode {
+            // The byte after the opcode is read and thrown away
+            AddrMode::Implied | AddrMode::Accumulator => {
+                self.load_byte(self.pc);
+                (0, false)
+            }
+            AddrMode::Immediate => {
+                let addr = self.pc;
+                self.pc = self.pc.wrapping_add(1);
+                (addr, false)
+            }
+            AddrMode::ZeroPage => (self.addr_zero(), false),
+            AddrMode::ZeroPageX => (self.addr_zerox(), false),
+            AddrMode::ZeroPageY => (self.addr_zeroy(), false),
+            AddrMode::Relative => (self.addr_rel(), false),
+            // Resolves to the low byte of the target, JSR fetches the high one
+            AddrMode::Absolute if access == Access::Call => (self.fetch_op() as u16, false),
+            AddrMode::Absolute | AddrMode::Indirect => (self.addr_abs(), false),
+            AddrMode::AbsoluteX => {
+                let (base, addr) = self.addr_absx();
+                self.fix_page(base, addr, access)
+            }
+            AddrMode::AbsoluteY => {
+                let (base, addr) = self.addr_absy();
+                self.fix_page(base, addr, access)
+            }
+            AddrMode::IndirectX => (self.addr_indx(), false),
+            AddrMode::IndirectY => {
+                let (base, addr) = self.addr_indy();
+                self.fix_page(base, addr, access)
+            }
+            AddrMode::ZeroPageIndirect => {
                 let addr = self.fetch_op();
-                self.op_lda(addr.into(), 2);
-            }
-            0xAA => self.op_tax(),
-            0xAC => {
-                let addr = self.addr_abs();
-                let value = self.load_byte(addr);
-                self.op_ldy(value, 4);
-            }
-            0xAD => {
-                let addr = self.addr_abs();
-                let value = self.load_byte(addr);
-                self.op_lda(value, 4);
-            }
-            0xAE => {
-                let addr = self.addr_abs();
-                let value = self.load_byte(addr);
-                self.op_ldx(value, 4);
-            }
-            0xB0 => self.op_bcs(),
-            0xB1 => {
-                let addr = self.addr_indy();
-                let value = self.load_byte(addr);
-                self.op_lda(value, 5);
-            }
-            0xB4 => {
-                let addr = self.addr_zerox();
-                let value = self.load_byte(addr);
-                self.op_ldy(value, 3);
-            }
-            0xB5 => {
-                let addr = self.addr_zerox();
-                let value = self.load_byte(addr);
-                self.op_lda(value, 3);
-            }
-            0xB6 => {
-                let addr = self.addr_zeroy();
-                let value = self.load_byte(addr);
-                self.op_ldx(value, 3);
-            }
-            0xB8 => self.op_clv(),
-            0xB9 => {
-                let addr = self.addr_absy();
+                (self.read_word_zp(addr), false)
+            }
+            // Resolves to the pointer, JMP reads the target from it
+            AddrMode::AbsoluteIndexedIndirect => {
+                (self.fetch_opw().wrapping_add(self.x as u16), false)
+            }
+            // Resolves to the zero page operand, the branch offset is
+            // fetched by the instruction
+            AddrMode::ZeroPageRelative => (self.addr_zero(), false),
+            // Only in the 65816 table, Cpu65816 resolves them itself
+            AddrMode::StackRelative
+            | AddrMode::StackRelativeIndirectY
+            | AddrMode::DirectIndirectLong
+            | AddrMode::DirectIndirectLongY
+            | AddrMode::AbsoluteLong
+            | AddrMode::AbsoluteLongX
+            | AddrMode::AbsoluteIndirectLong
+            | AddrMode::RelativeLong
+            | AddrMode::BlockMove => unreachable!("{:?} is a 65816 addressing mode", mode),
+        }
+    }
+
+    pub(crate) fn execute(&mut self, mnemonic: Mnemonic, mode: AddrMode, addr: u16) {
+        match mnemonic {
+            Mnemonic::Adc => {
                 let value = self.load_byte(addr);
-                self.op_lda(value, 4);
+                self.op_adc(value)
             }
-            0xBA => self.op_tsx(),
-            0xBC => {
-                let addr = self.addr_absx();
+            Mnemonic::And => {
                 let value = self.load_byte(addr);
-                self.op_ldy(value, 4);
-            }
-            0xBD => {
-                let addr = self.addr_absx();
+                self.op_and(value)
+            }
+            Mnemonic::Asl if mode == AddrMode::Accumulator => self.op_asl_a(),
+            Mnemonic::Asl => self.op_asl(addr),
+            Mnemonic::Bcc => self.branch(!self.p.contains(Flags::CARRY), addr),
+            Mnemonic::Bcs => self.branch(self.p.contains(Flags::CARRY), addr),
+            Mnemonic::Beq => self.branch(self.p.contains(Flags::ZERO), addr),
+            Mnemonic::Bit => self.op_bit(addr),
+            Mnemonic::Bmi => self.branch(self.p.contains(Flags::NEGATIVE), addr),
+            Mnemonic::Bne => self.branch(!self.p.contains(Flags::ZERO), addr),
+            Mnemonic::Bpl => self.branch(!self.p.contains(Flags::NEGATIVE), addr),
+            Mnemonic::Brk => self.op_brk(),
+            Mnemonic::Bvc => self.branch(!self.p.contains(Flags::OVERFLOW), addr),
+            Mnemonic::Bvs => self.branch(self.p.contains(Flags::OVERFLOW), addr),
+            Mnemonic::Clc => self.op_clc(),
+            Mnemonic::Cld => self.op_cld(),
+            Mnemonic::Cli => self.op_cli(),
+            Mnemonic::Clv => self.op_clv(),
+            Mnemonic::Cmp => {
                 let value = self.load_byte(addr);
-                self.op_lda(value, 4);
+                self.op_cmp(value)
             }
-            0xBE => {
-                let addr = self.addr_absy();
+            Mnemonic::Cpx => {
                 let value = self.load_byte(addr);
-                self.op_ldx(value, 4);
-            }
-            0xC0 => {
-                let addr = self.fetch_op();
-                self.op_cpy(addr, 2);
+                self.op_cpx(value)
             }
-            0xC1 => {
-                let addr = self.addr_indx();
+            Mnemonic::Cpy => {
                 let value = self.load_byte(addr);
-                self.op_cmp(value, 6);
+                self.op_cpy(value)
             }
-            0xC4 => {
-                let addr = self.addr_zero();
+            Mnemonic::Dec => self.op_dec(addr),
+            Mnemonic::Dex => self.op_dex(),
+            Mnemonic::Dey => self.op_dey(),
+            Mnemonic::Eor => {
                 let value = self.load_byte(addr);
-                self.op_cpy(value, 3);
-            }
-            0xC5 => {
-                let addr = self.addr_zero();
+                self.op_eor(value)
+            }
+            Mnemonic::Inc => self.op_inc(addr),
+            Mnemonic::Inx => self.op_inx(),
+            Mnemonic::Iny => self.op_iny(),
+            Mnemonic::Jmp if mode == AddrMode::Indirect => self.op_jmp_ind(addr),
+            Mnemonic::Jmp => self.op_jmp(addr),
+            Mnemonic::Jsr => self.op_jsr(addr),
+            Mnemonic::Lda => {
                 let value = self.load_byte(addr);
-                self.op_cmp(value, 3);
-            }
-            0xC6 => {
-                let addr = self.addr_zero();
-                self.op_dec(addr, 5);
+                self.op_lda(value)
             }
-            0xC8 => self.op_iny(),
-            0xC9 => {
-                let addr = self.fetch_op();
-                self.op_cmp(addr, 2);
-            }
-            0xCA => self.op_dex(),
-            0xCC => {
-                let addr = self.addr_abs();
+            Mnemonic::Ldx => {
                 let value = self.load_byte(addr);
-                self.op_cpy(value, 4);
+                self.op_ldx(value)
             }
-            0xCD => {
-                let addr = self.addr_abs();
+            Mnemonic::Ldy => {
                 let value = self.load_byte(addr);
-                self.op_cmp(value, 4);
+                self.op_ldy(value)
             }
-            0xCE => {
-                let addr = self.addr_abs();
-                self.op_dec(addr, 6);
+            Mnemonic::Lsr if mode == AddrMode::Accumulator => self.op_lsr_a(),
+            Mnemonic::Lsr => self.op_lsr(addr),
+            // The undocumented NOPs with an operand read it
+            Mnemonic::Nop if mode != AddrMode::Implied => {
+                self.load_byte(addr);
             }
-            0xD0 => self.op_bne(),
-            0xD1 => {
-                let addr = self.addr_indy();
+            Mnemonic::Nop => self.op_nop(),
+            Mnemonic::Ora => {
                 let value = self.load_byte(addr);
-                self.op_cmp(value, 5);
-            }
-            0xD5 => {
-                let addr = self.addr_zerox();
+                self.op_ora(value)
+            }
+            Mnemonic::Pha => self.op_pha(),
+            Mnemonic::Php => self.op_php(),
+            Mnemonic::Pla => self.op_pla(),
+            Mnemonic::Plp => self.op_plp(),
+            Mnemonic::Rol if mode == AddrMode::Accumulator => self.op_rol_a(),
+            Mnemonic::Rol => self.op_rol(addr),
+            Mnemonic::Ror if mode == AddrMode::Accumulator => self.op_ror_a(),
+            Mnemonic::Ror => self.op_ror(addr),
+            Mnemonic::Rti => self.op_rti(),
+            Mnemonic::Rts => self.op_rts(),
+            Mnemonic::Sbc => {
                 let value = self.load_byte(addr);
-                self.op_cmp(value, 4);
-            }
-            0xD6 => {
-                let addr = self.addr_zerox();
-                self.op_dec(addr, 6);
-            }
-            0xD8 => self.op_cld(),
-            0xD9 => {
-                let addr = self.addr_absy();
+                self.op_sbc(value)
+            }
+            Mnemonic::Sec => self.op_sec(),
+            Mnemonic::Sed => self.op_sed(),
+            Mnemonic::Sei => self.op_sei(),
+            Mnemonic::Sta => self.op_sta(addr),
+            Mnemonic::Stx => self.op_stx(addr),
+            Mnemonic::Sty => self.op_sty(addr),
+            Mnemonic::Tax => self.op_tax(),
+            Mnemonic::Tay => self.op_tay(),
+            Mnemonic::Tsx => self.op_tsx(),
+            Mnemonic::Txa => self.op_txa(),
+            Mnemonic::Txs => self.op_txs(),
+            Mnemonic::Tya => self.op_tya(),
+            // Undocumented opcodes
+            Mnemonic::Alr => {
                 let value = self.load_byte(addr);
-                self.op_cmp(value, 4);
+                self.op_alr(value)
             }
-            0xDD => {
-                let addr = self.addr_absx();
+            Mnemonic::Anc => {
                 let value = self.load_byte(addr);
-                self.op_cmp(value, 4);
-            }
-            0xDE => {
-                let addr = self.addr_absx();
-                self.op_dec(addr, 7);
-            }
-            0xE0 => {
-                let addr = self.fetch_op();
-                self.op_cpx(addr.into(), 2);
+                self.op_anc(value)
             }
-            0xE1 => {
-                let addr = self.addr_indx();
+            Mnemonic::Ane => {
                 let value = self.load_byte(addr);
-                self.op_sbc(value, 6);
+                self.op_ane(value)
             }
-            0xE4 => {
-                let addr = self.addr_zero();
+            Mnemonic::Arr => {
                 let value = self.load_byte(addr);
-                self.op_cpx(value, 3);
+                self.op_arr(value)
             }
-            0xE5 => {
-                let addr = self.addr_zero();
+            Mnemonic::Dcp => self.op_dcp(addr),
+            Mnemonic::Isc => self.op_isc(addr),
+            Mnemonic::Las => {
                 let value = self.load_byte(addr);
-                self.op_sbc(value, 3);
+                self.op_las(value)
             }
-            0xE6 => {
-                let addr = self.addr_zero();
-                self.op_inc(addr, 5);
-            }
-            0xE8 => self.op_inx(),
-            0xE9 => {
-                let addr = self.fetch_op();
-                self.op_sbc(addr, 2);
-            }
-            0xEA => self.op_nop(),
-            0xEC => {
-                let addr = self.addr_abs();
+            Mnemonic::Lax => {
                 let value = self.load_byte(addr);
-                self.op_cpx(value, 4);
+                self.op_lax(value)
             }
-            0xED => {
-                let addr = self.addr_abs();
+            Mnemonic::Lxa => {
                 let value = self.load_byte(addr);
-                self.op_sbc(value, 4);
+                self.op_lxa(value)
             }
-            0xEE => {
-                let addr = self.addr_abs();
-                self.op_inc(addr, 6);
-            }
-            0xF0 => self.op_beq(),
-            0xF1 => {
-                let addr = self.addr_indy();
+            Mnemonic::Rla => self.op_rla(addr),
+            Mnemonic::Rra => self.op_rra(addr),
+            Mnemonic::Sax => self.op_sax(addr),
+            Mnemonic::Sbx => {
                 let value = self.load_byte(addr);
-                self.op_sbc(value, 5);
-            }
-            0xF5 => {
-                let addr = self.addr_zerox();
-                let value = self.load_byte(addr);
-                self.op_sbc(value, 4);
-            }
-            0xF6 => {
-                let addr = self.addr_zerox();
-                self.op_inc(addr, 6);
-            }
-            0xF8 => self.op_sed(),
-            0xF9 => {
-                let addr = self.addr_absy();
-                let value = self.load_byte(addr);
-                self.op_sbc(value, 4);
-            }
-            0xFD => {
-                let addr = self.addr_absx();
-                let value = self.load_byte(addr);
-                self.op_sbc(value, 4);
-            }
-            0xFE => {
-                let addr = self.addr_absx();
-                self.op_inc(addr, 7);
-            }
-            _ => {
-                println!("Unknown opcode: {:02X}", opcode);
-                retval = false;
-            }
+                self.op_sbx(value)
+            }
+            Mnemonic::Sha => self.op_sh(addr, self.y, self.a & self.x),
+            Mnemonic::Shx => self.op_sh(addr, self.y, self.x),
+            Mnemonic::Shy => self.op_sh(addr, self.x, self.y),
+            Mnemonic::Slo => self.op_slo(addr),
+            Mnemonic::Sre => self.op_sre(addr),
+            Mnemonic::Tas => {
+                self.sp = self.a & self.x;
+                self.op_sh(addr, self.y, self.sp)
+            }
+            // JAM is handled by step(), the 65C02 opcodes by Cpu65C02
+            _ => unreachable!("{} is not an NMOS opcode", mnemonic),
         }
-        retval
     }
 
     // ---- Helper Functions ----
@@ -624,57 +385,179 @@ impl<'a> Cpu<'a> {
         self.cycles
     }
 
-    pub fn nmi(&mut self) {
-        self.push((self.pc >> 8) as u8 & 0xff);
-        self.push(self.pc as u8 & 0xff);
-        // push flags with BCF (Break Command flag) cleared
-        self.push(self.status_from_flags() & 0xef);
-        self.pc = self.memory.read_word(Memory::ADDR_NMI_VECTOR);
+    /// Number of cycles an interrupt line has to be active before the CPU
+    /// notices it. Interrupts are polled ahead of the last cycle of an
+    /// instruction, so a line asserted during the last cycle is serviced
+    /// one instruction later.
+    pub const INTERRUPT_LATENCY: u32 = 2;
+
+    /// Drives the /IRQ line on behalf of a single source. The line is level
+    /// sensitive: it is serviced whenever it is active and the I flag is
+    /// clear, and it stays active until every source releases it.
+    pub fn set_irq(&mut self, source: IrqSource, active: bool) {
+        self.set_irq_at(source, active, self.cycles);
+    }
+
+    /// Like set_irq, for a line that changed at cycle `at` of the
+    /// instruction that just ran. Chips catching up after each step pass
+    /// the cycle the line really changed, so the latency counts from there
+    /// and not from the end of the instruction.
+    pub fn set_irq_at(&mut self, source: IrqSource, active: bool, at: u32) {
+        let lines = if active {
+            self.irq_lines | source as u8
+        } else {
+            self.irq_lines & !(source as u8)
+        };
+        if self.irq_lines == 0 && lines != 0 {
+            self.irq_asserted_at = at;
+        }
+        self.irq_lines = lines;
+    }
+
+    /// Drives the /NMI line on behalf of a single source. Only the transition
+    /// from inactive to active latches an NMI; holding the line does not
+    /// trigger another one until all sources release it.
+    pub fn set_nmi(&mut self, source: NmiSource, active: bool) {
+        self.set_nmi_at(source, active, self.cycles);
+    }
+
+    /// Like set_nmi, for a line that changed at cycle `at`, see set_irq_at
+    pub fn set_nmi_at(&mut self, source: NmiSource, active: bool, at: u32) {
+        let lines = if active {
+            self.nmi_lines | source as u8
+        } else {
+            self.nmi_lines & !(source as u8)
+        };
+        if self.nmi_lines == 0 && lines != 0 {
+            self.nmi_pending = true;
+            self.nmi_edge_at = at;
+        }
+        self.nmi_lines = lines;
+    }
+
+    // Services a pending interrupt at an instruction boundary
+    pub(crate) fn poll_interrupts(&mut self) -> bool {
+        self.interrupted = self.interrupt_due();
+        self.irq_poll_disable = None;
+        match self.interrupted {
+            Some(Interrupt::Nmi) => {
+                self.nmi_pending = false;
+                self.nmi();
+            }
+            Some(Interrupt::Irq) => self.irq(),
+            None => return false,
+        }
+        true
+    }
+
+    /// Interrupt the next step will enter instead of running the instruction
+    /// at PC. CLI, SEI and PLP change the I flag after the poll has happened,
+    /// so the poll that follows them still sees the previous value.
+    pub fn interrupt_due(&self) -> Option<Interrupt> {
+        let interrupt_disable = self
+            .irq_poll_disable
+            .unwrap_or(self.p.contains(Flags::INTERRUPT_DISABLE));
+
+        if self.nmi_pending && self.cycles.wrapping_sub(self.nmi_edge_at) >= Self::INTERRUPT_LATENCY
+        {
+            Some(Interrupt::Nmi)
+        } else if self.irq_lines != 0
+            && !interrupt_disable
+            && self.cycles.wrapping_sub(self.irq_asserted_at) >= Self::INTERRUPT_LATENCY
+        {
+            Some(Interrupt::Irq)
+        } else {
+            None
+        }
+    }
+
+    /// Interrupt the last step entered, if it didn't run an instruction
+    pub fn interrupted(&self) -> Option<Interrupt> {
+        self.interrupted
+    }
+
+    /// Shadow call stack of the subroutines and interrupts entered
+    pub fn calls(&self) -> &CallStack {
+        &self.calls
+    }
+
+    pub fn calls_mut(&mut self) -> &mut CallStack {
+        &mut self.calls
+    }
+
+    // Whether an interrupt line wants attention, regardless of the I flag
+    pub(crate) fn interrupt_pending(&self) -> bool {
+        self.nmi_pending || self.irq_lines != 0
+    }
+
+    fn nmi(&mut self) {
+        let (caller, sp) = (self.pc, self.sp);
+        // The opcode and the byte after it are read, then dropped
+        self.load_byte(self.pc);
+        self.load_byte(self.pc);
+        self.push_word(self.pc);
+        // Hardware interrupts push the flags with B clear
+        self.push(self.p.to_stack(false));
+        self.p.insert(Flags::INTERRUPT_DISABLE);
+        self.pc = self.read_word(Memory::ADDR_NMI_VECTOR);
         self.tick(7);
+        self.calls.enter(FrameKind::Nmi, caller, self.pc, sp);
     }
 
-    pub fn irq(&mut self) {
+    fn irq(&mut self) {
+        let (caller, sp) = (self.pc, self.sp);
+        // The opcode and the byte after it are read, then dropped
+        self.load_byte(self.pc);
+        self.load_byte(self.pc);
+
         // Push the current program counter onto the stack
         self.push_word(self.pc);
 
-        // Push the processor status onto the stack
-        self.status_from_flags();
-        self.push(self.status);
+        // Push the processor status onto the stack with B clear
+        self.push(self.p.to_stack(false));
 
         // Set the IRQ disable flag
-        self.interrupt_disable = true;
+        self.p.insert(Flags::INTERRUPT_DISABLE);
 
         // Load the program counter with the address from the IRQ vector
-        let lo = self.memory.read_byte(Memory::ADDR_IRQ_VECTOR) as u16;
-        let hi = self.memory.read_byte(Memory::ADDR_IRQ_VECTOR + 1) as u16;
-        self.pc = (hi << 8) | lo;
+        self.pc = self.read_word(Memory::ADDR_IRQ_VECTOR);
+        self.tick(7);
+        self.calls.enter(FrameKind::Irq, caller, self.pc, sp);
+    }
+
+    pub fn load_byte(&mut self, addr: u16) -> u8 {
+        self.bus.read(addr)
     }
 
-    pub fn load_byte(&self, addr: u16) -> u8 {
-        self.memory.read_byte(addr)
+    // Read-modify-write instructions write the value back unchanged in the
+    // cycle they modify it
+    fn read_modify(&mut self, addr: u16) -> u8 {
+        let value = self.bus.read(addr);
+        self.bus.write(addr, value);
+        value
     }
 
     pub fn push(&mut self, v: u8) {
         let addr = Memory::BASE_ADDR_STACK + self.sp as u16;
-        self.memory.write_byte(addr, v);
-        self.sp -= 1;
+        self.bus.write(addr, v);
+        self.sp = self.sp.wrapping_sub(1);
     }
 
     pub fn pop(&mut self) -> u8 {
-        let addr = (self.sp + 1) as u16 + Memory::BASE_ADDR_STACK;
-        self.sp += 1;
+        self.sp = self.sp.wrapping_add(1);
+        let addr = Memory::BASE_ADDR_STACK + self.sp as u16;
         self.load_byte(addr)
     }
 
     pub fn fetch_op(&mut self) -> u8 {
         let opcode = self.load_byte(self.pc);
-        self.pc += 1;
+        self.pc = self.pc.wrapping_add(1);
         opcode
     }
 
     pub fn fetch_opw(&mut self) -> u16 {
-        let retval = self.memory.read_word(self.pc);
-        self.pc += 2;
+        let retval = self.read_word(self.pc);
+        self.pc = self.pc.wrapping_add(2);
         retval
     }
 
@@ -682,57 +565,101 @@ impl<'a> Cpu<'a> {
         self.fetch_op() as u16
     }
 
+    // The zero page indexed modes read the unindexed address while adding
+    // the index
     pub fn addr_zerox(&mut self) -> u16 {
-        (self.fetch_op() as u16 + self.x as u16) & 0xff
+        let base = self.fetch_op();
+        self.load_byte(base as u16);
+        base.wrapping_add(self.x) as u16
     }
 
     pub fn addr_zeroy(&mut self) -> u16 {
-        (self.fetch_op() as u16 + self.y as u16) & 0xff
+        let base = self.fetch_op();
+        self.load_byte(base as u16);
+        base.wrapping_add(self.y) as u16
     }
 
     pub fn addr_abs(&mut self) -> u16 {
         self.fetch_opw()
     }
 
-    pub fn addr_absy(&mut self) -> u16 {
-        self.fetch_opw() + self.y as u16
+    // Indexed modes return the base and the effective address
+    pub fn addr_absy(&mut self) -> (u16, u16) {
+        let base = self.fetch_opw();
+        (base, base.wrapping_add(self.y as u16))
     }
 
-    pub fn addr_absx(&mut self) -> u16 {
-        self.fetch_opw() + self.x as u16
+    pub fn addr_absx(&mut self) -> (u16, u16) {
+        let base = self.fetch_opw();
+        (base, base.wrapping_add(self.x as u16))
     }
 
     pub fn addr_indx(&mut self) -> u16 {
-        let addr = (self.addr_zero() + self.x as u16) & 0xff;
-        self.memory.read_word(addr)
+        let zp = self.fetch_op();
+        self.load_byte(zp as u16);
+        self.read_word_zp(zp.wrapping_add(self.x))
+    }
+
+    pub fn addr_indy(&mut self) -> (u16, u16) {
+        let zp = self.fetch_op();
+        let base = self.read_word_zp(zp);
+        (base, base.wrapping_add(self.y as u16))
+    }
+
+    // The index is added to the low byte first, and the CPU reads from the
+    // address before the carry reaches the high byte. For reads that is the
+    // operand when no page was crossed, writes and read-modify-writes
+    // always spend the cycle. Returns the address and whether a page was
+    // crossed.
+    fn fix_page(&mut self, base: u16, addr: u16, access: Access) -> (u16, bool) {
+        let page_crossed = (base & 0xff00) != (addr & 0xff00);
+        if page_crossed || access != Access::Read {
+            self.load_byte((base & 0xff00) | (addr & 0x00ff));
+        }
+        (addr, page_crossed)
     }
 
-    pub fn addr_indy(&mut self) -> u16 {
-        let addr = self.addr_zero();
-        self.memory.read_word(addr) + self.y as u16
+    // Relative branch target, the offset is a signed byte added to the PC
+    // of the next instruction
+    pub fn addr_rel(&mut self) -> u16 {
+        let offset = self.fetch_op() as i8;
+        self.pc.wrapping_add(offset as u16)
     }
 
     // Advenced cycle count
-    fn tick(&mut self, cycles: u32) {
+    pub(crate) fn tick(&mut self, cycles: u32) {
         self.cycles += cycles;
     }
 
     /// Writes a byte to the memory the CPU is using
     pub fn write_memory(&mut self, addr: u16, value: u8) {
-        self.memory.write_byte(addr, value);
+        self.bus.write(addr, value);
     }
 
     /// Reads a byte from the memory the CPU is using
-    pub fn read_memory(&self, addr: u16) -> u8 {
-        self.memory.read_byte(addr)
+    pub fn read_memory(&mut self, addr: u16) -> u8 {
+        self.bus.read(addr)
+    }
+
+    /// Reads a byte without triggering I/O side effects
+    pub fn peek_memory(&self, addr: u16) -> u8 {
+        self.bus.peek(addr)
     }
 
     /// Read a word from the memory the CPU is using
     /// The 6502 is little endian, so the first byte is the LSB
     /// and the second byte is the MSB
-    fn read_word(&self, addr: u16) -> u16 {
+    pub(crate) fn read_word(&mut self, addr: u16) -> u16 {
         let lsb = self.read_memory(addr) as u16;
-        let msb = self.read_memory(addr + 1) as u16;
+        let msb = self.read_memory(addr.wrapping_add(1)) as u16;
+        lsb | (msb << 8)
+    }
+
+    /// Read a word from the zero page, the pointer wraps from $FF to $00
+    /// instead of crossing into the stack page
+    fn read_word_zp(&mut self, addr: u8) -> u16 {
+        let lsb = self.read_memory(addr as u16) as u16;
+        let msb = self.read_memory(addr.wrapping_add(1) as u16) as u16;
         lsb | (msb << 8)
     }
 
@@ -740,568 +667,708 @@ impl<'a> Cpu<'a> {
         let lsb = value as u8;
         let msb = (value >> 8) as u8;
         self.write_memory(addr, lsb);
-        self.write_memory(addr + 1, msb);
+        self.write_memory(addr.wrapping_add(1), msb);
     }
 
     // ---- Math Instructions ----
     // ADC: Add with Carry
-    fn op_adc(&mut self, value: u8, cycles: u32) {
-        let temp = self.a as u16 + value as u16 + if self.carry { 1 } else { 0 };
+    fn op_adc(&mut self, value: u8) {
+        if self.p.contains(Flags::DECIMAL) {
+            self.adc_decimal(value);
+            return;
+        }
+
+        let temp = self.a as u16 + value as u16 + if self.p.contains(Flags::CARRY) { 1 } else { 0 };
 
-        self.overflow = (!(self.a ^ value) & (self.a ^ temp as u8) & 0x80) != 0;
-        self.carry = temp > 0xFF;
+        self.p.set(
+            Flags::OVERFLOW,
+            (!(self.a ^ value) & (self.a ^ temp as u8) & 0x80) != 0,
+        );
+        self.p.set(Flags::CARRY, temp > 0xFF);
 
         self.a = temp as u8;
         self.update_zero_negative_flags(self.a);
-        self.tick(cycles);
     }
 
     // SBC: Subtract with Carry
-    fn op_sbc(&mut self, value: u8, cycles: u32) {
-        let temp = self.a as i16 - value as i16 - if self.carry { 0 } else { 1 };
+    fn op_sbc(&mut self, value: u8) {
+        let a = self.a;
+        let borrow = !self.p.contains(Flags::CARRY);
+        let temp = a as i16 - value as i16 - borrow as i16;
 
-        self.overflow = ((self.a ^ temp as u8) & (self.a ^ value) & 0x80) != 0;
-        self.carry = temp >= 0;
+        self.p.set(
+            Flags::OVERFLOW,
+            ((a ^ temp as u8) & (a ^ value) & 0x80) != 0,
+        );
+        self.p.set(Flags::CARRY, temp >= 0);
 
         self.a = temp as u8;
         self.update_zero_negative_flags(self.a);
-        self.tick(cycles);
+        if self.p.contains(Flags::DECIMAL) {
+            self.a = Self::sbc_decimal_nmos(a, value, borrow);
+        }
+    }
+
+    // Decimal ADC on the NMOS 6502 takes Z from the binary sum, and N and V
+    // from the intermediate result before the high nibble is adjusted
+    fn adc_decimal(&mut self, value: u8) {
+        let a = self.a as u16;
+        let v = value as u16;
+        let c = self.p.contains(Flags::CARRY) as u16;
+
+        let mut lo = (a & 0x0f) + (v & 0x0f) + c;
+        if lo > 0x09 {
+            lo += 0x06;
+        }
+        let mut temp = (a & 0xf0) + (v & 0xf0) + (lo & 0x0f);
+        if lo > 0x0f {
+            temp += 0x10;
+        }
+
+        self.p.set(Flags::ZERO, (a + v + c) & 0xff == 0);
+        self.p.set(Flags::NEGATIVE, (temp & 0x80) != 0);
+        self.p.set(
+            Flags::OVERFLOW,
+            ((a ^ temp) & 0x80) != 0 && ((a ^ v) & 0x80) == 0,
+        );
+        if (temp & 0x1f0) > 0x90 {
+            temp += 0x60;
+        }
+        self.p.set(Flags::CARRY, (temp & 0xff0) > 0xf0);
+        self.a = temp as u8;
+    }
+
+    // Decimal SBC on the NMOS 6502 sets every flag from the binary result,
+    // only the accumulator gets the BCD adjusted value
+    fn sbc_decimal_nmos(a: u8, value: u8, borrow: bool) -> u8 {
+        let a = a as u16;
+        let v = value as u16;
+        let borrow = borrow as u16;
+
+        let lo = (a & 0x0f).wrapping_sub(v & 0x0f).wrapping_sub(borrow);
+        let mut temp = if (lo & 0x10) != 0 {
+            (lo.wrapping_sub(0x06) & 0x0f) | (a & 0xf0).wrapping_sub(v & 0xf0).wrapping_sub(0x10)
+        } else {
+            (lo & 0x0f) | (a & 0xf0).wrapping_sub(v & 0xf0)
+        };
+        if (temp & 0x100) != 0 {
+            temp = temp.wrapping_sub(0x60);
+        }
+        temp as u8
     }
 
     // ---- Memory Instructions ----
     // LDA: Load Accumulator
-    fn op_lda(&mut self, value: u8, cycles: u32) {
+    fn op_lda(&mut self, value: u8) {
         self.a = value;
         self.update_zero_negative_flags(self.a);
-        self.tick(cycles);
     }
 
     // LDX: Load X Register
-    fn op_ldx(&mut self, value: u8, cycles: u32) {
+    fn op_ldx(&mut self, value: u8) {
         self.x = value;
         self.update_zero_negative_flags(self.x);
-        self.tick(cycles);
     }
 
     // LDY: Load Y Register
-    fn op_ldy(&mut self, value: u8, cycles: u32) {
+    fn op_ldy(&mut self, value: u8) {
         self.y = value;
         self.update_zero_negative_flags(self.y);
-        self.tick(cycles);
     }
 
     // STA: Store Accumulator
-    fn op_sta(&mut self, addr: u16, cycles: u32) {
-        self.memory.write_byte(addr, self.a);
-        self.tick(cycles);
+    fn op_sta(&mut self, addr: u16) {
+        self.bus.write(addr, self.a);
     }
 
     // INC: Increment Memory
-    fn op_inc(&mut self, addr: u16, cycles: u32) {
-        let mut value = self.memory.read_byte(addr);
+    fn op_inc(&mut self, addr: u16) {
+        let mut value = self.read_modify(addr);
         value = value.wrapping_add(1);
-        self.memory.write_byte(addr, value);
+        self.bus.write(addr, value);
         self.update_zero_negative_flags(value);
-        self.tick(cycles);
     }
 
     // DEC: Decrement Memory
-    fn op_dec(&mut self, addr: u16, cycles: u32) {
-        let mut value = self.memory.read_byte(addr);
+    fn op_dec(&mut self, addr: u16) {
+        let mut value = self.read_modify(addr);
         value = value.wrapping_sub(1);
-        self.memory.write_byte(addr, value);
+        self.bus.write(addr, value);
         self.update_zero_negative_flags(value);
-        self.tick(cycles);
     }
 
     // ---- Branching Instructions ----
-    // BEQ: Branch if Equal (Zero flag is set)
-    fn op_beq(&mut self) {
-        let addr: u16 = self.fetch_op() as u16 + self.pc;
-        if self.zero {
-            self.branch(addr);
-        }
-        self.tick(2);
-    }
-
-    // BNE: Branch if Not Equal (Zero flag is clear)
-    fn op_bne(&mut self) {
-        let addr: u16 = self.fetch_op() as u16 + self.pc;
-        if !self.zero {
-            self.branch(addr);
+    // BCC, BCS, BEQ, BMI, BNE, BPL, BVC and BVS all branch to the relative
+    // target when their condition holds. A taken branch costs one extra
+    // cycle, and another one when the target is on a different page.
+    pub(crate) fn branch(&mut self, condition: bool, addr: u16) {
+        if condition {
+            // The extra cycles read the next opcode, then the target before
+            // its high byte is fixed up
+            self.load_byte(self.pc);
+            self.tick(1);
+            if (self.pc & 0xff00) != (addr & 0xff00) {
+                self.load_byte((self.pc & 0xff00) | (addr & 0x00ff));
+                self.tick(1);
+            }
+            self.pc = addr;
         }
-        self.tick(2);
-    }
-
-    // BCS: Branch if Carry Set
-    fn op_bcs(&mut self) {
-        let addr: u16 = self.fetch_op() as u16 + self.pc;
-        if self.carry {
-            self.branch(addr);
-        }
-        self.tick(2);
-    }
-
-    // BCC: Branch if Carry Clear
-    fn op_bcc(&mut self) {
-        let addr: u16 = self.fetch_op() as u16 + self.pc;
-        if !self.carry {
-            self.branch(addr);
-        }
-        self.tick(2);
-    }
-
-    // BMI: Branch if Minus (Negative flag is set)
-    fn op_bmi(&mut self) {
-        let addr = self.fetch_op() as u16 + self.pc;
-        if self.negative {
-            self.branch(addr);
-        }
-        self.tick(2);
-    }
-
-    // BPL: Branch if Positive (Negative flag is clear)
-    fn op_bpl(&mut self) {
-        let addr = self.fetch_op() as u16 + self.pc;
-        if !self.negative {
-            self.branch(addr);
-        }
-        self.tick(2);
-    }
-
-    // BVS: Branch if Overflow Set
-    fn op_bvs(&mut self) {
-        let addr: u16 = self.fetch_op() as u16 + self.pc;
-        if self.overflow {
-            self.branch(addr);
-        }
-        self.tick(2);
-    }
-
-    // BVC: Branch if Overflow Clear
-    fn op_bvc(&mut self) {
-        let addr: u16 = self.fetch_op() as u16 + self.pc;
-        if !self.overflow {
-            self.branch(addr);
-        }
-    }
-
-    // Helper function to handle branching
-    // fn branch(&mut self, offset: i8) {
-    //     self.pc = (self.pc as i16 + offset as i16) as u16;
-    // }
-    fn branch(&mut self, addr: u16) {
-        self.pc = addr;
     }
 
     // ---- Bitwise Instructions ----
     // AND: Logical AND
-    fn op_and(&mut self, value: u8, cycles: u32) {
+    fn op_and(&mut self, value: u8) {
         self.a &= value;
         self.update_zero_negative_flags(self.a);
-        self.tick(cycles);
     }
 
     // ORA: Logical OR
-    fn op_ora(&mut self, value: u8, cycles: u32) {
+    fn op_ora(&mut self, value: u8) {
         self.a |= value;
         self.update_zero_negative_flags(self.a);
-        self.tick(cycles);
     }
 
     // EOR: Exclusive OR
-    fn op_eor(&mut self, value: u8, cycles: u32) {
+    fn op_eor(&mut self, value: u8) {
         self.a ^= value;
         self.update_zero_negative_flags(self.a);
-        self.tick(cycles);
     }
 
     // ASL: Arithmetic Shift Left
-    fn op_asl(&mut self, addr: u16, cycles: u32) {
-        let mut value = self.memory.read_byte(addr);
-        self.carry = (value & 0x80) != 0;
+    fn op_asl(&mut self, addr: u16) {
+        let mut value = self.read_modify(addr);
+        self.p.set(Flags::CARRY, (value & 0x80) != 0);
         value <<= 1;
-        self.memory.write_byte(addr, value);
+        self.bus.write(addr, value);
         self.update_zero_negative_flags(value);
-        self.tick(cycles);
     }
 
     fn op_asl_a(&mut self) {
-        self.carry = (self.a & 0x80) != 0;
+        self.p.set(Flags::CARRY, (self.a & 0x80) != 0);
         self.a <<= 1;
         self.update_zero_negative_flags(self.a);
-        self.tick(2);
     }
 
     // LSR: Logical Shift Right
-    fn op_lsr(&mut self, addr: u16, cycles: u32) {
-        let mut value = self.memory.read_byte(addr);
-        self.carry = (value & 0x01) != 0;
+    fn op_lsr(&mut self, addr: u16) {
+        let mut value = self.read_modify(addr);
+        self.p.set(Flags::CARRY, (value & 0x01) != 0);
         value >>= 1;
-        self.memory.write_byte(addr, value);
+        self.bus.write(addr, value);
         self.update_zero_negative_flags(value);
-        self.tick(cycles);
     }
 
     fn op_lsr_a(&mut self) {
-        self.carry = (self.a & 0x01) != 0;
+        self.p.set(Flags::CARRY, (self.a & 0x01) != 0);
         self.a >>= 1;
         self.update_zero_negative_flags(self.a);
-        self.tick(2);
     }
 
     // ROL: Rotate Left
-    fn op_rol(&mut self, addr: u16, cycles: u32) {
-        let mut value = self.memory.read_byte(addr);
+    fn op_rol(&mut self, addr: u16) {
+        let mut value = self.read_modify(addr);
         let new_carry = (value & 0x80) != 0;
         value <<= 1;
-        if self.carry {
+        if self.p.contains(Flags::CARRY) {
             value |= 0x01;
         }
-        self.memory.write_byte(addr, value);
-        self.carry = new_carry;
+        self.bus.write(addr, value);
+        self.p.set(Flags::CARRY, new_carry);
         self.update_zero_negative_flags(value);
-        self.tick(cycles);
     }
 
     fn op_rol_a(&mut self) {
         let new_carry = (self.a & 0x80) != 0;
         self.a <<= 1;
-        if self.carry {
+        if self.p.contains(Flags::CARRY) {
             self.a |= 0x01;
         }
-        self.carry = new_carry;
+        self.p.set(Flags::CARRY, new_carry);
         self.update_zero_negative_flags(self.a);
-        self.tick(2);
     }
 
     // ROR: Rotate Right
-    fn op_ror(&mut self, addr: u16, cycles: u32) {
-        let mut value = self.memory.read_byte(addr);
+    fn op_ror(&mut self, addr: u16) {
+        let mut value = self.read_modify(addr);
         let new_carry = (value & 0x01) != 0;
         value >>= 1;
-        if self.carry {
+        if self.p.contains(Flags::CARRY) {
             value |= 0x80;
         }
-        self.memory.write_byte(addr, value);
-        self.carry = new_carry;
+        self.bus.write(addr, value);
+        self.p.set(Flags::CARRY, new_carry);
         self.update_zero_negative_flags(value);
-        self.tick(cycles);
     }
 
     fn op_ror_a(&mut self) {
         let new_carry = (self.a & 0x01) != 0;
         self.a >>= 1;
-        if self.carry {
+        if self.p.contains(Flags::CARRY) {
             self.a |= 0x80;
         }
-        self.carry = new_carry;
+        self.p.set(Flags::CARRY, new_carry);
         self.update_zero_negative_flags(self.a);
-        self.tick(2);
     }
 
     // ---- Stack Instructions ----
     // PHA: Push Accumulator onto Stack
     fn op_pha(&mut self) {
-        self.memory.write_byte(0x0100 + self.sp as u16, self.a);
+        self.bus.write(0x0100 + self.sp as u16, self.a);
         self.sp = self.sp.wrapping_sub(1);
-        self.tick(3);
     }
 
     // PHP: Push Processor Status onto Stack
     fn op_php(&mut self) {
-        let status = self.status_from_flags();
-        self.memory.write_byte(0x0100 + self.sp as u16, status);
+        // PHP and BRK always push the B flag set
+        let status = self.p.to_stack(true);
+        self.bus.write(0x0100 + self.sp as u16, status);
         self.sp = self.sp.wrapping_sub(1);
-        self.tick(3);
     }
 
     // PLA: Pull Accumulator from Stack
     fn op_pla(&mut self) {
+        self.load_byte(0x0100 + self.sp as u16);
         self.sp = self.sp.wrapping_add(1);
-        self.a = self.memory.read_byte(0x0100 + self.sp as u16);
+        self.a = self.bus.read(0x0100 + self.sp as u16);
         self.update_zero_negative_flags(self.a);
-        self.tick(4);
     }
 
     // PLP: Pull Processor Status from Stack
     fn op_plp(&mut self) {
+        self.irq_poll_disable = Some(self.p.contains(Flags::INTERRUPT_DISABLE));
+        // Pulls read the stack once before incrementing the stack pointer
+        self.load_byte(0x0100 + self.sp as u16);
         self.sp = self.sp.wrapping_add(1);
-        let status = self.memory.read_byte(0x0100 + self.sp as u16);
-        self.flags_from_status(status);
-        self.tick(4);
+        let status = self.bus.read(0x0100 + self.sp as u16);
+        self.p = Flags::from_stack(status);
     }
 
     // STX: Store X Register
-    fn op_stx(&mut self, addr: u16, cycles: u32) {
-        self.memory.write_byte(addr, self.x);
-        self.tick(cycles);
+    fn op_stx(&mut self, addr: u16) {
+        self.bus.write(addr, self.x);
     }
 
     // STY: Store Y Register
-    fn op_sty(&mut self, addr: u16, cycles: u32) {
-        self.memory.write_byte(addr, self.y);
-        self.tick(cycles);
+    fn op_sty(&mut self, addr: u16) {
+        self.bus.write(addr, self.y);
     }
 
     // TXS: Transfer X to Stack Pointer
     fn op_txs(&mut self) {
         self.sp = self.x;
-        self.tick(2);
     }
 
     // TSX: Transfer Stack Pointer to X
     fn op_tsx(&mut self) {
         self.x = self.sp;
         self.update_zero_negative_flags(self.x);
-        self.tick(2);
-    }
-
-    // Helper functions to convert between status flags and a single byte
-    fn status_from_flags(&self) -> u8 {
-        let mut status = 0;
-        if self.carry {
-            status |= 1 << 0;
-        }
-        if self.zero {
-            status |= 1 << 1;
-        }
-        if self.interrupt_disable {
-            status |= 1 << 2;
-        }
-        if self.decimal {
-            status |= 1 << 3;
-        }
-        if self.break_command {
-            status |= 1 << 4;
-        }
-        // bit 5 is always 1
-        status |= 1 << 5;
-        if self.overflow {
-            status |= 1 << 6;
-        }
-        if self.negative {
-            status |= 1 << 7;
-        }
-        status
-    }
-
-    fn flags_from_status(&mut self, status: u8) {
-        self.carry = (status & (1 << 0)) != 0;
-        self.zero = (status & (1 << 1)) != 0;
-        self.interrupt_disable = (status & (1 << 2)) != 0;
-        self.decimal = (status & (1 << 3)) != 0;
-        self.break_command = (status & (1 << 4)) != 0;
-        self.overflow = (status & (1 << 6)) != 0;
-        self.negative = (status & (1 << 7)) != 0;
     }
 
     // -- Register Instructions --
     fn op_tax(&mut self) {
         self.x = self.a;
         self.update_zero_negative_flags(self.x);
-        self.tick(2);
     }
 
     fn op_tay(&mut self) {
         self.y = self.a;
         self.update_zero_negative_flags(self.y);
-        self.tick(2);
     }
 
     fn op_txa(&mut self) {
         self.a = self.x;
         self.update_zero_negative_flags(self.a);
-        self.tick(2);
     }
 
     fn op_tya(&mut self) {
         self.a = self.y;
         self.update_zero_negative_flags(self.a);
-        self.tick(2);
     }
 
     fn op_dex(&mut self) {
         self.x = self.x.wrapping_sub(1);
         self.update_zero_negative_flags(self.x);
-        self.tick(2);
     }
 
     fn op_dey(&mut self) {
         self.y = self.y.wrapping_sub(1);
         self.update_zero_negative_flags(self.y);
-        self.tick(2);
     }
 
     fn op_inx(&mut self) {
         self.x = self.x.wrapping_add(1);
         self.update_zero_negative_flags(self.x);
-        self.tick(2);
     }
 
     fn op_iny(&mut self) {
         self.y = self.y.wrapping_add(1);
         self.update_zero_negative_flags(self.y);
-        self.tick(2);
     }
 
     // ---- Jump Instructions ----
     // JMP: Jump to Address
-    fn op_jmp(&mut self) {
-        let addr = self.addr_abs();
+    fn op_jmp(&mut self, addr: u16) {
         self.pc = addr;
-        self.tick(3);
     }
 
-    fn op_jmp_ind(&mut self) {
-        let addr = self.addr_abs();
-        let value = self.read_word(addr);
-        self.pc = value;
-        self.tick(3);
+    // The NMOS 6502 doesn't carry into the high byte when fetching the
+    // pointer, so JMP ($xxFF) reads its MSB from $xx00
+    fn op_jmp_ind(&mut self, addr: u16) {
+        let lsb = self.read_memory(addr) as u16;
+        let msb = self.read_memory((addr & 0xff00) | (addr.wrapping_add(1) & 0x00ff)) as u16;
+        self.pc = lsb | (msb << 8);
     }
 
-    // JSR: Jump to Subroutine
-    fn op_jsr(&mut self, addr: u16) {
-        // Push the return address (minus one) onto the stack
-        self.push_word(self.pc.wrapping_sub(1));
+    // JSR: Jump to Subroutine. `lsb` is the low byte of the target, the
+    // high byte is fetched after the return address has been pushed.
+    fn op_jsr(&mut self, lsb: u16) {
+        let sp = self.sp;
+        self.load_byte(0x0100 + self.sp as u16);
+        // Push the return address (minus one) onto the stack, the PC still
+        // points at the last operand byte
+        self.push_word(self.pc);
+        let addr = lsb | (self.fetch_op() as u16) << 8;
+        self.calls
+            .enter(FrameKind::Call, self.pc.wrapping_sub(3), addr, sp);
         self.pc = addr;
     }
 
     // RTS: Return from Subroutine
     fn op_rts(&mut self) {
-        self.pc = self.pull_word().wrapping_add(1);
-        self.tick(6);
+        let from = self.pc.wrapping_sub(1);
+        self.load_byte(0x0100 + self.sp as u16);
+        self.pc = self.pull_word();
+        // Reads the byte at the pulled address while incrementing it
+        self.fetch_op();
+        self.calls.leave(from, self.pc, self.sp);
     }
 
     // RTI: Return from Interrupt
     fn op_rti(&mut self) {
+        let from = self.pc.wrapping_sub(1);
         self.op_plp();
+        // Unlike PLP, RTI restores the I flag before the next poll
+        self.irq_poll_disable = None;
         self.pc = self.pull_word();
-        self.tick(7);
+        self.calls.leave(from, self.pc, self.sp);
     }
 
     // Helper functions for stack operations
-    fn push_word(&mut self, value: u16) {
+    pub(crate) fn push_word(&mut self, value: u16) {
         let hi = ((value >> 8) & 0xFF) as u8;
         let lo = (value & 0xFF) as u8;
-        self.memory.write_byte(0x0100 + self.sp as u16, hi);
+        self.bus.write(0x0100 + self.sp as u16, hi);
         self.sp = self.sp.wrapping_sub(1);
-        self.memory.write_byte(0x0100 + self.sp as u16, lo);
+        self.bus.write(0x0100 + self.sp as u16, lo);
         self.sp = self.sp.wrapping_sub(1);
     }
 
-    fn pull_word(&mut self) -> u16 {
+    pub(crate) fn pull_word(&mut self) -> u16 {
         self.sp = self.sp.wrapping_add(1);
-        let lo = self.memory.read_byte(0x0100 + self.sp as u16) as u16;
+        let lo = self.bus.read(0x0100 + self.sp as u16) as u16;
         self.sp = self.sp.wrapping_add(1);
-        let hi = self.memory.read_byte(0x0100 + self.sp as u16) as u16;
+        let hi = self.bus.read(0x0100 + self.sp as u16) as u16;
         (hi << 8) | lo
     }
 
     // ---- Compare Instructions ----
     // CMP: Compare Accumulator
-    fn op_cmp(&mut self, value: u8, cycles: u32) {
+    fn op_cmp(&mut self, value: u8) {
         let result = self.a.wrapping_sub(value);
         self.update_zero_negative_flags(result);
-        self.carry = self.a >= value;
-        self.tick(cycles);
+        self.p.set(Flags::CARRY, self.a >= value);
     }
 
     // CPX: Compare X Register
-    fn op_cpx(&mut self, value: u8, cycles: u32) {
+    fn op_cpx(&mut self, value: u8) {
         let result = self.x.wrapping_sub(value);
         self.update_zero_negative_flags(result);
-        self.carry = self.x >= value;
-        self.tick(cycles);
+        self.p.set(Flags::CARRY, self.x >= value);
     }
 
     // CPY: Compare Y Register
-    fn op_cpy(&mut self, value: u8, cycles: u32) {
+    fn op_cpy(&mut self, value: u8) {
         let result = self.y.wrapping_sub(value);
         self.update_zero_negative_flags(result);
-        self.carry = self.y >= value;
-        self.tick(cycles);
+        self.p.set(Flags::CARRY, self.y >= value);
     }
 
     // BIT: Bit Test
-    fn op_bit(&mut self, addr: u16, cycles: u32) {
-        let value = self.memory.read_byte(addr);
+    fn op_bit(&mut self, addr: u16) {
+        let value = self.bus.read(addr);
         let result = self.a & value;
 
-        self.zero = result == 0;
-        self.overflow = (value & 0x40) != 0;
-        self.negative = (value & 0x80) != 0;
-        self.tick(cycles);
+        self.p.set(Flags::ZERO, result == 0);
+        self.p.set(Flags::OVERFLOW, (value & 0x40) != 0);
+        self.p.set(Flags::NEGATIVE, (value & 0x80) != 0);
     }
 
     // ---- Flag Instructions ----
     // CLC: Clear Carry Flag
     fn op_clc(&mut self) {
-        self.carry = false;
+        self.p.remove(Flags::CARRY);
     }
 
     // SEC: Set Carry Flag
     fn op_sec(&mut self) {
-        self.carry = true;
-        self.tick(2);
+        self.p.insert(Flags::CARRY);
     }
 
     // CLI: Clear Interrupt Disable Flag
     fn op_cli(&mut self) {
-        self.interrupt_disable = false;
-        self.tick(2);
+        self.irq_poll_disable = Some(self.p.contains(Flags::INTERRUPT_DISABLE));
+        self.p.remove(Flags::INTERRUPT_DISABLE);
     }
 
     // SEI: Set Interrupt Disable Flag
     fn op_sei(&mut self) {
-        self.interrupt_disable = true;
-        self.tick(2);
+        self.irq_poll_disable = Some(self.p.contains(Flags::INTERRUPT_DISABLE));
+        self.p.insert(Flags::INTERRUPT_DISABLE);
     }
 
     // CLV: Clear Overflow Flag
     fn op_clv(&mut self) {
-        self.overflow = false;
-        self.tick(2);
+        self.p.remove(Flags::OVERFLOW);
     }
 
     // CLD: Clear Decimal Mode Flag
     fn op_cld(&mut self) {
-        self.decimal = false;
-        self.tick(2);
+        self.p.remove(Flags::DECIMAL);
     }
 
     // SED: Set Decimal Mode Flag
     fn op_sed(&mut self) {
-        self.decimal = true;
-        self.tick(2);
+        self.p.insert(Flags::DECIMAL);
     }
 
     // ---- Other Instructions ----
     // BRK: Break
     fn op_brk(&mut self) {
-        // Push program counter to stack
-        self.push_word(self.pc);
+        let (caller, sp) = (self.pc.wrapping_sub(1), self.sp);
+        // Push program counter to stack, BRK skips the padding byte
+        // following the opcode
+        self.push_word(self.pc.wrapping_add(1));
 
         // Push status register to stack
         self.op_php();
 
         // Set interrupt disable flag to prevent further interrupts
-        self.interrupt_disable = true;
+        self.p.insert(Flags::INTERRUPT_DISABLE);
 
         // Load interrupt vector into program counter
-        self.pc = self.memory.read_word(0xFFFE);
-
-        self.tick(7);
+        self.pc = self.read_word(0xFFFE);
+        self.calls.enter(FrameKind::Brk, caller, self.pc, sp);
     }
 
     // NOP: No Operation
-    fn op_nop(&mut self) {
-        self.tick(2);
+    fn op_nop(&mut self) {}
+
+    // ---- Undocumented Instructions ----
+    // "Magic" constant ANE and LXA OR into the accumulator. It depends on
+    // the chip and temperature, $EE is the most commonly observed value.
+    const MAGIC: u8 = 0xee;
+
+    // ALR: AND immediate then LSR A
+    fn op_alr(&mut self, value: u8) {
+        self.a &= value;
+        self.op_lsr_a();
+    }
+
+    // ANC: AND immediate, C gets a copy of N
+    fn op_anc(&mut self, value: u8) {
+        self.op_and(value);
+        self.p.set(Flags::CARRY, self.p.contains(Flags::NEGATIVE));
+    }
+
+    // ANE: A = (A | magic) & X & immediate
+    fn op_ane(&mut self, value: u8) {
+        self.a = (self.a | Self::MAGIC) & self.x & value;
+        self.update_zero_negative_flags(self.a);
+    }
+
+    // ARR: AND immediate then ROR A, with C and V taken from bits 6 and 5
+    // of the result. In decimal mode the result gets a BCD fixup.
+    fn op_arr(&mut self, value: u8) {
+        let t = self.a & value;
+        let result = (t >> 1) | ((self.p.contains(Flags::CARRY) as u8) << 7);
+        if !self.p.contains(Flags::DECIMAL) {
+            self.a = result;
+            self.update_zero_negative_flags(self.a);
+            self.p.set(Flags::CARRY, (result & 0x40) != 0);
+            self.p
+                .set(Flags::OVERFLOW, ((result >> 6) ^ (result >> 5)) & 0x01 != 0);
+            return;
+        }
+
+        self.p.set(Flags::NEGATIVE, self.p.contains(Flags::CARRY));
+        self.p.set(Flags::ZERO, result == 0);
+        self.p.set(Flags::OVERFLOW, ((result ^ t) & 0x40) != 0);
+        let mut result = result;
+        if (t & 0x0f) + (t & 0x01) > 0x05 {
+            result = (result & 0xf0) | (result.wrapping_add(0x06) & 0x0f);
+        }
+        self.p
+            .set(Flags::CARRY, (t as u16 & 0xf0) + (t as u16 & 0x10) > 0x50);
+        if self.p.contains(Flags::CARRY) {
+            result = result.wrapping_add(0x60);
+        }
+        self.a = result;
+    }
+
+    // DCP: DEC memory then CMP
+    fn op_dcp(&mut self, addr: u16) {
+        let value = self.read_modify(addr).wrapping_sub(1);
+        self.bus.write(addr, value);
+        self.op_cmp(value);
+    }
+
+    // ISC: INC memory then SBC
+    fn op_isc(&mut self, addr: u16) {
+        let value = self.read_modify(addr).wrapping_add(1);
+        self.bus.write(addr, value);
+        self.op_sbc(value);
+    }
+
+    // LAS: A, X and SP = memory & SP
+    fn op_las(&mut self, value: u8) {
+        self.sp &= value;
+        self.a = self.sp;
+        self.x = self.sp;
+        self.update_zero_negative_flags(self.a);
+    }
+
+    // LAX: LDA and LDX at once
+    fn op_lax(&mut self, value: u8) {
+        self.a = value;
+        self.x = value;
+        self.update_zero_negative_flags(value);
+    }
+
+    // LXA: A = X = (A | magic) & immediate
+    fn op_lxa(&mut self, value: u8) {
+        self.op_lax((self.a | Self::MAGIC) & value);
+    }
+
+    // RLA: ROL memory then AND
+    fn op_rla(&mut self, addr: u16) {
+        let value = self.read_modify(addr);
+        let result = (value << 1) | self.p.contains(Flags::CARRY) as u8;
+        self.p.set(Flags::CARRY, (value & 0x80) != 0);
+        self.bus.write(addr, result);
+        self.op_and(result);
+    }
+
+    // RRA: ROR memory then ADC
+    fn op_rra(&mut self, addr: u16) {
+        let value = self.read_modify(addr);
+        let result = (value >> 1) | ((self.p.contains(Flags::CARRY) as u8) << 7);
+        self.p.set(Flags::CARRY, (value & 0x01) != 0);
+        self.bus.write(addr, result);
+        self.op_adc(result);
+    }
+
+    // SAX: store A & X
+    fn op_sax(&mut self, addr: u16) {
+        self.bus.write(addr, self.a & self.x);
+    }
+
+    // SBX: X = (A & X) - immediate, setting flags like CMP
+    fn op_sbx(&mut self, value: u8) {
+        let t = self.a & self.x;
+        self.p.set(Flags::CARRY, t >= value);
+        self.x = t.wrapping_sub(value);
+        self.update_zero_negative_flags(self.x);
+    }
+
+    // SHA, SHX, SHY and TAS store `value` ANDed with the high byte of the
+    // base address plus one. When indexing crosses a page the stored value
+    // also replaces the high byte of the target address.
+    fn op_sh(&mut self, addr: u16, index: u8, value: u8) {
+        let base = addr.wrapping_sub(index as u16);
+        let value = value & ((base >> 8) as u8).wrapping_add(1);
+        let addr = if (base & 0xff00) != (addr & 0xff00) {
+            ((value as u16) << 8) | (addr & 0x00ff)
+        } else {
+            addr
+        };
+        self.bus.write(addr, value);
+    }
+
+    // SLO: ASL memory then ORA
+    fn op_slo(&mut self, addr: u16) {
+        let value = self.read_modify(addr);
+        self.p.set(Flags::CARRY, (value & 0x80) != 0);
+        let result = value << 1;
+        self.bus.write(addr, result);
+        self.op_ora(result);
+    }
+
+    // SRE: LSR memory then EOR
+    fn op_sre(&mut self, addr: u16) {
+        let value = self.read_modify(addr);
+        self.p.set(Flags::CARRY, (value & 0x01) != 0);
+        let result = value >> 1;
+        self.bus.write(addr, result);
+        self.op_eor(result);
     }
 
     // Helper function to update the Zero and Negative flags
-    fn update_zero_negative_flags(&mut self, value: u8) {
-        self.zero = value == 0;
-        self.negative = (value & 0x80) != 0;
+    pub(crate) fn update_zero_negative_flags(&mut self, value: u8) {
+        self.p.set(Flags::ZERO, value == 0);
+        self.p.set(Flags::NEGATIVE, (value & 0x80) != 0);
+    }
+}
+
+impl<B: Bus> Processor for Cpu<B> {
+    fn reset(&mut self) {
+        Cpu::reset(self);
+    }
+
+    fn step(&mut self) -> bool {
+        Cpu::step(self)
+    }
+
+    fn cycles(&self) -> u32 {
+        Cpu::cycles(self)
+    }
+
+    fn registers(&self) -> Registers {
+        Registers {
+            pc: self.pc,
+            sp: self.sp,
+            a: self.a,
+            x: self.x,
+            y: self.y,
+            p: self.p,
+        }
+    }
+
+    fn set_registers(&mut self, registers: &Registers) {
+        self.pc = registers.pc;
+        self.sp = registers.sp;
+        self.a = registers.a;
+        self.x = registers.x;
+        self.y = registers.y;
+        self.p = Flags::from_stack(registers.p.bits());
+    }
+
+    fn set_irq(&mut self, source: IrqSource, active: bool) {
+        Cpu::set_irq(self, source, active);
+    }
+
+    fn set_nmi(&mut self, source: NmiSource, active: bool) {
+        Cpu::set_nmi(self, source, active);
+    }
+
+    fn read_memory(&mut self, addr: u16) -> u8 {
+        Cpu::read_memory(self, addr)
+    }
+
+    fn peek_memory(&self, addr: u16) -> u8 {
+        Cpu::peek_memory(self, addr)
+    }
+
+    fn write_memory(&mut self, addr: u16, value: u8) {
+        Cpu::write_memory(self, addr, value);
     }
 }
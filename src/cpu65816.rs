@@ -0,0 +1,400 @@
+use crate::bus::Bus;
+use crate::callstack::FrameKind;
+use crate::cpu::{Access, IrqSource, NmiSource, Processor, Registers};
+use crate::cpu65c02::Cpu65C02;
+use crate::flags::Flags;
+use crate::opcode::{AddrMode, Mnemonic, OPCODES_65816};
+
+/// WDC 65816 in emulation mode, the mode it resets to and runs 6502 code
+/// in. Registers are 8 bits wide and the stack stays in page one; the
+/// CMOS instructions run on the 65C02 core, this adds the relocatable
+/// direct page, the long addressing modes and the new instructions.
+///
+/// Native mode isn't emulated, an XCE that clears the E flag stops the
+/// CPU. The bus has no bank lines either: the bank registers and the bank
+/// byte of long addresses are kept, but every access goes to bank 0.
+pub struct Cpu65816<B: Bus> {
+    cmos: Cpu65C02<B>,
+    pub b: u8,    // Accumulator high byte, swapped in by XBA
+    pub d: u16,   // Direct page
+    pub dbr: u8,  // Data bank
+    pub pbr: u8,  // Program bank
+    native: bool, // XCE left emulation mode
+}
+
+// Modes taking their operand from the direct page, they cost a cycle more
+// when it doesn't start on a page boundary
+fn direct_mode(mode: AddrMode) -> bool {
+    matches!(
+        mode,
+        AddrMode::ZeroPage
+            | AddrMode::ZeroPageX
+            | AddrMode::ZeroPageY
+            | AddrMode::IndirectX
+            | AddrMode::IndirectY
+            | AddrMode::ZeroPageIndirect
+            | AddrMode::DirectIndirectLong
+            | AddrMode::DirectIndirectLongY
+    )
+}
+
+impl<B: Bus> Cpu65816<B> {
+    pub fn new(bus: B) -> Self {
+        Cpu65816 {
+            cmos: Cpu65C02::new(bus),
+            b: 0,
+            d: 0,
+            dbr: 0,
+            pbr: 0,
+            native: false,
+        }
+    }
+
+    /// Whether an XCE switched to native mode, which stops the CPU
+    pub fn native(&self) -> bool {
+        self.native
+    }
+
+    pub fn step(&mut self) -> bool {
+        if self.native {
+            return false;
+        }
+        if let Some(running) = self.cmos.halted() {
+            return running;
+        }
+        if self.cmos.poll_interrupts() {
+            // Handlers run in bank 0
+            self.pbr = 0;
+            return true;
+        }
+
+        let opcode = self.cmos.core.fetch_op();
+        let op = &OPCODES_65816[opcode as usize];
+        let (addr, page_crossed) = self.operand_addr(op.mode, Access::of(op.mnemonic));
+        self.cmos.core.tick(op.cycles as u32);
+        if page_crossed && op.page_penalty {
+            self.cmos.core.tick(1);
+        }
+        if self.d & 0xff != 0 && direct_mode(op.mode) {
+            self.cmos.core.tick(1);
+        }
+        self.execute(op.mnemonic, op.mode, addr);
+        true
+    }
+
+    // Like Cpu::operand_addr, with 24-bit addresses for the long modes
+    fn operand_addr(&mut self, mode: AddrMode, access: Access) -> (u32, bool) {
+        let (x, y) = (self.cmos.core.x, self.cmos.core.y);
+        match mode {
+            AddrMode::ZeroPage => {
+                let offset = self.cmos.core.fetch_op();
+                (self.direct(offset, 0) as u32, false)
+            }
+            AddrMode::ZeroPageX => {
+                let offset = self.cmos.core.fetch_op();
+                (self.direct(offset, x) as u32, false)
+            }
+            AddrMode::ZeroPageY => {
+                let offset = self.cmos.core.fetch_op();
+                (self.direct(offset, y) as u32, false)
+            }
+            AddrMode::IndirectX => {
+                let offset = self.cmos.core.fetch_op();
+                (self.direct_word(self.direct(offset, x)) as u32, false)
+            }
+            AddrMode::IndirectY => {
+                let offset = self.cmos.core.fetch_op();
+                let base = self.direct_word(self.direct(offset, 0));
+                let addr = base.wrapping_add(y as u16);
+                (addr as u32, (base & 0xff00) != (addr & 0xff00))
+            }
+            AddrMode::ZeroPageIndirect => {
+                let offset = self.cmos.core.fetch_op();
+                (self.direct_word(self.direct(offset, 0)) as u32, false)
+            }
+            AddrMode::StackRelative => {
+                let offset = self.cmos.core.fetch_op();
+                (self.stack(offset) as u32, false)
+            }
+            AddrMode::StackRelativeIndirectY => {
+                let offset = self.cmos.core.fetch_op();
+                let pointer = self.stack(offset);
+                let base = self.cmos.core.read_word(pointer);
+                (base.wrapping_add(y as u16) as u32, false)
+            }
+            AddrMode::DirectIndirectLong => {
+                let offset = self.cmos.core.fetch_op();
+                (self.read_long(self.direct(offset, 0)), false)
+            }
+            AddrMode::DirectIndirectLongY => {
+                let offset = self.cmos.core.fetch_op();
+                let base = self.read_long(self.direct(offset, 0));
+                ((base + y as u32) & 0xff_ffff, false)
+            }
+            AddrMode::AbsoluteLong => (self.fetch_long(), false),
+            AddrMode::AbsoluteLongX => ((self.fetch_long() + x as u32) & 0xff_ffff, false),
+            // Resolves to the pointer, JML reads the target from it
+            AddrMode::AbsoluteIndirectLong => (self.cmos.core.fetch_opw() as u32, false),
+            AddrMode::RelativeLong => {
+                let offset = self.cmos.core.fetch_opw();
+                (self.cmos.core.pc.wrapping_add(offset) as u32, false)
+            }
+            // Destination bank in the low byte, source bank in the high one
+            AddrMode::BlockMove => (self.cmos.core.fetch_opw() as u32, false),
+            _ => {
+                let (addr, page_crossed) = self.cmos.core.operand_addr(mode, access);
+                (addr as u32, page_crossed)
+            }
+        }
+    }
+
+    // Direct page address of `offset` plus `index`. With the direct page on
+    // a page boundary the sum wraps within the page like on the 6502,
+    // otherwise it carries into the high byte.
+    fn direct(&self, offset: u8, index: u8) -> u16 {
+        if self.d & 0xff == 0 {
+            self.d | offset.wrapping_add(index) as u16
+        } else {
+            self.d
+                .wrapping_add(offset as u16)
+                .wrapping_add(index as u16)
+        }
+    }
+
+    // Pointer in the direct page, wrapping like `direct`
+    fn direct_word(&mut self, addr: u16) -> u16 {
+        let next = if self.d & 0xff == 0 {
+            (addr & 0xff00) | (addr as u8).wrapping_add(1) as u16
+        } else {
+            addr.wrapping_add(1)
+        };
+        let lsb = self.cmos.core.read_memory(addr) as u16;
+        let msb = self.cmos.core.read_memory(next) as u16;
+        lsb | (msb << 8)
+    }
+
+    // Stack relative address, S is $01xx in emulation mode
+    fn stack(&self, offset: u8) -> u16 {
+        (0x0100 | self.cmos.core.sp as u16).wrapping_add(offset as u16)
+    }
+
+    fn read_long(&mut self, addr: u16) -> u32 {
+        let word = self.cmos.core.read_word(addr) as u32;
+        let bank = self.cmos.core.read_memory(addr.wrapping_add(2)) as u32;
+        word | (bank << 16)
+    }
+
+    fn fetch_long(&mut self) -> u32 {
+        let pc = self.cmos.core.pc;
+        self.cmos.core.pc = pc.wrapping_add(3);
+        self.read_long(pc)
+    }
+
+    // The 16-bit accumulator, B:A
+    fn c(&self) -> u16 {
+        (self.b as u16) << 8 | self.cmos.core.a as u16
+    }
+
+    fn set_c(&mut self, value: u16) {
+        self.cmos.core.a = value as u8;
+        self.b = (value >> 8) as u8;
+    }
+
+    fn update_zero_negative_flags16(&mut self, value: u16) {
+        let p = &mut self.cmos.core.p;
+        p.set(Flags::ZERO, value == 0);
+        p.set(Flags::NEGATIVE, value & 0x8000 != 0);
+    }
+
+    fn execute(&mut self, mnemonic: Mnemonic, mode: AddrMode, addr: u32) {
+        let core = &mut self.cmos.core;
+        match mnemonic {
+            // Decimal mode gets valid N and Z flags like on the 65C02, but
+            // without the extra cycle
+            Mnemonic::Adc if core.p.contains(Flags::DECIMAL) => {
+                let value = core.load_byte(addr as u16);
+                self.cmos.op_adc_decimal(value);
+            }
+            Mnemonic::Sbc if core.p.contains(Flags::DECIMAL) => {
+                let value = core.load_byte(addr as u16);
+                self.cmos.op_sbc_decimal(value);
+            }
+            Mnemonic::Brk => {
+                self.cmos.execute(mnemonic, mode, addr as u16);
+                self.pbr = 0;
+            }
+            // COP is a BRK with its own vector, only BRK pushes B set.
+            // The signature byte was fetched as an immediate operand.
+            Mnemonic::Cop => {
+                let (caller, sp) = (core.pc.wrapping_sub(2), core.sp);
+                core.push_word(core.pc);
+                core.push(core.p.to_stack(false));
+                core.p.insert(Flags::INTERRUPT_DISABLE);
+                core.p.remove(Flags::DECIMAL);
+                let target = core.read_word(0xfff4);
+                core.pc = target;
+                core.calls_mut().enter(FrameKind::Brk, caller, target, sp);
+                self.pbr = 0;
+            }
+            Mnemonic::Jsl => {
+                let (caller, sp) = (core.pc.wrapping_sub(4), core.sp);
+                core.push(self.pbr);
+                core.push_word(core.pc.wrapping_sub(1));
+                core.calls_mut()
+                    .enter(FrameKind::Call, caller, addr as u16, sp);
+                core.pc = addr as u16;
+                self.pbr = (addr >> 16) as u8;
+            }
+            Mnemonic::Rtl => {
+                let from = core.pc.wrapping_sub(1);
+                let to = core.pull_word().wrapping_add(1);
+                core.pc = to;
+                self.pbr = core.pop();
+                let sp = core.sp;
+                core.calls_mut().leave(from, to, sp);
+            }
+            Mnemonic::Jml => {
+                let target = match mode {
+                    AddrMode::AbsoluteIndirectLong => self.read_long(addr as u16),
+                    _ => addr,
+                };
+                self.cmos.core.pc = target as u16;
+                self.pbr = (target >> 16) as u8;
+            }
+            Mnemonic::Jsr if mode == AddrMode::AbsoluteIndexedIndirect => {
+                let (caller, sp) = (core.pc.wrapping_sub(3), core.sp);
+                let target = core.read_word(addr as u16);
+                core.push_word(core.pc.wrapping_sub(1));
+                core.calls_mut().enter(FrameKind::Call, caller, target, sp);
+                core.pc = target;
+            }
+            Mnemonic::Brl => core.pc = addr as u16,
+            // PEA pushes its operand, PEI the pointer in the direct page and
+            // PER the PC relative address, all resolved by operand_addr
+            Mnemonic::Pea | Mnemonic::Pei | Mnemonic::Per => core.push_word(addr as u16),
+            Mnemonic::Phb => core.push(self.dbr),
+            Mnemonic::Phk => core.push(self.pbr),
+            Mnemonic::Phd => core.push_word(self.d),
+            Mnemonic::Plb => {
+                self.dbr = core.pop();
+                core.update_zero_negative_flags(self.dbr);
+            }
+            Mnemonic::Pld => {
+                self.d = core.pull_word();
+                self.update_zero_negative_flags16(self.d);
+            }
+            // M and X are fixed in emulation mode, their bits are B and bit
+            // 5 of the pushed status there
+            Mnemonic::Rep => {
+                let value = core.load_byte(addr as u16);
+                let mask = Flags::from_bits(value & !(Flags::BREAK | Flags::UNUSED).bits());
+                core.p.remove(mask);
+            }
+            Mnemonic::Sep => {
+                let value = core.load_byte(addr as u16);
+                let mask = Flags::from_bits(value & !(Flags::BREAK | Flags::UNUSED).bits());
+                core.p.insert(mask);
+            }
+            Mnemonic::Tcd => {
+                self.d = self.c();
+                self.update_zero_negative_flags16(self.d);
+            }
+            Mnemonic::Tdc => {
+                self.set_c(self.d);
+                self.update_zero_negative_flags16(self.d);
+            }
+            // The stack pointer high byte stays $01
+            Mnemonic::Tcs => core.sp = core.a,
+            Mnemonic::Tsc => {
+                let s = 0x0100 | core.sp as u16;
+                self.set_c(s);
+                self.update_zero_negative_flags16(s);
+            }
+            Mnemonic::Txy => {
+                core.y = core.x;
+                core.update_zero_negative_flags(core.y);
+            }
+            Mnemonic::Tyx => {
+                core.x = core.y;
+                core.update_zero_negative_flags(core.x);
+            }
+            Mnemonic::Xba => {
+                std::mem::swap(&mut core.a, &mut self.b);
+                core.update_zero_negative_flags(core.a);
+            }
+            // Swaps C and E. E is always set here, clearing it would enter
+            // native mode.
+            Mnemonic::Xce => {
+                self.native = !core.p.contains(Flags::CARRY);
+                core.p.insert(Flags::CARRY);
+            }
+            // Moves one byte from X to Y per iteration and repeats itself
+            // until C wraps to $FFFF. The index registers are 8 bits wide.
+            Mnemonic::Mvn | Mnemonic::Mvp => {
+                self.dbr = addr as u8;
+                let value = core.read_memory(core.x as u16);
+                core.write_memory(core.y as u16, value);
+                if mnemonic == Mnemonic::Mvn {
+                    core.x = core.x.wrapping_add(1);
+                    core.y = core.y.wrapping_add(1);
+                } else {
+                    core.x = core.x.wrapping_sub(1);
+                    core.y = core.y.wrapping_sub(1);
+                }
+                let count = self.c().wrapping_sub(1);
+                self.set_c(count);
+                if count != 0xffff {
+                    self.cmos.core.pc = self.cmos.core.pc.wrapping_sub(3);
+                }
+            }
+            Mnemonic::Wdm => {}
+            _ => self.cmos.execute(mnemonic, mode, addr as u16),
+        }
+    }
+}
+
+impl<B: Bus> Processor for Cpu65816<B> {
+    fn reset(&mut self) {
+        self.cmos.reset();
+        self.d = 0;
+        self.dbr = 0;
+        self.pbr = 0;
+        self.native = false;
+    }
+
+    fn step(&mut self) -> bool {
+        Cpu65816::step(self)
+    }
+
+    fn cycles(&self) -> u32 {
+        self.cmos.cycles()
+    }
+
+    fn registers(&self) -> Registers {
+        self.cmos.registers()
+    }
+
+    fn set_registers(&mut self, registers: &Registers) {
+        self.cmos.set_registers(registers);
+    }
+
+    fn set_irq(&mut self, source: IrqSource, active: bool) {
+        self.cmos.set_irq(source, active);
+    }
+
+    fn set_nmi(&mut self, source: NmiSource, active: bool) {
+        self.cmos.set_nmi(source, active);
+    }
+
+    fn read_memory(&mut self, addr: u16) -> u8 {
+        self.cmos.read_memory(addr)
+    }
+
+    fn peek_memory(&self, addr: u16) -> u8 {
+        self.cmos.peek_memory(addr)
+    }
+
+    fn write_memory(&mut self, addr: u16, value: u8) {
+        self.cmos.write_memory(addr, value);
+    }
+}
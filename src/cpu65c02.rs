@@ -0,0 +1,245 @@
+use crate::bus::Bus;
+use crate::cpu::{Access, Cpu, IrqSource, NmiSource, Processor, Registers};
+use crate::flags::Flags;
+use crate::opcode::{AddrMode, Mnemonic, OPCODES_65C02};
+
+/// WDC 65C02. Instructions both CPUs have in common run on the NMOS core,
+/// on top of it this adds the CMOS opcodes, the fixed JMP indirect and the
+/// decimal mode flag fixes.
+pub struct Cpu65C02<B: Bus> {
+    pub(crate) core: Cpu<B>,
+    waiting: bool, // WAI executed, waiting for an interrupt
+    stopped: bool, // STP executed, only a reset restarts the CPU
+}
+
+impl<B: Bus> Cpu65C02<B> {
+    pub fn new(bus: B) -> Self {
+        Cpu65C02 {
+            core: Cpu::new(bus),
+            waiting: false,
+            stopped: false,
+        }
+    }
+
+    pub fn step(&mut self) -> bool {
+        if let Some(running) = self.halted() {
+            return running;
+        }
+        if self.poll_interrupts() {
+            return true;
+        }
+
+        let opcode = self.core.fetch_op();
+        let op = &OPCODES_65C02[opcode as usize];
+        let (addr, page_crossed) = self.core.operand_addr(op.mode, Access::of(op.mnemonic));
+        self.core.tick(op.cycles as u32);
+        if page_crossed && op.page_penalty {
+            self.core.tick(1);
+        }
+        self.execute(op.mnemonic, op.mode, addr);
+        true
+    }
+
+    // After STP or while WAI waits, whether the CPU is still running.
+    // None when it can execute the next instruction.
+    pub(crate) fn halted(&mut self) -> Option<bool> {
+        if self.stopped {
+            return Some(false);
+        }
+        if self.waiting {
+            // WAI resumes on an interrupt even when the I flag masks it
+            if !self.core.interrupt_pending() {
+                self.core.tick(1);
+                return Some(true);
+            }
+            self.waiting = false;
+        }
+        None
+    }
+
+    pub(crate) fn poll_interrupts(&mut self) -> bool {
+        if self.core.poll_interrupts() {
+            // Unlike the NMOS part the 65C02 leaves decimal mode on interrupts
+            self.core.p.remove(Flags::DECIMAL);
+            return true;
+        }
+        false
+    }
+
+    pub(crate) fn execute(&mut self, mnemonic: Mnemonic, mode: AddrMode, addr: u16) {
+        match mnemonic {
+            // Decimal mode takes an extra cycle to fix up N and Z
+            Mnemonic::Adc if self.core.p.contains(Flags::DECIMAL) => {
+                let value = self.core.load_byte(addr);
+                self.op_adc_decimal(value);
+                self.core.tick(1);
+            }
+            Mnemonic::Sbc if self.core.p.contains(Flags::DECIMAL) => {
+                let value = self.core.load_byte(addr);
+                self.op_sbc_decimal(value);
+                self.core.tick(1);
+            }
+            Mnemonic::Bit if mode == AddrMode::Immediate => {
+                // BIT #imm only affects Z
+                let value = self.core.load_byte(addr);
+                self.core.p.set(Flags::ZERO, (self.core.a & value) == 0);
+            }
+            Mnemonic::Brk => {
+                self.core.execute(mnemonic, mode, addr);
+                self.core.p.remove(Flags::DECIMAL);
+            }
+            // The CMOS part fetches the pointer without the page wrap bug
+            Mnemonic::Jmp if mode == AddrMode::Indirect => {
+                self.core.pc = self.core.read_word(addr);
+            }
+            Mnemonic::Jmp if mode == AddrMode::AbsoluteIndexedIndirect => {
+                self.core.pc = self.core.read_word(addr);
+            }
+            Mnemonic::Inc if mode == AddrMode::Accumulator => {
+                self.core.a = self.core.a.wrapping_add(1);
+                self.core.update_zero_negative_flags(self.core.a);
+            }
+            Mnemonic::Dec if mode == AddrMode::Accumulator => {
+                self.core.a = self.core.a.wrapping_sub(1);
+                self.core.update_zero_negative_flags(self.core.a);
+            }
+            Mnemonic::Bra => self.core.branch(true, addr),
+            Mnemonic::Bbr(bit) => {
+                let value = self.core.load_byte(addr);
+                let target = self.core.addr_rel();
+                self.core.branch((value & (1 << bit)) == 0, target);
+            }
+            Mnemonic::Bbs(bit) => {
+                let value = self.core.load_byte(addr);
+                let target = self.core.addr_rel();
+                self.core.branch((value & (1 << bit)) != 0, target);
+            }
+            Mnemonic::Rmb(bit) => {
+                let value = self.core.load_byte(addr);
+                self.core.write_memory(addr, value & !(1 << bit));
+            }
+            Mnemonic::Smb(bit) => {
+                let value = self.core.load_byte(addr);
+                self.core.write_memory(addr, value | (1 << bit));
+            }
+            Mnemonic::Phx => self.core.push(self.core.x),
+            Mnemonic::Phy => self.core.push(self.core.y),
+            Mnemonic::Plx => {
+                self.core.x = self.core.pop();
+                self.core.update_zero_negative_flags(self.core.x);
+            }
+            Mnemonic::Ply => {
+                self.core.y = self.core.pop();
+                self.core.update_zero_negative_flags(self.core.y);
+            }
+            Mnemonic::Stz => self.core.write_memory(addr, 0),
+            Mnemonic::Trb => {
+                let value = self.core.load_byte(addr);
+                self.core.p.set(Flags::ZERO, (self.core.a & value) == 0);
+                self.core.write_memory(addr, value & !self.core.a);
+            }
+            Mnemonic::Tsb => {
+                let value = self.core.load_byte(addr);
+                self.core.p.set(Flags::ZERO, (self.core.a & value) == 0);
+                self.core.write_memory(addr, value | self.core.a);
+            }
+            Mnemonic::Wai => self.waiting = true,
+            Mnemonic::Stp => self.stopped = true,
+            _ => self.core.execute(mnemonic, mode, addr),
+        }
+    }
+
+    // Decimal ADC on the 65C02 sets N and Z from the BCD result. V is
+    // computed like on the NMOS part.
+    pub(crate) fn op_adc_decimal(&mut self, value: u8) {
+        let a = self.core.a as u16;
+        let v = value as u16;
+
+        let mut lo = (a & 0x0f) + (v & 0x0f) + self.core.p.contains(Flags::CARRY) as u16;
+        if lo >= 0x0a {
+            lo = ((lo + 0x06) & 0x0f) + 0x10;
+        }
+        let mut temp = (a & 0xf0) + (v & 0xf0) + lo;
+
+        self.core.p.set(
+            Flags::OVERFLOW,
+            ((a ^ temp) & 0x80) != 0 && ((a ^ v) & 0x80) == 0,
+        );
+        if temp >= 0xa0 {
+            temp += 0x60;
+        }
+        self.core.p.set(Flags::CARRY, temp >= 0x100);
+        self.core.a = temp as u8;
+        self.core.update_zero_negative_flags(self.core.a);
+    }
+
+    // Decimal SBC on the 65C02, C and V come from the binary difference,
+    // N and Z from the BCD result
+    pub(crate) fn op_sbc_decimal(&mut self, value: u8) {
+        let a = self.core.a as i16;
+        let v = value as i16;
+        let borrow = !self.core.p.contains(Flags::CARRY) as i16;
+
+        let lo = (a & 0x0f) - (v & 0x0f) - borrow;
+        let binary = a - v - borrow;
+        let mut temp = binary;
+        if temp < 0 {
+            temp -= 0x60;
+        }
+        if lo < 0 {
+            temp -= 0x06;
+        }
+
+        self.core.p.set(Flags::CARRY, binary >= 0);
+        self.core.p.set(
+            Flags::OVERFLOW,
+            ((a ^ binary) & 0x80) != 0 && ((a ^ v) & 0x80) != 0,
+        );
+        self.core.a = temp as u8;
+        self.core.update_zero_negative_flags(self.core.a);
+    }
+}
+
+impl<B: Bus> Processor for Cpu65C02<B> {
+    fn reset(&mut self) {
+        self.waiting = false;
+        self.stopped = false;
+        self.core.reset();
+    }
+
+    fn step(&mut self) -> bool {
+        Cpu65C02::step(self)
+    }
+
+    fn cycles(&self) -> u32 {
+        self.core.cycles()
+    }
+
+    fn registers(&self) -> Registers {
+        self.core.registers()
+    }
+
+    fn set_registers(&mut self, registers: &Registers) {
+        self.core.set_registers(registers);
+    }
+
+    fn set_irq(&mut self, source: IrqSource, active: bool) {
+        self.core.set_irq(source, active);
+    }
+
+    fn set_nmi(&mut self, source: NmiSource, active: bool) {
+        self.core.set_nmi(source, active);
+    }
+
+    fn read_memory(&mut self, addr: u16) -> u8 {
+        self.core.read_memory(addr)
+    }
+
+    fn peek_memory(&self, addr: u16) -> u8 {
+        self.core.peek_memory(addr)
+    }
+
+    fn write_memory(&mut self, addr: u16, value: u8) {
+        self.core.write_memory(addr, value);
+    }
+}
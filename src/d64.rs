@@ -0,0 +1,125 @@
+// D64 disk images: a sector dump of a 35 track 1541 disk. The directory
+// starts at track 18, sector 1, every file is a chain of sectors whose first
+// two bytes link to the next track and sector.
+use std::fs;
+
+pub struct DirEntry {
+    pub name: String,
+    pub file_type: u8,
+    track: u8,
+    sector: u8,
+}
+
+impl DirEntry {
+    pub const TYPE_PRG: u8 = 2;
+}
+
+pub struct D64 {
+    data: Vec<u8>,
+}
+
+impl D64 {
+    pub const SECTOR_SIZE: usize = 256;
+    pub const IMAGE_SIZE: usize = 174848; // 683 sectors
+    const DIR_TRACK: u8 = 18;
+
+    pub fn open(path: &str) -> Result<Self, String> {
+        let data = fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+        D64::from_bytes(data)
+    }
+
+    pub fn from_bytes(data: Vec<u8>) -> Result<Self, String> {
+        // Images may carry a trailing error table, one byte per sector
+        if data.len() < Self::IMAGE_SIZE {
+            return Err(format!("not a D64 image ({} bytes)", data.len()));
+        }
+        Ok(D64 { data })
+    }
+
+    fn sectors_per_track(track: u8) -> usize {
+        match track {
+            1..=17 => 21,
+            18..=24 => 19,
+            25..=30 => 18,
+            _ => 17,
+        }
+    }
+
+    fn sector(&self, track: u8, sector: u8) -> Result<&[u8], String> {
+        if track == 0 || track > 35 || sector as usize >= Self::sectors_per_track(track) {
+            return Err(format!("invalid sector {}/{}", track, sector));
+        }
+        let index: usize = (1..track).map(Self::sectors_per_track).sum::<usize>() + sector as usize;
+        let offset = index * Self::SECTOR_SIZE;
+        Ok(&self.data[offset..offset + Self::SECTOR_SIZE])
+    }
+
+    // Follows a sector chain, calling `f` with the used part of each sector
+    fn walk(&self, track: u8, sector: u8, mut f: impl FnMut(&[u8])) -> Result<(), String> {
+        let (mut track, mut sector) = (track, sector);
+        // A disk has 683 sectors, any longer chain loops
+        for _ in 0..683 {
+            let data = self.sector(track, sector)?;
+            if data[0] == 0 {
+                // Last sector, the second byte is the index of the last
+                // used byte
+                let last = (data[1] as usize).max(1);
+                f(&data[2..=last]);
+                return Ok(());
+            }
+            f(&data[2..]);
+            track = data[0];
+            sector = data[1];
+        }
+        Err("sector chain loops".to_string())
+    }
+
+    /// Files in directory order, scratched entries left out
+    pub fn files(&self) -> Result<Vec<DirEntry>, String> {
+        let mut entries = Vec::new();
+        let mut sectors = Vec::new();
+        self.walk(Self::DIR_TRACK, 1, |data| sectors.push(data.to_vec()))?;
+        // Every sector but the last holds 8 entries of 32 bytes, the link
+        // bytes of the first entry are the sector link walk() strips off
+        for data in sectors {
+            for entry in data.chunks(32) {
+                if entry.len() < 30 || entry[0] & 0x07 == 0 {
+                    continue;
+                }
+                let name: String = entry[3..19]
+                    .iter()
+                    .take_while(|&&c| c != 0xa0)
+                    .map(|&c| petscii_to_ascii(c))
+                    .collect();
+                entries.push(DirEntry {
+                    name,
+                    file_type: entry[0] & 0x07,
+                    track: entry[1],
+                    sector: entry[2],
+                });
+            }
+        }
+        Ok(entries)
+    }
+
+    pub fn read_file(&self, entry: &DirEntry) -> Result<Vec<u8>, String> {
+        let mut contents = Vec::new();
+        self.walk(entry.track, entry.sector, |data| {
+            contents.extend_from_slice(data)
+        })?;
+        Ok(contents)
+    }
+}
+
+/// PETSCII as printed in the default upper case character set, characters
+/// without an ASCII equivalent turn into '?'
+pub fn petscii_to_ascii(c: u8) -> char {
+    match c {
+        0x0d => '\n',
+        0x20..=0x5a => c as char,
+        0x5b => '[',
+        0x5d => ']',
+        0xc1..=0xda => (c - 0x80) as char,
+        _ => '?',
+    }
+}
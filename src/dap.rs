@@ -0,0 +1,855 @@
+// Debug Adapter Protocol server, so editors can debug programs running on
+// the emulated machine. Messages are JSON preceded by a Content-Length
+// header, over stdio or a local TCP port.
+//
+// Launching an assembler source assembles it with the built-in assembler,
+// which gives the line information for breakpoints by line, and autostarts
+// the result. PRG and D64 files are autostarted as they are, the label or
+// debug files in the "symbols" launch argument name their addresses and,
+// for KickAssembler and ca65, give the lines. There is one
+// thread, the 6510, with a single frame whose variables are the registers.
+// Memory references are addresses, the exception breakpoints are traps.
+// Stepping back goes through the execution history of the target.
+//
+// https://microsoft.github.io/debug-adapter-protocol/specification
+use crate::asm::Assembler;
+use crate::breakpoint::{Condition, Hit, Kind, Trap};
+use crate::cpu::Processor;
+use crate::disasm::Instruction;
+use crate::flags::Flags;
+use crate::gdb::Session;
+use crate::sourcemap::SourceMap;
+use crate::symbols::Symbols;
+use crate::target::{Step, Target};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+const THREAD_ID: u32 = 1;
+const REGISTERS_REFERENCE: u32 = 1;
+
+// Instructions run between checks for a request from the editor
+const POLL_INTERVAL: u32 = 1000;
+
+// How far the machine runs by itself
+#[derive(Clone, Copy)]
+enum Run {
+    Free,
+    /// Until a subroutine called at `next - 3` returned
+    Over {
+        next: u16,
+        sp: u8,
+    },
+    /// Until the current subroutine returned
+    Out {
+        sp: u8,
+    },
+}
+
+// Why the machine stopped, for the stopped event
+enum Stop {
+    Step(Step),
+    Pause,
+    Entry,
+}
+
+// What the server does after responding to a request
+enum Next {
+    Wait,
+    Run(Run),
+    Stop(Stop),
+    Initialized,
+    Terminated,
+    End(Session),
+}
+
+pub struct DapServer<T: Target> {
+    target: T,
+    sources: SourceMap,
+    symbols: Symbols,
+    seq: u64,
+    line_breakpoints: HashMap<PathBuf, Vec<u32>>,
+    instruction_breakpoints: Vec<u32>,
+    stop_on_entry: bool,
+    terminated: bool,
+}
+
+impl<T: Target> DapServer<T> {
+    pub fn new(target: T) -> Self {
+        DapServer {
+            target,
+            sources: SourceMap::new(),
+            symbols: Symbols::rom(),
+            seq: 0,
+            line_breakpoints: HashMap::new(),
+            instruction_breakpoints: Vec::new(),
+            stop_on_entry: false,
+            terminated: false,
+        }
+    }
+
+    /// Names addresses from `symbols`, e.g. the --labels files. The launch
+    /// request adds its own on top.
+    pub fn set_symbols(&mut self, symbols: Symbols) {
+        self.symbols = symbols;
+    }
+
+    pub fn into_target(self) -> T {
+        self.target
+    }
+
+    /// Serves an editor that started the emulator to talk over stdio
+    pub fn serve_stdio(&mut self) -> Result<Session, String> {
+        self.serve(std::io::stdin(), std::io::stdout())
+    }
+
+    /// Waits for an editor to connect to `addr`, e.g. "127.0.0.1:6502",
+    /// and serves it
+    pub fn listen(&mut self, addr: &str) -> Result<Session, String> {
+        let listener = TcpListener::bind(addr).map_err(|e| format!("{}: {}", addr, e))?;
+        println!("Waiting for a debug adapter client on {}", addr);
+        let (stream, peer) = listener.accept().map_err(|e| format!("{}: {}", addr, e))?;
+        println!("Debug adapter client connected from {}", peer);
+        let input = stream
+            .try_clone()
+            .map_err(|e| format!("dap connection: {}", e))?;
+        self.serve(input, stream)
+    }
+
+    /// Serves requests until the editor disconnects. They are read on
+    /// their own thread, so they can come in while the machine runs.
+    pub fn serve<R: Read + Send + 'static>(
+        &mut self,
+        input: R,
+        mut output: impl Write,
+    ) -> Result<Session, String> {
+        let requests = spawn_reader(input);
+        let out: &mut dyn Write = &mut output;
+        let mut running = None;
+        loop {
+            let message = match running {
+                Some(run) => match requests.try_recv() {
+                    Ok(message) => message,
+                    Err(TryRecvError::Empty) => {
+                        if let Some(step) = self.run(run) {
+                            running = None;
+                            self.stopped(out, Stop::Step(step))?;
+                        }
+                        continue;
+                    }
+                    Err(TryRecvError::Disconnected) => return Ok(Session::Detached),
+                },
+                None => match requests.recv() {
+                    Ok(message) => message,
+                    Err(_) => return Ok(Session::Detached),
+                },
+            }?;
+            // Responses to requests of ours, there are none
+            if message["type"] != "request" {
+                continue;
+            }
+            let command = message["command"].as_str().unwrap_or_default();
+            let (result, next) =
+                match self.handle(command, &message["arguments"], running.is_some()) {
+                    Ok((body, next)) => (Ok(body), next),
+                    Err(e) => (Err(e), Next::Wait),
+                };
+            self.respond(out, &message, result)?;
+            match next {
+                Next::Wait => {}
+                Next::Run(run) => running = Some(run),
+                Next::Stop(stop) => {
+                    running = None;
+                    self.stopped(out, stop)?;
+                }
+                Next::Initialized => self.event(out, "initialized", Value::Null)?,
+                Next::Terminated => self.event(out, "terminated", Value::Null)?,
+                Next::End(session) => return Ok(session),
+            }
+        }
+    }
+
+    fn handle(
+        &mut self,
+        command: &str,
+        args: &Value,
+        running: bool,
+    ) -> Result<(Value, Next), String> {
+        let cpu = self.target.cpu();
+        Ok(match command {
+            "initialize" => (capabilities(), Next::Wait),
+            // Breakpoints by line need the line information from launching,
+            // so the editor is asked for them only afterwards
+            "launch" => {
+                let program = args["program"].as_str().ok_or("launch needs a program")?;
+                self.stop_on_entry = args["stopOnEntry"].as_bool().unwrap_or(false);
+                // One file or a list of them
+                let symbols = match &args["symbols"] {
+                    Value::Array(files) => files.iter().filter_map(Value::as_str).collect(),
+                    file => file.as_str().into_iter().collect(),
+                };
+                self.launch(Path::new(program), symbols)?;
+                (Value::Null, Next::Initialized)
+            }
+            "attach" => (Value::Null, Next::Initialized),
+            "setBreakpoints" => (self.set_breakpoints(args)?, Next::Wait),
+            "setInstructionBreakpoints" => (self.set_instruction_breakpoints(args)?, Next::Wait),
+            "setExceptionBreakpoints" => {
+                let filters: Vec<&str> = args["filters"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(Value::as_str)
+                    .collect();
+                let breakpoints = self.target.breakpoints();
+                for trap in Trap::ALL {
+                    breakpoints.set_trap(trap, filters.contains(&trap.to_string().as_str()));
+                }
+                let verified = vec![json!({ "verified": true }); filters.len()];
+                (json!({ "breakpoints": verified }), Next::Wait)
+            }
+            "configurationDone" => {
+                let next = if self.stop_on_entry {
+                    Next::Stop(Stop::Entry)
+                } else {
+                    Next::Run(Run::Free)
+                };
+                (Value::Null, next)
+            }
+            "threads" => (
+                json!({ "threads": [{ "id": THREAD_ID, "name": "6510" }] }),
+                Next::Wait,
+            ),
+            "stackTrace" => {
+                let cpu = cpu.borrow();
+                let frames: Vec<Value> = cpu
+                    .calls()
+                    .backtrace(cpu.pc)
+                    .iter()
+                    .enumerate()
+                    .map(|(id, (pc, frame))| {
+                        let name = match frame {
+                            Some(frame) => self.symbols.frame_name(frame),
+                            None => self.symbols.operand(*pc, false),
+                        };
+                        let mut frame = json!({
+                            "id": id,
+                            "name": name,
+                            "line": 0,
+                            "column": 0,
+                            "instructionPointerReference": reference(*pc),
+                        });
+                        if let Some((path, line)) = self.sources.line(*pc) {
+                            frame["source"] = source(path);
+                            frame["line"] = json!(line);
+                            frame["column"] = json!(1);
+                        }
+                        frame
+                    })
+                    .collect();
+                let total = frames.len();
+                (
+                    json!({ "stackFrames": frames, "totalFrames": total }),
+                    Next::Wait,
+                )
+            }
+            "scopes" => (
+                json!({ "scopes": [{
+                    "name": "Registers",
+                    "presentationHint": "registers",
+                    "variablesReference": REGISTERS_REFERENCE,
+                    "expensive": false,
+                }] }),
+                Next::Wait,
+            ),
+            "variables" => {
+                let variables = if args["variablesReference"] == REGISTERS_REFERENCE {
+                    self.registers()
+                } else {
+                    Vec::new()
+                };
+                (json!({ "variables": variables }), Next::Wait)
+            }
+            "setVariable" => {
+                let name = args["name"].as_str().unwrap_or_default();
+                let value = parse_number(args["value"].as_str().unwrap_or_default())?;
+                let mut registers = cpu.borrow().registers();
+                let max = if name == "PC" { 0xffff } else { 0xff };
+                if !(0..=max).contains(&value) {
+                    return Err(format!("{} can't hold {}", name, value));
+                }
+                match name {
+                    "A" => registers.a = value as u8,
+                    "X" => registers.x = value as u8,
+                    "Y" => registers.y = value as u8,
+                    "SP" => registers.sp = value as u8,
+                    "PC" => registers.pc = value as u16,
+                    "P" => registers.p = Flags::from_stack(value as u8),
+                    _ => return Err(format!("no register {}", name)),
+                }
+                cpu.borrow_mut().set_registers(&registers);
+                let variable = self
+                    .registers()
+                    .into_iter()
+                    .find(|v| v["name"] == name)
+                    .unwrap_or_default();
+                (json!({ "value": variable["value"] }), Next::Wait)
+            }
+            "evaluate" => {
+                let expression = args["expression"].as_str().unwrap_or_default();
+                let condition = Condition::parse(&self.symbols.resolve(expression)?)?;
+                let value = condition.eval(&cpu.borrow(), self.target.raster());
+                let mut body = json!({
+                    "result": format!("${:X} ({})", value, value),
+                    "variablesReference": 0,
+                });
+                if (0..=0xffff).contains(&value) {
+                    body["memoryReference"] = json!(reference(value as u16));
+                }
+                (body, Next::Wait)
+            }
+            "readMemory" => {
+                let addr = memory_address(args)?;
+                let count = args["count"].as_i64().unwrap_or(0).max(0);
+                // Only the 64K the CPU addresses are readable
+                let start = addr.clamp(0, 0x10000);
+                let end = (addr + count).clamp(start, 0x10000);
+                let cpu = cpu.borrow();
+                let data: Vec<u8> = (start..end)
+                    .map(|addr| cpu.peek_memory(addr as u16))
+                    .collect();
+                (
+                    json!({
+                        "address": format!("0x{:04X}", start),
+                        "data": base64_encode(&data),
+                        "unreadableBytes": count - data.len() as i64,
+                    }),
+                    Next::Wait,
+                )
+            }
+            "writeMemory" => {
+                let addr = memory_address(args)?;
+                let data = base64_decode(args["data"].as_str().unwrap_or_default())?;
+                if addr < 0 || addr + data.len() as i64 > 0x10000 {
+                    return Err("write outside of memory".to_string());
+                }
+                // Like the monitor, writes don't trigger watchpoints
+                let mut cpu = cpu.borrow_mut();
+                for (i, &value) in data.iter().enumerate() {
+                    cpu.bus.write_byte(addr as u16 + i as u16, value);
+                }
+                (json!({ "bytesWritten": data.len() }), Next::Wait)
+            }
+            "disassemble" => (self.disassemble(args)?, Next::Wait),
+            "continue" => (json!({ "allThreadsContinued": true }), Next::Run(Run::Free)),
+            "next" => {
+                let (opcode, next, sp) = {
+                    let cpu = cpu.borrow();
+                    (cpu.peek_memory(cpu.pc), cpu.pc.wrapping_add(3), cpu.sp)
+                };
+                let next = if opcode == 0x20 {
+                    Next::Run(Run::Over { next, sp })
+                } else {
+                    Next::Stop(Stop::Step(self.target.step_checked()))
+                };
+                (Value::Null, next)
+            }
+            "stepIn" => (
+                Value::Null,
+                Next::Stop(Stop::Step(self.target.step_checked())),
+            ),
+            "stepOut" => {
+                let sp = cpu.borrow().sp;
+                (Value::Null, Next::Run(Run::Out { sp }))
+            }
+            // Backwards through the execution history, reverseContinue
+            // stops at a breakpoint or where the history starts
+            "stepBack" => match self.target.step_back_checked() {
+                Some(step) => (Value::Null, Next::Stop(Stop::Step(step))),
+                None => return Err("no execution history to step back through".to_string()),
+            },
+            "reverseContinue" => {
+                let step = loop {
+                    match self.target.step_back_checked() {
+                        Some(Step::Done) => {}
+                        Some(step) => break step,
+                        None => break Step::Done,
+                    }
+                };
+                (Value::Null, Next::Stop(Stop::Step(step)))
+            }
+            "pause" if running => (Value::Null, Next::Stop(Stop::Pause)),
+            "pause" => (Value::Null, Next::Wait),
+            "terminate" => {
+                self.terminated = true;
+                (Value::Null, Next::Terminated)
+            }
+            "disconnect" => {
+                let session = if self.terminated || args["terminateDebuggee"] == true {
+                    Session::Killed
+                } else {
+                    Session::Detached
+                };
+                (Value::Null, Next::End(session))
+            }
+            _ => return Err(format!("unsupported request '{}'", command)),
+        })
+    }
+
+    // Assembler sources are assembled to a PRG next to them first
+    fn launch(&mut self, path: &Path, symbols: Vec<&str>) -> Result<(), String> {
+        let extension = path
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        let program = match extension.as_str() {
+            "prg" | "d64" => path.to_path_buf(),
+            _ => {
+                let source =
+                    fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+                let mut assembler = Assembler::new(0xc000);
+                assembler
+                    .assemble(&source)
+                    .map_err(|e| format!("{}: {}", path.display(), e))?;
+                let program = path.with_extension("prg");
+                fs::write(&program, assembler.prg()?)
+                    .map_err(|e| format!("{}: {}", program.display(), e))?;
+                self.sources = SourceMap::from_assembler(path, &assembler);
+                for (name, &addr) in assembler.labels() {
+                    self.symbols.add(name, addr);
+                }
+                program
+            }
+        };
+        for file in symbols {
+            self.symbols.load(Path::new(file))?;
+        }
+        if self.sources.is_empty() {
+            self.sources = self.symbols.lines().clone();
+        }
+        self.target.autostart(&program.to_string_lossy(), 0, true)
+    }
+
+    // Replaces the breakpoints of one source file
+    fn set_breakpoints(&mut self, args: &Value) -> Result<Value, String> {
+        let path = PathBuf::from(
+            args["source"]["path"]
+                .as_str()
+                .ok_or("breakpoints need a source path")?,
+        );
+        for id in self.line_breakpoints.remove(&path).unwrap_or_default() {
+            self.target.breakpoints().remove(id)?;
+        }
+        let mut ids = Vec::new();
+        let mut results = Vec::new();
+        for requested in args["breakpoints"].as_array().into_iter().flatten() {
+            let line = requested["line"].as_u64().unwrap_or_default() as usize;
+            let condition = match parse_condition(requested, &self.symbols) {
+                Ok(condition) => condition,
+                Err(e) => {
+                    results.push(json!({ "verified": false, "line": line, "message": e }));
+                    continue;
+                }
+            };
+            match self.sources.address(&path, line) {
+                Some((addr, line)) => {
+                    let id = self
+                        .target
+                        .breakpoints()
+                        .add(Kind::Exec, addr..=addr, condition);
+                    ids.push(id);
+                    results.push(json!({
+                        "id": id,
+                        "verified": true,
+                        "line": line,
+                        "instructionReference": reference(addr),
+                    }));
+                }
+                None => results.push(json!({
+                    "verified": false,
+                    "line": line,
+                    "message": "No code at or after this line",
+                })),
+            }
+        }
+        self.line_breakpoints.insert(path, ids);
+        self.target.sync_breakpoints();
+        Ok(json!({ "breakpoints": results }))
+    }
+
+    // Replaces all breakpoints set from the disassembly
+    fn set_instruction_breakpoints(&mut self, args: &Value) -> Result<Value, String> {
+        for id in std::mem::take(&mut self.instruction_breakpoints) {
+            self.target.breakpoints().remove(id)?;
+        }
+        let mut results = Vec::new();
+        for requested in args["breakpoints"].as_array().into_iter().flatten() {
+            let addr = parse_number(
+                requested["instructionReference"]
+                    .as_str()
+                    .unwrap_or_default(),
+            )
+            .map(|addr| addr + requested["offset"].as_i64().unwrap_or(0));
+            let condition = parse_condition(requested, &self.symbols);
+            match (addr, condition) {
+                (Ok(addr @ 0..=0xffff), Ok(condition)) => {
+                    let addr = addr as u16;
+                    let id = self
+                        .target
+                        .breakpoints()
+                        .add(Kind::Exec, addr..=addr, condition);
+                    self.instruction_breakpoints.push(id);
+                    results.push(json!({
+                        "id": id,
+                        "verified": true,
+                        "instructionReference": reference(addr),
+                    }));
+                }
+                (Ok(_), Ok(_)) => results.push(json!({
+                    "verified": false,
+                    "message": "Address outside of memory",
+                })),
+                (Err(e), _) | (_, Err(e)) => {
+                    results.push(json!({ "verified": false, "message": e }))
+                }
+            }
+        }
+        self.target.sync_breakpoints();
+        Ok(json!({ "breakpoints": results }))
+    }
+
+    fn disassemble(&mut self, args: &Value) -> Result<Value, String> {
+        let addr = memory_address(args)?.clamp(0, 0xffff) as u16;
+        let offset = args["instructionOffset"].as_i64().unwrap_or(0);
+        let count = args["instructionCount"].as_u64().unwrap_or(0);
+        let cpu = self.target.cpu();
+        let cpu = cpu.borrow();
+        let decode = |addr| Instruction::decode(addr, |a| cpu.peek_memory(a));
+
+        let mut pc = addr;
+        for _ in offset..0 {
+            pc = previous(pc, decode);
+        }
+        for _ in 0..offset {
+            pc = decode(pc).next();
+        }
+        let mut instructions = Vec::new();
+        for _ in 0..count {
+            let instruction = decode(pc);
+            let mut entry = json!({
+                "address": reference(pc),
+                "instructionBytes": instruction.hex(),
+                "instruction": instruction.text(|addr, zp| self.symbols.operand(addr, zp)),
+            });
+            if let Some(name) = self.symbols.name(pc) {
+                entry["symbol"] = json!(name);
+            }
+            if let Some((path, line)) = self.sources.line(pc) {
+                entry["location"] = source(path);
+                entry["line"] = json!(line);
+            }
+            instructions.push(entry);
+            pc = instruction.next();
+        }
+        Ok(json!({ "instructions": instructions }))
+    }
+
+    // Runs a while, returning how the machine stopped if it did
+    fn run(&mut self, run: Run) -> Option<Step> {
+        let cpu = self.target.cpu();
+        for _ in 0..POLL_INTERVAL {
+            let step = self.target.step_checked();
+            if step != Step::Done {
+                return Some(step);
+            }
+            let cpu = cpu.borrow();
+            let done = match run {
+                Run::Free => false,
+                Run::Over { next, sp } => cpu.pc == next && cpu.sp >= sp,
+                Run::Out { sp } => cpu.sp > sp,
+            };
+            if done {
+                return Some(step);
+            }
+        }
+        None
+    }
+
+    fn registers(&self) -> Vec<Value> {
+        let registers = self.target.cpu().borrow().registers();
+        let byte = |name: &str, value: u8| json!({ "name": name, "value": format!("${:02X}", value), "variablesReference": 0 });
+        vec![
+            byte("A", registers.a),
+            byte("X", registers.x),
+            byte("Y", registers.y),
+            json!({
+                "name": "SP",
+                "value": format!("${:02X}", registers.sp),
+                "variablesReference": 0,
+                "memoryReference": reference(0x0100 | registers.sp as u16),
+            }),
+            json!({
+                "name": "PC",
+                "value": format!("${:04X}", registers.pc),
+                "variablesReference": 0,
+                "memoryReference": reference(registers.pc),
+            }),
+            // In binary, in the order of NV-BDIZC
+            json!({
+                "name": "P",
+                "value": format!("%{:08b}", registers.p.bits()),
+                "type": "NV-BDIZC",
+                "variablesReference": 0,
+            }),
+        ]
+    }
+
+    fn stopped(&mut self, out: &mut dyn Write, stop: Stop) -> Result<(), String> {
+        let pc = self.target.cpu().borrow().pc;
+        let mut body = json!({ "threadId": THREAD_ID, "allThreadsStopped": true });
+        let (reason, description) = match stop {
+            Stop::Entry => ("entry", None),
+            Stop::Pause => ("pause", None),
+            Stop::Step(Step::Done) => ("step", None),
+            Stop::Step(Step::Hit(Hit::Break { id, .. })) => {
+                body["hitBreakpointIds"] = json!([id]);
+                if self.instruction_breakpoints.contains(&id) {
+                    ("instruction breakpoint", None)
+                } else {
+                    ("breakpoint", None)
+                }
+            }
+            Stop::Step(Step::Hit(hit @ Hit::Watch { .. })) => {
+                ("data breakpoint", Some(hit.to_string()))
+            }
+            Stop::Step(Step::Hit(hit)) => ("exception", Some(hit.to_string())),
+            Stop::Step(Step::Halted) => {
+                ("exception", Some(format!("Machine stopped at ${:04X}", pc)))
+            }
+        };
+        body["reason"] = json!(reason);
+        if let Some(description) = description {
+            body["description"] = json!(description);
+            body["text"] = json!(description);
+        }
+        self.event(out, "stopped", body)
+    }
+
+    fn respond(
+        &mut self,
+        out: &mut dyn Write,
+        request: &Value,
+        result: Result<Value, String>,
+    ) -> Result<(), String> {
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": result.is_ok(),
+        });
+        match result {
+            Ok(Value::Null) => {}
+            Ok(body) => response["body"] = body,
+            Err(message) => response["message"] = json!(message),
+        }
+        self.send(out, response)
+    }
+
+    fn event(&mut self, out: &mut dyn Write, event: &str, body: Value) -> Result<(), String> {
+        let mut message = json!({ "type": "event", "event": event });
+        if !body.is_null() {
+            message["body"] = body;
+        }
+        self.send(out, message)
+    }
+
+    fn send(&mut self, out: &mut dyn Write, mut message: Value) -> Result<(), String> {
+        self.seq += 1;
+        message["seq"] = json!(self.seq);
+        let body = message.to_string();
+        write!(out, "Content-Length: {}\r\n\r\n{}", body.len(), body)
+            .and_then(|_| out.flush())
+            .map_err(|e| format!("dap connection: {}", e))
+    }
+}
+
+fn capabilities() -> Value {
+    let filters: Vec<Value> = Trap::ALL
+        .iter()
+        .map(|trap| {
+            let label = match trap {
+                Trap::Irq => "IRQ",
+                Trap::Nmi => "NMI",
+                Trap::Brk => "BRK",
+                Trap::Illegal => "Illegal opcode",
+                Trap::Jam => "JAM",
+                Trap::Banking => "Banking change",
+                Trap::Stack => "Call stack imbalance",
+            };
+            json!({ "filter": trap.to_string(), "label": label, "default": false })
+        })
+        .collect();
+    json!({
+        "supportsConfigurationDoneRequest": true,
+        "supportsConditionalBreakpoints": true,
+        "supportsEvaluateForHovers": true,
+        "supportsSetVariable": true,
+        "supportsReadMemoryRequest": true,
+        "supportsWriteMemoryRequest": true,
+        "supportsDisassembleRequest": true,
+        "supportsInstructionBreakpoints": true,
+        "supportsStepBack": true,
+        "supportsTerminateRequest": true,
+        "exceptionBreakpointFilters": filters,
+    })
+}
+
+// Reads requests on their own thread, the channel closes with the input
+fn spawn_reader<R: Read + Send + 'static>(input: R) -> Receiver<Result<Value, String>> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut reader = BufReader::new(input);
+        loop {
+            let message = read_message(&mut reader);
+            let last = !matches!(message, Ok(Some(_)));
+            if let Some(message) = message.transpose() {
+                if sender.send(message).is_err() {
+                    break;
+                }
+            }
+            if last {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+// Reads one message, None at the end of the input
+fn read_message(reader: &mut impl BufRead) -> Result<Option<Value>, String> {
+    let mut len = None;
+    loop {
+        let mut line = String::new();
+        let read = reader
+            .read_line(&mut line)
+            .map_err(|e| format!("dap connection: {}", e))?;
+        if read == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() && len.is_some() {
+            break;
+        }
+        if let Some(value) = line.strip_prefix("Content-Length:") {
+            len = Some(
+                value
+                    .trim()
+                    .parse::<usize>()
+                    .map_err(|_| format!("bad Content-Length: {}", value.trim()))?,
+            );
+        }
+    }
+    let mut body = vec![0; len.unwrap_or_default()];
+    reader
+        .read_exact(&mut body)
+        .map_err(|e| format!("dap connection: {}", e))?;
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(|e| format!("bad dap message: {}", e))
+}
+
+fn reference(addr: u16) -> String {
+    format!("0x{:04X}", addr)
+}
+
+fn source(path: &Path) -> Value {
+    json!({
+        "name": path.file_name().map(|n| n.to_string_lossy()),
+        "path": path,
+    })
+}
+
+// Address of a memory reference plus its offset, may lie outside of memory
+fn memory_address(args: &Value) -> Result<i64, String> {
+    let reference = args["memoryReference"]
+        .as_str()
+        .ok_or("missing memory reference")?;
+    Ok(parse_number(reference)? + args["offset"].as_i64().unwrap_or(0))
+}
+
+// Conditions may use labels, ".name"
+fn parse_condition(breakpoint: &Value, symbols: &Symbols) -> Result<Option<Condition>, String> {
+    breakpoint["condition"]
+        .as_str()
+        .filter(|c| !c.trim().is_empty())
+        .map(|c| Condition::parse(&symbols.resolve(c)?))
+        .transpose()
+}
+
+// Editors send decimal numbers, "0x" or '$' marks hex and '%' binary
+fn parse_number(text: &str) -> Result<i64, String> {
+    let text = text.trim();
+    let parsed = if let Some(hex) = text.strip_prefix("0x").or(text.strip_prefix('$')) {
+        i64::from_str_radix(hex, 16)
+    } else if let Some(binary) = text.strip_prefix('%') {
+        i64::from_str_radix(binary, 2)
+    } else {
+        text.parse()
+    };
+    parsed.map_err(|_| format!("bad number: {}", text))
+}
+
+// Start of the instruction before `addr`. Code can't be decoded backwards
+// reliably, this takes the longest instruction that ends right there.
+fn previous(addr: u16, decode: impl Fn(u16) -> Instruction) -> u16 {
+    for len in [3, 2] {
+        let start = addr.wrapping_sub(len);
+        if decode(start).size() as u16 == len {
+            return start;
+        }
+    }
+    addr.wrapping_sub(1)
+}
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64_encode(data: &[u8]) -> String {
+    let mut text = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bits = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |bits, (i, &b)| bits | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                text.push(BASE64[(bits >> (18 - 6 * i)) as usize & 0x3f] as char);
+            } else {
+                text.push('=');
+            }
+        }
+    }
+    text
+}
+
+fn base64_decode(text: &str) -> Result<Vec<u8>, String> {
+    let mut data = Vec::with_capacity(text.len() / 4 * 3);
+    let (mut bits, mut count) = (0u32, 0);
+    for c in text.bytes().filter(|&c| c != b'=') {
+        let value = BASE64
+            .iter()
+            .position(|&b| b == c)
+            .ok_or(format!("bad base64 data: {}", text))?;
+        bits = bits << 6 | value as u32;
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            data.push((bits >> count) as u8);
+        }
+    }
+    Ok(data)
+}
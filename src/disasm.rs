@@ -0,0 +1,188 @@
+// Disassembler built on the opcode metadata tables. Memory is read through
+// Bus::peek, so disassembling never has side effects on I/O registers.
+// Undocumented opcodes are marked with a '*' in front of the mnemonic.
+use crate::bus::Bus;
+use crate::opcode::{AddrMode, Opcode, OPCODES};
+use std::fmt;
+
+/// One decoded instruction
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Instruction {
+    pub addr: u16,
+    pub opcode: u8,
+    pub operand: u32, // Operand bytes, little endian
+    pub op: &'static Opcode,
+}
+
+impl Instruction {
+    /// Decodes the NMOS instruction at `addr`, `peek` reads memory
+    pub fn decode(addr: u16, peek: impl Fn(u16) -> u8) -> Self {
+        Self::decode_with(&OPCODES, addr, peek)
+    }
+
+    /// Decodes with another opcode table, such as the 65C02 one
+    pub fn decode_with(table: &'static [Opcode; 256], addr: u16, peek: impl Fn(u16) -> u8) -> Self {
+        let opcode = peek(addr);
+        let op = &table[opcode as usize];
+        let mut operand = 0;
+        for i in 0..op.mode.operand_len() as u16 {
+            operand |= (peek(addr.wrapping_add(1 + i)) as u32) << (8 * i);
+        }
+        Instruction {
+            addr,
+            opcode,
+            operand,
+            op,
+        }
+    }
+
+    pub fn size(&self) -> u8 {
+        self.op.size()
+    }
+
+    /// Address of the following instruction
+    pub fn next(&self) -> u16 {
+        self.addr.wrapping_add(self.size() as u16)
+    }
+
+    /// Opcode and operand bytes
+    pub fn bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![self.opcode];
+        for i in 0..self.op.mode.operand_len() {
+            bytes.push((self.operand >> (8 * i)) as u8);
+        }
+        bytes
+    }
+
+    /// Opcode and operand bytes as hex, "A9 00"
+    pub fn hex(&self) -> String {
+        let bytes: Vec<String> = self.bytes().iter().map(|b| format!("{:02X}", b)).collect();
+        bytes.join(" ")
+    }
+
+    /// Target of a branch, the operand is relative to the next instruction
+    pub fn branch_target(&self) -> Option<u16> {
+        let offset = match self.op.mode {
+            AddrMode::Relative => self.operand as u8 as i8 as u16,
+            AddrMode::ZeroPageRelative => (self.operand >> 8) as u8 as i8 as u16,
+            AddrMode::RelativeLong => self.operand as u16,
+            _ => return None,
+        };
+        Some(self.next().wrapping_add(offset))
+    }
+
+    /// Address the operand refers to, for operands naming one directly.
+    /// Used to print labels in place of addresses.
+    pub fn operand_addr(&self) -> Option<u16> {
+        match self.op.mode {
+            AddrMode::Implied
+            | AddrMode::Accumulator
+            | AddrMode::Immediate
+            | AddrMode::StackRelative
+            | AddrMode::StackRelativeIndirectY
+            | AddrMode::BlockMove => None,
+            AddrMode::Relative | AddrMode::RelativeLong => self.branch_target(),
+            AddrMode::ZeroPageRelative => Some((self.operand & 0xff) as u16),
+            _ => Some(self.operand as u16),
+        }
+    }
+
+    /// Operand in assembler syntax, `name` can replace addresses with labels
+    pub fn operand_text(&self, name: impl Fn(u16, bool) -> String) -> String {
+        let zp = || name((self.operand & 0xff) as u16, true);
+        let abs = || name(self.operand as u16, false);
+        match self.op.mode {
+            AddrMode::Implied => String::new(),
+            AddrMode::Accumulator => "A".to_string(),
+            AddrMode::Immediate => format!("#${:02X}", self.operand),
+            AddrMode::ZeroPage => zp(),
+            AddrMode::ZeroPageX => format!("{},X", zp()),
+            AddrMode::ZeroPageY => format!("{},Y", zp()),
+            AddrMode::Relative => name(self.branch_target().unwrap_or_default(), false),
+            AddrMode::Absolute => abs(),
+            AddrMode::AbsoluteX => format!("{},X", abs()),
+            AddrMode::AbsoluteY => format!("{},Y", abs()),
+            AddrMode::Indirect => format!("({})", abs()),
+            AddrMode::IndirectX => format!("({},X)", zp()),
+            AddrMode::IndirectY => format!("({}),Y", zp()),
+            AddrMode::ZeroPageIndirect => format!("({})", zp()),
+            AddrMode::AbsoluteIndexedIndirect => format!("({},X)", abs()),
+            AddrMode::ZeroPageRelative => format!(
+                "{},{}",
+                zp(),
+                name(self.branch_target().unwrap_or_default(), false)
+            ),
+            AddrMode::StackRelative => format!("${:02X},S", self.operand),
+            AddrMode::StackRelativeIndirectY => format!("(${:02X},S),Y", self.operand),
+            AddrMode::DirectIndirectLong => format!("[{}]", zp()),
+            AddrMode::DirectIndirectLongY => format!("[{}],Y", zp()),
+            AddrMode::AbsoluteLong => format!("${:06X}", self.operand),
+            AddrMode::AbsoluteLongX => format!("${:06X},X", self.operand),
+            AddrMode::AbsoluteIndirectLong => format!("[{}]", abs()),
+            AddrMode::RelativeLong => name(self.branch_target().unwrap_or_default(), false),
+            // MVN and MVP take the source bank first, it's the second byte
+            AddrMode::BlockMove => {
+                format!("${:02X},${:02X}", self.operand >> 8, self.operand & 0xff)
+            }
+        }
+    }
+}
+
+/// "$12" for zero page operands, "$1234" otherwise
+pub fn hex_addr(addr: u16, zero_page: bool) -> String {
+    if zero_page {
+        format!("${:02X}", addr)
+    } else {
+        format!("${:04X}", addr)
+    }
+}
+
+impl Instruction {
+    /// Mnemonic and operand, `name` as for operand_text
+    pub fn text(&self, name: impl Fn(u16, bool) -> String) -> String {
+        let operand = self.operand_text(name);
+        let mark = if self.op.illegal { "*" } else { "" };
+        if operand.is_empty() {
+            format!("{}{}", mark, self.op.mnemonic)
+        } else {
+            format!("{}{} {}", mark, self.op.mnemonic, operand)
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.text(hex_addr))
+    }
+}
+
+/// Decodes the instructions from `start` up to and including `end`
+pub fn disassemble<B: Bus>(bus: &B, start: u16, end: u16) -> Vec<Instruction> {
+    let mut instructions = Vec::new();
+    let mut addr = start;
+    while addr <= end {
+        let instruction = Instruction::decode(addr, |a| bus.peek(a));
+        instructions.push(instruction);
+        // Stop rather than wrap around at the top of memory
+        match addr.checked_add(instruction.size() as u16) {
+            Some(next) => addr = next,
+            None => break,
+        }
+    }
+    instructions
+}
+
+/// "C000  A9 05     LDA #$05"
+pub fn listing_line(instruction: &Instruction) -> String {
+    listing_line_with(instruction, hex_addr)
+}
+
+/// Listing line with operands named by `name`, "C000  20 D2 FF  JSR CHROUT"
+pub fn listing_line_with(instruction: &Instruction, name: impl Fn(u16, bool) -> String) -> String {
+    format!(
+        "{:04X}  {:<8}  {}",
+        instruction.addr,
+        instruction.hex(),
+        instruction.text(name)
+    )
+}
@@ -0,0 +1,76 @@
+// Pictures of the screen for debuggers. The VIC doesn't draw yet, so the
+// picture is rendered from memory the way it would show standard character
+// mode, the mode BASIC and the KERNAL use. Other modes come out as if they
+// were characters.
+use crate::memory::Memory;
+
+// Visible PAL area and where the 320x200 display window sits in it
+const WIDTH: u16 = 384;
+const HEIGHT: u16 = 272;
+const INNER_X: u16 = 32;
+const INNER_Y: u16 = 35;
+const INNER_WIDTH: u16 = 320;
+const INNER_HEIGHT: u16 = 200;
+
+const COLUMNS: u16 = 40;
+const ROWS: u16 = 25;
+
+/// One byte per pixel, holding a colour from 0 to 15
+pub struct Frame {
+    pub width: u16,
+    pub height: u16,
+    pub inner_x: u16,
+    pub inner_y: u16,
+    pub inner_width: u16,
+    pub inner_height: u16,
+    pub pixels: Vec<u8>,
+}
+
+impl Frame {
+    /// Renders the screen from memory, with the VIC and CIA 2 registers
+    /// peeked so nothing changes
+    pub fn capture(memory: &Memory) -> Self {
+        let border = memory.peek_byte(0xd020) & 0x0f;
+        let mut frame = Frame {
+            width: WIDTH,
+            height: HEIGHT,
+            inner_x: INNER_X,
+            inner_y: INNER_Y,
+            inner_width: INNER_WIDTH,
+            inner_height: INNER_HEIGHT,
+            pixels: vec![border; WIDTH as usize * HEIGHT as usize],
+        };
+        // DEN off blanks the screen to the border colour
+        if memory.peek_byte(0xd011) & 0x10 == 0 {
+            return frame;
+        }
+
+        // CIA 2 port A selects the bank with inverted bits
+        let bank = 3 - (memory.peek_byte(0xdd00) & 0x03);
+        let layout = memory.peek_byte(0xd018) as u16;
+        let screen = (layout >> 4) * 0x0400;
+        let chars = ((layout >> 1) & 0x07) * 0x0800;
+        let background = memory.peek_byte(0xd021) & 0x0f;
+
+        for row in 0..ROWS {
+            for column in 0..COLUMNS {
+                let cell = row * COLUMNS + column;
+                let code = memory.vic_read(bank, screen + cell) as u16;
+                let color = memory.read_byte_no_io(Memory::ADDR_COLOR_RAM + cell) & 0x0f;
+                for line in 0..8 {
+                    let bits = memory.vic_read(bank, chars + code * 8 + line);
+                    let y = (INNER_Y + row * 8 + line) as usize;
+                    let x = (INNER_X + column * 8) as usize;
+                    for bit in 0..8 {
+                        frame.pixels[y * WIDTH as usize + x + bit] = if bits & (0x80 >> bit) != 0 {
+                            color
+                        } else {
+                            background
+                        };
+                    }
+                }
+            }
+        }
+        frame
+    }
+}
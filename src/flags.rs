@@ -0,0 +1,211 @@
+// Processor status register (P). Bit 5 and the B flag have no storage in
+// the CPU, they only exist in the copy of P pushed to the stack: bit 5 is
+// always 1, B tells a BRK or PHP apart from a hardware interrupt.
+use std::fmt;
+use std::ops::{BitAnd, BitOr, Not};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Flags(u8);
+
+impl Flags {
+    pub const CARRY: Flags = Flags(1 << 0);
+    pub const ZERO: Flags = Flags(1 << 1);
+    pub const INTERRUPT_DISABLE: Flags = Flags(1 << 2);
+    pub const DECIMAL: Flags = Flags(1 << 3);
+    pub const BREAK: Flags = Flags(1 << 4);
+    pub const UNUSED: Flags = Flags(1 << 5);
+    pub const OVERFLOW: Flags = Flags(1 << 6);
+    pub const NEGATIVE: Flags = Flags(1 << 7);
+
+    pub const fn empty() -> Self {
+        Flags(0)
+    }
+
+    pub const fn from_bits(bits: u8) -> Self {
+        Flags(bits)
+    }
+
+    pub const fn bits(self) -> u8 {
+        self.0
+    }
+
+    pub const fn contains(self, other: Flags) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn insert(&mut self, other: Flags) {
+        self.0 |= other.0;
+    }
+
+    pub fn remove(&mut self, other: Flags) {
+        self.0 &= !other.0;
+    }
+
+    pub fn set(&mut self, other: Flags, value: bool) {
+        if value {
+            self.insert(other);
+        } else {
+            self.remove(other);
+        }
+    }
+
+    /// The register as it is after reset or as read back by PLP and RTI:
+    /// bit 5 set and B clear
+    pub const fn from_stack(value: u8) -> Self {
+        Flags((value | Self::UNUSED.0) & !Self::BREAK.0)
+    }
+
+    /// Byte pushed to the stack, B is set by PHP and BRK and clear when an
+    /// IRQ or NMI is serviced
+    pub const fn to_stack(self, brk: bool) -> u8 {
+        let value = self.0 | Self::UNUSED.0;
+        if brk {
+            value | Self::BREAK.0
+        } else {
+            value & !Self::BREAK.0
+        }
+    }
+}
+
+impl Default for Flags {
+    fn default() -> Self {
+        Flags::from_stack(Self::INTERRUPT_DISABLE.0)
+    }
+}
+
+impl BitOr for Flags {
+    type Output = Flags;
+
+    fn bitor(self, rhs: Flags) -> Flags {
+        Flags(self.0 | rhs.0)
+    }
+}
+
+impl BitAnd for Flags {
+    type Output = Flags;
+
+    fn bitand(self, rhs: Flags) -> Flags {
+        Flags(self.0 & rhs.0)
+    }
+}
+
+impl Not for Flags {
+    type Output = Flags;
+
+    fn not(self) -> Flags {
+        Flags(!self.0)
+    }
+}
+
+impl From<Flags> for u8 {
+    fn from(flags: Flags) -> u8 {
+        flags.0
+    }
+}
+
+impl From<u8> for Flags {
+    fn from(bits: u8) -> Flags {
+        Flags(bits)
+    }
+}
+
+/// "NV-BDIZC" with a '.' for each clear flag
+impl fmt::Display for Flags {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, name) in "NV-BDIZC".chars().enumerate() {
+            let set = self.0 & (0x80 >> i) != 0;
+            write!(f, "{}", if set { name } else { '.' })?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::{Bus, FlatRam};
+    use crate::cpu::{Cpu, IrqSource, NmiSource};
+
+    // A CPU at $0200 running `code`, with the stack empty and the
+    // interrupt vectors pointing at a NOP
+    fn cpu(code: &[u8]) -> Cpu<FlatRam> {
+        let mut ram = FlatRam::new();
+        ram.load(0x0200, code);
+        ram.load(0x0300, &[0xea]);
+        ram.load(0xfffa, &[0x00, 0x03, 0x00, 0x03, 0x00, 0x03]);
+        let mut cpu = Cpu::new(ram);
+        cpu.pc = 0x0200;
+        cpu.p = Flags::from_stack(0);
+        cpu
+    }
+
+    fn pushed(cpu: &Cpu<FlatRam>) -> u8 {
+        cpu.bus.peek(0x0100 + cpu.sp.wrapping_add(1) as u16)
+    }
+
+    #[test]
+    fn php_pushes_b_set() {
+        let mut cpu = cpu(&[0x08]);
+        cpu.step();
+        assert_eq!(pushed(&cpu), 0x30);
+    }
+
+    #[test]
+    fn brk_pushes_b_set() {
+        let mut cpu = cpu(&[0x00, 0xea]);
+        cpu.step();
+        assert_eq!(pushed(&cpu), 0x30);
+        assert_eq!(cpu.pc, 0x0300);
+    }
+
+    #[test]
+    fn irq_pushes_b_clear() {
+        let mut cpu = cpu(&[0xea, 0xea, 0xea]);
+        cpu.set_irq(IrqSource::Cia1, true);
+        cpu.step();
+        cpu.step();
+        assert_eq!(cpu.pc, 0x0300);
+        assert_eq!(pushed(&cpu), 0x20);
+    }
+
+    #[test]
+    fn nmi_pushes_b_clear() {
+        let mut cpu = cpu(&[0xea, 0xea, 0xea]);
+        cpu.p.insert(Flags::INTERRUPT_DISABLE);
+        cpu.set_nmi(NmiSource::Restore, true);
+        cpu.step();
+        cpu.step();
+        assert_eq!(cpu.pc, 0x0300);
+        assert_eq!(pushed(&cpu), 0x24);
+    }
+
+    #[test]
+    fn bit_5_always_reads_1() {
+        assert_eq!(Flags::default().bits(), 0x24);
+        assert_eq!(Flags::from_stack(0x00).bits(), 0x20);
+        // PLP of $00 drops B and keeps bit 5, PHP pushes it back with B
+        let mut cpu = cpu(&[0xa9, 0x00, 0x48, 0x28, 0x08]);
+        for _ in 0..3 {
+            cpu.step();
+        }
+        assert_eq!(cpu.p.bits(), 0x20);
+        cpu.step();
+        assert_eq!(pushed(&cpu), 0x30);
+        // and PLP of $FF drops B
+        assert_eq!(Flags::from_stack(0xff).bits(), 0xef);
+    }
+
+    #[test]
+    fn to_stack_sets_bit_5_and_b_on_request() {
+        let flags = Flags::from_bits(Flags::CARRY.bits());
+        assert_eq!(flags.to_stack(true), 0x31);
+        assert_eq!(flags.to_stack(false), 0x21);
+        assert_eq!(Flags::from_bits(0xff).to_stack(false), 0xef);
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(Flags::from_bits(0xa5).to_string(), "N.-..I.C");
+        assert_eq!(Flags::from_bits(0xff).to_string(), "NV-BDIZC");
+    }
+}
@@ -0,0 +1,464 @@
+// GDB remote serial protocol server, so GDB and other frontends speaking
+// the protocol can debug the emulated machine over a local TCP port.
+//
+// GDB has no 6502 architecture, so the registers are described in a target
+// description: A, X, Y, P and SP are 8 bits wide, PC 16 bits, sent in this
+// order as little endian hex. Z0/Z1 set execution breakpoints and Z2-Z4
+// watchpoints, sharing the breakpoints of the internal debugger. bs and
+// bc go backwards through the execution history, for reverse-stepi and
+// reverse-continue.
+//
+// https://sourceware.org/gdb/current/onlinedocs/gdb.html/Remote-Protocol.html
+use crate::breakpoint::{Hit, Kind};
+use crate::cpu::{Processor, Registers};
+use crate::flags::Flags;
+use crate::target::{Step, Target};
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.c64rs.mos6502">
+    <reg name="a" bitsize="8" type="uint8" regnum="0"/>
+    <reg name="x" bitsize="8" type="uint8"/>
+    <reg name="y" bitsize="8" type="uint8"/>
+    <reg name="p" bitsize="8" type="uint8"/>
+    <reg name="sp" bitsize="8" type="uint8"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+  </feature>
+</target>
+"#;
+
+const PACKET_SIZE: usize = 0x1000;
+
+// Instructions run between checks for an interrupt from the debugger
+const POLL_INTERVAL: u32 = 1000;
+
+// Signals in stop replies
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+
+/// How the debugger left
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Session {
+    /// The machine should carry on running
+    Detached,
+    /// The debugger asked to end the program
+    Killed,
+}
+
+pub struct GdbServer<T: Target> {
+    target: T,
+    no_ack: bool,
+}
+
+impl<T: Target> GdbServer<T> {
+    pub fn new(target: T) -> Self {
+        GdbServer {
+            target,
+            no_ack: false,
+        }
+    }
+
+    pub fn into_target(self) -> T {
+        self.target
+    }
+
+    /// Waits for a debugger to connect to `addr`, e.g. "127.0.0.1:6502",
+    /// and serves it
+    pub fn listen(&mut self, addr: &str) -> Result<Session, String> {
+        let listener = TcpListener::bind(addr).map_err(|e| format!("{}: {}", addr, e))?;
+        println!("Waiting for GDB on {}", addr);
+        let (stream, peer) = listener.accept().map_err(|e| format!("{}: {}", addr, e))?;
+        println!("GDB connected from {}", peer);
+        self.serve(stream)
+    }
+
+    /// Serves one connection until the debugger detaches, kills the
+    /// program or hangs up
+    pub fn serve(&mut self, mut stream: TcpStream) -> Result<Session, String> {
+        self.no_ack = false;
+        loop {
+            let Some(packet) = self.read_packet(&mut stream)? else {
+                return Ok(Session::Detached);
+            };
+            match packet.as_bytes().first() {
+                Some(b'D') => {
+                    self.send(&mut stream, "OK")?;
+                    return Ok(Session::Detached);
+                }
+                Some(b'k') => return Ok(Session::Killed),
+                _ => {}
+            }
+            let reply = match self.handle(&packet, &mut stream) {
+                Ok(reply) => reply,
+                Err(_) => "E01".to_string(),
+            };
+            self.send(&mut stream, &reply)?;
+        }
+    }
+
+    fn handle(&mut self, packet: &str, stream: &mut TcpStream) -> Result<String, String> {
+        let Some(command) = packet.get(..1) else {
+            return Ok(String::new());
+        };
+        let args = &packet[1..];
+        let cpu = self.target.cpu();
+        Ok(match command {
+            "?" => format!("S{:02x}", SIGTRAP),
+            "g" => encode_registers(&cpu.borrow().registers()),
+            "G" => {
+                let bytes = decode_hex(args)?;
+                let mut registers = cpu.borrow().registers();
+                for n in 0..=5 {
+                    let (start, len) = register_span(n)?;
+                    let value = bytes.get(start..start + len).ok_or("G packet too short")?;
+                    set_register(&mut registers, n, value)?;
+                }
+                cpu.borrow_mut().set_registers(&registers);
+                "OK".to_string()
+            }
+            "p" => {
+                let n = parse_hex(args)?;
+                let bytes = encode_registers(&cpu.borrow().registers());
+                let (start, len) = register_span(n)?;
+                bytes[start * 2..(start + len) * 2].to_string()
+            }
+            "P" => {
+                let (n, value) = args.split_once('=').ok_or("bad P packet")?;
+                let mut registers = cpu.borrow().registers();
+                set_register(&mut registers, parse_hex(n)?, &decode_hex(value)?)?;
+                cpu.borrow_mut().set_registers(&registers);
+                "OK".to_string()
+            }
+            "m" => {
+                let (addr, len) = parse_addr_len(args)?;
+                let cpu = cpu.borrow();
+                (0..len)
+                    .map(|i| format!("{:02x}", cpu.peek_memory(addr.wrapping_add(i))))
+                    .collect()
+            }
+            "M" => {
+                let (range, data) = args.split_once(':').ok_or("bad M packet")?;
+                let (addr, len) = parse_addr_len(range)?;
+                let data = decode_hex(data)?;
+                if data.len() != len as usize {
+                    return Err("M packet length mismatch".to_string());
+                }
+                // Like the monitor, writes don't trigger watchpoints
+                let mut cpu = cpu.borrow_mut();
+                for (i, &value) in data.iter().enumerate() {
+                    cpu.bus.write_byte(addr.wrapping_add(i as u16), value);
+                }
+                "OK".to_string()
+            }
+            "c" | "s" => {
+                if !args.is_empty() {
+                    cpu.borrow_mut().pc = parse_hex(args)? as u16;
+                }
+                if command == "s" {
+                    stop_reply(self.target.step_checked())
+                } else {
+                    self.resume(stream)?
+                }
+            }
+            // Backwards through the execution history
+            "b" if args == "s" => match self.target.step_back_checked() {
+                Some(step) => stop_reply(step),
+                None => format!("T{:02x}replaylog:begin;", SIGTRAP),
+            },
+            "b" if args == "c" => loop {
+                match self.target.step_back_checked() {
+                    Some(Step::Done) => {}
+                    Some(step) => break stop_reply(step),
+                    None => break format!("T{:02x}replaylog:begin;", SIGTRAP),
+                }
+            },
+            "Z" | "z" => self.breakpoint(command == "Z", args)?,
+            "H" => "OK".to_string(),
+            "q" | "Q" => self.query(packet)?,
+            // Unsupported, GDB falls back to simpler packets
+            _ => String::new(),
+        })
+    }
+
+    fn query(&mut self, packet: &str) -> Result<String, String> {
+        let (name, args) = packet.split_once(':').unwrap_or((packet, ""));
+        Ok(match name {
+            "qSupported" => format!(
+                "PacketSize={:x};qXfer:features:read+;swbreak+;hwbreak+;QStartNoAckMode+;ReverseStep+;ReverseContinue+",
+                PACKET_SIZE
+            ),
+            "QStartNoAckMode" => {
+                self.no_ack = true;
+                "OK".to_string()
+            }
+            "qAttached" => "1".to_string(),
+            "qC" => "QC1".to_string(),
+            "qfThreadInfo" => "m1".to_string(),
+            "qsThreadInfo" => "l".to_string(),
+            "qXfer" => {
+                // features:read:target.xml:offset,length
+                let spec = args
+                    .strip_prefix("features:read:target.xml:")
+                    .ok_or("unknown qXfer object")?;
+                let (offset, len) = spec.split_once(',').ok_or("bad qXfer packet")?;
+                let offset = parse_hex(offset)?.min(TARGET_XML.len());
+                let end = offset.saturating_add(parse_hex(len)?).min(TARGET_XML.len());
+                let more = if end < TARGET_XML.len() { 'm' } else { 'l' };
+                format!("{}{}", more, &TARGET_XML[offset..end])
+            }
+            _ => String::new(),
+        })
+    }
+
+    // Z0/Z1 are execution breakpoints, Z2 write, Z3 read and Z4 access
+    // watchpoints, their length is the number of bytes watched
+    fn breakpoint(&mut self, insert: bool, args: &str) -> Result<String, String> {
+        let mut fields = args.split(',');
+        let kind = match fields.next() {
+            Some("0" | "1") => Kind::Exec,
+            Some("2") => Kind::Store,
+            Some("3") => Kind::Load,
+            Some("4") => Kind::Access,
+            _ => return Ok(String::new()),
+        };
+        let addr = parse_hex(fields.next().ok_or("missing address")?)? as u16;
+        let len = parse_hex(fields.next().ok_or("missing length")?)? as u16;
+        let end = match kind {
+            Kind::Exec => addr,
+            _ => addr.saturating_add(len.max(1) - 1),
+        };
+        let breakpoints = self.target.breakpoints();
+        if insert {
+            breakpoints.add(kind, addr..=end, None);
+        } else {
+            let id = breakpoints
+                .iter()
+                .find(|b| b.kind == kind && b.range == (addr..=end) && b.condition.is_none())
+                .map(|b| b.id)
+                .ok_or("no such breakpoint")?;
+            breakpoints.remove(id)?;
+        }
+        self.target.sync_breakpoints();
+        Ok("OK".to_string())
+    }
+
+    // Runs until a breakpoint, a halt, or an interrupt (^C) from GDB
+    fn resume(&mut self, stream: &mut TcpStream) -> Result<String, String> {
+        let mut steps = 0u32;
+        loop {
+            let step = self.target.step_checked();
+            if step != Step::Done {
+                return Ok(stop_reply(step));
+            }
+            steps += 1;
+            if steps.is_multiple_of(POLL_INTERVAL) && interrupted(stream)? {
+                return Ok(format!("S{:02x}", SIGINT));
+            }
+        }
+    }
+
+    // Reads the next packet and acknowledges it, None once GDB hung up
+    fn read_packet(&mut self, stream: &mut TcpStream) -> Result<Option<String>, String> {
+        loop {
+            // Skip acks and stray interrupts up to the start of a packet
+            loop {
+                match read_byte(stream)? {
+                    None => return Ok(None),
+                    Some(b'$') => break,
+                    Some(_) => {}
+                }
+            }
+            let mut data = Vec::new();
+            loop {
+                match read_byte(stream)? {
+                    None => return Ok(None),
+                    Some(b'#') => break,
+                    Some(b) => data.push(b),
+                }
+            }
+            let mut checksum = [0; 2];
+            for digit in checksum.iter_mut() {
+                *digit = read_byte(stream)?.unwrap_or(0);
+            }
+            let expected = std::str::from_utf8(&checksum)
+                .ok()
+                .and_then(|s| u8::from_str_radix(s, 16).ok());
+            let valid = expected == Some(checksum_of(&data));
+            if !self.no_ack {
+                write_all(stream, if valid { b"+" } else { b"-" })?;
+            }
+            if valid || self.no_ack {
+                return Ok(Some(String::from_utf8_lossy(&unescape(&data)).into_owned()));
+            }
+        }
+    }
+
+    fn send(&mut self, stream: &mut TcpStream, reply: &str) -> Result<(), String> {
+        let data = escape(reply.as_bytes());
+        let mut packet = Vec::with_capacity(data.len() + 4);
+        packet.push(b'$');
+        packet.extend(&data);
+        packet.extend(format!("#{:02x}", checksum_of(&data)).as_bytes());
+        loop {
+            write_all(stream, &packet)?;
+            if self.no_ack {
+                return Ok(());
+            }
+            // Resend when GDB asks for it
+            if read_byte(stream)? != Some(b'-') {
+                return Ok(());
+            }
+        }
+    }
+}
+
+fn stop_reply(step: Step) -> String {
+    match step {
+        Step::Done => format!("S{:02x}", SIGTRAP),
+        Step::Halted => format!("S{:02x}", SIGILL),
+        Step::Hit(Hit::Break { .. }) => format!("T{:02x}swbreak:;", SIGTRAP),
+        Step::Hit(Hit::Watch { access, .. }) => format!(
+            "T{:02x}{}:{:04x};",
+            SIGTRAP,
+            if access.write { "watch" } else { "rwatch" },
+            access.addr
+        ),
+        Step::Hit(_) => format!("S{:02x}", SIGTRAP),
+    }
+}
+
+// A, X, Y, P, SP and PC as they are sent
+fn encode_registers(registers: &Registers) -> String {
+    let [pcl, pch] = registers.pc.to_le_bytes();
+    [
+        registers.a,
+        registers.x,
+        registers.y,
+        registers.p.bits(),
+        registers.sp,
+        pcl,
+        pch,
+    ]
+    .iter()
+    .map(|b| format!("{:02x}", b))
+    .collect()
+}
+
+// Byte offset and size of register `n` in the g packet
+fn register_span(n: usize) -> Result<(usize, usize), String> {
+    match n {
+        0..=4 => Ok((n, 1)),
+        5 => Ok((5, 2)),
+        _ => Err(format!("no register {}", n)),
+    }
+}
+
+fn set_register(registers: &mut Registers, n: usize, value: &[u8]) -> Result<(), String> {
+    let (_, len) = register_span(n)?;
+    if value.len() != len {
+        return Err(format!("register {} takes {} bytes", n, len));
+    }
+    match n {
+        0 => registers.a = value[0],
+        1 => registers.x = value[0],
+        2 => registers.y = value[0],
+        3 => registers.p = Flags::from_stack(value[0]),
+        4 => registers.sp = value[0],
+        _ => registers.pc = u16::from_le_bytes([value[0], value[1]]),
+    }
+    Ok(())
+}
+
+fn parse_hex(s: &str) -> Result<usize, String> {
+    usize::from_str_radix(s, 16).map_err(|_| format!("bad hex number: {}", s))
+}
+
+fn parse_addr_len(s: &str) -> Result<(u16, u16), String> {
+    let (addr, len) = s.split_once(',').ok_or("expected addr,length")?;
+    Ok((
+        parse_hex(addr)? as u16,
+        parse_hex(len)?.min(PACKET_SIZE) as u16,
+    ))
+}
+
+fn decode_hex(s: &str) -> Result<Vec<u8>, String> {
+    if !s.len().is_multiple_of(2) {
+        return Err(format!("odd number of hex digits: {}", s));
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| {
+            s.get(i..i + 2)
+                .and_then(|b| u8::from_str_radix(b, 16).ok())
+                .ok_or(format!("bad hex data: {}", s))
+        })
+        .collect()
+}
+
+fn checksum_of(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum, &b| sum.wrapping_add(b))
+}
+
+// '}' escapes the next byte, which is XORed with $20
+fn unescape(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    let mut bytes = data.iter();
+    while let Some(&b) = bytes.next() {
+        match b {
+            b'}' => out.extend(bytes.next().map(|b| b ^ 0x20)),
+            _ => out.push(b),
+        }
+    }
+    out
+}
+
+fn escape(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    for &b in data {
+        if matches!(b, b'$' | b'#' | b'}' | b'*') {
+            out.extend([b'}', b ^ 0x20]);
+        } else {
+            out.push(b);
+        }
+    }
+    out
+}
+
+fn read_byte(stream: &mut TcpStream) -> Result<Option<u8>, String> {
+    let mut byte = [0];
+    match stream.read(&mut byte) {
+        Ok(0) => Ok(None),
+        Ok(_) => Ok(Some(byte[0])),
+        Err(e) if e.kind() == ErrorKind::ConnectionReset => Ok(None),
+        Err(e) => Err(format!("gdb connection: {}", e)),
+    }
+}
+
+fn write_all(stream: &mut TcpStream, data: &[u8]) -> Result<(), String> {
+    stream
+        .write_all(data)
+        .map_err(|e| format!("gdb connection: {}", e))
+}
+
+// Whether GDB sent a ^C while the machine was running
+fn interrupted(stream: &mut TcpStream) -> Result<bool, String> {
+    stream
+        .set_nonblocking(true)
+        .map_err(|e| format!("gdb connection: {}", e))?;
+    let mut byte = [0];
+    let result = stream.read(&mut byte);
+    stream
+        .set_nonblocking(false)
+        .map_err(|e| format!("gdb connection: {}", e))?;
+    match result {
+        Ok(1) => Ok(byte[0] == 0x03),
+        // Hung up, stop running for nobody
+        Ok(_) => Ok(true),
+        Err(e) if e.kind() == ErrorKind::WouldBlock => Ok(false),
+        Err(e) => Err(format!("gdb connection: {}", e)),
+    }
+}
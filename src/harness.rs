@@ -0,0 +1,231 @@
+// Headless runner for CPU test programs such as Klaus Dormann's suites:
+// the program is loaded into a flat RAM, run until it loops on itself, and
+// the address it got stuck at is looked up in the assembler listing.
+//
+// https://github.com/Klaus2m5/6502_65C02_functional_tests
+use crate::cpu::{IrqSource, NmiSource, Processor};
+use std::fmt;
+use std::fs;
+
+/// Memory mapped register the interrupt test writes to raise IRQ and NMI
+pub struct FeedbackPort {
+    pub addr: u16,
+    pub irq_mask: u8,
+    pub nmi_mask: u8,
+    pub active_low: bool, // open collector, a 0 bit asserts the line
+}
+
+impl Default for FeedbackPort {
+    // I_port, IRQ_bit, NMI_bit and I_drive as configured in
+    // 6502_interrupt_test.a65
+    fn default() -> Self {
+        FeedbackPort {
+            addr: 0xbffc,
+            irq_mask: 0x01,
+            nmi_mask: 0x02,
+            active_low: true,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    /// The program jumped or branched to itself, changing nothing
+    Trapped(u16),
+    /// The program reached the address it was asked to run to
+    Reached(u16),
+    /// The CPU refused to execute the opcode at this address
+    Stopped(u16),
+    /// The instruction limit ran out while at this address
+    Timeout(u16),
+}
+
+impl Outcome {
+    pub fn pc(&self) -> u16 {
+        match *self {
+            Outcome::Trapped(pc)
+            | Outcome::Reached(pc)
+            | Outcome::Stopped(pc)
+            | Outcome::Timeout(pc) => pc,
+        }
+    }
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Outcome::Trapped(pc) => write!(f, "trapped at ${:04X}", pc),
+            Outcome::Reached(pc) => write!(f, "reached ${:04X}", pc),
+            Outcome::Stopped(pc) => write!(f, "stopped at ${:04X}", pc),
+            Outcome::Timeout(pc) => write!(f, "timed out at ${:04X}", pc),
+        }
+    }
+}
+
+pub struct Harness<P: Processor> {
+    pub cpu: P,
+    feedback: Option<FeedbackPort>,
+    instructions: u64,
+}
+
+impl<P: Processor> Harness<P> {
+    pub fn new(cpu: P) -> Self {
+        Harness {
+            cpu,
+            feedback: None,
+            instructions: 0,
+        }
+    }
+
+    /// Connect the IRQ and NMI lines to a feedback register in memory
+    pub fn set_feedback(&mut self, port: FeedbackPort) {
+        // Start out with both lines released
+        let idle = if port.active_low {
+            port.irq_mask | port.nmi_mask
+        } else {
+            0
+        };
+        self.cpu.write_memory(port.addr, idle);
+        self.feedback = Some(port);
+    }
+
+    pub fn load(&mut self, addr: u16, data: &[u8]) {
+        for (i, &byte) in data.iter().enumerate() {
+            self.cpu.write_memory(addr.wrapping_add(i as u16), byte);
+        }
+    }
+
+    /// Load a binary image, a full 64K image always goes to $0000
+    pub fn load_file(&mut self, path: &str, addr: u16) -> Result<(), String> {
+        let data = fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+        let addr = if data.len() == 0x10000 { 0 } else { addr };
+        self.load(addr, &data);
+        Ok(())
+    }
+
+    /// Number of instructions executed by `run`
+    pub fn instructions(&self) -> u64 {
+        self.instructions
+    }
+
+    /// Run from `start` until the program traps, reaches `until` or has
+    /// executed `limit` instructions
+    pub fn run(&mut self, start: u16, until: Option<u16>, limit: u64) -> Outcome {
+        let mut registers = self.cpu.registers();
+        registers.pc = start;
+        self.cpu.set_registers(&registers);
+        self.instructions = 0;
+
+        loop {
+            let before = self.cpu.registers();
+            let pc = before.pc;
+            if until == Some(pc) {
+                return Outcome::Reached(pc);
+            }
+            if self.instructions >= limit {
+                return Outcome::Timeout(pc);
+            }
+            if !self.cpu.step() {
+                return Outcome::Stopped(pc);
+            }
+            self.instructions += 1;
+            self.update_interrupt_lines();
+            // MVN and MVP repeat themselves too, but they change registers
+            if self.cpu.registers() == before {
+                return Outcome::Trapped(pc);
+            }
+        }
+    }
+
+    fn update_interrupt_lines(&mut self) {
+        if let Some(port) = &self.feedback {
+            let mut value = self.cpu.peek_memory(port.addr);
+            if port.active_low {
+                value = !value;
+            }
+            let irq = value & port.irq_mask != 0;
+            let nmi = value & port.nmi_mask != 0;
+            self.cpu.set_irq(IrqSource::Cartridge, irq);
+            self.cpu.set_nmi(NmiSource::Cartridge, nmi);
+        }
+    }
+}
+
+/// Address to source line map of an as65 listing (`.lst`) file
+pub struct Listing {
+    lines: Vec<(u16, usize, String)>,
+}
+
+impl Listing {
+    // Source text starts after "xxxx : " and the 16 character wide field
+    // of code bytes, labels start right at this column
+    const SOURCE_COLUMN: usize = 24;
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        Ok(Listing::parse(&text))
+    }
+
+    pub fn parse(text: &str) -> Self {
+        let mut lines = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            // Code lines are "xxxx : ", symbols defined without code
+            // (e.g. zero page variables) are "xxxx = "
+            let separator = line.get(4..7);
+            if separator != Some(" : ") && separator != Some(" = ") {
+                continue;
+            }
+            if let Ok(addr) = u16::from_str_radix(&line[..4], 16) {
+                lines.push((addr, number + 1, line.to_string()));
+            }
+        }
+        Listing { lines }
+    }
+
+    /// Line number and text of the instruction at `addr`
+    pub fn line(&self, addr: u16) -> Option<(usize, &str)> {
+        let mut found = None;
+        for (line_addr, number, text) in &self.lines {
+            if *line_addr != addr {
+                continue;
+            }
+            // Prefer the line holding the instruction bytes over label lines
+            let has_bytes = text.get(4..7) == Some(" : ")
+                && !text.get(7..).unwrap_or_default().starts_with(' ');
+            if has_bytes {
+                return Some((*number, text.as_str()));
+            }
+            found.get_or_insert((*number, text.as_str()));
+        }
+        found
+    }
+
+    /// Address of the first line containing `text`
+    pub fn find(&self, text: &str) -> Option<u16> {
+        self.lines
+            .iter()
+            .find(|(_, _, line)| line.contains(text))
+            .map(|(addr, _, _)| *addr)
+    }
+
+    /// Address of a label defined in the listing
+    pub fn label(&self, name: &str) -> Option<u16> {
+        self.lines.iter().find_map(|(addr, _, line)| {
+            let source = line.get(Self::SOURCE_COLUMN..)?;
+            let label = source.split_whitespace().next()?;
+            if source.starts_with(label) && label == name {
+                Some(*addr)
+            } else {
+                None
+            }
+        })
+    }
+
+    /// "line N: text" for `addr`, used when reporting a failed test
+    pub fn describe(&self, addr: u16) -> String {
+        match self.line(addr) {
+            Some((number, text)) => format!("line {}: {}", number, text.trim_end()),
+            None => format!("${:04X} is not in the listing", addr),
+        }
+    }
+}
@@ -0,0 +1,121 @@
+// Execution history: the last instructions the CPU ran, each with the CPU
+// state before it and the memory it wrote, so a debugger can show how the
+// machine got somewhere and step backwards.
+//
+// Stepping back restores the registers, the shadow call stack and the RAM
+// the instruction wrote. Writes to chip registers can't be undone, and the
+// chips and the cycle counter keep running forward.
+use crate::callstack::CallStack;
+use crate::cpu::{Cpu, Interrupt, Processor, Registers};
+use crate::memory::{Memory, MemoryWrite};
+use crate::opcode::Mnemonic;
+use crate::trace::TraceLine;
+use std::collections::VecDeque;
+use std::fmt;
+
+/// One step of the CPU
+pub struct Record {
+    pub line: TraceLine,              // The CPU before the step
+    pub interrupt: Option<Interrupt>, // Entered instead of running the instruction
+    pub writes: Vec<MemoryWrite>,
+    pub calls: Option<CallStack>, // Before the step, if it could change
+}
+
+impl Record {
+    /// Registers before the step
+    pub fn registers(&self) -> Registers {
+        Registers {
+            pc: self.line.instruction.addr,
+            sp: self.line.sp,
+            a: self.line.a,
+            x: self.line.x,
+            y: self.line.y,
+            p: self.line.p,
+        }
+    }
+}
+
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.interrupt {
+            Some(Interrupt::Irq) => write!(f, "{}  IRQ", self.line),
+            Some(Interrupt::Nmi) => write!(f, "{}  NMI", self.line),
+            None => write!(f, "{}", self.line),
+        }
+    }
+}
+
+pub struct History {
+    records: VecDeque<Record>,
+    capacity: usize,
+}
+
+impl History {
+    /// Keeps the last `capacity` steps, none with 0
+    pub fn new(capacity: usize) -> Self {
+        History {
+            records: VecDeque::with_capacity(capacity.min(0x10000)),
+            capacity,
+        }
+    }
+
+    /// Steps the CPU and records the step. Returns what Cpu::step does.
+    pub fn step(&mut self, cpu: &mut Cpu<Memory>, raster: (u16, u16)) -> bool {
+        if self.capacity == 0 {
+            return cpu.step();
+        }
+        let line = TraceLine::capture(cpu, raster);
+        // Only calls, returns and interrupts change the call stack
+        let calls = (matches!(
+            line.instruction.op.mnemonic,
+            Mnemonic::Jsr | Mnemonic::Rts | Mnemonic::Rti | Mnemonic::Brk
+        ) || cpu.interrupt_pending())
+        .then(|| cpu.calls().clone());
+        // Writes from outside the CPU, such as the monitor's, aren't steps
+        cpu.bus.journal_writes(true);
+        cpu.bus.take_writes();
+        let running = cpu.step();
+        if self.records.len() == self.capacity {
+            self.records.pop_front();
+        }
+        self.records.push_back(Record {
+            line,
+            interrupt: cpu.interrupted(),
+            writes: cpu.bus.take_writes(),
+            calls,
+        });
+        running
+    }
+
+    /// Undoes the last step recorded, None when there is none left
+    pub fn step_back(&mut self, cpu: &mut Cpu<Memory>) -> Option<Record> {
+        let record = self.records.pop_back()?;
+        for write in record.writes.iter().rev() {
+            cpu.bus.undo_write(write);
+        }
+        cpu.set_registers(&record.registers());
+        if let Some(calls) = &record.calls {
+            *cpu.calls_mut() = calls.clone();
+        }
+        Some(record)
+    }
+
+    /// The last `count` steps, oldest first
+    pub fn last(&self, count: usize) -> impl Iterator<Item = &Record> {
+        self.records
+            .iter()
+            .skip(self.records.len().saturating_sub(count))
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.records.clear();
+    }
+}
@@ -1,4 +1,5 @@
-use super::cpu::Cpu;
+use c64_rs::cpu::{Cpu, NmiSource};
+use c64_rs::memory::Memory;
 use sdl2::keyboard::Keycode;
 use sdl2::render::WindowCanvas;
 use sdl2::video::Window;
@@ -8,16 +9,16 @@ use std::collections::{HashMap, VecDeque};
 use std::rc::Rc;
 use std::vec::Vec;
 
-pub struct IO<'a> {
-    cpu: Rc<RefCell<Cpu<'a>>>,
+pub struct IO {
+    cpu: Rc<RefCell<Cpu<Memory>>>,
     keyboard_matrix: [u8; 8],
     keymap: HashMap<Keycode, (i32, i32)>,
     charmap: HashMap<char, Vec<Keycode>>,
     key_event_queue: VecDeque<(KeyEvent, Keycode)>,
     next_key_event_at: u32,
-    event_pump: EventPump,
+    event_pump: Option<EventPump>, // None without a window
     retval: bool,
-    renderer: WindowCanvas,
+    renderer: Option<WindowCanvas>,
     frame: Vec<u32>,
 }
 
@@ -26,9 +27,10 @@ enum KeyEvent {
     Release,
 }
 
-impl<'a> IO<'a> {
+impl IO {
     pub const WAIT_DURATION: u32 = 18000;
-    pub fn new(cpu: Rc<RefCell<Cpu<'a>>>) -> Result<Self, String> {
+    pub const KEY_RESTORE: Keycode = Keycode::PageUp;
+    pub fn new(cpu: Rc<RefCell<Cpu<Memory>>>) -> Result<Self, String> {
         let sdl_context = sdl2::init()?;
         let video_subsystem = sdl_context.video()?;
 
@@ -40,15 +42,29 @@ impl<'a> IO<'a> {
             .map_err(|e| e.to_string())?;
 
         let mut canvas = window.into_canvas().build().map_err(|e| e.to_string())?;
+        // canvas.set_draw_color(Color::RGB(255, 0, 0));
+        canvas.clear();
+        canvas.present();
+        let event_pump = sdl_context.event_pump()?;
+        Ok(Self::with_window(cpu, Some(event_pump), Some(canvas)))
+    }
+
+    /// Keyboard only, without a window or events
+    #[cfg(test)]
+    pub fn headless(cpu: Rc<RefCell<Cpu<Memory>>>) -> Self {
+        Self::with_window(cpu, None, None)
+    }
+
+    fn with_window(
+        cpu: Rc<RefCell<Cpu<Memory>>>,
+        event_pump: Option<EventPump>,
+        renderer: Option<WindowCanvas>,
+    ) -> Self {
         // TODO: get these values from VIC-II
         let cols = 80;
         let rows = 25;
         let frame: Vec<u32> = vec![0; cols * rows];
 
-        // canvas.set_draw_color(Color::RGB(255, 0, 0));
-        canvas.clear();
-        canvas.present();
-        let event_pump = sdl_context.event_pump()?;
         let mut io = IO {
             cpu,
             keyboard_matrix: [0xff; 8],
@@ -58,7 +74,7 @@ impl<'a> IO<'a> {
             next_key_event_at: 0,
             event_pump,
             retval: true,
-            renderer: canvas,
+            renderer,
             frame,
         };
 
@@ -182,7 +198,7 @@ impl<'a> IO<'a> {
         io.keymap.insert(Keycode::Quote, (5, 6));
         io.keymap.insert(Keycode::LGui, (7, 5)); // Commodore key
 
-        Ok(io)
+        io
     }
 
     pub fn keyboard_matrix_row(&self, col: usize) -> u8 {
@@ -217,10 +233,23 @@ impl<'a> IO<'a> {
     }
 
     pub fn process_events(&mut self) {
-        let events: Vec<sdl2::event::Event> = self.event_pump.poll_iter().collect();
+        let events: Vec<sdl2::event::Event> = match &mut self.event_pump {
+            Some(event_pump) => event_pump.poll_iter().collect(),
+            None => Vec::new(),
+        };
 
         for event in events {
             match event {
+                // RESTORE is not part of the keyboard matrix, it is wired
+                // straight to the NMI line
+                sdl2::event::Event::KeyDown {
+                    keycode: Some(Self::KEY_RESTORE),
+                    ..
+                } => self.cpu.borrow_mut().set_nmi(NmiSource::Restore, true),
+                sdl2::event::Event::KeyUp {
+                    keycode: Some(Self::KEY_RESTORE),
+                    ..
+                } => self.cpu.borrow_mut().set_nmi(NmiSource::Restore, false),
                 sdl2::event::Event::KeyDown {
                     keycode: Some(keycode),
                     ..
@@ -266,8 +295,10 @@ impl<'a> IO<'a> {
 
     fn screen_refresh(&mut self) {
         // Clear the renderer, copy texture, and present
-        self.renderer.clear();
-        // Additional rendering operations...
-        self.renderer.present();
+        if let Some(renderer) = &mut self.renderer {
+            renderer.clear();
+            // Additional rendering operations...
+            renderer.present();
+        }
     }
 }
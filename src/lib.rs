@@ -0,0 +1,25 @@
+pub mod asm;
+pub mod binmon;
+pub mod breakpoint;
+pub mod bus;
+pub mod callstack;
+pub mod cpu;
+pub mod cpu65816;
+pub mod cpu65c02;
+pub mod d64;
+pub mod dap;
+pub mod disasm;
+pub mod display;
+pub mod flags;
+pub mod gdb;
+pub mod harness;
+pub mod history;
+pub mod lockstep;
+pub mod memory;
+pub mod opcode;
+pub mod profile;
+pub mod sourcemap;
+pub mod suite;
+pub mod symbols;
+pub mod target;
+pub mod trace;
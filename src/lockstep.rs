@@ -0,0 +1,269 @@
+// Runs the CPU in lockstep with a reference trace recorded by a known good
+// emulator, and stops at the first instruction where the two disagree.
+//
+// The reference is read in the layout written by the trace module, which is
+// that of the VICE CPU history. Only PC is required on a line; A, X, Y, SP,
+// the flags and the cycle count are compared when present. Cycle counters of
+// two emulators rarely start at the same value, so cycles are compared
+// relative to the first instruction.
+use crate::cpu::Cpu;
+use crate::flags::Flags;
+use crate::memory::Memory;
+use crate::trace::TraceLine;
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Lines};
+
+/// CPU state before an instruction as recorded in the reference trace
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RefState {
+    pub line: usize,
+    pub pc: u16,
+    pub a: Option<u8>,
+    pub x: Option<u8>,
+    pub y: Option<u8>,
+    pub sp: Option<u8>,
+    pub p: Option<Flags>,
+    pub cycles: Option<u32>,
+}
+
+// B and bit 5 only exist on the stack, traces show them differently
+const FLAGS_MASK: u8 = 0xcf;
+
+/// Parses a trace line such as
+/// ".C:e5cd  A5 C6  LDA $C6  - A:00 X:00 Y:0A SP:f3 ..-..IZC 012 034  1234"
+pub fn parse_line(line: usize, text: &str) -> Option<RefState> {
+    let mut tokens = text.split_whitespace();
+    let pc = tokens.next()?.strip_prefix(".C:")?;
+    let mut state = RefState {
+        line,
+        pc: u16::from_str_radix(pc, 16).ok()?,
+        a: None,
+        x: None,
+        y: None,
+        sp: None,
+        p: None,
+        cycles: None,
+    };
+    let hex = |s: &str| u8::from_str_radix(s, 16).ok();
+    let mut last = None;
+    for token in tokens {
+        if let Some(v) = token.strip_prefix("A:") {
+            state.a = hex(v);
+        } else if let Some(v) = token.strip_prefix("X:") {
+            state.x = hex(v);
+        } else if let Some(v) = token.strip_prefix("Y:") {
+            state.y = hex(v);
+        } else if let Some(v) = token.strip_prefix("SP:") {
+            state.sp = hex(v);
+        } else if state.sp.is_some() && state.p.is_none() {
+            state.p = parse_flags(token);
+        }
+        last = Some(token);
+    }
+    // The cycle count is the last column, after the registers
+    if state.p.is_some() {
+        state.cycles = last.and_then(|s| s.parse().ok());
+    }
+    Some(state)
+}
+
+// "NV-BDIZC" with any character other than the flag letter for a clear flag
+fn parse_flags(token: &str) -> Option<Flags> {
+    if token.len() != 8 {
+        return None;
+    }
+    let mut bits = 0;
+    for (i, (c, name)) in token.chars().zip("NV-BDIZC".chars()).enumerate() {
+        if c.eq_ignore_ascii_case(&name) && name != '-' {
+            bits |= 0x80 >> i;
+        }
+    }
+    Some(Flags::from_stack(bits))
+}
+
+/// Where the emulator and the reference first disagree
+pub struct Divergence {
+    pub expected: RefState,
+    pub actual: TraceLine,
+    pub cycle_offset: Option<i64>,
+    pub history: Vec<TraceLine>, // Instructions before this one, oldest first
+}
+
+impl Divergence {
+    fn fields(&self) -> Vec<(&'static str, String, String, bool)> {
+        let e = &self.expected;
+        let a = &self.actual;
+        let byte = |v: Option<u8>| v.map_or("-".to_string(), |v| format!("{:02X}", v));
+        let mut fields = vec![
+            (
+                "PC",
+                format!("{:04X}", e.pc),
+                format!("{:04X}", a.instruction.addr),
+                e.pc != a.instruction.addr,
+            ),
+            (
+                "A",
+                byte(e.a),
+                format!("{:02X}", a.a),
+                e.a.is_some_and(|v| v != a.a),
+            ),
+            (
+                "X",
+                byte(e.x),
+                format!("{:02X}", a.x),
+                e.x.is_some_and(|v| v != a.x),
+            ),
+            (
+                "Y",
+                byte(e.y),
+                format!("{:02X}", a.y),
+                e.y.is_some_and(|v| v != a.y),
+            ),
+            (
+                "SP",
+                byte(e.sp),
+                format!("{:02X}", a.sp),
+                e.sp.is_some_and(|v| v != a.sp),
+            ),
+            (
+                "P",
+                e.p.map_or("-".to_string(), |p| p.to_string()),
+                a.p.to_string(),
+                e.p.is_some_and(|p| (p.bits() ^ a.p.bits()) & FLAGS_MASK != 0),
+            ),
+        ];
+        if let (Some(cycles), Some(offset)) = (e.cycles, self.cycle_offset) {
+            let actual = a.cycles as i64 + offset;
+            fields.push((
+                "CYCLES",
+                cycles.to_string(),
+                actual.to_string(),
+                cycles as i64 != actual,
+            ));
+        }
+        fields
+    }
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Diverged at reference line {}", self.expected.line)?;
+        if !self.history.is_empty() {
+            writeln!(f, "Last {} instructions:", self.history.len())?;
+            for line in &self.history {
+                writeln!(f, "  {}", line)?;
+            }
+        }
+        writeln!(f, "  {}", self.actual)?;
+        writeln!(f, "{:<8}{:<12}{:<12}", "", "expected", "actual")?;
+        for (name, expected, actual, differs) in self.fields() {
+            let marker = if differs { "  <--" } else { "" };
+            writeln!(f, "{:<8}{:<12}{:<12}{}", name, expected, actual, marker)?;
+        }
+        Ok(())
+    }
+}
+
+pub enum Check {
+    /// Waiting for the CPU to reach the state of the first reference line
+    Waiting,
+    Matched,
+    /// The step enters an interrupt, there is no instruction to compare
+    Interrupt,
+    /// The reference ran out after this many instructions
+    Finished(u64),
+    Diverged(Box<Divergence>),
+}
+
+pub struct Lockstep {
+    reference: Lines<BufReader<File>>,
+    line: usize,
+    next: Option<RefState>,
+    history: VecDeque<TraceLine>,
+    depth: usize,
+    cycle_offset: Option<i64>,
+    matched: u64,
+}
+
+impl Lockstep {
+    pub const DEFAULT_DEPTH: usize = 20;
+
+    /// Opens a reference trace, `depth` instructions are kept to show what
+    /// led up to a divergence
+    pub fn open(path: &str, depth: usize) -> Result<Self, String> {
+        let file = File::open(path).map_err(|e| format!("{}: {}", path, e))?;
+        let mut lockstep = Lockstep {
+            reference: BufReader::new(file).lines(),
+            line: 0,
+            next: None,
+            history: VecDeque::with_capacity(depth),
+            depth,
+            cycle_offset: None,
+            matched: 0,
+        };
+        lockstep.next = lockstep.read_state()?;
+        if lockstep.next.is_none() {
+            return Err(format!("{}: no trace lines found", path));
+        }
+        Ok(lockstep)
+    }
+
+    // Next line holding CPU state, other lines such as monitor prompts
+    // are skipped
+    fn read_state(&mut self) -> Result<Option<RefState>, String> {
+        for text in self.reference.by_ref() {
+            let text = text.map_err(|e| format!("reference trace: {}", e))?;
+            self.line += 1;
+            if let Some(state) = parse_line(self.line, &text) {
+                return Ok(Some(state));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Compares the instruction at PC with the reference, call before
+    /// stepping the CPU
+    pub fn check(&mut self, cpu: &Cpu<Memory>, raster: (u16, u16)) -> Result<Check, String> {
+        let Some(expected) = self.next else {
+            return Ok(Check::Finished(self.matched));
+        };
+        // Traces don't show interrupt entries, the instruction at PC runs
+        // after the handler returns
+        if cpu.interrupt_due().is_some() {
+            return Ok(Check::Interrupt);
+        }
+        let mut divergence = Divergence {
+            expected,
+            actual: TraceLine::capture(cpu, raster),
+            cycle_offset: self.cycle_offset,
+            history: Vec::new(),
+        };
+        let differs = divergence.fields().iter().any(|field| field.3);
+        if self.matched == 0 {
+            // The reference may start anywhere, e.g. at the entry point of a
+            // program or inside a loop. Run freely until the CPU is in the
+            // state of the first line, its cycle count sets the offset.
+            if differs {
+                return Ok(Check::Waiting);
+            }
+            self.cycle_offset = expected
+                .cycles
+                .map(|c| c as i64 - divergence.actual.cycles as i64);
+        } else if differs {
+            divergence.history = self.history.drain(..).collect();
+            return Ok(Check::Diverged(Box::new(divergence)));
+        }
+
+        if self.history.len() == self.depth {
+            self.history.pop_front();
+        }
+        if self.depth > 0 {
+            self.history.push_back(divergence.actual);
+        }
+        self.matched += 1;
+        self.next = self.read_state()?;
+        Ok(Check::Matched)
+    }
+}
@@ -0,0 +1,236 @@
+// Runner for Wolfgang Lorenz's C64 emulator test suite. It boots the
+// machine, autostarts a test, and follows it through the KERNAL calls it
+// makes with a suite::Watch.
+use crate::autostart;
+use crate::cia1::Cia1;
+use crate::cia2::Cia2;
+use crate::io::IO;
+use crate::vic::Vic;
+use c64_rs::cpu::Cpu;
+use c64_rs::d64::{DirEntry, D64};
+use c64_rs::memory::Memory;
+use c64_rs::suite::{TestResult, Trap, Verdict, Watch};
+use std::cell::RefCell;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+/// Where the test programs come from, a D64 image or a host directory
+pub enum Source {
+    Disk(D64),
+    Dir(PathBuf),
+}
+
+impl Source {
+    pub fn open(path: &str) -> Result<Self, String> {
+        if Path::new(path).is_dir() {
+            Ok(Source::Dir(PathBuf::from(path)))
+        } else {
+            Ok(Source::Disk(D64::open(path)?))
+        }
+    }
+
+    /// Names of the programs, in directory order for a disk image and
+    /// sorted for a host directory
+    pub fn names(&self) -> Result<Vec<String>, String> {
+        match self {
+            Source::Disk(disk) => Ok(disk
+                .files()?
+                .into_iter()
+                .filter(|entry| entry.file_type == DirEntry::TYPE_PRG)
+                .map(|entry| entry.name.to_lowercase())
+                .collect()),
+            Source::Dir(dir) => {
+                let mut names: Vec<String> = fs::read_dir(dir)
+                    .map_err(|e| format!("{}: {}", dir.display(), e))?
+                    .filter_map(|entry| entry.ok())
+                    .filter(|entry| entry.path().is_file())
+                    .map(|entry| {
+                        let name = entry.file_name().to_string_lossy().to_lowercase();
+                        name.strip_suffix(".prg").unwrap_or(&name).to_string()
+                    })
+                    .collect();
+                names.sort();
+                Ok(names)
+            }
+        }
+    }
+
+    /// Contents of a program, names are compared without regard to case
+    pub fn load(&self, name: &str) -> Result<Vec<u8>, String> {
+        let name = name.to_lowercase();
+        match self {
+            Source::Disk(disk) => {
+                let files = disk.files()?;
+                let entry = files
+                    .iter()
+                    .find(|entry| entry.name.to_lowercase() == name)
+                    .ok_or(format!("{}: not on disk", name))?;
+                disk.read_file(entry)
+            }
+            Source::Dir(dir) => {
+                let entries = fs::read_dir(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+                for entry in entries.filter_map(|entry| entry.ok()) {
+                    let file_name = entry.file_name().to_string_lossy().to_lowercase();
+                    if file_name == name || file_name == format!("{}.prg", name) {
+                        return fs::read(entry.path()).map_err(|e| format!("{}: {}", name, e));
+                    }
+                }
+                Err(format!("{}: not found in {}", name, dir.display()))
+            }
+        }
+    }
+}
+
+pub struct Runner {
+    cpu: Rc<RefCell<Cpu<Memory>>>,
+    cia1: Rc<RefCell<Cia1>>,
+    cia2: Rc<RefCell<Cia2>>,
+    vic: Rc<RefCell<Vic>>,
+    io: Rc<RefCell<IO>>,
+    source: Source,
+    cycle_limit: u64,
+    quit: bool,
+}
+
+impl Runner {
+    pub const DEFAULT_CYCLE_LIMIT: u64 = 300_000_000;
+
+    pub fn new(
+        cpu: Rc<RefCell<Cpu<Memory>>>,
+        cia1: Rc<RefCell<Cia1>>,
+        cia2: Rc<RefCell<Cia2>>,
+        vic: Rc<RefCell<Vic>>,
+        io: Rc<RefCell<IO>>,
+        source: Source,
+        cycle_limit: u64,
+    ) -> Self {
+        Runner {
+            cpu,
+            cia1,
+            cia2,
+            vic,
+            io,
+            source,
+            cycle_limit,
+            quit: false,
+        }
+    }
+
+    /// Runs the suite from `start` (the first program by default), following
+    /// the chain of LOADs. A test that fails to chain is followed by the next
+    /// program in directory order.
+    pub fn run(&mut self, start: Option<&str>) -> Result<Vec<TestResult>, String> {
+        let names = self.source.names()?;
+        let mut results: Vec<TestResult> = Vec::new();
+        let mut next = match start {
+            Some(name) => Some(name.to_lowercase()),
+            None => names.first().cloned(),
+        };
+
+        while let Some(name) = next.take() {
+            // The last test loads the first one again
+            if self.quit || results.iter().any(|r| r.name == name) {
+                break;
+            }
+            let (result, chained) = self.run_test(&name)?;
+            next = chained.or_else(|| {
+                let index = names.iter().position(|n| *n == name)?;
+                names.get(index + 1).cloned()
+            });
+            results.push(result);
+        }
+        Ok(results)
+    }
+
+    pub fn print_summary(results: &[TestResult]) {
+        println!("{:<16} {:<8} {:>12}  Detail", "Test", "Result", "Cycles");
+        for result in results {
+            let detail = if result.verdict == Verdict::Passed {
+                ""
+            } else {
+                result.detail()
+            };
+            println!(
+                "{:<16} {:<8} {:>12}  {}",
+                result.name, result.verdict, result.cycles, detail
+            );
+        }
+        let count = |verdict| results.iter().filter(|r| r.verdict == verdict).count();
+        println!(
+            "{} tests: {} passed, {} failed, {} timed out, {} missing",
+            results.len(),
+            count(Verdict::Passed),
+            count(Verdict::Failed),
+            count(Verdict::Timeout),
+            count(Verdict::Missing)
+        );
+    }
+
+    // Autostarts one test. Returns its result and the name of the program it
+    // tried to load next, if any.
+    fn run_test(&mut self, name: &str) -> Result<(TestResult, Option<String>), String> {
+        let program = match self.source.load(name) {
+            Ok(program) if program.len() > 2 => program,
+            _ => return Ok((TestResult::new(name, Verdict::Missing), None)),
+        };
+        println!("Running {}", name);
+
+        let cpu = self.cpu.clone();
+        autostart::boot(&cpu, || self.step())?;
+        autostart::autostart(&mut self.cpu.borrow_mut(), &program);
+
+        let mut watch = Watch::new(name);
+        let mut halted = false;
+        while watch.cycles() < self.cycle_limit {
+            let (pc, cycles) = {
+                let cpu = self.cpu.borrow();
+                (cpu.pc, cpu.cycles())
+            };
+            if !self.step() {
+                halted = true;
+                break;
+            }
+            let mut cpu = self.cpu.borrow_mut();
+            let cycles = cpu.cycles().wrapping_sub(cycles);
+            match watch.step(&cpu, pc, cycles) {
+                // Feed the key a failed test waits for so it carries on to
+                // the next test
+                Some(Trap::Key) => autostart::type_keys(&mut cpu, b" "),
+                Some(Trap::Load(next)) => return Ok((watch.loaded(), Some(next))),
+                None => {}
+            }
+        }
+        Ok((watch.stopped(halted, &self.screen_text()), None))
+    }
+
+    // Screen RAM as text, for tests that report without calling CHROUT
+    fn screen_text(&self) -> String {
+        let cpu = self.cpu.borrow();
+        (0..1000)
+            .map(
+                |i| match cpu.peek_memory(Memory::BASE_ADDR_SCREEN + i) & 0x7f {
+                    0x00 => '@',
+                    c @ 0x01..=0x1a => (b'A' + c - 1) as char,
+                    c @ 0x20..=0x3f => c as char,
+                    _ => ' ',
+                },
+            )
+            .collect()
+    }
+
+    // One step of the machine in the same order as run_c64
+    fn step(&mut self) -> bool {
+        self.cia1.borrow_mut().step();
+        self.cia2.borrow_mut().step();
+        self.vic.borrow_mut().step();
+        if !self.cpu.borrow_mut().step() {
+            return false;
+        }
+        if !self.io.borrow_mut().step() {
+            self.quit = true;
+            return false;
+        }
+        true
+    }
+}
@@ -1,170 +1,658 @@
 use crate::cia1::Cia1;
 use crate::cia2::Cia2;
-use crate::cpu::Cpu;
 use crate::io::IO;
-use crate::memory::Memory;
-use clap::{command, Command};
+use crate::lorenz::{Runner, Source};
+use crate::monitor::{Exit, Monitor};
+use crate::sid::Sid;
+use crate::vic::Vic;
+use c64_rs::asm::Assembler;
+use c64_rs::binmon::BinaryMonitor;
+use c64_rs::bus::FlatRam;
+use c64_rs::cpu::Cpu;
+use c64_rs::dap::DapServer;
+use c64_rs::disasm;
+use c64_rs::gdb::{GdbServer, Session};
+use c64_rs::harness::{Harness, Listing, Outcome};
+use c64_rs::history::History;
+use c64_rs::lockstep::{Check, Lockstep};
+use c64_rs::memory::Memory;
+use c64_rs::profile::{ProfileFormat, Profiler};
+use c64_rs::symbols::Symbols;
+use c64_rs::target::Target;
+use c64_rs::trace::{self, RomFilter, Tracer};
+use clap::{command, value_parser, Arg, ArgAction, ArgMatches, Command};
 use std::cell::RefCell;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
 
+mod autostart;
 mod cia1;
 mod cia2;
 mod common;
-mod cpu;
+#[macro_use]
+mod console;
 mod io;
-mod memory;
+mod lorenz;
+mod monitor;
+mod sid;
+mod vic;
 
-fn debug(cpu: Rc<RefCell<Cpu>>, cia1: Rc<RefCell<Cia1>>) {
-    // TEMP: Load the machine code into memory (for our sample program)
-    // LDX #$03      ; Load X register with the number 3
-    // LDA #$05      ; Load accumulator with the number 5
-    // ADC #$00      ; Add 0 to accumulator (effectively adding X because of the previously set carry)
-    // STA $0200     ; Store accumulator (result) at memory location $0200
-    // JMP $0004     ; Jump to this instruction (creates an infinite loop)
-
-    let program: [u8; 12] = [
-        0xA2, 0x03, 0xA9, 0x05, 0x69, 0x00, 0x8D, 0x00, 0x02, 0x4C, 0x04, 0x00,
-    ];
-    for (i, &byte) in program.iter().enumerate() {
-        cpu.borrow_mut().write_memory(i as u16, byte);
-    }
+// Runs Klaus Dormann's 6502 functional test, the same test is run by
+// `cargo test`
+//
+// https://github.com/Klaus2m5/6502_65C02_functional_tests
+fn test_cpu() -> Result<(), String> {
+    let mut harness = Harness::new(Cpu::new(FlatRam::new()));
+    harness.load_file("assets/tests/6502_functional_test.bin", 0x400)?;
+    let listing = Listing::load("assets/tests/6502_functional_test.lst")?;
+    let success = listing.find("test passed");
 
-    loop {
-        let mut input = String::new();
-        println!("Enter command (step/load/display/quit):");
-        std::io::stdin()
-            .read_line(&mut input)
-            .expect("Failed to read command");
-        match input.trim() {
-            "step" => {
-                cpu.borrow_mut().step();
-                println!(
-                    "Stepped. PC: {:#04X}, A: {:#02X}, X: {:#02X}, Y: {:#02X}",
-                    cpu.borrow().pc,
-                    cpu.borrow().a,
-                    cpu.borrow().x,
-                    cpu.borrow().y
-                );
-                cia1.borrow_mut().step();
-            }
-            "load" => {
-                println!("Enter memory address (hex):");
-                let mut address_input = String::new();
-                std::io::stdin()
-                    .read_line(&mut address_input)
-                    .expect("Failed to read address");
-                let address =
-                    u16::from_str_radix(address_input.trim(), 16).expect("Failed to parse address");
-
-                println!("Enter byte value (hex):");
-                let mut value_input = String::new();
-                std::io::stdin()
-                    .read_line(&mut value_input)
-                    .expect("Failed to read value");
-                let value =
-                    u8::from_str_radix(value_input.trim(), 16).expect("Failed to parse value");
-
-                cpu.borrow_mut().write_memory(address, value);
-                println!("Loaded {:#02X} into {:#04X}", value, address);
-            }
-            "display" => {
-                println!("Enter start memory address (hex):");
-                let mut address_input = String::new();
-                std::io::stdin()
-                    .read_line(&mut address_input)
-                    .expect("Failed to read address");
-                let start_address =
-                    u16::from_str_radix(address_input.trim(), 16).expect("Failed to parse address");
-
-                for i in 0..0x10 {
-                    print!("{:#02X} ", cpu.borrow().read_memory(start_address + i));
-                }
-                println!();
-            }
-            "quit" => {
-                println!("Exiting emulator.");
-                break;
-            }
-            _ => {
-                println!("Unknown command. Please enter a valid command.");
-            }
+    let outcome = harness.run(0x400, None, u64::MAX);
+    if let Outcome::Trapped(pc) = outcome {
+        if Some(pc) == success {
+            println!("Passed! ({} instructions)", harness.instructions());
+            return Ok(());
         }
     }
+    Err(format!(
+        "Failed: {}\n{}",
+        outcome,
+        listing.describe(outcome.pc())
+    ))
 }
 
-// uns Klaus Dormann's 6502 test suite
-//
-// https://github.com/Klaus2m5/6502_65C02_functional_tests
-fn test_cpu(cpu: Rc<RefCell<Cpu>>) {
-    let mut pc: u16 = 0x0;
-    cpu.borrow_mut()
-        .memory
-        .write_byte(Memory::ADDR_MEMORY_LAYOUT, 0);
-    cpu.borrow_mut()
-        .memory
-        .load_ram("tests/6502_functional_test.bin", 0x400)
-        .unwrap();
-    cpu.borrow_mut().pc = 0x400;
-    loop {
-        println!("PC: {:#04X}", pc);
-        if pc == cpu.borrow().pc {
-            println!("Infinit loop at {:#04X}", pc);
-            break;
-        } else if cpu.borrow().pc == 0x3463 {
-            println!("Passed!");
-            break;
-        }
-        pc = cpu.borrow().pc;
-        if !cpu.borrow_mut().step() {
-            break;
-        }
+// Assembles a source file into a PRG, and writes its labels in the VICE
+// format for the debugger. Code without a "*=" goes to $C000.
+fn assemble_file(args: &ArgMatches) -> Result<Vec<u8>, String> {
+    let path = Path::new(args.get_one::<String>("path").unwrap());
+    let source = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let mut assembler = Assembler::new(0xc000);
+    assembler
+        .assemble(&source)
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+    let program = assembler.prg()?;
+
+    let output = args
+        .get_one::<String>("output")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| path.with_extension("prg"));
+    std::fs::write(&output, &program).map_err(|e| format!("{}: {}", output.display(), e))?;
+    let labels = args
+        .get_one::<String>("labels")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| path.with_extension("vs"));
+    std::fs::write(&labels, assembler.vice_labels())
+        .map_err(|e| format!("{}: {}", labels.display(), e))?;
+
+    let start = u16::from_le_bytes([program[0], program[1]]);
+    println!(
+        "{}: ${:04X}-${:04X}, {} labels in {}",
+        output.display(),
+        start,
+        start.wrapping_add((program.len() - 3) as u16),
+        assembler.labels().len(),
+        labels.display()
+    );
+    Ok(program)
+}
+
+// Disassembles a PRG, the first two bytes of which are its load address
+fn disasm_prg(path: &str, range: Option<RangeInclusive<u16>>) -> Result<(), String> {
+    let data = std::fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+    if data.len() < 3 {
+        return Err(format!("{}: not a PRG file", path));
+    }
+    let load = u16::from_le_bytes([data[0], data[1]]);
+    let end = load.saturating_add((data.len() - 3) as u16);
+    let mut ram = FlatRam::new();
+    ram.load(load, &data[2..]);
+
+    let range = range.unwrap_or(load..=end);
+    for instruction in disasm::disassemble(&ram, *range.start(), *range.end()) {
+        println!("{}", disasm::listing_line(&instruction));
     }
+    Ok(())
+}
+
+// Optional tools hooked into the main loop
+#[derive(Default)]
+struct RunOptions {
+    tracer: Option<Tracer>,
+    lockstep: Option<Lockstep>,
+    autostart: Option<Vec<u8>>, // PRG started once the KERNAL is ready
+    monitor: Option<Monitor>,   // Entered when a breakpoint is hit
+    history: Option<Rc<RefCell<History>>>,
+    profiling: Option<Profiling>,
 }
 
+// Instructions shown when the machine stops by itself
+const CRASH_HISTORY: usize = 20;
+
+// Instructions kept for stepping back when debugging, unless --history
+// says otherwise
+const DEBUG_HISTORY: usize = 10000;
+
+// Routines listed after profiling
+const PROFILE_ROUTINES: usize = 10;
+
 fn run_c64(
-    cpu: Rc<RefCell<Cpu>>,
+    cpu: Rc<RefCell<Cpu<Memory>>>,
     cia1: Rc<RefCell<Cia1>>,
     cia2: Rc<RefCell<Cia2>>,
+    vic: Rc<RefCell<Vic>>,
     io: Rc<RefCell<IO>>,
-) {
+    mut options: RunOptions,
+) -> Result<(), String> {
     loop {
         if !cia1.borrow_mut().step() {
             break;
         }
-        if !cpu.borrow_mut().step() {
+        if !cia2.borrow_mut().step() {
+            break;
+        }
+        if !vic.borrow_mut().step() {
+            break;
+        }
+        if cpu.borrow().pc == autostart::ADDR_READY {
+            if let Some(program) = options.autostart.take() {
+                autostart::autostart(&mut cpu.borrow_mut(), &program);
+                // Only the program counts, not the boot
+                if let Some(profiling) = options.profiling.as_mut() {
+                    profiling.profiler.clear();
+                }
+            }
+        }
+        if let Some(tracer) = options.tracer.as_mut() {
+            tracer.trace(&cpu.borrow(), vic.borrow().raster_position())?;
+        }
+        if let Some(lockstep) = options.lockstep.as_mut() {
+            match lockstep.check(&cpu.borrow(), vic.borrow().raster_position())? {
+                Check::Waiting | Check::Matched | Check::Interrupt => {}
+                Check::Finished(count) => {
+                    outln!("Reference trace ended, {} instructions matched", count);
+                    break;
+                }
+                Check::Diverged(divergence) => {
+                    out!("{}", divergence);
+                    break;
+                }
+            }
+        }
+        let pc = cpu.borrow().pc;
+        let cycles = cpu.borrow().cycles();
+        let running = match &options.history {
+            Some(history) => {
+                let raster = vic.borrow().raster_position();
+                history.borrow_mut().step(&mut cpu.borrow_mut(), raster)
+            }
+            None => cpu.borrow_mut().step(),
+        };
+        if let Some(profiling) = options.profiling.as_mut() {
+            let cpu = cpu.borrow();
+            let cycles = cpu.cycles().wrapping_sub(cycles);
+            profiling
+                .profiler
+                .record(pc, cycles, cpu.calls(), cpu.interrupted());
+        }
+        if !running {
+            outln!("CPU jammed at ${:04X}", cpu.borrow().pc);
+            // Show how the machine got there
+            let history = options.history.as_ref().map(|h| h.borrow());
+            if let Some(history) = history.filter(|h| !h.is_empty()) {
+                outln!("Last {} instructions:", history.len().min(CRASH_HISTORY));
+                for record in history.last(CRASH_HISTORY) {
+                    outln!("  {}", record);
+                }
+            }
             break;
         }
+        if let Some(monitor) = options.monitor.as_mut() {
+            if let Some(hit) = monitor.check(pc) {
+                outln!("{}", hit);
+                if let Exit::Quit = monitor.run() {
+                    break;
+                }
+            }
+        }
 
         if !io.borrow_mut().step() {
             break;
         }
     }
+    if let Some(profiling) = &options.profiling {
+        profiling.finish()?;
+    }
+    match options.tracer.as_mut() {
+        Some(tracer) => tracer.flush(),
+        None => Ok(()),
+    }
 }
 
-fn main() -> Result<(), String> {
-    let mut mem = Memory::new()?;
-    let cpu = Rc::new(RefCell::new(Cpu::new(&mut mem)));
-    let io = Rc::new(RefCell::new(IO::new(cpu.clone())?));
-    let cia1 = Rc::new(RefCell::new(Cia1::new(cpu.clone(), io.clone())));
-    let cia2 = Rc::new(RefCell::new(Cia2::new(cpu.clone())));
+// Tracer configured by the --trace options, if tracing was asked for
+fn tracer(matches: &ArgMatches) -> Result<Option<Tracer>, String> {
+    let Some(path) = matches.get_one::<String>("trace") else {
+        return Ok(None);
+    };
+    let mut tracer = Tracer::create(path)?;
+    for range in matches
+        .get_many::<String>("trace-range")
+        .into_iter()
+        .flatten()
+    {
+        tracer.add_range(trace::parse_range(range)?);
+    }
+    if let Some(rom) = matches.get_one::<String>("trace-rom") {
+        tracer.set_rom_filter(rom.parse::<RomFilter>()?);
+    }
+    // Operands are only named when asked for, so traces still diff
+    // against VICE ones
+    if matches.get_many::<String>("labels").is_some() {
+        tracer.set_symbols(symbols(matches)?);
+    }
+    Ok(Some(tracer))
+}
+
+// The ROM names and those of the --labels files
+fn symbols(matches: &ArgMatches) -> Result<Symbols, String> {
+    let mut symbols = Symbols::rom();
+    for path in matches.get_many::<String>("labels").into_iter().flatten() {
+        symbols.load(Path::new(path))?;
+    }
+    Ok(symbols)
+}
+
+// Profiler asked for with --profile, saved when the machine stops
+struct Profiling {
+    profiler: Profiler,
+    path: String,
+    format: ProfileFormat,
+    symbols: Symbols,
+}
+
+impl Profiling {
+    fn new(matches: &ArgMatches) -> Result<Option<Self>, String> {
+        let Some(path) = matches.get_one::<String>("profile") else {
+            return Ok(None);
+        };
+        let format = match matches.get_one::<String>("profile-format") {
+            Some(format) => format.parse()?,
+            None => ProfileFormat::default(),
+        };
+        Ok(Some(Profiling {
+            profiler: Profiler::new(),
+            path: path.clone(),
+            format,
+            symbols: symbols(matches)?,
+        }))
+    }
+
+    // Saves the profile and shows where most of the time went
+    fn finish(&self) -> Result<(), String> {
+        self.profiler.save(&self.path, self.format, &self.symbols)?;
+        let total = self.profiler.total().cycles.max(1);
+        outln!(
+            "Profile of {} cycles saved to {}",
+            self.profiler.total().cycles,
+            self.path
+        );
+        outln!("    cycles   own  incl  routine");
+        for cost in self.profiler.routines().iter().take(PROFILE_ROUTINES) {
+            outln!(
+                "{:>10} {:>4}% {:>4}%  {}",
+                cost.own,
+                cost.own * 100 / total,
+                cost.inclusive * 100 / total,
+                Profiler::name(&self.symbols, cost.routine)
+            );
+        }
+        Ok(())
+    }
+}
+
+fn lockstep(matches: &ArgMatches) -> Result<Option<Lockstep>, String> {
+    let Some(path) = matches.get_one::<String>("lockstep") else {
+        return Ok(None);
+    };
+    let depth = matches
+        .get_one::<usize>("lockstep-depth")
+        .copied()
+        .unwrap_or(Lockstep::DEFAULT_DEPTH);
+    Lockstep::open(path, depth).map(Some)
+}
+
+// Serves the remote debugger asked for on the command line until it
+// detaches or ends the program
+fn serve_debugger(matches: &ArgMatches, monitor: Monitor) -> Result<(Session, Monitor), String> {
+    let addr = |id| {
+        matches
+            .get_one::<u16>(id)
+            .map(|port| format!("127.0.0.1:{}", port))
+    };
+    if let Some(addr) = addr("gdb") {
+        let mut server = GdbServer::new(monitor);
+        let session = server.listen(&addr)?;
+        return Ok((session, server.into_target()));
+    }
+    if let Some(addr) = addr("binmon") {
+        let mut server = BinaryMonitor::new(monitor);
+        let session = server.listen(&addr)?;
+        return Ok((session, server.into_target()));
+    }
+    let mut server = DapServer::new(monitor);
+    server.set_symbols(symbols(matches)?);
+    let session = match addr("dap-port") {
+        Some(addr) => server.listen(&addr)?,
+        None => server.serve_stdio()?,
+    };
+    Ok((session, server.into_target()))
+}
 
+fn main() -> Result<(), String> {
     let matches = command!()
-        .subcommand(Command::new("debug"))
+        .arg(
+            Arg::new("trace")
+                .long("trace")
+                .value_name("FILE")
+                .help("Log every executed instruction in VICE trace format, - for stdout"),
+        )
+        .arg(
+            Arg::new("trace-range")
+                .long("trace-range")
+                .value_name("FROM-TO")
+                .action(ArgAction::Append)
+                .help("Only trace instructions in this address range, e.g. c000-cfff"),
+        )
+        .arg(
+            Arg::new("trace-rom")
+                .long("trace-rom")
+                .value_parser(["any", "inside", "outside"])
+                .help("Only trace instructions inside or outside of ROM"),
+        )
+        .arg(
+            Arg::new("lockstep")
+                .long("lockstep")
+                .value_name("FILE")
+                .help(
+                    "Compare every instruction with a reference trace and stop where they differ",
+                ),
+        )
+        .arg(
+            Arg::new("lockstep-depth")
+                .long("lockstep-depth")
+                .value_name("N")
+                .value_parser(value_parser!(usize))
+                .help("Instructions to show before a divergence"),
+        )
+        .arg(
+            Arg::new("history")
+                .long("history")
+                .value_name("N")
+                .value_parser(value_parser!(usize))
+                .help(
+                    "Instructions kept for the monitor's history and stepping back, \
+                     10000 when debugging and 0 otherwise",
+                ),
+        )
+        .arg(
+            Arg::new("profile").long("profile").value_name("FILE").help(
+                "Count the cycles spent in each routine and save them when the machine stops",
+            ),
+        )
+        .arg(
+            Arg::new("profile-format")
+                .long("profile-format")
+                .value_parser(["callgrind", "folded"])
+                .help("Save the profile for callgrind tools or as folded stacks for flame graphs"),
+        )
+        .arg(
+            Arg::new("labels")
+                .long("labels")
+                .value_name("FILE")
+                .action(ArgAction::Append)
+                .help("Load labels from a VICE, ACME, 64tass, KickAssembler or ca65 file"),
+        )
+        .arg(
+            Arg::new("break")
+                .long("break")
+                .value_name("ADDR")
+                .action(ArgAction::Append)
+                .help("Stop in the monitor at an address, e.g. \"c000 if a == $ff\""),
+        )
+        .arg(
+            Arg::new("watch")
+                .long("watch")
+                .value_name("ADDR")
+                .action(ArgAction::Append)
+                .help("Stop in the monitor on access to an address, e.g. \"store d020\""),
+        )
+        .arg(
+            Arg::new("trap")
+                .long("trap")
+                .value_name("EVENT")
+                .action(ArgAction::Append)
+                .value_parser(["irq", "nmi", "brk", "illegal", "jam", "banking", "stack"])
+                .help("Stop in the monitor on an event"),
+        )
+        .arg(
+            Arg::new("gdb")
+                .long("gdb")
+                .value_name("PORT")
+                .value_parser(value_parser!(u16))
+                .help("Wait for GDB to connect to this local port and let it drive the machine"),
+        )
+        .arg(
+            Arg::new("binmon")
+                .long("binmon")
+                .value_name("PORT")
+                .value_parser(value_parser!(u16))
+                .conflicts_with("gdb")
+                .help("Serve the VICE binary monitor protocol on this local port"),
+        )
+        .arg(
+            Arg::new("dap")
+                .long("dap")
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all(["gdb", "binmon"])
+                .help("Serve the Debug Adapter Protocol on stdio, for editors"),
+        )
+        .arg(
+            Arg::new("dap-port")
+                .long("dap-port")
+                .value_name("PORT")
+                .value_parser(value_parser!(u16))
+                .conflicts_with_all(["gdb", "binmon", "dap"])
+                .help("Serve the Debug Adapter Protocol on this local port"),
+        )
+        .subcommand(Command::new("debug").about("Start in the machine language monitor"))
         .subcommand(Command::new("test"))
+        .subcommand(
+            Command::new("asm")
+                .about("Assemble a source file into a PRG")
+                .arg(Arg::new("path").required(true).help("Source file"))
+                .arg(
+                    Arg::new("output")
+                        .short('o')
+                        .long("output")
+                        .value_name("FILE")
+                        .help("PRG to write, defaults to the source name with .prg"),
+                )
+                .arg(
+                    Arg::new("labels")
+                        .long("labels")
+                        .value_name("FILE")
+                        .help("VICE label file to write, defaults to the source name with .vs"),
+                )
+                .arg(
+                    Arg::new("run")
+                        .long("run")
+                        .action(ArgAction::SetTrue)
+                        .help("Start the program in the emulator"),
+                ),
+        )
+        .subcommand(
+            Command::new("disasm")
+                .about("Disassemble a PRG file at its load address")
+                .arg(Arg::new("path").required(true).help("PRG file"))
+                .arg(
+                    Arg::new("range")
+                        .long("range")
+                        .value_name("FROM-TO")
+                        .help("Only disassemble this address range, e.g. c000-c0ff"),
+                ),
+        )
+        .subcommand(
+            Command::new("lorenz")
+                .about("Run the Wolfgang Lorenz test suite")
+                .arg(
+                    Arg::new("path")
+                        .required(true)
+                        .help("D64 image or directory holding the tests"),
+                )
+                .arg(
+                    Arg::new("start")
+                        .long("start")
+                        .help("Test to start with, defaults to the first one"),
+                )
+                .arg(
+                    Arg::new("cycles")
+                        .long("cycles")
+                        .value_parser(value_parser!(u64))
+                        .help("Cycles a test may take before it times out"),
+                ),
+        )
         .get_matches();
 
-    match matches.subcommand_name() {
-        Some("debug") => {
-            println!("Debug mode enabled");
-            debug(cpu, cia1);
+    // The CPU test runs headless on a flat RAM
+    if matches.subcommand_name() == Some("test") {
+        println!("Test mode enabled");
+        return test_cpu();
+    }
+    if let Some(("disasm", args)) = matches.subcommand() {
+        let range = args
+            .get_one::<String>("range")
+            .map(|r| trace::parse_range(r))
+            .transpose()?;
+        return disasm_prg(args.get_one::<String>("path").unwrap(), range);
+    }
+
+    // Assembling runs headless, the machine is only needed to run the result
+    let mut autostart = None;
+    if let Some(("asm", args)) = matches.subcommand() {
+        let program = assemble_file(args)?;
+        if !args.get_flag("run") {
             return Ok(());
         }
-        Some("test") => {
-            println!("Test mode enabled");
-            test_cpu(cpu);
-            return Ok(());
+        autostart = Some(program);
+    }
+
+    let cpu = Rc::new(RefCell::new(Cpu::new(Memory::new()?)));
+    let io = Rc::new(RefCell::new(IO::new(cpu.clone())?));
+    let cia1 = Rc::new(RefCell::new(Cia1::new(cpu.clone(), io.clone())));
+    let cia2 = Rc::new(RefCell::new(Cia2::new(cpu.clone())));
+    let vic = Rc::new(RefCell::new(Vic::new(cpu.clone())));
+    let sid = Rc::new(RefCell::new(Sid::new()));
+    {
+        let memory = &mut cpu.borrow_mut().bus;
+        memory.attach_vic(vic.clone());
+        memory.attach_sid(sid.clone());
+        memory.attach_cia1(cia1.clone());
+        memory.attach_cia2(cia2.clone());
+    }
+
+    // Recording the history slows every instruction down, only debug
+    // sessions keep it unless asked to
+    let remote = ["gdb", "binmon", "dap-port"]
+        .into_iter()
+        .any(|id| matches.get_one::<u16>(id).is_some());
+    let debugging = remote || matches.get_flag("dap") || matches.subcommand_name() == Some("debug");
+    let capacity = match matches.get_one::<usize>("history") {
+        Some(&capacity) => capacity,
+        None if debugging => DEBUG_HISTORY,
+        None => 0,
+    };
+    let history = Rc::new(RefCell::new(History::new(capacity)));
+
+    // With DAP on stdio, stdout carries the protocol from the start
+    if matches.get_flag("dap") {
+        console::use_stderr();
+    }
+
+    // Breakpoints from the command line stop in the monitor
+    let mut monitor = Monitor::new(
+        cpu.clone(),
+        cia1.clone(),
+        cia2.clone(),
+        vic.clone(),
+        sid,
+        io.clone(),
+        history.clone(),
+    );
+    for path in matches.get_many::<String>("labels").into_iter().flatten() {
+        monitor.command(&format!("ll \"{}\"", path))?;
+    }
+    for command in ["break", "watch", "trap"] {
+        for spec in matches.get_many::<String>(command).into_iter().flatten() {
+            monitor.command(&format!("{} {}", command, spec))?;
+        }
+    }
+
+    // A remote debugger drives the machine until it detaches
+    if remote || matches.get_flag("dap") {
+        cpu.borrow_mut().reset();
+        let session;
+        (session, monitor) = serve_debugger(&matches, monitor)?;
+        if session == Session::Detached {
+            let options = RunOptions {
+                tracer: tracer(&matches)?,
+                lockstep: lockstep(&matches)?,
+                autostart,
+                monitor: Some(monitor),
+                history: Some(history),
+                profiling: Profiling::new(&matches)?,
+            };
+            run_c64(cpu, cia1, cia2, vic, io, options)?;
+        }
+        return Ok(());
+    }
+
+    match matches.subcommand() {
+        Some(("debug", _)) => {
+            cpu.borrow_mut().reset();
+            if let Exit::Run = monitor.run() {
+                let options = RunOptions {
+                    tracer: tracer(&matches)?,
+                    lockstep: lockstep(&matches)?,
+                    autostart,
+                    monitor: Some(monitor),
+                    history: Some(history),
+                    profiling: Profiling::new(&matches)?,
+                };
+                run_c64(cpu, cia1, cia2, vic, io, options)?;
+            }
+        }
+        Some(("lorenz", args)) => {
+            let source = Source::open(args.get_one::<String>("path").unwrap())?;
+            let cycles = args
+                .get_one::<u64>("cycles")
+                .copied()
+                .unwrap_or(Runner::DEFAULT_CYCLE_LIMIT);
+            let mut runner = Runner::new(cpu, cia1, cia2, vic, io, source, cycles);
+            let results = runner.run(args.get_one::<String>("start").map(String::as_str))?;
+            Runner::print_summary(&results);
+        }
+        _ => {
+            let options = RunOptions {
+                tracer: tracer(&matches)?,
+                lockstep: lockstep(&matches)?,
+                autostart,
+                monitor: (!monitor.breakpoints().is_empty()).then_some(monitor),
+                history: Some(history),
+                profiling: Profiling::new(&matches)?,
+            };
+            cpu.borrow_mut().reset();
+            run_c64(cpu, cia1, cia2, vic, io, options)?;
         }
-        _ => run_c64(cpu, cia1, cia2, io),
     }
 
     Ok(())
@@ -1,6 +1,10 @@
+use crate::bus::{Bus, IoDevice};
+use std::cell::RefCell;
 use std::fs::File;
 use std::io::{self, Read};
+use std::ops::RangeInclusive;
 use std::path::Path;
+use std::rc::Rc;
 
 // DRAM
 // $0000-$00FF  Page 0        Zeropage addressing
@@ -52,14 +56,42 @@ impl Banks {
     }
 }
 
+/// A CPU read or write of a watched address
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Access {
+    pub addr: u16,
+    pub value: u8,
+    pub write: bool,
+}
+
+/// A CPU write with the value it replaced, so it can be undone. Writes
+/// to chip registers can't be, `chip` marks them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MemoryWrite {
+    pub addr: u16,
+    pub old: u8,
+    pub value: u8,
+    pub chip: bool,
+}
+
+// Bits of Memory::watched
+const WATCH_READ: u8 = 1 << 0;
+const WATCH_WRITE: u8 = 1 << 1;
+
 pub struct Memory {
     mem_ram: Vec<u8>, // RAM buffer
     mem_rom: Vec<u8>, // ROM buffer
     banks: [u8; 7],   // Memory bank configurations
-                      // vic: Option<*mut Vic>, // Using raw pointers for external device references
-                      // cia1: Option<*mut Cia1>,
-                      // cia2: Option<*mut Cia2>,
-                      // sid: Option<*mut Sid>,
+    // Chips in the I/O area, reads and writes go to RAM while not attached
+    vic: Option<Rc<RefCell<dyn IoDevice>>>,
+    sid: Option<Rc<RefCell<dyn IoDevice>>>,
+    cia1: Option<Rc<RefCell<dyn IoDevice>>>,
+    cia2: Option<Rc<RefCell<dyn IoDevice>>>,
+    // Watchpoints, one byte per address and empty while nothing is watched
+    watched: Vec<u8>,
+    accesses: Vec<Access>,
+    banking_changed: Option<u8>, // Configuration before the last change
+    journal: Option<Vec<MemoryWrite>>, // CPU writes, while recording them
 }
 
 impl Memory {
@@ -80,6 +112,8 @@ impl Memory {
     pub const ADDR_ZERO_PAGE: u16 = 0x0000;
     pub const ADDR_VIC_FIRST_PAGE: u16 = 0xd000;
     pub const ADDR_VIC_LAST_PAGE: u16 = 0xd300;
+    pub const ADDR_SID_FIRST_PAGE: u16 = 0xd400;
+    pub const ADDR_SID_LAST_PAGE: u16 = 0xd700;
     pub const ADDR_CIA1_PAGE: u16 = 0xdc00;
     pub const ADDR_CIA2_PAGE: u16 = 0xdd00;
     pub const ADDR_BASIC_FIRST_PAGE: u16 = 0xa000;
@@ -89,6 +123,7 @@ impl Memory {
     pub const LORAM: u8 = 1 << 0;
     pub const HIRAM: u8 = 1 << 1;
     pub const CHAREN: u8 = 1 << 2;
+    pub const BANKING_BITS: u8 = Self::LORAM | Self::HIRAM | Self::CHAREN;
 
     pub fn new() -> Result<Self, String> {
         let mem_ram = vec![0; Memory::MEM_SIZE]; // 64KB buffer initialized to zero
@@ -99,15 +134,130 @@ impl Memory {
             mem_ram,
             mem_rom,
             banks,
+            vic: None,
+            sid: None,
+            cia1: None,
+            cia2: None,
+            watched: Vec::new(),
+            accesses: Vec::new(),
+            banking_changed: None,
+            journal: None,
         };
 
         memory
             .setup_memory_banks(Self::LORAM | Self::HIRAM | Self::CHAREN)
             .map_err(|e| format!("Failed to load ROMs: {}", e))?;
+        memory.banking_changed = None;
 
         Ok(memory)
     }
 
+    pub fn attach_vic(&mut self, vic: Rc<RefCell<dyn IoDevice>>) {
+        self.vic = Some(vic);
+    }
+
+    pub fn attach_sid(&mut self, sid: Rc<RefCell<dyn IoDevice>>) {
+        self.sid = Some(sid);
+    }
+
+    pub fn attach_cia1(&mut self, cia1: Rc<RefCell<dyn IoDevice>>) {
+        self.cia1 = Some(cia1);
+    }
+
+    pub fn attach_cia2(&mut self, cia2: Rc<RefCell<dyn IoDevice>>) {
+        self.cia2 = Some(cia2);
+    }
+
+    /// Records CPU reads and/or writes of `range` until the watches are
+    /// cleared. Accesses by the debugger through read_byte, write_byte or
+    /// peek are never recorded.
+    pub fn watch(&mut self, range: RangeInclusive<u16>, read: bool, write: bool) {
+        if self.watched.is_empty() {
+            self.watched = vec![0; Self::MEM_SIZE];
+        }
+        let bits = if read { WATCH_READ } else { 0 } | if write { WATCH_WRITE } else { 0 };
+        for addr in range {
+            self.watched[addr as usize] |= bits;
+        }
+    }
+
+    pub fn clear_watches(&mut self) {
+        self.watched = Vec::new();
+        self.accesses.clear();
+    }
+
+    /// Watched accesses since the last call, oldest first
+    pub fn take_accesses(&mut self) -> Vec<Access> {
+        std::mem::take(&mut self.accesses)
+    }
+
+    /// The banking configuration before and after the last change, if it
+    /// changed since the last call
+    pub fn take_banking_change(&mut self) -> Option<(u8, u8)> {
+        let old = self.banking_changed.take()?;
+        let new = self.mem_ram[Self::ADDR_MEMORY_LAYOUT as usize] & Self::BANKING_BITS;
+        (old != new).then_some((old, new))
+    }
+
+    /// Starts or stops recording the CPU writes for take_writes
+    pub fn journal_writes(&mut self, enabled: bool) {
+        match (enabled, &self.journal) {
+            (true, None) => self.journal = Some(Vec::new()),
+            (false, _) => self.journal = None,
+            _ => {}
+        }
+    }
+
+    /// CPU writes since the last call, oldest first
+    pub fn take_writes(&mut self) -> Vec<MemoryWrite> {
+        self.journal
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    /// Puts back the value a write replaced. Chip registers are left as
+    /// they are.
+    pub fn undo_write(&mut self, write: &MemoryWrite) {
+        if write.chip {
+            return;
+        }
+        if write.addr == Self::ADDR_MEMORY_LAYOUT {
+            self.setup_memory_banks(write.old)
+                .expect("Failed to set up memory banks");
+        } else {
+            self.mem_ram[write.addr as usize] = write.old;
+        }
+    }
+
+    // Whether a write to `addr` goes to a chip rather than RAM
+    fn writes_chip(&self, addr: u16) -> bool {
+        let page = addr & 0xff00;
+        let device = if (Self::ADDR_VIC_FIRST_PAGE..=Self::ADDR_VIC_LAST_PAGE).contains(&page) {
+            &self.vic
+        } else if (Self::ADDR_SID_FIRST_PAGE..=Self::ADDR_SID_LAST_PAGE).contains(&page) {
+            &self.sid
+        } else if page == Self::ADDR_CIA1_PAGE {
+            &self.cia1
+        } else if page == Self::ADDR_CIA2_PAGE {
+            &self.cia2
+        } else {
+            return false;
+        };
+        device.is_some() && self.io_visible()
+    }
+
+    fn record(&mut self, addr: u16, value: u8, write: bool) {
+        let bit = if write { WATCH_WRITE } else { WATCH_READ };
+        if self
+            .watched
+            .get(addr as usize)
+            .is_some_and(|w| w & bit != 0)
+        {
+            self.accesses.push(Access { addr, value, write });
+        }
+    }
+
     // Writes a byte to RAM without performing I/O
     pub fn write_byte_no_io(&mut self, addr: u16, value: u8) {
         self.mem_ram[addr as usize] = value;
@@ -124,25 +274,25 @@ impl Memory {
             } else {
                 self.mem_ram[addr as usize] = value;
             }
-        } else if page >= Self::ADDR_VIC_FIRST_PAGE && addr <= Self::ADDR_VIC_LAST_PAGE {
-            if self.banks[Banks::BankCharen.to_usize()] == BankCfg::Io.as_u8() {
-                // vic.write_register(addr&0x7f, value);
-                todo!();
-            } else {
-                self.mem_ram[addr as usize] = value;
+        } else if (Self::ADDR_VIC_FIRST_PAGE..=Self::ADDR_VIC_LAST_PAGE).contains(&page) {
+            match (&self.vic, self.io_visible()) {
+                (Some(vic), true) => vic.borrow_mut().write_register((addr & 0x3f) as u8, value),
+                _ => self.mem_ram[addr as usize] = value,
+            }
+        } else if (Self::ADDR_SID_FIRST_PAGE..=Self::ADDR_SID_LAST_PAGE).contains(&page) {
+            match (&self.sid, self.io_visible()) {
+                (Some(sid), true) => sid.borrow_mut().write_register((addr & 0x1f) as u8, value),
+                _ => self.mem_ram[addr as usize] = value,
             }
         } else if page == Self::ADDR_CIA1_PAGE {
-            if self.banks[Banks::BankCharen.to_usize()] == BankCfg::Io.as_u8() {
-                // cia1.write_register(addr&0x0f, value);
-                todo!();
-            } else {
-                self.mem_ram[addr as usize] = value;
+            match (&self.cia1, self.io_visible()) {
+                (Some(cia1), true) => cia1.borrow_mut().write_register((addr & 0x0f) as u8, value),
+                _ => self.mem_ram[addr as usize] = value,
             }
         } else if page == Self::ADDR_CIA2_PAGE {
-            if self.banks[Banks::BankCharen.to_usize()] == BankCfg::Io.as_u8() {
-                // cia2.write_register(addr&0x0f, value);
-            } else {
-                self.mem_ram[addr as usize] = value;
+            match (&self.cia2, self.io_visible()) {
+                (Some(cia2), true) => cia2.borrow_mut().write_register((addr & 0x0f) as u8, value),
+                _ => self.mem_ram[addr as usize] = value,
             }
         } else {
             self.mem_ram[addr as usize] = value;
@@ -151,38 +301,65 @@ impl Memory {
 
     // Reads a byte from RAM or ROM depending on the bank configuration
     pub fn read_byte(&self, addr: u16) -> u8 {
+        self.read_mapped(addr, false)
+    }
+
+    // Reads a byte like read_byte, but I/O registers are peeked so the read
+    // has no side effects on the chips
+    pub fn peek_byte(&self, addr: u16) -> u8 {
+        self.read_mapped(addr, true)
+    }
+
+    fn io_visible(&self) -> bool {
+        self.banks[Banks::BankCharen.to_usize()] == BankCfg::Io.as_u8()
+    }
+
+    /// Whether reads from `addr` come from ROM with the current banking
+    pub fn is_rom(&self, addr: u16) -> bool {
+        let bank = match addr {
+            0xa000..=0xbfff => Banks::BankBasic,
+            0xd000..=0xdfff => Banks::BankCharen,
+            0xe000..=0xffff => Banks::BankKernal,
+            _ => return false,
+        };
+        self.banks[bank.to_usize()] == BankCfg::Rom.as_u8()
+    }
+
+    fn read_register(device: &Option<Rc<RefCell<dyn IoDevice>>>, r: u8, peek: bool) -> Option<u8> {
+        let device = device.as_ref()?;
+        if peek {
+            Some(device.borrow().peek_register(r))
+        } else {
+            Some(device.borrow_mut().read_register(r))
+        }
+    }
+
+    fn read_mapped(&self, addr: u16, peek: bool) -> u8 {
         let page = addr & 0xff00;
         match page {
             _ if (Self::ADDR_VIC_FIRST_PAGE..=Self::ADDR_VIC_LAST_PAGE).contains(&page) => {
-                // match self.banks[Banks::BankCharen.to_usize()] {
-                //     BankCfg::Io => self.vic.read_register(addr & 0x7f),
-                //     BankCfg::Rom => self.mem_rom[addr as usize],
-                //     _ => self.mem_ram[addr as usize],
-                // }
-                if self.banks[Banks::BankCharen.to_usize()] == BankCfg::Io.as_u8() {
-                    // self.vic.read_register(addr & 0x7f)
-                    todo!();
+                if self.io_visible() {
+                    Self::read_register(&self.vic, (addr & 0x3f) as u8, peek)
+                        .unwrap_or(self.mem_ram[addr as usize])
                 } else if self.banks[Banks::BankCharen.to_usize()] == BankCfg::Rom.as_u8() {
                     self.mem_rom[addr as usize]
                 } else {
                     self.mem_ram[addr as usize]
                 }
             }
-            _ if page == Self::ADDR_CIA1_PAGE => {
-                if self.banks[Banks::BankCharen.to_usize()] == BankCfg::Io.as_u8() {
-                    // self.cia1.read_register(addr & 0x0f)
-                    todo!();
-                } else {
-                    self.mem_ram[addr as usize]
-                }
+            _ if (Self::ADDR_SID_FIRST_PAGE..=Self::ADDR_SID_LAST_PAGE).contains(&page)
+                && self.io_visible() =>
+            {
+                Self::read_register(&self.sid, (addr & 0x1f) as u8, peek)
+                    .unwrap_or(self.mem_ram[addr as usize])
             }
-            _ if page == Self::ADDR_CIA2_PAGE => {
-                if self.banks[Banks::BankCharen.to_usize()] == BankCfg::Io.as_u8() {
-                    // self.cia2.read_register(addr & 0x0f)
-                    todo!();
-                } else {
-                    self.mem_ram[addr as usize]
-                }
+            _ if page == Self::ADDR_CIA1_PAGE && self.io_visible() => {
+                Self::read_register(&self.cia1, (addr & 0x0f) as u8, peek)
+                    .unwrap_or(self.mem_ram[addr as usize])
+            }
+            _ if page == Self::ADDR_CIA2_PAGE && self.io_visible() => {
+                Self::read_register(&self.cia2, (addr & 0x0f) as u8, peek)
+                    .unwrap_or(self.mem_ram[addr as usize])
             }
             _ if (Self::ADDR_BASIC_FIRST_PAGE..=Self::ADDR_BASIC_LAST_PAGE).contains(&page) => {
                 if self.banks[Banks::BankBasic.to_usize()] == BankCfg::Rom.as_u8() {
@@ -207,12 +384,28 @@ impl Memory {
         self.mem_ram[addr as usize]
     }
 
+    /// Reads a byte the way the VIC sees its 16K `bank`: RAM, except for
+    /// the character ROM at $1000-$1FFF of banks 0 and 2
+    pub fn vic_read(&self, bank: u8, addr: u16) -> u8 {
+        let addr = addr & 0x3fff;
+        if bank & 1 == 0 && (0x1000..0x2000).contains(&addr) {
+            self.mem_rom[(Self::BASE_ADDR_CHARS | (addr & 0x0fff)) as usize]
+        } else {
+            self.mem_ram[((bank as usize & 3) << 14) | addr as usize]
+        }
+    }
+
     // Sets up the memory bank configuration based on specific flags
     pub fn setup_memory_banks(&mut self, config: u8) -> io::Result<()> {
         let hiram = (config & Self::HIRAM) != 0;
         let loram = (config & Self::LORAM) != 0;
         let charen = (config & Self::CHAREN) != 0;
 
+        let old = self.mem_ram[Self::ADDR_MEMORY_LAYOUT as usize] & Self::BANKING_BITS;
+        if old != config & Self::BANKING_BITS {
+            self.banking_changed.get_or_insert(old);
+        }
+
         // Initialize everything to RAM
         for bank in self.banks.iter_mut() {
             *bank = BankCfg::Ram.as_u8();
@@ -247,7 +440,7 @@ impl Memory {
     /// Reads a 16-bit word from memory at the given address
     pub fn read_word(&self, addr: u16) -> u16 {
         let lsb = self.read_byte(addr) as u16;
-        let msb = self.read_byte(addr + 1) as u16;
+        let msb = self.read_byte(addr.wrapping_add(1)) as u16;
         (msb << 8) | lsb
     }
 
@@ -256,7 +449,7 @@ impl Memory {
         let lsb = (value & 0xFF) as u8;
         let msb = ((value >> 8) & 0xFF) as u8;
         self.write_byte(addr, lsb);
-        self.write_byte(addr + 1, msb);
+        self.write_byte(addr.wrapping_add(1), msb);
     }
 
     pub fn load_rom(&mut self, filename: &str, baseaddr: u16) -> io::Result<()> {
@@ -297,3 +490,41 @@ impl Memory {
         Ok(())
     }
 }
+
+impl Bus for Memory {
+    fn read(&mut self, addr: u16) -> u8 {
+        let value = self.read_byte(addr);
+        self.record(addr, value, false);
+        value
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        if self.journal.is_some() {
+            let chip = self.writes_chip(addr);
+            let old = if chip {
+                self.peek_byte(addr)
+            } else {
+                self.mem_ram[addr as usize]
+            };
+            if let Some(journal) = &mut self.journal {
+                match journal.last_mut() {
+                    // Read-modify-write instructions write the old value
+                    // back before the new one, keep that as one write
+                    Some(last) if last.addr == addr => last.value = value,
+                    _ => journal.push(MemoryWrite {
+                        addr,
+                        old,
+                        value,
+                        chip,
+                    }),
+                }
+            }
+        }
+        self.write_byte(addr, value);
+        self.record(addr, value, true);
+    }
+
+    fn peek(&self, addr: u16) -> u8 {
+        self.peek_byte(addr)
+    }
+}
@@ -0,0 +1,1279 @@
+// Machine language monitor in the style of VICE and the Action Replay
+// cartridge. Every command fits on one line, numbers are hex with an
+// optional '$', '+' marks a decimal and '%' a binary number. Labels can
+// stand in for addresses, written ".name" as in VICE.
+//
+// Memory is read through peek so that looking at I/O registers doesn't
+// acknowledge interrupts or clear latches. Writes go through the bus like
+// a store instruction would, but never trigger watchpoints.
+use crate::autostart;
+use crate::cia1::Cia1;
+use crate::cia2::Cia2;
+use crate::io::IO;
+use crate::sid::Sid;
+use crate::vic::Vic;
+use c64_rs::asm::Assembler;
+use c64_rs::breakpoint::{Breakpoints, Condition, Hit, Kind, Trap};
+use c64_rs::cpu::Cpu;
+use c64_rs::d64::D64;
+use c64_rs::disasm::{self, Instruction};
+use c64_rs::flags::Flags;
+use c64_rs::history::History;
+use c64_rs::memory::Memory;
+use c64_rs::symbols::Symbols;
+use c64_rs::target::{Step, Target};
+use c64_rs::trace::TraceLine;
+use std::cell::RefCell;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::rc::Rc;
+
+const HELP: &str = "\
+r [reg=value ...]        show or set registers (a x y sp p pc)
+m [start [end]]          show memory
+d [start [end]]          disassemble
+a [addr] [instruction]   assemble, without an instruction until an empty line
+g [addr]                 run until a breakpoint, a BRK or the machine stops
+z [count]                step into
+n [count]                step over subroutine calls
+back [count]             step backwards through the execution history
+chis [count]             show the last instructions run, 20 by default
+ret                      run until the current subroutine returns
+bt                       show the call stack
+break [start [end]] [if condition]
+                         add an execution breakpoint, or list them
+watch [load|store|io] start [end] [if condition]
+                         add a watchpoint on loads and/or stores, io
+                         catches register stores through any mirror
+trap [event ...]         stop on irq, nmi, brk, illegal, jam, banking or a
+                         stack the call stack can't follow, without an
+                         event lists them
+untrap [event ...]       no longer stop on events, all without one
+delete [n]               delete breakpoint n, or all of them
+enable n, disable n      switch breakpoint n on or off
+cond n [condition]       set or remove the condition of breakpoint n
+f start end byte ...     fill memory with a pattern
+t start end dest         transfer memory
+h start end byte|\"text\" hunt for bytes
+c start end dest         compare memory
+l \"file\" [addr]          load a PRG, at its own load address by default
+s \"file\" start end       save memory as a PRG
+ll \"file\"                load labels from a VICE, ACME, 64tass, KickAssembler
+                         or ca65 file
+al addr .name            add a label
+shl                      list the labels
+io [vic|cia1|cia2|sid]   show the decoded state of a chip, or of all of them
+history                  list commands, !! repeats the last one, !n number n
+x                        leave the monitor and run
+q                        quit
+An empty line repeats z, n, m and d, carrying on where they stopped.
+Conditions use registers a x y sp pc p, cycles, the raster line rl and
+cycle cy, and @addr for memory, e.g. \"a == $ff && @d012 > $80\".
+Labels such as .CHROUT can be used wherever an address goes.";
+
+// Lines shown by m and instructions shown by d without an end address
+const MEMORY_LINES: u16 = 8;
+const DISASM_LINES: usize = 16;
+const CHIS_LINES: u32 = 20;
+
+/// How the monitor was left
+pub enum Exit {
+    Run,
+    Quit,
+}
+
+pub struct Monitor {
+    cpu: Rc<RefCell<Cpu<Memory>>>,
+    cia1: Rc<RefCell<Cia1>>,
+    cia2: Rc<RefCell<Cia2>>,
+    vic: Rc<RefCell<Vic>>,
+    sid: Rc<RefCell<Sid>>,
+    io: Rc<RefCell<IO>>,
+    assembler: Assembler, // Labels stay defined between "a" commands
+    symbols: Symbols,
+    breakpoints: Breakpoints,
+    execution: Rc<RefCell<History>>, // Instructions run, for going back
+    history: Vec<String>,
+    next_memory: u16,
+    next_disasm: Option<u16>, // None starts at PC
+}
+
+impl Monitor {
+    pub fn new(
+        cpu: Rc<RefCell<Cpu<Memory>>>,
+        cia1: Rc<RefCell<Cia1>>,
+        cia2: Rc<RefCell<Cia2>>,
+        vic: Rc<RefCell<Vic>>,
+        sid: Rc<RefCell<Sid>>,
+        io: Rc<RefCell<IO>>,
+        execution: Rc<RefCell<History>>,
+    ) -> Self {
+        Monitor {
+            cpu,
+            cia1,
+            cia2,
+            vic,
+            sid,
+            io,
+            assembler: Assembler::new(0),
+            symbols: Symbols::rom(),
+            breakpoints: Breakpoints::new(),
+            execution,
+            history: Vec::new(),
+            next_memory: 0,
+            next_disasm: None,
+        }
+    }
+
+    /// Reads commands from stdin until x, q or the end of input
+    pub fn run(&mut self) -> Exit {
+        self.show_registers();
+        loop {
+            out!("(C:${:04X}) ", self.cpu.borrow().pc);
+            let Some(line) = read_line() else {
+                outln!();
+                return Exit::Quit;
+            };
+            let line = match self.expand(line.trim()) {
+                Ok(line) => line,
+                Err(e) => {
+                    outln!("Error: {}", e);
+                    continue;
+                }
+            };
+            match self.execute(&line) {
+                Ok(Some(exit)) => return exit,
+                Ok(None) => {}
+                Err(e) => outln!("Error: {}", e),
+            }
+        }
+    }
+
+    /// Runs a single command, such as a breakpoint from the command line
+    pub fn command(&mut self, line: &str) -> Result<(), String> {
+        self.execute(line).map(|_| ())
+    }
+
+    /// Checks the breakpoints after the instruction at `from` has run
+    pub fn check(&mut self, from: u16) -> Option<Hit> {
+        let raster = self.vic.borrow().raster_position();
+        self.breakpoints
+            .check(&mut self.cpu.borrow_mut(), from, raster)
+    }
+
+    // Resolves the history shortcuts and records the line
+    fn expand(&mut self, line: &str) -> Result<String, String> {
+        let line = if line.is_empty() {
+            // Only commands that carry on make sense to repeat
+            match self.history.last().map(|l| command_name(l)) {
+                Some(name @ ("z" | "n" | "m" | "d")) => name.to_string(),
+                _ => return Ok(String::new()),
+            }
+        } else if line == "!!" {
+            self.history.last().cloned().ok_or("history is empty")?
+        } else if let Some(n) = line.strip_prefix('!') {
+            let n: usize = n
+                .parse()
+                .map_err(|_| format!("bad history number: {}", n))?;
+            self.history
+                .get(n.wrapping_sub(1))
+                .cloned()
+                .ok_or(format!("no command {} in history", n))?
+        } else {
+            line.to_string()
+        };
+        if !line.is_empty() && self.history.last() != Some(&line) {
+            self.history.push(line.clone());
+        }
+        Ok(line)
+    }
+
+    fn execute(&mut self, line: &str) -> Result<Option<Exit>, String> {
+        let name = command_name(line);
+        let rest = line[name.len()..].trim();
+        // The assembler has labels of its own, and the label commands take
+        // names rather than use them
+        let resolved;
+        let rest = match name.to_lowercase().as_str() {
+            "a" | "al" | "ll" => rest,
+            _ => {
+                resolved = self.symbols.resolve(rest)?;
+                resolved.as_str()
+            }
+        };
+        let args = split_args(rest)?;
+        match name.to_lowercase().as_str() {
+            "" => {}
+            "r" => self.registers(rest)?,
+            "m" => self.memory(&args)?,
+            "d" => self.disassemble(&args)?,
+            "a" => self.assemble(rest)?,
+            "g" => {
+                if let Some(addr) = args.first() {
+                    self.cpu.borrow_mut().pc = parse_addr(addr)?;
+                }
+                self.go();
+            }
+            "z" => {
+                for _ in 0..count(&args)? {
+                    if !self.step() {
+                        break;
+                    }
+                    self.show_step();
+                }
+                self.next_disasm = None;
+            }
+            "n" => {
+                for _ in 0..count(&args)? {
+                    if !self.step_over() {
+                        break;
+                    }
+                    self.show_step();
+                }
+                self.next_disasm = None;
+            }
+            "back" => {
+                for _ in 0..count(&args)? {
+                    let record = self
+                        .execution
+                        .borrow_mut()
+                        .step_back(&mut self.cpu.borrow_mut());
+                    match record {
+                        Some(record) => outln!("{}", record),
+                        None => {
+                            outln!("No execution history left");
+                            break;
+                        }
+                    }
+                }
+                self.next_disasm = None;
+            }
+            "chis" => {
+                let count = args.first().map_or(Ok(CHIS_LINES), |n| parse_number(n))?;
+                let execution = self.execution.borrow();
+                for record in execution.last(count as usize) {
+                    outln!("{}", record);
+                }
+            }
+            "bt" => {
+                let cpu = self.cpu.borrow();
+                for (i, (pc, frame)) in cpu.calls().backtrace(cpu.pc).iter().enumerate() {
+                    match frame {
+                        Some(frame) => {
+                            outln!(
+                                "#{:<3} ${:04X}  in {}",
+                                i,
+                                pc,
+                                self.symbols.frame_name(frame)
+                            )
+                        }
+                        None => outln!("#{:<3} ${:04X}", i, pc),
+                    }
+                }
+            }
+            "ret" => {
+                self.step_out();
+                self.show_step();
+            }
+            "f" => self.fill(&args)?,
+            "t" => self.transfer(&args)?,
+            "h" => self.hunt(&args)?,
+            "c" => self.compare(&args)?,
+            "l" => self.load(&args)?,
+            "s" => self.save(&args)?,
+            "break" => self.add_breakpoint(rest, Kind::Exec)?,
+            "watch" => self.add_watchpoint(rest)?,
+            "trap" if args.is_empty() => {
+                let mut traps: Vec<String> = Trap::ALL
+                    .iter()
+                    .filter(|trap| self.breakpoints.traps().contains(trap))
+                    .map(Trap::to_string)
+                    .collect();
+                if traps.is_empty() {
+                    traps.push("none".to_string());
+                }
+                outln!("Stopping on: {}", traps.join(", "));
+            }
+            "trap" | "untrap" => {
+                let enable = name.eq_ignore_ascii_case("trap");
+                let traps = if args.is_empty() {
+                    Trap::ALL.to_vec()
+                } else {
+                    args.iter()
+                        .map(|arg| arg.parse())
+                        .collect::<Result<Vec<Trap>, String>>()?
+                };
+                for trap in traps {
+                    self.breakpoints.set_trap(trap, enable);
+                }
+            }
+            "delete" => {
+                match args.first() {
+                    Some(id) => self.breakpoints.remove(parse_id(id)?)?,
+                    None => self.breakpoints.clear(),
+                }
+                self.sync_breakpoints();
+            }
+            "enable" | "disable" => {
+                let id = parse_id(args.first().ok_or("missing breakpoint number")?)?;
+                self.breakpoints.get_mut(id)?.enabled = name.eq_ignore_ascii_case("enable");
+                self.sync_breakpoints();
+            }
+            "cond" => {
+                let (id, condition) = match rest.split_once(char::is_whitespace) {
+                    Some((id, condition)) => (id, condition.trim()),
+                    None => (rest, ""),
+                };
+                let condition = condition.strip_prefix("if ").unwrap_or(condition);
+                let condition = match condition {
+                    "" => None,
+                    _ => Some(Condition::parse(condition)?),
+                };
+                self.breakpoints.get_mut(parse_id(id)?)?.condition = condition;
+            }
+            "history" => {
+                for (i, line) in self.history.iter().enumerate() {
+                    outln!("{:4}  {}", i + 1, line);
+                }
+            }
+            "ll" => {
+                let path = unquote(args.first().ok_or("missing file name")?);
+                let count = self.symbols.load(Path::new(path))?;
+                outln!("Loaded {} labels from {}", count, path);
+            }
+            "al" => {
+                let (Some(addr), Some(label)) = (args.first(), args.get(1)) else {
+                    return Err("usage: al addr .name".to_string());
+                };
+                let addr = parse_addr(addr)?;
+                self.symbols
+                    .add(label.strip_prefix('.').unwrap_or(label), addr);
+            }
+            "shl" => {
+                for (addr, name) in self.symbols.iter() {
+                    outln!("${:04X} .{}", addr, name);
+                }
+            }
+            "io" => self.show_chips(rest)?,
+            "?" | "help" => outln!("{}", HELP),
+            "x" => return Ok(Some(Exit::Run)),
+            "q" => return Ok(Some(Exit::Quit)),
+            _ => return Err(format!("unknown command: {}, ? lists them", name)),
+        }
+        Ok(None)
+    }
+
+    fn step(&mut self) -> bool {
+        if !self.step_machine() {
+            outln!("Machine stopped at ${:04X}", self.cpu.borrow().pc);
+            return false;
+        }
+        true
+    }
+
+    // One step of the machine in the same order as run_c64
+    fn step_machine(&mut self) -> bool {
+        if !(self.cia1.borrow_mut().step()
+            && self.cia2.borrow_mut().step()
+            && self.vic.borrow_mut().step())
+        {
+            return false;
+        }
+        let raster = self.vic.borrow().raster_position();
+        self.execution
+            .borrow_mut()
+            .step(&mut self.cpu.borrow_mut(), raster)
+            && self.io.borrow_mut().step()
+    }
+
+    // Steps at least once, then until `done` holds, a breakpoint is hit or
+    // a BRK is next
+    fn run_until(&mut self, done: impl Fn(&Cpu<Memory>) -> bool) -> bool {
+        loop {
+            match self.step_checked() {
+                Step::Done => {}
+                Step::Hit(hit) => {
+                    outln!("{}", hit);
+                    return false;
+                }
+                Step::Halted => return false,
+            }
+            let cpu = self.cpu.borrow();
+            if done(&cpu) {
+                return true;
+            }
+            if cpu.peek_memory(cpu.pc) == 0x00 {
+                outln!("BRK at ${:04X}", cpu.pc);
+                return false;
+            }
+        }
+    }
+
+    fn go(&mut self) {
+        self.run_until(|_| false);
+        self.next_disasm = None;
+        self.show_registers();
+    }
+
+    // A JSR runs until the CPU is back after it with the same stack
+    fn step_over(&mut self) -> bool {
+        let (instruction, sp) = {
+            let cpu = self.cpu.borrow();
+            (Instruction::decode(cpu.pc, |a| cpu.peek_memory(a)), cpu.sp)
+        };
+        if instruction.opcode != 0x20 {
+            return self.step();
+        }
+        let next = instruction.next();
+        self.run_until(|cpu| cpu.pc == next && cpu.sp >= sp)
+    }
+
+    // The routine has returned once the stack is above where it started
+    fn step_out(&mut self) -> bool {
+        let sp = self.cpu.borrow().sp;
+        self.run_until(|cpu| cpu.sp > sp)
+    }
+
+    // Chip state from the chips' own accessors, so nothing is acknowledged
+    fn show_chips(&self, chip: &str) -> Result<(), String> {
+        let chip = chip.to_lowercase();
+        let chips = match chip.as_str() {
+            "" => vec!["vic", "cia1", "cia2", "sid"],
+            chip @ ("vic" | "cia1" | "cia2" | "sid") => vec![chip],
+            _ => {
+                return Err(format!(
+                    "unknown chip: {}, use vic, cia1, cia2 or sid",
+                    chip
+                ))
+            }
+        };
+        for chip in chips {
+            match chip {
+                "vic" => {
+                    let bank = (self.cia2.borrow().vic_base_address() >> 14) as u8;
+                    outln!("VIC-II\n{}", self.vic.borrow().state(bank));
+                }
+                "cia1" => outln!("CIA 1 $DC00\n{}", self.cia1.borrow().state()),
+                "cia2" => outln!("CIA 2 $DD00\n{}", self.cia2.borrow().state()),
+                _ => outln!("SID $D400\n{}", self.sid.borrow().state()),
+            }
+        }
+        Ok(())
+    }
+
+    fn show_step(&self) {
+        let raster = self.vic.borrow().raster_position();
+        let line = TraceLine::capture(&self.cpu.borrow(), raster);
+        outln!(
+            "{}",
+            line.to_string_with(|addr, zp| self.symbols.operand(addr, zp))
+        );
+    }
+
+    fn show_registers(&self) {
+        let cpu = self.cpu.borrow();
+        let (line, cycle) = self.vic.borrow().raster_position();
+        outln!("  ADDR A  X  Y  SP 00 01 NV-BDIZC LIN CYC  CYCLES");
+        outln!(
+            ".;{:04X} {:02X} {:02X} {:02X} {:02X} {:02X} {:02X} {} {:03} {:03}  {}",
+            cpu.pc,
+            cpu.a,
+            cpu.x,
+            cpu.y,
+            cpu.sp,
+            cpu.peek_memory(0x00),
+            cpu.peek_memory(0x01),
+            cpu.p,
+            line,
+            cycle,
+            cpu.cycles()
+        );
+    }
+
+    // "r" shows the registers, "r a=01, pc=c000" sets them
+    fn registers(&mut self, rest: &str) -> Result<(), String> {
+        if !rest.is_empty() {
+            // Check everything before changing anything
+            let mut changes = Vec::new();
+            for assignment in rest.split(',').flat_map(|s| s.split_whitespace()) {
+                let (name, value) = assignment
+                    .split_once('=')
+                    .ok_or(format!("expected register=value: {}", assignment))?;
+                let name = name.to_lowercase();
+                let value = if name == "pc" {
+                    parse_addr(value)?
+                } else {
+                    parse_byte(value)? as u16
+                };
+                if !matches!(name.as_str(), "a" | "x" | "y" | "sp" | "p" | "pc") {
+                    return Err(format!("unknown register: {}", name));
+                }
+                changes.push((name, value));
+            }
+            let mut cpu = self.cpu.borrow_mut();
+            for (name, value) in changes {
+                match name.as_str() {
+                    "a" => cpu.a = value as u8,
+                    "x" => cpu.x = value as u8,
+                    "y" => cpu.y = value as u8,
+                    "sp" => cpu.sp = value as u8,
+                    "p" => cpu.p = Flags::from_stack(value as u8),
+                    _ => cpu.pc = value,
+                }
+            }
+            self.next_disasm = None;
+        }
+        self.show_registers();
+        Ok(())
+    }
+
+    fn memory(&mut self, args: &[String]) -> Result<(), String> {
+        let start = match args.first() {
+            Some(start) => parse_addr(start)?,
+            None => self.next_memory,
+        };
+        let end = match args.get(1) {
+            Some(end) => parse_end(start, end)?,
+            None => start.saturating_add(MEMORY_LINES * 16 - 1),
+        };
+        let cpu = self.cpu.borrow();
+        let mut addr = start as u32;
+        while addr <= end as u32 {
+            let last = (addr + 15).min(end as u32);
+            let bytes: Vec<u8> = (addr..=last).map(|a| cpu.peek_memory(a as u16)).collect();
+            let hex: Vec<String> = bytes.iter().map(|b| format!("{:02X}", b)).collect();
+            let text: String = bytes.iter().map(|&b| printable(b)).collect();
+            outln!(">C:{:04X}  {:<48} {}", addr, hex.join(" "), text);
+            addr += 16;
+        }
+        self.next_memory = addr as u16;
+        Ok(())
+    }
+
+    fn disassemble(&mut self, args: &[String]) -> Result<(), String> {
+        let cpu = self.cpu.borrow();
+        let start = match args.first() {
+            Some(start) => parse_addr(start)?,
+            None => self.next_disasm.unwrap_or(cpu.pc),
+        };
+        let instructions = match args.get(1) {
+            Some(end) => disasm::disassemble(&cpu.bus, start, parse_end(start, end)?),
+            None => {
+                let mut addr = start;
+                let mut instructions = Vec::new();
+                for _ in 0..DISASM_LINES {
+                    let instruction = Instruction::decode(addr, |a| cpu.peek_memory(a));
+                    addr = instruction.next();
+                    instructions.push(instruction);
+                }
+                instructions
+            }
+        };
+        let name = |addr, zero_page| self.symbols.operand(addr, zero_page);
+        for instruction in &instructions {
+            if let Some(label) = self.symbols.name(instruction.addr) {
+                outln!(".{}:", label);
+            }
+            let marker = if instruction.addr == cpu.pc { '>' } else { ' ' };
+            let listing = disasm::listing_line_with(instruction, name);
+            match self.symbols.lines().line(instruction.addr) {
+                Some((path, line)) => {
+                    let file = path.file_name().unwrap_or_default().to_string_lossy();
+                    outln!("{}{:<32}  ; {}:{}", marker, listing, file, line);
+                }
+                None => outln!("{}{}", marker, listing),
+            }
+        }
+        self.next_disasm = instructions.last().map(Instruction::next);
+        Ok(())
+    }
+
+    // "a c000 lda #$00" assembles one instruction, "a c000" reads them until
+    // an empty line, and a plain "a" carries on where the last one stopped
+    fn assemble(&mut self, rest: &str) -> Result<(), String> {
+        let (addr, instruction) = match rest.split_once(char::is_whitespace) {
+            Some((addr, instruction)) => (addr, instruction.trim()),
+            None => (rest, ""),
+        };
+        if !addr.is_empty() {
+            self.assembler.set_pc(parse_addr(addr)?);
+        }
+        if !instruction.is_empty() {
+            let result = self.assembler.assemble_line(instruction);
+            self.write_assembled();
+            return result;
+        }
+
+        outln!("Enter instructions, an empty line ends:");
+        loop {
+            out!("{:04X}  ", self.assembler.pc());
+            let line = match read_line() {
+                Some(line) if !line.trim().is_empty() => line,
+                _ => break,
+            };
+            if let Err(e) = self.assembler.assemble_line(&line) {
+                outln!("Error: {}", e);
+            }
+            self.write_assembled();
+        }
+        let unresolved = self.assembler.unresolved();
+        if !unresolved.is_empty() {
+            outln!("Undefined labels: {}", unresolved.join(", "));
+        }
+        Ok(())
+    }
+
+    // "break c000 c0ff if x == 0", without an address lists the breakpoints
+    fn add_breakpoint(&mut self, rest: &str, kind: Kind) -> Result<(), String> {
+        let split = match rest.strip_prefix("if ") {
+            Some(condition) => Some(("", condition)),
+            None => rest.split_once(" if "),
+        };
+        let (range, condition) = match split {
+            Some((range, condition)) => (range, Some(Condition::parse(condition)?)),
+            None => (rest, None),
+        };
+        let args = split_args(range)?;
+        if args.is_empty() {
+            if condition.is_some() {
+                return Err("missing address".to_string());
+            }
+            for b in self.breakpoints.iter() {
+                outln!("{}", b);
+            }
+            return Ok(());
+        }
+        let start = parse_addr(&args[0])?;
+        let end = match args.get(1) {
+            Some(end) => parse_end(start, end)?,
+            None => start,
+        };
+        let id = self.breakpoints.add(kind, start..=end, condition);
+        outln!("{}", self.breakpoints.get_mut(id)?);
+        self.sync_breakpoints();
+        Ok(())
+    }
+
+    // "watch store d020", loads and stores when neither is given
+    fn add_watchpoint(&mut self, rest: &str) -> Result<(), String> {
+        let (kind, rest) = match rest.split_once(char::is_whitespace).unwrap_or((rest, "")) {
+            ("load", rest) => (Kind::Load, rest),
+            ("store", rest) => (Kind::Store, rest),
+            ("io", rest) => (Kind::Io, rest),
+            _ => (Kind::Access, rest),
+        };
+        if rest.trim().is_empty() {
+            return Err("missing address".to_string());
+        }
+        self.add_breakpoint(rest, kind)
+    }
+
+    fn write_assembled(&mut self) {
+        let mut cpu = self.cpu.borrow_mut();
+        for (addr, value) in self.assembler.take_written() {
+            cpu.bus.write_byte(addr, value);
+        }
+    }
+
+    fn fill(&mut self, args: &[String]) -> Result<(), String> {
+        let (start, end) = parse_range(args)?;
+        let pattern = parse_bytes(&args[2..])?;
+        let mut cpu = self.cpu.borrow_mut();
+        for (addr, &value) in (start..=end).zip(pattern.iter().cycle()) {
+            cpu.bus.write_byte(addr, value);
+        }
+        Ok(())
+    }
+
+    fn transfer(&mut self, args: &[String]) -> Result<(), String> {
+        let (start, end) = parse_range(args)?;
+        let dest = parse_addr(args.get(2).ok_or("missing destination")?)?;
+        // Read everything first so overlapping ranges copy correctly
+        let bytes = self.read_range(start, end);
+        let mut cpu = self.cpu.borrow_mut();
+        for (i, value) in bytes.into_iter().enumerate() {
+            cpu.bus.write_byte(dest.wrapping_add(i as u16), value);
+        }
+        Ok(())
+    }
+
+    fn hunt(&mut self, args: &[String]) -> Result<(), String> {
+        let (start, end) = parse_range(args)?;
+        let pattern = parse_bytes(&args[2..])?;
+        let bytes = self.read_range(start, end);
+        let found: Vec<String> = bytes
+            .windows(pattern.len())
+            .enumerate()
+            .filter(|(_, window)| *window == pattern.as_slice())
+            .map(|(i, _)| format!("{:04X}", start as usize + i))
+            .collect();
+        for line in found.chunks(8) {
+            outln!("{}", line.join(" "));
+        }
+        Ok(())
+    }
+
+    fn compare(&mut self, args: &[String]) -> Result<(), String> {
+        let (start, end) = parse_range(args)?;
+        let dest = parse_addr(args.get(2).ok_or("missing destination")?)?;
+        let cpu = self.cpu.borrow();
+        let mut differences = 0;
+        for addr in start..=end {
+            let other = dest.wrapping_add(addr - start);
+            let (a, b) = (cpu.peek_memory(addr), cpu.peek_memory(other));
+            if a != b {
+                outln!("{:04X} {:02X}  {:04X} {:02X}", addr, a, other, b);
+                differences += 1;
+            }
+        }
+        outln!("{} bytes differ", differences);
+        Ok(())
+    }
+
+    fn load(&mut self, args: &[String]) -> Result<(), String> {
+        let path = unquote(args.first().ok_or("missing file name")?);
+        let data = std::fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+        if data.len() < 3 {
+            return Err(format!("{}: not a PRG file", path));
+        }
+        let start = match args.get(1) {
+            Some(addr) => parse_addr(addr)?,
+            None => u16::from_le_bytes([data[0], data[1]]),
+        };
+        let mut cpu = self.cpu.borrow_mut();
+        for (i, &value) in data[2..].iter().enumerate() {
+            cpu.bus.write_byte(start.wrapping_add(i as u16), value);
+        }
+        let end = start.wrapping_add((data.len() - 3) as u16);
+        outln!("Loaded ${:04X}-${:04X}", start, end);
+        Ok(())
+    }
+
+    fn save(&mut self, args: &[String]) -> Result<(), String> {
+        let path = unquote(args.first().ok_or("missing file name")?);
+        let (start, end) = parse_range(&args[1..])?;
+        let mut data = start.to_le_bytes().to_vec();
+        data.extend(self.read_range(start, end));
+        std::fs::write(path, data).map_err(|e| format!("{}: {}", path, e))?;
+        outln!("Saved ${:04X}-${:04X}", start, end);
+        Ok(())
+    }
+
+    fn read_range(&self, start: u16, end: u16) -> Vec<u8> {
+        let cpu = self.cpu.borrow();
+        (start..=end).map(|a| cpu.peek_memory(a)).collect()
+    }
+}
+
+fn read_line() -> Option<String> {
+    let _ = std::io::stdout().flush();
+    let mut line = String::new();
+    match std::io::stdin().read_line(&mut line) {
+        Ok(0) | Err(_) => None,
+        Ok(_) => Some(line),
+    }
+}
+
+// Commands are letters, or '?'
+fn command_name(line: &str) -> &str {
+    let end = line
+        .find(|c: char| !c.is_ascii_alphabetic())
+        .unwrap_or(line.len());
+    if end == 0 && line.starts_with('?') {
+        "?"
+    } else {
+        &line[..end]
+    }
+}
+
+// Arguments are separated by spaces or commas, quoted strings are kept
+// whole with their quotes
+fn split_args(rest: &str) -> Result<Vec<String>, String> {
+    let mut args = Vec::new();
+    let mut chars = rest.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() || c == ',' {
+            chars.next();
+        } else if c == '"' {
+            let mut arg = String::from(chars.next().unwrap());
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some(c) => arg.push(c),
+                    None => return Err("unterminated string".to_string()),
+                }
+            }
+            arg.push('"');
+            args.push(arg);
+        } else {
+            let mut arg = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() || c == ',' {
+                    break;
+                }
+                arg.push(c);
+                chars.next();
+            }
+            args.push(arg);
+        }
+    }
+    Ok(args)
+}
+
+fn unquote(arg: &str) -> &str {
+    arg.strip_prefix('"')
+        .and_then(|s| s.strip_suffix('"'))
+        .unwrap_or(arg)
+}
+
+fn parse_number(s: &str) -> Result<u32, String> {
+    let (digits, radix) = if let Some(d) = s.strip_prefix('+') {
+        (d, 10)
+    } else if let Some(b) = s.strip_prefix('%') {
+        (b, 2)
+    } else {
+        (s.strip_prefix('$').unwrap_or(s), 16)
+    };
+    u32::from_str_radix(digits, radix).map_err(|_| format!("bad number: {}", s))
+}
+
+fn parse_addr(s: &str) -> Result<u16, String> {
+    u16::try_from(parse_number(s)?).map_err(|_| format!("address out of range: {}", s))
+}
+
+fn parse_byte(s: &str) -> Result<u8, String> {
+    u8::try_from(parse_number(s)?).map_err(|_| format!("byte out of range: {}", s))
+}
+
+fn parse_end(start: u16, end: &str) -> Result<u16, String> {
+    let end = parse_addr(end)?;
+    if end < start {
+        return Err(format!("end ${:04X} is before start ${:04X}", end, start));
+    }
+    Ok(end)
+}
+
+fn parse_range(args: &[String]) -> Result<(u16, u16), String> {
+    let start = parse_addr(args.first().ok_or("missing start address")?)?;
+    let end = parse_end(start, args.get(1).ok_or("missing end address")?)?;
+    Ok((start, end))
+}
+
+// Bytes and quoted text, at least one
+fn parse_bytes(args: &[String]) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+    for arg in args {
+        if arg.starts_with('"') {
+            bytes.extend(unquote(arg).bytes());
+        } else {
+            bytes.push(parse_byte(arg)?);
+        }
+    }
+    if bytes.is_empty() {
+        return Err("missing bytes".to_string());
+    }
+    Ok(bytes)
+}
+
+fn parse_id(s: &str) -> Result<u32, String> {
+    s.trim_start_matches('#')
+        .parse()
+        .map_err(|_| format!("bad breakpoint number: {}", s))
+}
+
+fn count(args: &[String]) -> Result<u32, String> {
+    args.first().map_or(Ok(1), |n| parse_number(n))
+}
+
+fn printable(b: u8) -> char {
+    match b {
+        0x20..=0x7e => b as char,
+        _ => '.',
+    }
+}
+
+// Remote debuggers drive the machine through the monitor, so they share its
+// breakpoints and step it the same way
+impl Target for Monitor {
+    fn cpu(&self) -> Rc<RefCell<Cpu<Memory>>> {
+        self.cpu.clone()
+    }
+
+    // Quietly, remote debuggers report a halted machine themselves
+    fn step(&mut self) -> bool {
+        self.step_machine()
+    }
+
+    fn step_back(&mut self) -> bool {
+        self.execution
+            .borrow_mut()
+            .step_back(&mut self.cpu.borrow_mut())
+            .is_some()
+    }
+
+    fn raster(&self) -> (u16, u16) {
+        self.vic.borrow().raster_position()
+    }
+
+    fn breakpoints(&mut self) -> &mut Breakpoints {
+        &mut self.breakpoints
+    }
+
+    fn autostart(&mut self, path: &str, index: u16, run: bool) -> Result<(), String> {
+        let program = if path.to_lowercase().ends_with(".d64") {
+            let image = D64::open(path)?;
+            let files = image.files()?;
+            let entry = files
+                .get(index as usize)
+                .ok_or(format!("{}: no file {}", path, index))?;
+            image.read_file(entry)?
+        } else {
+            fs::read(path).map_err(|e| format!("{}: {}", path, e))?
+        };
+        if program.len() < 2 {
+            return Err(format!("{}: no load address", path));
+        }
+
+        let cpu = self.cpu.clone();
+        autostart::boot(&cpu, || self.step())?;
+        // Going back past the reset would mix up the machines
+        self.execution.borrow_mut().clear();
+
+        let mut cpu = self.cpu.borrow_mut();
+        if run {
+            autostart::autostart(&mut cpu, &program);
+        } else {
+            autostart::load(&mut cpu, &program);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::console;
+
+    // The whole machine, without a window
+    fn monitor() -> Monitor {
+        let cpu = Rc::new(RefCell::new(Cpu::new(Memory::new().unwrap())));
+        let io = Rc::new(RefCell::new(IO::headless(cpu.clone())));
+        let cia1 = Rc::new(RefCell::new(Cia1::new(cpu.clone(), io.clone())));
+        let cia2 = Rc::new(RefCell::new(Cia2::new(cpu.clone())));
+        let vic = Rc::new(RefCell::new(Vic::new(cpu.clone())));
+        let sid = Rc::new(RefCell::new(Sid::new()));
+        {
+            let memory = &mut cpu.borrow_mut().bus;
+            memory.attach_vic(vic.clone());
+            memory.attach_sid(sid.clone());
+            memory.attach_cia1(cia1.clone());
+            memory.attach_cia2(cia2.clone());
+        }
+        let history = Rc::new(RefCell::new(History::new(0)));
+        Monitor::new(cpu, cia1, cia2, vic, sid, io, history)
+    }
+
+    // Runs `line` and returns what it printed
+    fn run(monitor: &mut Monitor, line: &str) -> Result<String, String> {
+        let mut result = Ok(());
+        let output = console::capture(|| result = monitor.command(line));
+        result.map(|_| output)
+    }
+
+    fn error(monitor: &mut Monitor, line: &str) -> String {
+        match run(monitor, line) {
+            Ok(output) => panic!("{} succeeded: {}", line, output),
+            Err(e) => e,
+        }
+    }
+
+    #[test]
+    fn fill_and_dump_memory() {
+        let mut monitor = monitor();
+        run(&mut monitor, "f 1000 100f 41 42").unwrap();
+        run(&mut monitor, "f 1010 1014 \"hi\" 00").unwrap();
+        let output = run(&mut monitor, "m 1000 1014").unwrap();
+        assert_eq!(
+            output,
+            ">C:1000  41 42 41 42 41 42 41 42 41 42 41 42 41 42 41 42  ABABABABABABABAB\n\
+             >C:1010  68 69 00 68 69                                   hi.hi\n"
+        );
+    }
+
+    #[test]
+    fn dump_carries_on() {
+        let mut monitor = monitor();
+        let output = run(&mut monitor, "m $0800").unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), MEMORY_LINES as usize);
+        assert!(lines[0].starts_with(">C:0800 "), "{}", lines[0]);
+        let output = run(&mut monitor, "m").unwrap();
+        assert!(output.starts_with(">C:0880 "), "{}", output);
+    }
+
+    #[test]
+    fn dump_stops_at_the_top_of_memory() {
+        let mut monitor = monitor();
+        let output = run(&mut monitor, "m fff8").unwrap();
+        assert_eq!(output.lines().count(), 1);
+        assert!(output.starts_with(">C:FFF8 "), "{}", output);
+    }
+
+    #[test]
+    fn registers() {
+        let mut monitor = monitor();
+        let output = run(&mut monitor, "r a=12, x=+52 pc=c000 p=%10000001").unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(
+            lines[0],
+            "  ADDR A  X  Y  SP 00 01 NV-BDIZC LIN CYC  CYCLES"
+        );
+        assert!(lines[1].starts_with(".;C000 12 34 00 "), "{}", lines[1]);
+        let cpu = monitor.cpu.borrow();
+        assert_eq!((cpu.a, cpu.x, cpu.pc), (0x12, 0x34, 0xc000));
+        assert!(cpu.p.contains(Flags::NEGATIVE) && cpu.p.contains(Flags::CARRY));
+    }
+
+    #[test]
+    fn bad_register_changes_nothing() {
+        let mut monitor = monitor();
+        assert_eq!(error(&mut monitor, "r x=1 q=2"), "unknown register: q");
+        assert_eq!(error(&mut monitor, "r x=1 a"), "expected register=value: a");
+        assert_eq!(error(&mut monitor, "r x=1 a=100"), "byte out of range: 100");
+        assert_eq!(error(&mut monitor, "r pc=zz"), "bad number: zz");
+        assert_eq!(monitor.cpu.borrow().x, 0);
+    }
+
+    #[test]
+    fn go_runs_to_a_brk() {
+        let mut monitor = monitor();
+        // INX, INX, INX, BRK
+        run(&mut monitor, "f c000 c003 e8 e8 e8 00").unwrap();
+        let output = run(&mut monitor, "g c000").unwrap();
+        assert!(output.starts_with("BRK at $C003\n"), "{}", output);
+        assert!(output.contains(".;C003 "), "{}", output);
+        assert_eq!(monitor.cpu.borrow().x, 3);
+    }
+
+    #[test]
+    fn go_stops_at_a_breakpoint() {
+        let mut monitor = monitor();
+        run(&mut monitor, "f c000 c003 e8 e8 e8 00").unwrap();
+        let output = run(&mut monitor, "break c002").unwrap();
+        assert_eq!(output, "#1   exec       $C002, 0 hits\n");
+        let output = run(&mut monitor, "g c000").unwrap();
+        assert!(output.starts_with("Breakpoint #1 at $C002\n"), "{}", output);
+        assert_eq!(monitor.cpu.borrow().x, 2);
+    }
+
+    #[test]
+    fn step_and_disassemble() {
+        let mut monitor = monitor();
+        run(&mut monitor, "f c000 c003 e8 e8 e8 00").unwrap();
+        run(&mut monitor, "r pc=c000").unwrap();
+        let output = run(&mut monitor, "z 2").unwrap();
+        assert_eq!(output.lines().count(), 2);
+        assert_eq!(monitor.cpu.borrow().pc, 0xc002);
+        let output = run(&mut monitor, "d c001 c003").unwrap();
+        assert_eq!(
+            output,
+            " C001  E8        INX\n>C002  E8        INX\n C003  00        BRK\n"
+        );
+    }
+
+    #[test]
+    fn bad_numbers() {
+        let mut monitor = monitor();
+        assert_eq!(error(&mut monitor, "m zz"), "bad number: zz");
+        assert_eq!(error(&mut monitor, "m $"), "bad number: $");
+        assert_eq!(error(&mut monitor, "m %12"), "bad number: %12");
+        assert_eq!(
+            error(&mut monitor, "m 10000"),
+            "address out of range: 10000"
+        );
+        assert_eq!(error(&mut monitor, "f 1000 1010 4g"), "bad number: 4g");
+        assert_eq!(
+            error(&mut monitor, "f 1000 1010 100"),
+            "byte out of range: 100"
+        );
+        assert_eq!(
+            error(&mut monitor, "f 2000 1000 00"),
+            "end $1000 is before start $2000"
+        );
+        assert_eq!(error(&mut monitor, "delete x"), "bad breakpoint number: x");
+    }
+
+    #[test]
+    fn missing_arguments() {
+        let mut monitor = monitor();
+        assert_eq!(error(&mut monitor, "f"), "missing start address");
+        assert_eq!(error(&mut monitor, "f 1000"), "missing end address");
+        assert_eq!(error(&mut monitor, "f 1000 1010"), "missing bytes");
+        assert_eq!(error(&mut monitor, "t 1000 1010"), "missing destination");
+        assert_eq!(error(&mut monitor, "c 1000 1010"), "missing destination");
+        assert_eq!(error(&mut monitor, "l"), "missing file name");
+        assert_eq!(error(&mut monitor, "watch store"), "missing address");
+        assert_eq!(error(&mut monitor, "break if a == 1"), "missing address");
+        assert_eq!(error(&mut monitor, "enable"), "missing breakpoint number");
+        assert_eq!(error(&mut monitor, "al 1000"), "usage: al addr .name");
+        assert_eq!(
+            error(&mut monitor, "h 1000 1010 \"ab"),
+            "unterminated string"
+        );
+        assert_eq!(
+            error(&mut monitor, "foo"),
+            "unknown command: foo, ? lists them"
+        );
+    }
+
+    #[test]
+    fn nothing_panics() {
+        let lines = [
+            "",
+            " ",
+            "?",
+            "m ffff ffff",
+            "d ffff",
+            "d fff0 ffff",
+            "f ffff ffff 1",
+            "h 0 ffff 00",
+            "c fff0 ffff 0",
+            "t fff0 ffff fff8",
+            "r pc=",
+            "r =",
+            "r ,",
+            "z 0",
+            "n 0",
+            "back",
+            "chis",
+            "chis 0",
+            "bt",
+            "cond",
+            "cond 9",
+            "delete 9",
+            "enable 9",
+            "break 1 if",
+            "break 1 if (",
+            "watch load",
+            "trap",
+            "trap foo",
+            "untrap",
+            "io",
+            "io foo",
+            "history",
+            "shl",
+            "al 1 .",
+            "m .",
+            "m .nolabel",
+            "l \"\"",
+            "s \"\" 0",
+            "x",
+            "q",
+        ];
+        let mut monitor = monitor();
+        for line in lines {
+            let _ = run(&mut monitor, line);
+        }
+    }
+
+    // Timer A of CIA 2 counting down from 1 with its interrupt enabled,
+    // underflowed by the time the NOPs have run
+    fn cia2_underflowed() -> Monitor {
+        let mut monitor = monitor();
+        run(&mut monitor, "f c000 c0ff ea").unwrap();
+        run(&mut monitor, "r pc=c000").unwrap();
+        run(&mut monitor, "f dd04 dd05 01 00").unwrap();
+        run(&mut monitor, "f dd0d dd0d 81").unwrap();
+        run(&mut monitor, "f dd0e dd0e 01").unwrap();
+        run(&mut monitor, "z 2").unwrap();
+        monitor
+    }
+
+    #[test]
+    fn io_shows_the_decoded_cia_state() {
+        let mut monitor = cia2_underflowed();
+        assert_eq!(
+            run(&mut monitor, "io cia2").unwrap(),
+            "CIA 2 $DD00\n\
+             Timer A  $0001 latch $0001  running, continuous, counts cycles\n\
+             Timer B  $0000 latch $0000  stopped, continuous, counts cycles\n\
+             ICR      enabled: timer A  pending: timer A\n\
+             Ports    A $FF  B $FF\n"
+        );
+    }
+
+    #[test]
+    fn io_shows_the_decoded_sid_state() {
+        let mut monitor = monitor();
+        // Voice 1 at $1234 with pulse and gate, filtered through the low pass
+        run(&mut monitor, "f d400 d406 34 12 00 08 41 09 a5").unwrap();
+        run(&mut monitor, "f d415 d418 07 10 f1 1f").unwrap();
+        assert_eq!(
+            run(&mut monitor, "io sid").unwrap(),
+            "SID $D400\n\
+             Voice 1  freq $1234 (273.7 Hz)  pulse $800  ADSR 09A5  gate pulse\n\
+             Voice 2  freq $0000 (0.0 Hz)  pulse $000  ADSR 0000  off\n\
+             Voice 3  freq $0000 (0.0 Hz)  pulse $000  ADSR 0000  off\n\
+             Filter   cutoff $087  resonance 15  voices 1  mode low\n\
+             Volume   15\n"
+        );
+    }
+
+    #[test]
+    fn io_shows_the_decoded_vic_state() {
+        let mut monitor = monitor();
+        // 25 rows, 40 columns, screen at $0400, raster interrupt at line $30
+        run(&mut monitor, "f d011 d012 1b 30").unwrap();
+        run(&mut monitor, "f d016 d016 08").unwrap();
+        run(&mut monitor, "f d018 d018 14").unwrap();
+        run(&mut monitor, "f d01a d01a 01").unwrap();
+        run(&mut monitor, "f d020 d021 0e 06").unwrap();
+        let output = run(&mut monitor, "io vic").unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines[0], "VIC-II");
+        assert_eq!(
+            lines[1],
+            "Mode     standard text, display on, 25 rows, 40 columns, scroll 0,3"
+        );
+        assert!(lines[2].ends_with(", interrupt at line 48"), "{}", lines[2]);
+        assert_eq!(lines[3], "IRQ      enabled: raster  pending: none");
+        assert_eq!(
+            lines[4],
+            "Memory   bank 0 $0000-$3FFF  screen $0400  charset $1000 (ROM)  bitmap $0000"
+        );
+        assert_eq!(lines[5], "Colors   border 14  background 6");
+    }
+
+    #[test]
+    fn io_of_all_chips() {
+        let mut monitor = monitor();
+        let output = run(&mut monitor, "io").unwrap();
+        let at = |header: &str| {
+            output
+                .find(&format!("{}\n", header))
+                .unwrap_or_else(|| panic!("no {} in {}", header, output))
+        };
+        assert!(at("VIC-II") < at("CIA 1 $DC00"));
+        assert!(at("CIA 1 $DC00") < at("CIA 2 $DD00"));
+        assert!(at("CIA 2 $DD00") < at("SID $D400"));
+        assert_eq!(
+            error(&mut monitor, "io vdc"),
+            "unknown chip: vdc, use vic, cia1, cia2 or sid"
+        );
+    }
+
+    #[test]
+    fn inspecting_a_cia_keeps_its_interrupt_pending() {
+        let mut monitor = cia2_underflowed();
+        let first = run(&mut monitor, "io cia2").unwrap();
+        assert!(first.contains("pending: timer A"), "{}", first);
+        assert_eq!(run(&mut monitor, "io cia2").unwrap(), first);
+        for _ in 0..2 {
+            let output = run(&mut monitor, "m dd0d dd0d").unwrap();
+            assert!(output.starts_with(">C:DD0D  81 "), "{}", output);
+        }
+        assert_eq!(run(&mut monitor, "io cia2").unwrap(), first);
+        assert_eq!(monitor.cia2.borrow().state().icr_pending, 0x01);
+    }
+}
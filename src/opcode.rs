@@ -0,0 +1,1171 @@
+use std::fmt;
+
+// Static description of every opcode of the NMOS 6510, the WDC 65C02 and
+// the 65816.
+// The CPUs dispatch through these tables, and tools such as a disassembler,
+// assembler or tracer can use the same metadata instead of keeping their own
+// copy.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AddrMode {
+    Implied,
+    Accumulator,
+    Immediate,
+    ZeroPage,
+    ZeroPageX,
+    ZeroPageY,
+    Relative,
+    Absolute,
+    AbsoluteX,
+    AbsoluteY,
+    Indirect,
+    IndirectX,
+    IndirectY,
+    // 65C02 only
+    ZeroPageIndirect,
+    AbsoluteIndexedIndirect,
+    ZeroPageRelative,
+    // 65816 only
+    StackRelative,
+    StackRelativeIndirectY,
+    DirectIndirectLong,
+    DirectIndirectLongY,
+    AbsoluteLong,
+    AbsoluteLongX,
+    AbsoluteIndirectLong,
+    RelativeLong,
+    BlockMove,
+}
+
+impl AddrMode {
+    /// Number of operand bytes following the opcode
+    pub const fn operand_len(self) -> u8 {
+        match self {
+            AddrMode::Implied | AddrMode::Accumulator => 0,
+            AddrMode::Immediate
+            | AddrMode::ZeroPage
+            | AddrMode::ZeroPageX
+            | AddrMode::ZeroPageY
+            | AddrMode::Relative
+            | AddrMode::IndirectX
+            | AddrMode::IndirectY
+            | AddrMode::ZeroPageIndirect
+            | AddrMode::StackRelative
+            | AddrMode::StackRelativeIndirectY
+            | AddrMode::DirectIndirectLong
+            | AddrMode::DirectIndirectLongY => 1,
+            AddrMode::Absolute
+            | AddrMode::AbsoluteX
+            | AddrMode::AbsoluteY
+            | AddrMode::Indirect
+            | AddrMode::AbsoluteIndexedIndirect
+            | AddrMode::ZeroPageRelative
+            | AddrMode::AbsoluteIndirectLong
+            | AddrMode::RelativeLong
+            | AddrMode::BlockMove => 2,
+            AddrMode::AbsoluteLong | AddrMode::AbsoluteLongX => 3,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Mnemonic {
+    Adc,
+    And,
+    Asl,
+    Bcc,
+    Bcs,
+    Beq,
+    Bit,
+    Bmi,
+    Bne,
+    Bpl,
+    Brk,
+    Bvc,
+    Bvs,
+    Clc,
+    Cld,
+    Cli,
+    Clv,
+    Cmp,
+    Cpx,
+    Cpy,
+    Dec,
+    Dex,
+    Dey,
+    Eor,
+    Inc,
+    Inx,
+    Iny,
+    Jmp,
+    Jsr,
+    Lda,
+    Ldx,
+    Ldy,
+    Lsr,
+    Nop,
+    Ora,
+    Pha,
+    Php,
+    Pla,
+    Plp,
+    Rol,
+    Ror,
+    Rti,
+    Rts,
+    Sbc,
+    Sec,
+    Sed,
+    Sei,
+    Sta,
+    Stx,
+    Sty,
+    Tax,
+    Tay,
+    Tsx,
+    Txa,
+    Txs,
+    Tya,
+    // Undocumented NMOS opcodes
+    Alr,
+    Anc,
+    Ane,
+    Arr,
+    Dcp,
+    Isc,
+    Jam,
+    Las,
+    Lax,
+    Lxa,
+    Rla,
+    Rra,
+    Sax,
+    Sbx,
+    Sha,
+    Shx,
+    Shy,
+    Slo,
+    Sre,
+    Tas,
+    // 65C02 additions, the bit instructions carry the bit number
+    Bbr(u8),
+    Bbs(u8),
+    Bra,
+    Phx,
+    Phy,
+    Plx,
+    Ply,
+    Rmb(u8),
+    Smb(u8),
+    Stp,
+    Stz,
+    Trb,
+    Tsb,
+    Wai,
+    // 65816 additions
+    Brl,
+    Cop,
+    Jml,
+    Jsl,
+    Mvn,
+    Mvp,
+    Pea,
+    Pei,
+    Per,
+    Phb,
+    Phd,
+    Phk,
+    Plb,
+    Pld,
+    Rep,
+    Rtl,
+    Sep,
+    Tcd,
+    Tcs,
+    Tdc,
+    Tsc,
+    Txy,
+    Tyx,
+    Wdm,
+    Xba,
+    Xce,
+}
+
+const BBR: [&str; 8] = [
+    "BBR0", "BBR1", "BBR2", "BBR3", "BBR4", "BBR5", "BBR6", "BBR7",
+];
+const BBS: [&str; 8] = [
+    "BBS0", "BBS1", "BBS2", "BBS3", "BBS4", "BBS5", "BBS6", "BBS7",
+];
+const RMB: [&str; 8] = [
+    "RMB0", "RMB1", "RMB2", "RMB3", "RMB4", "RMB5", "RMB6", "RMB7",
+];
+const SMB: [&str; 8] = [
+    "SMB0", "SMB1", "SMB2", "SMB3", "SMB4", "SMB5", "SMB6", "SMB7",
+];
+
+impl Mnemonic {
+    pub const fn name(self) -> &'static str {
+        match self {
+            Mnemonic::Adc => "ADC",
+            Mnemonic::And => "AND",
+            Mnemonic::Asl => "ASL",
+            Mnemonic::Bcc => "BCC",
+            Mnemonic::Bcs => "BCS",
+            Mnemonic::Beq => "BEQ",
+            Mnemonic::Bit => "BIT",
+            Mnemonic::Bmi => "BMI",
+            Mnemonic::Bne => "BNE",
+            Mnemonic::Bpl => "BPL",
+            Mnemonic::Brk => "BRK",
+            Mnemonic::Bvc => "BVC",
+            Mnemonic::Bvs => "BVS",
+            Mnemonic::Clc => "CLC",
+            Mnemonic::Cld => "CLD",
+            Mnemonic::Cli => "CLI",
+            Mnemonic::Clv => "CLV",
+            Mnemonic::Cmp => "CMP",
+            Mnemonic::Cpx => "CPX",
+            Mnemonic::Cpy => "CPY",
+            Mnemonic::Dec => "DEC",
+            Mnemonic::Dex => "DEX",
+            Mnemonic::Dey => "DEY",
+            Mnemonic::Eor => "EOR",
+            Mnemonic::Inc => "INC",
+            Mnemonic::Inx => "INX",
+            Mnemonic::Iny => "INY",
+            Mnemonic::Jmp => "JMP",
+            Mnemonic::Jsr => "JSR",
+            Mnemonic::Lda => "LDA",
+            Mnemonic::Ldx => "LDX",
+            Mnemonic::Ldy => "LDY",
+            Mnemonic::Lsr => "LSR",
+            Mnemonic::Nop => "NOP",
+            Mnemonic::Ora => "ORA",
+            Mnemonic::Pha => "PHA",
+            Mnemonic::Php => "PHP",
+            Mnemonic::Pla => "PLA",
+            Mnemonic::Plp => "PLP",
+            Mnemonic::Rol => "ROL",
+            Mnemonic::Ror => "ROR",
+            Mnemonic::Rti => "RTI",
+            Mnemonic::Rts => "RTS",
+            Mnemonic::Sbc => "SBC",
+            Mnemonic::Sec => "SEC",
+            Mnemonic::Sed => "SED",
+            Mnemonic::Sei => "SEI",
+            Mnemonic::Sta => "STA",
+            Mnemonic::Stx => "STX",
+            Mnemonic::Sty => "STY",
+            Mnemonic::Tax => "TAX",
+            Mnemonic::Tay => "TAY",
+            Mnemonic::Tsx => "TSX",
+            Mnemonic::Txa => "TXA",
+            Mnemonic::Txs => "TXS",
+            Mnemonic::Tya => "TYA",
+            Mnemonic::Alr => "ALR",
+            Mnemonic::Anc => "ANC",
+            Mnemonic::Ane => "ANE",
+            Mnemonic::Arr => "ARR",
+            Mnemonic::Dcp => "DCP",
+            Mnemonic::Isc => "ISC",
+            Mnemonic::Jam => "JAM",
+            Mnemonic::Las => "LAS",
+            Mnemonic::Lax => "LAX",
+            Mnemonic::Lxa => "LXA",
+            Mnemonic::Rla => "RLA",
+            Mnemonic::Rra => "RRA",
+            Mnemonic::Sax => "SAX",
+            Mnemonic::Sbx => "SBX",
+            Mnemonic::Sha => "SHA",
+            Mnemonic::Shx => "SHX",
+            Mnemonic::Shy => "SHY",
+            Mnemonic::Slo => "SLO",
+            Mnemonic::Sre => "SRE",
+            Mnemonic::Tas => "TAS",
+            Mnemonic::Bbr(bit) => BBR[bit as usize & 7],
+            Mnemonic::Bbs(bit) => BBS[bit as usize & 7],
+            Mnemonic::Bra => "BRA",
+            Mnemonic::Phx => "PHX",
+            Mnemonic::Phy => "PHY",
+            Mnemonic::Plx => "PLX",
+            Mnemonic::Ply => "PLY",
+            Mnemonic::Rmb(bit) => RMB[bit as usize & 7],
+            Mnemonic::Smb(bit) => SMB[bit as usize & 7],
+            Mnemonic::Stp => "STP",
+            Mnemonic::Stz => "STZ",
+            Mnemonic::Trb => "TRB",
+            Mnemonic::Tsb => "TSB",
+            Mnemonic::Wai => "WAI",
+            Mnemonic::Brl => "BRL",
+            Mnemonic::Cop => "COP",
+            Mnemonic::Jml => "JML",
+            Mnemonic::Jsl => "JSL",
+            Mnemonic::Mvn => "MVN",
+            Mnemonic::Mvp => "MVP",
+            Mnemonic::Pea => "PEA",
+            Mnemonic::Pei => "PEI",
+            Mnemonic::Per => "PER",
+            Mnemonic::Phb => "PHB",
+            Mnemonic::Phd => "PHD",
+            Mnemonic::Phk => "PHK",
+            Mnemonic::Plb => "PLB",
+            Mnemonic::Pld => "PLD",
+            Mnemonic::Rep => "REP",
+            Mnemonic::Rtl => "RTL",
+            Mnemonic::Sep => "SEP",
+            Mnemonic::Tcd => "TCD",
+            Mnemonic::Tcs => "TCS",
+            Mnemonic::Tdc => "TDC",
+            Mnemonic::Tsc => "TSC",
+            Mnemonic::Txy => "TXY",
+            Mnemonic::Tyx => "TYX",
+            Mnemonic::Wdm => "WDM",
+            Mnemonic::Xba => "XBA",
+            Mnemonic::Xce => "XCE",
+        }
+    }
+}
+
+impl fmt::Display for Mnemonic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Opcode {
+    pub mnemonic: Mnemonic,
+    pub mode: AddrMode,
+    pub cycles: u8,         // Base cycle count
+    pub page_penalty: bool, // One extra cycle when indexing crosses a page
+    pub illegal: bool,      // Undocumented opcode
+}
+
+impl Opcode {
+    const fn new(mnemonic: Mnemonic, mode: AddrMode, cycles: u8) -> Self {
+        Opcode {
+            mnemonic,
+            mode,
+            cycles,
+            page_penalty: false,
+            illegal: false,
+        }
+    }
+
+    const fn with_page_penalty(mut self) -> Self {
+        self.page_penalty = true;
+        self
+    }
+
+    const fn illegal(mut self) -> Self {
+        self.illegal = true;
+        self
+    }
+
+    /// Instruction length in bytes, including the opcode
+    pub const fn size(&self) -> u8 {
+        1 + self.mode.operand_len()
+    }
+}
+
+/// NMOS 6510 opcode table indexed by the opcode byte. Branches are listed with their
+/// untaken cycle count; taking one costs an extra cycle, plus another one
+/// when the target is on a different page.
+#[rustfmt::skip]
+pub static OPCODES: [Opcode; 256] = {
+    use AddrMode::*;
+    use Mnemonic::*;
+    [
+        /* 0x00 */ Opcode::new(Brk, Implied, 7),
+        /* 0x01 */ Opcode::new(Ora, IndirectX, 6),
+        /* 0x02 */ Opcode::new(Jam, Implied, 2).illegal(),
+        /* 0x03 */ Opcode::new(Slo, IndirectX, 8).illegal(),
+        /* 0x04 */ Opcode::new(Nop, ZeroPage, 3).illegal(),
+        /* 0x05 */ Opcode::new(Ora, ZeroPage, 3),
+        /* 0x06 */ Opcode::new(Asl, ZeroPage, 5),
+        /* 0x07 */ Opcode::new(Slo, ZeroPage, 5).illegal(),
+        /* 0x08 */ Opcode::new(Php, Implied, 3),
+        /* 0x09 */ Opcode::new(Ora, Immediate, 2),
+        /* 0x0A */ Opcode::new(Asl, Accumulator, 2),
+        /* 0x0B */ Opcode::new(Anc, Immediate, 2).illegal(),
+        /* 0x0C */ Opcode::new(Nop, Absolute, 4).illegal(),
+        /* 0x0D */ Opcode::new(Ora, Absolute, 4),
+        /* 0x0E */ Opcode::new(Asl, Absolute, 6),
+        /* 0x0F */ Opcode::new(Slo, Absolute, 6).illegal(),
+        /* 0x10 */ Opcode::new(Bpl, Relative, 2).with_page_penalty(),
+        /* 0x11 */ Opcode::new(Ora, IndirectY, 5).with_page_penalty(),
+        /* 0x12 */ Opcode::new(Jam, Implied, 2).illegal(),
+        /* 0x13 */ Opcode::new(Slo, IndirectY, 8).illegal(),
+        /* 0x14 */ Opcode::new(Nop, ZeroPageX, 4).illegal(),
+        /* 0x15 */ Opcode::new(Ora, ZeroPageX, 4),
+        /* 0x16 */ Opcode::new(Asl, ZeroPageX, 6),
+        /* 0x17 */ Opcode::new(Slo, ZeroPageX, 6).illegal(),
+        /* 0x18 */ Opcode::new(Clc, Implied, 2),
+        /* 0x19 */ Opcode::new(Ora, AbsoluteY, 4).with_page_penalty(),
+        /* 0x1A */ Opcode::new(Nop, Implied, 2).illegal(),
+        /* 0x1B */ Opcode::new(Slo, AbsoluteY, 7).illegal(),
+        /* 0x1C */ Opcode::new(Nop, AbsoluteX, 4).with_page_penalty().illegal(),
+        /* 0x1D */ Opcode::new(Ora, AbsoluteX, 4).with_page_penalty(),
+        /* 0x1E */ Opcode::new(Asl, AbsoluteX, 7),
+        /* 0x1F */ Opcode::new(Slo, AbsoluteX, 7).illegal(),
+        /* 0x20 */ Opcode::new(Jsr, Absolute, 6),
+        /* 0x21 */ Opcode::new(And, IndirectX, 6),
+        /* 0x22 */ Opcode::new(Jam, Implied, 2).illegal(),
+        /* 0x23 */ Opcode::new(Rla, IndirectX, 8).illegal(),
+        /* 0x24 */ Opcode::new(Bit, ZeroPage, 3),
+        /* 0x25 */ Opcode::new(And, ZeroPage, 3),
+        /* 0x26 */ Opcode::new(Rol, ZeroPage, 5),
+        /* 0x27 */ Opcode::new(Rla, ZeroPage, 5).illegal(),
+        /* 0x28 */ Opcode::new(Plp, Implied, 4),
+        /* 0x29 */ Opcode::new(And, Immediate, 2),
+        /* 0x2A */ Opcode::new(Rol, Accumulator, 2),
+        /* 0x2B */ Opcode::new(Anc, Immediate, 2).illegal(),
+        /* 0x2C */ Opcode::new(Bit, Absolute, 4),
+        /* 0x2D */ Opcode::new(And, Absolute, 4),
+        /* 0x2E */ Opcode::new(Rol, Absolute, 6),
+        /* 0x2F */ Opcode::new(Rla, Absolute, 6).illegal(),
+        /* 0x30 */ Opcode::new(Bmi, Relative, 2).with_page_penalty(),
+        /* 0x31 */ Opcode::new(And, IndirectY, 5).with_page_penalty(),
+        /* 0x32 */ Opcode::new(Jam, Implied, 2).illegal(),
+        /* 0x33 */ Opcode::new(Rla, IndirectY, 8).illegal(),
+        /* 0x34 */ Opcode::new(Nop, ZeroPageX, 4).illegal(),
+        /* 0x35 */ Opcode::new(And, ZeroPageX, 4),
+        /* 0x36 */ Opcode::new(Rol, ZeroPageX, 6),
+        /* 0x37 */ Opcode::new(Rla, ZeroPageX, 6).illegal(),
+        /* 0x38 */ Opcode::new(Sec, Implied, 2),
+        /* 0x39 */ Opcode::new(And, AbsoluteY, 4).with_page_penalty(),
+        /* 0x3A */ Opcode::new(Nop, Implied, 2).illegal(),
+        /* 0x3B */ Opcode::new(Rla, AbsoluteY, 7).illegal(),
+        /* 0x3C */ Opcode::new(Nop, AbsoluteX, 4).with_page_penalty().illegal(),
+        /* 0x3D */ Opcode::new(And, AbsoluteX, 4).with_page_penalty(),
+        /* 0x3E */ Opcode::new(Rol, AbsoluteX, 7),
+        /* 0x3F */ Opcode::new(Rla, AbsoluteX, 7).illegal(),
+        /* 0x40 */ Opcode::new(Rti, Implied, 6),
+        /* 0x41 */ Opcode::new(Eor, IndirectX, 6),
+        /* 0x42 */ Opcode::new(Jam, Implied, 2).illegal(),
+        /* 0x43 */ Opcode::new(Sre, IndirectX, 8).illegal(),
+        /* 0x44 */ Opcode::new(Nop, ZeroPage, 3).illegal(),
+        /* 0x45 */ Opcode::new(Eor, ZeroPage, 3),
+        /* 0x46 */ Opcode::new(Lsr, ZeroPage, 5),
+        /* 0x47 */ Opcode::new(Sre, ZeroPage, 5).illegal(),
+        /* 0x48 */ Opcode::new(Pha, Implied, 3),
+        /* 0x49 */ Opcode::new(Eor, Immediate, 2),
+        /* 0x4A */ Opcode::new(Lsr, Accumulator, 2),
+        /* 0x4B */ Opcode::new(Alr, Immediate, 2).illegal(),
+        /* 0x4C */ Opcode::new(Jmp, Absolute, 3),
+        /* 0x4D */ Opcode::new(Eor, Absolute, 4),
+        /* 0x4E */ Opcode::new(Lsr, Absolute, 6),
+        /* 0x4F */ Opcode::new(Sre, Absolute, 6).illegal(),
+        /* 0x50 */ Opcode::new(Bvc, Relative, 2).with_page_penalty(),
+        /* 0x51 */ Opcode::new(Eor, IndirectY, 5).with_page_penalty(),
+        /* 0x52 */ Opcode::new(Jam, Implied, 2).illegal(),
+        /* 0x53 */ Opcode::new(Sre, IndirectY, 8).illegal(),
+        /* 0x54 */ Opcode::new(Nop, ZeroPageX, 4).illegal(),
+        /* 0x55 */ Opcode::new(Eor, ZeroPageX, 4),
+        /* 0x56 */ Opcode::new(Lsr, ZeroPageX, 6),
+        /* 0x57 */ Opcode::new(Sre, ZeroPageX, 6).illegal(),
+        /* 0x58 */ Opcode::new(Cli, Implied, 2),
+        /* 0x59 */ Opcode::new(Eor, AbsoluteY, 4).with_page_penalty(),
+        /* 0x5A */ Opcode::new(Nop, Implied, 2).illegal(),
+        /* 0x5B */ Opcode::new(Sre, AbsoluteY, 7).illegal(),
+        /* 0x5C */ Opcode::new(Nop, AbsoluteX, 4).with_page_penalty().illegal(),
+        /* 0x5D */ Opcode::new(Eor, AbsoluteX, 4).with_page_penalty(),
+        /* 0x5E */ Opcode::new(Lsr, AbsoluteX, 7),
+        /* 0x5F */ Opcode::new(Sre, AbsoluteX, 7).illegal(),
+        /* 0x60 */ Opcode::new(Rts, Implied, 6),
+        /* 0x61 */ Opcode::new(Adc, IndirectX, 6),
+        /* 0x62 */ Opcode::new(Jam, Implied, 2).illegal(),
+        /* 0x63 */ Opcode::new(Rra, IndirectX, 8).illegal(),
+        /* 0x64 */ Opcode::new(Nop, ZeroPage, 3).illegal(),
+        /* 0x65 */ Opcode::new(Adc, ZeroPage, 3),
+        /* 0x66 */ Opcode::new(Ror, ZeroPage, 5),
+        /* 0x67 */ Opcode::new(Rra, ZeroPage, 5).illegal(),
+        /* 0x68 */ Opcode::new(Pla, Implied, 4),
+        /* 0x69 */ Opcode::new(Adc, Immediate, 2),
+        /* 0x6A */ Opcode::new(Ror, Accumulator, 2),
+        /* 0x6B */ Opcode::new(Arr, Immediate, 2).illegal(),
+        /* 0x6C */ Opcode::new(Jmp, Indirect, 5),
+        /* 0x6D */ Opcode::new(Adc, Absolute, 4),
+        /* 0x6E */ Opcode::new(Ror, Absolute, 6),
+        /* 0x6F */ Opcode::new(Rra, Absolute, 6).illegal(),
+        /* 0x70 */ Opcode::new(Bvs, Relative, 2).with_page_penalty(),
+        /* 0x71 */ Opcode::new(Adc, IndirectY, 5).with_page_penalty(),
+        /* 0x72 */ Opcode::new(Jam, Implied, 2).illegal(),
+        /* 0x73 */ Opcode::new(Rra, IndirectY, 8).illegal(),
+        /* 0x74 */ Opcode::new(Nop, ZeroPageX, 4).illegal(),
+        /* 0x75 */ Opcode::new(Adc, ZeroPageX, 4),
+        /* 0x76 */ Opcode::new(Ror, ZeroPageX, 6),
+        /* 0x77 */ Opcode::new(Rra, ZeroPageX, 6).illegal(),
+        /* 0x78 */ Opcode::new(Sei, Implied, 2),
+        /* 0x79 */ Opcode::new(Adc, AbsoluteY, 4).with_page_penalty(),
+        /* 0x7A */ Opcode::new(Nop, Implied, 2).illegal(),
+        /* 0x7B */ Opcode::new(Rra, AbsoluteY, 7).illegal(),
+        /* 0x7C */ Opcode::new(Nop, AbsoluteX, 4).with_page_penalty().illegal(),
+        /* 0x7D */ Opcode::new(Adc, AbsoluteX, 4).with_page_penalty(),
+        /* 0x7E */ Opcode::new(Ror, AbsoluteX, 7),
+        /* 0x7F */ Opcode::new(Rra, AbsoluteX, 7).illegal(),
+        /* 0x80 */ Opcode::new(Nop, Immediate, 2).illegal(),
+        /* 0x81 */ Opcode::new(Sta, IndirectX, 6),
+        /* 0x82 */ Opcode::new(Nop, Immediate, 2).illegal(),
+        /* 0x83 */ Opcode::new(Sax, IndirectX, 6).illegal(),
+        /* 0x84 */ Opcode::new(Sty, ZeroPage, 3),
+        /* 0x85 */ Opcode::new(Sta, ZeroPage, 3),
+        /* 0x86 */ Opcode::new(Stx, ZeroPage, 3),
+        /* 0x87 */ Opcode::new(Sax, ZeroPage, 3).illegal(),
+        /* 0x88 */ Opcode::new(Dey, Implied, 2),
+        /* 0x89 */ Opcode::new(Nop, Immediate, 2).illegal(),
+        /* 0x8A */ Opcode::new(Txa, Implied, 2),
+        /* 0x8B */ Opcode::new(Ane, Immediate, 2).illegal(),
+        /* 0x8C */ Opcode::new(Sty, Absolute, 4),
+        /* 0x8D */ Opcode::new(Sta, Absolute, 4),
+        /* 0x8E */ Opcode::new(Stx, Absolute, 4),
+        /* 0x8F */ Opcode::new(Sax, Absolute, 4).illegal(),
+        /* 0x90 */ Opcode::new(Bcc, Relative, 2).with_page_penalty(),
+        /* 0x91 */ Opcode::new(Sta, IndirectY, 6),
+        /* 0x92 */ Opcode::new(Jam, Implied, 2).illegal(),
+        /* 0x93 */ Opcode::new(Sha, IndirectY, 6).illegal(),
+        /* 0x94 */ Opcode::new(Sty, ZeroPageX, 4),
+        /* 0x95 */ Opcode::new(Sta, ZeroPageX, 4),
+        /* 0x96 */ Opcode::new(Stx, ZeroPageY, 4),
+        /* 0x97 */ Opcode::new(Sax, ZeroPageY, 4).illegal(),
+        /* 0x98 */ Opcode::new(Tya, Implied, 2),
+        /* 0x99 */ Opcode::new(Sta, AbsoluteY, 5),
+        /* 0x9A */ Opcode::new(Txs, Implied, 2),
+        /* 0x9B */ Opcode::new(Tas, AbsoluteY, 5).illegal(),
+        /* 0x9C */ Opcode::new(Shy, AbsoluteX, 5).illegal(),
+        /* 0x9D */ Opcode::new(Sta, AbsoluteX, 5),
+        /* 0x9E */ Opcode::new(Shx, AbsoluteY, 5).illegal(),
+        /* 0x9F */ Opcode::new(Sha, AbsoluteY, 5).illegal(),
+        /* 0xA0 */ Opcode::new(Ldy, Immediate, 2),
+        /* 0xA1 */ Opcode::new(Lda, IndirectX, 6),
+        /* 0xA2 */ Opcode::new(Ldx, Immediate, 2),
+        /* 0xA3 */ Opcode::new(Lax, IndirectX, 6).illegal(),
+        /* 0xA4 */ Opcode::new(Ldy, ZeroPage, 3),
+        /* 0xA5 */ Opcode::new(Lda, ZeroPage, 3),
+        /* 0xA6 */ Opcode::new(Ldx, ZeroPage, 3),
+        /* 0xA7 */ Opcode::new(Lax, ZeroPage, 3).illegal(),
+        /* 0xA8 */ Opcode::new(Tay, Implied, 2),
+        /* 0xA9 */ Opcode::new(Lda, Immediate, 2),
+        /* 0xAA */ Opcode::new(Tax, Implied, 2),
+        /* 0xAB */ Opcode::new(Lxa, Immediate, 2).illegal(),
+        /* 0xAC */ Opcode::new(Ldy, Absolute, 4),
+        /* 0xAD */ Opcode::new(Lda, Absolute, 4),
+        /* 0xAE */ Opcode::new(Ldx, Absolute, 4),
+        /* 0xAF */ Opcode::new(Lax, Absolute, 4).illegal(),
+        /* 0xB0 */ Opcode::new(Bcs, Relative, 2).with_page_penalty(),
+        /* 0xB1 */ Opcode::new(Lda, IndirectY, 5).with_page_penalty(),
+        /* 0xB2 */ Opcode::new(Jam, Implied, 2).illegal(),
+        /* 0xB3 */ Opcode::new(Lax, IndirectY, 5).with_page_penalty().illegal(),
+        /* 0xB4 */ Opcode::new(Ldy, ZeroPageX, 4),
+        /* 0xB5 */ Opcode::new(Lda, ZeroPageX, 4),
+        /* 0xB6 */ Opcode::new(Ldx, ZeroPageY, 4),
+        /* 0xB7 */ Opcode::new(Lax, ZeroPageY, 4).illegal(),
+        /* 0xB8 */ Opcode::new(Clv, Implied, 2),
+        /* 0xB9 */ Opcode::new(Lda, AbsoluteY, 4).with_page_penalty(),
+        /* 0xBA */ Opcode::new(Tsx, Implied, 2),
+        /* 0xBB */ Opcode::new(Las, AbsoluteY, 4).with_page_penalty().illegal(),
+        /* 0xBC */ Opcode::new(Ldy, AbsoluteX, 4).with_page_penalty(),
+        /* 0xBD */ Opcode::new(Lda, AbsoluteX, 4).with_page_penalty(),
+        /* 0xBE */ Opcode::new(Ldx, AbsoluteY, 4).with_page_penalty(),
+        /* 0xBF */ Opcode::new(Lax, AbsoluteY, 4).with_page_penalty().illegal(),
+        /* 0xC0 */ Opcode::new(Cpy, Immediate, 2),
+        /* 0xC1 */ Opcode::new(Cmp, IndirectX, 6),
+        /* 0xC2 */ Opcode::new(Nop, Immediate, 2).illegal(),
+        /* 0xC3 */ Opcode::new(Dcp, IndirectX, 8).illegal(),
+        /* 0xC4 */ Opcode::new(Cpy, ZeroPage, 3),
+        /* 0xC5 */ Opcode::new(Cmp, ZeroPage, 3),
+        /* 0xC6 */ Opcode::new(Dec, ZeroPage, 5),
+        /* 0xC7 */ Opcode::new(Dcp, ZeroPage, 5).illegal(),
+        /* 0xC8 */ Opcode::new(Iny, Implied, 2),
+        /* 0xC9 */ Opcode::new(Cmp, Immediate, 2),
+        /* 0xCA */ Opcode::new(Dex, Implied, 2),
+        /* 0xCB */ Opcode::new(Sbx, Immediate, 2).illegal(),
+        /* 0xCC */ Opcode::new(Cpy, Absolute, 4),
+        /* 0xCD */ Opcode::new(Cmp, Absolute, 4),
+        /* 0xCE */ Opcode::new(Dec, Absolute, 6),
+        /* 0xCF */ Opcode::new(Dcp, Absolute, 6).illegal(),
+        /* 0xD0 */ Opcode::new(Bne, Relative, 2).with_page_penalty(),
+        /* 0xD1 */ Opcode::new(Cmp, IndirectY, 5).with_page_penalty(),
+        /* 0xD2 */ Opcode::new(Jam, Implied, 2).illegal(),
+        /* 0xD3 */ Opcode::new(Dcp, IndirectY, 8).illegal(),
+        /* 0xD4 */ Opcode::new(Nop, ZeroPageX, 4).illegal(),
+        /* 0xD5 */ Opcode::new(Cmp, ZeroPageX, 4),
+        /* 0xD6 */ Opcode::new(Dec, ZeroPageX, 6),
+        /* 0xD7 */ Opcode::new(Dcp, ZeroPageX, 6).illegal(),
+        /* 0xD8 */ Opcode::new(Cld, Implied, 2),
+        /* 0xD9 */ Opcode::new(Cmp, AbsoluteY, 4).with_page_penalty(),
+        /* 0xDA */ Opcode::new(Nop, Implied, 2).illegal(),
+        /* 0xDB */ Opcode::new(Dcp, AbsoluteY, 7).illegal(),
+        /* 0xDC */ Opcode::new(Nop, AbsoluteX, 4).with_page_penalty().illegal(),
+        /* 0xDD */ Opcode::new(Cmp, AbsoluteX, 4).with_page_penalty(),
+        /* 0xDE */ Opcode::new(Dec, AbsoluteX, 7),
+        /* 0xDF */ Opcode::new(Dcp, AbsoluteX, 7).illegal(),
+        /* 0xE0 */ Opcode::new(Cpx, Immediate, 2),
+        /* 0xE1 */ Opcode::new(Sbc, IndirectX, 6),
+        /* 0xE2 */ Opcode::new(Nop, Immediate, 2).illegal(),
+        /* 0xE3 */ Opcode::new(Isc, IndirectX, 8).illegal(),
+        /* 0xE4 */ Opcode::new(Cpx, ZeroPage, 3),
+        /* 0xE5 */ Opcode::new(Sbc, ZeroPage, 3),
+        /* 0xE6 */ Opcode::new(Inc, ZeroPage, 5),
+        /* 0xE7 */ Opcode::new(Isc, ZeroPage, 5).illegal(),
+        /* 0xE8 */ Opcode::new(Inx, Implied, 2),
+        /* 0xE9 */ Opcode::new(Sbc, Immediate, 2),
+        /* 0xEA */ Opcode::new(Nop, Implied, 2),
+        /* 0xEB */ Opcode::new(Sbc, Immediate, 2).illegal(),
+        /* 0xEC */ Opcode::new(Cpx, Absolute, 4),
+        /* 0xED */ Opcode::new(Sbc, Absolute, 4),
+        /* 0xEE */ Opcode::new(Inc, Absolute, 6),
+        /* 0xEF */ Opcode::new(Isc, Absolute, 6).illegal(),
+        /* 0xF0 */ Opcode::new(Beq, Relative, 2).with_page_penalty(),
+        /* 0xF1 */ Opcode::new(Sbc, IndirectY, 5).with_page_penalty(),
+        /* 0xF2 */ Opcode::new(Jam, Implied, 2).illegal(),
+        /* 0xF3 */ Opcode::new(Isc, IndirectY, 8).illegal(),
+        /* 0xF4 */ Opcode::new(Nop, ZeroPageX, 4).illegal(),
+        /* 0xF5 */ Opcode::new(Sbc, ZeroPageX, 4),
+        /* 0xF6 */ Opcode::new(Inc, ZeroPageX, 6),
+        /* 0xF7 */ Opcode::new(Isc, ZeroPageX, 6).illegal(),
+        /* 0xF8 */ Opcode::new(Sed, Implied, 2),
+        /* 0xF9 */ Opcode::new(Sbc, AbsoluteY, 4).with_page_penalty(),
+        /* 0xFA */ Opcode::new(Nop, Implied, 2).illegal(),
+        /* 0xFB */ Opcode::new(Isc, AbsoluteY, 7).illegal(),
+        /* 0xFC */ Opcode::new(Nop, AbsoluteX, 4).with_page_penalty().illegal(),
+        /* 0xFD */ Opcode::new(Sbc, AbsoluteX, 4).with_page_penalty(),
+        /* 0xFE */ Opcode::new(Inc, AbsoluteX, 7),
+        /* 0xFF */ Opcode::new(Isc, AbsoluteX, 7).illegal(),
+    ]
+};
+
+/// WDC 65C02 opcode table. Opcodes WDC left undefined are NOPs of a fixed
+/// size and duration, they are marked as illegal.
+#[rustfmt::skip]
+pub static OPCODES_65C02: [Opcode; 256] = {
+    use AddrMode::*;
+    use Mnemonic::*;
+    [
+        /* 0x00 */ Opcode::new(Brk, Implied, 7),
+        /* 0x01 */ Opcode::new(Ora, IndirectX, 6),
+        /* 0x02 */ Opcode::new(Nop, Immediate, 2).illegal(),
+        /* 0x03 */ Opcode::new(Nop, Implied, 1).illegal(),
+        /* 0x04 */ Opcode::new(Tsb, ZeroPage, 5),
+        /* 0x05 */ Opcode::new(Ora, ZeroPage, 3),
+        /* 0x06 */ Opcode::new(Asl, ZeroPage, 5),
+        /* 0x07 */ Opcode::new(Rmb(0), ZeroPage, 5),
+        /* 0x08 */ Opcode::new(Php, Implied, 3),
+        /* 0x09 */ Opcode::new(Ora, Immediate, 2),
+        /* 0x0A */ Opcode::new(Asl, Accumulator, 2),
+        /* 0x0B */ Opcode::new(Nop, Implied, 1).illegal(),
+        /* 0x0C */ Opcode::new(Tsb, Absolute, 6),
+        /* 0x0D */ Opcode::new(Ora, Absolute, 4),
+        /* 0x0E */ Opcode::new(Asl, Absolute, 6),
+        /* 0x0F */ Opcode::new(Bbr(0), ZeroPageRelative, 5),
+        /* 0x10 */ Opcode::new(Bpl, Relative, 2).with_page_penalty(),
+        /* 0x11 */ Opcode::new(Ora, IndirectY, 5).with_page_penalty(),
+        /* 0x12 */ Opcode::new(Ora, ZeroPageIndirect, 5),
+        /* 0x13 */ Opcode::new(Nop, Implied, 1).illegal(),
+        /* 0x14 */ Opcode::new(Trb, ZeroPage, 5),
+        /* 0x15 */ Opcode::new(Ora, ZeroPageX, 4),
+        /* 0x16 */ Opcode::new(Asl, ZeroPageX, 6),
+        /* 0x17 */ Opcode::new(Rmb(1), ZeroPage, 5),
+        /* 0x18 */ Opcode::new(Clc, Implied, 2),
+        /* 0x19 */ Opcode::new(Ora, AbsoluteY, 4).with_page_penalty(),
+        /* 0x1A */ Opcode::new(Inc, Accumulator, 2),
+        /* 0x1B */ Opcode::new(Nop, Implied, 1).illegal(),
+        /* 0x1C */ Opcode::new(Trb, Absolute, 6),
+        /* 0x1D */ Opcode::new(Ora, AbsoluteX, 4).with_page_penalty(),
+        /* 0x1E */ Opcode::new(Asl, AbsoluteX, 6).with_page_penalty(),
+        /* 0x1F */ Opcode::new(Bbr(1), ZeroPageRelative, 5),
+        /* 0x20 */ Opcode::new(Jsr, Absolute, 6),
+        /* 0x21 */ Opcode::new(And, IndirectX, 6),
+        /* 0x22 */ Opcode::new(Nop, Immediate, 2).illegal(),
+        /* 0x23 */ Opcode::new(Nop, Implied, 1).illegal(),
+        /* 0x24 */ Opcode::new(Bit, ZeroPage, 3),
+        /* 0x25 */ Opcode::new(And, ZeroPage, 3),
+        /* 0x26 */ Opcode::new(Rol, ZeroPage, 5),
+        /* 0x27 */ Opcode::new(Rmb(2), ZeroPage, 5),
+        /* 0x28 */ Opcode::new(Plp, Implied, 4),
+        /* 0x29 */ Opcode::new(And, Immediate, 2),
+        /* 0x2A */ Opcode::new(Rol, Accumulator, 2),
+        /* 0x2B */ Opcode::new(Nop, Implied, 1).illegal(),
+        /* 0x2C */ Opcode::new(Bit, Absolute, 4),
+        /* 0x2D */ Opcode::new(And, Absolute, 4),
+        /* 0x2E */ Opcode::new(Rol, Absolute, 6),
+        /* 0x2F */ Opcode::new(Bbr(2), ZeroPageRelative, 5),
+        /* 0x30 */ Opcode::new(Bmi, Relative, 2).with_page_penalty(),
+        /* 0x31 */ Opcode::new(And, IndirectY, 5).with_page_penalty(),
+        /* 0x32 */ Opcode::new(And, ZeroPageIndirect, 5),
+        /* 0x33 */ Opcode::new(Nop, Implied, 1).illegal(),
+        /* 0x34 */ Opcode::new(Bit, ZeroPageX, 4),
+        /* 0x35 */ Opcode::new(And, ZeroPageX, 4),
+        /* 0x36 */ Opcode::new(Rol, ZeroPageX, 6),
+        /* 0x37 */ Opcode::new(Rmb(3), ZeroPage, 5),
+        /* 0x38 */ Opcode::new(Sec, Implied, 2),
+        /* 0x39 */ Opcode::new(And, AbsoluteY, 4).with_page_penalty(),
+        /* 0x3A */ Opcode::new(Dec, Accumulator, 2),
+        /* 0x3B */ Opcode::new(Nop, Implied, 1).illegal(),
+        /* 0x3C */ Opcode::new(Bit, AbsoluteX, 4).with_page_penalty(),
+        /* 0x3D */ Opcode::new(And, AbsoluteX, 4).with_page_penalty(),
+        /* 0x3E */ Opcode::new(Rol, AbsoluteX, 6).with_page_penalty(),
+        /* 0x3F */ Opcode::new(Bbr(3), ZeroPageRelative, 5),
+        /* 0x40 */ Opcode::new(Rti, Implied, 6),
+        /* 0x41 */ Opcode::new(Eor, IndirectX, 6),
+        /* 0x42 */ Opcode::new(Nop, Immediate, 2).illegal(),
+        /* 0x43 */ Opcode::new(Nop, Implied, 1).illegal(),
+        /* 0x44 */ Opcode::new(Nop, ZeroPage, 3).illegal(),
+        /* 0x45 */ Opcode::new(Eor, ZeroPage, 3),
+        /* 0x46 */ Opcode::new(Lsr, ZeroPage, 5),
+        /* 0x47 */ Opcode::new(Rmb(4), ZeroPage, 5),
+        /* 0x48 */ Opcode::new(Pha, Implied, 3),
+        /* 0x49 */ Opcode::new(Eor, Immediate, 2),
+        /* 0x4A */ Opcode::new(Lsr, Accumulator, 2),
+        /* 0x4B */ Opcode::new(Nop, Implied, 1).illegal(),
+        /* 0x4C */ Opcode::new(Jmp, Absolute, 3),
+        /* 0x4D */ Opcode::new(Eor, Absolute, 4),
+        /* 0x4E */ Opcode::new(Lsr, Absolute, 6),
+        /* 0x4F */ Opcode::new(Bbr(4), ZeroPageRelative, 5),
+        /* 0x50 */ Opcode::new(Bvc, Relative, 2).with_page_penalty(),
+        /* 0x51 */ Opcode::new(Eor, IndirectY, 5).with_page_penalty(),
+        /* 0x52 */ Opcode::new(Eor, ZeroPageIndirect, 5),
+        /* 0x53 */ Opcode::new(Nop, Implied, 1).illegal(),
+        /* 0x54 */ Opcode::new(Nop, ZeroPageX, 4).illegal(),
+        /* 0x55 */ Opcode::new(Eor, ZeroPageX, 4),
+        /* 0x56 */ Opcode::new(Lsr, ZeroPageX, 6),
+        /* 0x57 */ Opcode::new(Rmb(5), ZeroPage, 5),
+        /* 0x58 */ Opcode::new(Cli, Implied, 2),
+        /* 0x59 */ Opcode::new(Eor, AbsoluteY, 4).with_page_penalty(),
+        /* 0x5A */ Opcode::new(Phy, Implied, 3),
+        /* 0x5B */ Opcode::new(Nop, Implied, 1).illegal(),
+        /* 0x5C */ Opcode::new(Nop, Absolute, 8).illegal(),
+        /* 0x5D */ Opcode::new(Eor, AbsoluteX, 4).with_page_penalty(),
+        /* 0x5E */ Opcode::new(Lsr, AbsoluteX, 6).with_page_penalty(),
+        /* 0x5F */ Opcode::new(Bbr(5), ZeroPageRelative, 5),
+        /* 0x60 */ Opcode::new(Rts, Implied, 6),
+        /* 0x61 */ Opcode::new(Adc, IndirectX, 6),
+        /* 0x62 */ Opcode::new(Nop, Immediate, 2).illegal(),
+        /* 0x63 */ Opcode::new(Nop, Implied, 1).illegal(),
+        /* 0x64 */ Opcode::new(Stz, ZeroPage, 3),
+        /* 0x65 */ Opcode::new(Adc, ZeroPage, 3),
+        /* 0x66 */ Opcode::new(Ror, ZeroPage, 5),
+        /* 0x67 */ Opcode::new(Rmb(6), ZeroPage, 5),
+        /* 0x68 */ Opcode::new(Pla, Implied, 4),
+        /* 0x69 */ Opcode::new(Adc, Immediate, 2),
+        /* 0x6A */ Opcode::new(Ror, Accumulator, 2),
+        /* 0x6B */ Opcode::new(Nop, Implied, 1).illegal(),
+        /* 0x6C */ Opcode::new(Jmp, Indirect, 6),
+        /* 0x6D */ Opcode::new(Adc, Absolute, 4),
+        /* 0x6E */ Opcode::new(Ror, Absolute, 6),
+        /* 0x6F */ Opcode::new(Bbr(6), ZeroPageRelative, 5),
+        /* 0x70 */ Opcode::new(Bvs, Relative, 2).with_page_penalty(),
+        /* 0x71 */ Opcode::new(Adc, IndirectY, 5).with_page_penalty(),
+        /* 0x72 */ Opcode::new(Adc, ZeroPageIndirect, 5),
+        /* 0x73 */ Opcode::new(Nop, Implied, 1).illegal(),
+        /* 0x74 */ Opcode::new(Stz, ZeroPageX, 4),
+        /* 0x75 */ Opcode::new(Adc, ZeroPageX, 4),
+        /* 0x76 */ Opcode::new(Ror, ZeroPageX, 6),
+        /* 0x77 */ Opcode::new(Rmb(7), ZeroPage, 5),
+        /* 0x78 */ Opcode::new(Sei, Implied, 2),
+        /* 0x79 */ Opcode::new(Adc, AbsoluteY, 4).with_page_penalty(),
+        /* 0x7A */ Opcode::new(Ply, Implied, 4),
+        /* 0x7B */ Opcode::new(Nop, Implied, 1).illegal(),
+        /* 0x7C */ Opcode::new(Jmp, AbsoluteIndexedIndirect, 6),
+        /* 0x7D */ Opcode::new(Adc, AbsoluteX, 4).with_page_penalty(),
+        /* 0x7E */ Opcode::new(Ror, AbsoluteX, 6).with_page_penalty(),
+        /* 0x7F */ Opcode::new(Bbr(7), ZeroPageRelative, 5),
+        /* 0x80 */ Opcode::new(Bra, Relative, 2).with_page_penalty(),
+        /* 0x81 */ Opcode::new(Sta, IndirectX, 6),
+        /* 0x82 */ Opcode::new(Nop, Immediate, 2).illegal(),
+        /* 0x83 */ Opcode::new(Nop, Implied, 1).illegal(),
+        /* 0x84 */ Opcode::new(Sty, ZeroPage, 3),
+        /* 0x85 */ Opcode::new(Sta, ZeroPage, 3),
+        /* 0x86 */ Opcode::new(Stx, ZeroPage, 3),
+        /* 0x87 */ Opcode::new(Smb(0), ZeroPage, 5),
+        /* 0x88 */ Opcode::new(Dey, Implied, 2),
+        /* 0x89 */ Opcode::new(Bit, Immediate, 2),
+        /* 0x8A */ Opcode::new(Txa, Implied, 2),
+        /* 0x8B */ Opcode::new(Nop, Implied, 1).illegal(),
+        /* 0x8C */ Opcode::new(Sty, Absolute, 4),
+        /* 0x8D */ Opcode::new(Sta, Absolute, 4),
+        /* 0x8E */ Opcode::new(Stx, Absolute, 4),
+        /* 0x8F */ Opcode::new(Bbs(0), ZeroPageRelative, 5),
+        /* 0x90 */ Opcode::new(Bcc, Relative, 2).with_page_penalty(),
+        /* 0x91 */ Opcode::new(Sta, IndirectY, 6),
+        /* 0x92 */ Opcode::new(Sta, ZeroPageIndirect, 5),
+        /* 0x93 */ Opcode::new(Nop, Implied, 1).illegal(),
+        /* 0x94 */ Opcode::new(Sty, ZeroPageX, 4),
+        /* 0x95 */ Opcode::new(Sta, ZeroPageX, 4),
+        /* 0x96 */ Opcode::new(Stx, ZeroPageY, 4),
+        /* 0x97 */ Opcode::new(Smb(1), ZeroPage, 5),
+        /* 0x98 */ Opcode::new(Tya, Implied, 2),
+        /* 0x99 */ Opcode::new(Sta, AbsoluteY, 5),
+        /* 0x9A */ Opcode::new(Txs, Implied, 2),
+        /* 0x9B */ Opcode::new(Nop, Implied, 1).illegal(),
+        /* 0x9C */ Opcode::new(Stz, Absolute, 4),
+        /* 0x9D */ Opcode::new(Sta, AbsoluteX, 5),
+        /* 0x9E */ Opcode::new(Stz, AbsoluteX, 5),
+        /* 0x9F */ Opcode::new(Bbs(1), ZeroPageRelative, 5),
+        /* 0xA0 */ Opcode::new(Ldy, Immediate, 2),
+        /* 0xA1 */ Opcode::new(Lda, IndirectX, 6),
+        /* 0xA2 */ Opcode::new(Ldx, Immediate, 2),
+        /* 0xA3 */ Opcode::new(Nop, Implied, 1).illegal(),
+        /* 0xA4 */ Opcode::new(Ldy, ZeroPage, 3),
+        /* 0xA5 */ Opcode::new(Lda, ZeroPage, 3),
+        /* 0xA6 */ Opcode::new(Ldx, ZeroPage, 3),
+        /* 0xA7 */ Opcode::new(Smb(2), ZeroPage, 5),
+        /* 0xA8 */ Opcode::new(Tay, Implied, 2),
+        /* 0xA9 */ Opcode::new(Lda, Immediate, 2),
+        /* 0xAA */ Opcode::new(Tax, Implied, 2),
+        /* 0xAB */ Opcode::new(Nop, Implied, 1).illegal(),
+        /* 0xAC */ Opcode::new(Ldy, Absolute, 4),
+        /* 0xAD */ Opcode::new(Lda, Absolute, 4),
+        /* 0xAE */ Opcode::new(Ldx, Absolute, 4),
+        /* 0xAF */ Opcode::new(Bbs(2), ZeroPageRelative, 5),
+        /* 0xB0 */ Opcode::new(Bcs, Relative, 2).with_page_penalty(),
+        /* 0xB1 */ Opcode::new(Lda, IndirectY, 5).with_page_penalty(),
+        /* 0xB2 */ Opcode::new(Lda, ZeroPageIndirect, 5),
+        /* 0xB3 */ Opcode::new(Nop, Implied, 1).illegal(),
+        /* 0xB4 */ Opcode::new(Ldy, ZeroPageX, 4),
+        /* 0xB5 */ Opcode::new(Lda, ZeroPageX, 4),
+        /* 0xB6 */ Opcode::new(Ldx, ZeroPageY, 4),
+        /* 0xB7 */ Opcode::new(Smb(3), ZeroPage, 5),
+        /* 0xB8 */ Opcode::new(Clv, Implied, 2),
+        /* 0xB9 */ Opcode::new(Lda, AbsoluteY, 4).with_page_penalty(),
+        /* 0xBA */ Opcode::new(Tsx, Implied, 2),
+        /* 0xBB */ Opcode::new(Nop, Implied, 1).illegal(),
+        /* 0xBC */ Opcode::new(Ldy, AbsoluteX, 4).with_page_penalty(),
+        /* 0xBD */ Opcode::new(Lda, AbsoluteX, 4).with_page_penalty(),
+        /* 0xBE */ Opcode::new(Ldx, AbsoluteY, 4).with_page_penalty(),
+        /* 0xBF */ Opcode::new(Bbs(3), ZeroPageRelative, 5),
+        /* 0xC0 */ Opcode::new(Cpy, Immediate, 2),
+        /* 0xC1 */ Opcode::new(Cmp, IndirectX, 6),
+        /* 0xC2 */ Opcode::new(Nop, Immediate, 2).illegal(),
+        /* 0xC3 */ Opcode::new(Nop, Implied, 1).illegal(),
+        /* 0xC4 */ Opcode::new(Cpy, ZeroPage, 3),
+        /* 0xC5 */ Opcode::new(Cmp, ZeroPage, 3),
+        /* 0xC6 */ Opcode::new(Dec, ZeroPage, 5),
+        /* 0xC7 */ Opcode::new(Smb(4), ZeroPage, 5),
+        /* 0xC8 */ Opcode::new(Iny, Implied, 2),
+        /* 0xC9 */ Opcode::new(Cmp, Immediate, 2),
+        /* 0xCA */ Opcode::new(Dex, Implied, 2),
+        /* 0xCB */ Opcode::new(Wai, Implied, 3),
+        /* 0xCC */ Opcode::new(Cpy, Absolute, 4),
+        /* 0xCD */ Opcode::new(Cmp, Absolute, 4),
+        /* 0xCE */ Opcode::new(Dec, Absolute, 6),
+        /* 0xCF */ Opcode::new(Bbs(4), ZeroPageRelative, 5),
+        /* 0xD0 */ Opcode::new(Bne, Relative, 2).with_page_penalty(),
+        /* 0xD1 */ Opcode::new(Cmp, IndirectY, 5).with_page_penalty(),
+        /* 0xD2 */ Opcode::new(Cmp, ZeroPageIndirect, 5),
+        /* 0xD3 */ Opcode::new(Nop, Implied, 1).illegal(),
+        /* 0xD4 */ Opcode::new(Nop, ZeroPageX, 4).illegal(),
+        /* 0xD5 */ Opcode::new(Cmp, ZeroPageX, 4),
+        /* 0xD6 */ Opcode::new(Dec, ZeroPageX, 6),
+        /* 0xD7 */ Opcode::new(Smb(5), ZeroPage, 5),
+        /* 0xD8 */ Opcode::new(Cld, Implied, 2),
+        /* 0xD9 */ Opcode::new(Cmp, AbsoluteY, 4).with_page_penalty(),
+        /* 0xDA */ Opcode::new(Phx, Implied, 3),
+        /* 0xDB */ Opcode::new(Stp, Implied, 3),
+        /* 0xDC */ Opcode::new(Nop, Absolute, 4).illegal(),
+        /* 0xDD */ Opcode::new(Cmp, AbsoluteX, 4).with_page_penalty(),
+        /* 0xDE */ Opcode::new(Dec, AbsoluteX, 7),
+        /* 0xDF */ Opcode::new(Bbs(5), ZeroPageRelative, 5),
+        /* 0xE0 */ Opcode::new(Cpx, Immediate, 2),
+        /* 0xE1 */ Opcode::new(Sbc, IndirectX, 6),
+        /* 0xE2 */ Opcode::new(Nop, Immediate, 2).illegal(),
+        /* 0xE3 */ Opcode::new(Nop, Implied, 1).illegal(),
+        /* 0xE4 */ Opcode::new(Cpx, ZeroPage, 3),
+        /* 0xE5 */ Opcode::new(Sbc, ZeroPage, 3),
+        /* 0xE6 */ Opcode::new(Inc, ZeroPage, 5),
+        /* 0xE7 */ Opcode::new(Smb(6), ZeroPage, 5),
+        /* 0xE8 */ Opcode::new(Inx, Implied, 2),
+        /* 0xE9 */ Opcode::new(Sbc, Immediate, 2),
+        /* 0xEA */ Opcode::new(Nop, Implied, 2),
+        /* 0xEB */ Opcode::new(Nop, Implied, 1).illegal(),
+        /* 0xEC */ Opcode::new(Cpx, Absolute, 4),
+        /* 0xED */ Opcode::new(Sbc, Absolute, 4),
+        /* 0xEE */ Opcode::new(Inc, Absolute, 6),
+        /* 0xEF */ Opcode::new(Bbs(6), ZeroPageRelative, 5),
+        /* 0xF0 */ Opcode::new(Beq, Relative, 2).with_page_penalty(),
+        /* 0xF1 */ Opcode::new(Sbc, IndirectY, 5).with_page_penalty(),
+        /* 0xF2 */ Opcode::new(Sbc, ZeroPageIndirect, 5),
+        /* 0xF3 */ Opcode::new(Nop, Implied, 1).illegal(),
+        /* 0xF4 */ Opcode::new(Nop, ZeroPageX, 4).illegal(),
+        /* 0xF5 */ Opcode::new(Sbc, ZeroPageX, 4),
+        /* 0xF6 */ Opcode::new(Inc, ZeroPageX, 6),
+        /* 0xF7 */ Opcode::new(Smb(7), ZeroPage, 5),
+        /* 0xF8 */ Opcode::new(Sed, Implied, 2),
+        /* 0xF9 */ Opcode::new(Sbc, AbsoluteY, 4).with_page_penalty(),
+        /* 0xFA */ Opcode::new(Plx, Implied, 4),
+        /* 0xFB */ Opcode::new(Nop, Implied, 1).illegal(),
+        /* 0xFC */ Opcode::new(Nop, Absolute, 4).illegal(),
+        /* 0xFD */ Opcode::new(Sbc, AbsoluteX, 4).with_page_penalty(),
+        /* 0xFE */ Opcode::new(Inc, AbsoluteX, 7),
+        /* 0xFF */ Opcode::new(Bbs(7), ZeroPageRelative, 5),
+    ]
+};
+
+/// 65816 opcode table, with the cycle counts of emulation mode: 8-bit
+/// registers and the direct page at a page boundary. Every opcode is
+/// defined, the 65C02 bit instructions gave way to the long addressing
+/// modes.
+#[rustfmt::skip]
+pub static OPCODES_65816: [Opcode; 256] = {
+    use AddrMode::*;
+    use Mnemonic::*;
+    [
+        /* 0x00 */ Opcode::new(Brk, Implied, 7),
+        /* 0x01 */ Opcode::new(Ora, IndirectX, 6),
+        /* 0x02 */ Opcode::new(Cop, Immediate, 7),
+        /* 0x03 */ Opcode::new(Ora, StackRelative, 4),
+        /* 0x04 */ Opcode::new(Tsb, ZeroPage, 5),
+        /* 0x05 */ Opcode::new(Ora, ZeroPage, 3),
+        /* 0x06 */ Opcode::new(Asl, ZeroPage, 5),
+        /* 0x07 */ Opcode::new(Ora, DirectIndirectLong, 6),
+        /* 0x08 */ Opcode::new(Php, Implied, 3),
+        /* 0x09 */ Opcode::new(Ora, Immediate, 2),
+        /* 0x0A */ Opcode::new(Asl, Accumulator, 2),
+        /* 0x0B */ Opcode::new(Phd, Implied, 4),
+        /* 0x0C */ Opcode::new(Tsb, Absolute, 6),
+        /* 0x0D */ Opcode::new(Ora, Absolute, 4),
+        /* 0x0E */ Opcode::new(Asl, Absolute, 6),
+        /* 0x0F */ Opcode::new(Ora, AbsoluteLong, 5),
+        /* 0x10 */ Opcode::new(Bpl, Relative, 2).with_page_penalty(),
+        /* 0x11 */ Opcode::new(Ora, IndirectY, 5).with_page_penalty(),
+        /* 0x12 */ Opcode::new(Ora, ZeroPageIndirect, 5),
+        /* 0x13 */ Opcode::new(Ora, StackRelativeIndirectY, 7),
+        /* 0x14 */ Opcode::new(Trb, ZeroPage, 5),
+        /* 0x15 */ Opcode::new(Ora, ZeroPageX, 4),
+        /* 0x16 */ Opcode::new(Asl, ZeroPageX, 6),
+        /* 0x17 */ Opcode::new(Ora, DirectIndirectLongY, 6),
+        /* 0x18 */ Opcode::new(Clc, Implied, 2),
+        /* 0x19 */ Opcode::new(Ora, AbsoluteY, 4).with_page_penalty(),
+        /* 0x1A */ Opcode::new(Inc, Accumulator, 2),
+        /* 0x1B */ Opcode::new(Tcs, Implied, 2),
+        /* 0x1C */ Opcode::new(Trb, Absolute, 6),
+        /* 0x1D */ Opcode::new(Ora, AbsoluteX, 4).with_page_penalty(),
+        /* 0x1E */ Opcode::new(Asl, AbsoluteX, 7),
+        /* 0x1F */ Opcode::new(Ora, AbsoluteLongX, 5),
+        /* 0x20 */ Opcode::new(Jsr, Absolute, 6),
+        /* 0x21 */ Opcode::new(And, IndirectX, 6),
+        /* 0x22 */ Opcode::new(Jsl, AbsoluteLong, 8),
+        /* 0x23 */ Opcode::new(And, StackRelative, 4),
+        /* 0x24 */ Opcode::new(Bit, ZeroPage, 3),
+        /* 0x25 */ Opcode::new(And, ZeroPage, 3),
+        /* 0x26 */ Opcode::new(Rol, ZeroPage, 5),
+        /* 0x27 */ Opcode::new(And, DirectIndirectLong, 6),
+        /* 0x28 */ Opcode::new(Plp, Implied, 4),
+        /* 0x29 */ Opcode::new(And, Immediate, 2),
+        /* 0x2A */ Opcode::new(Rol, Accumulator, 2),
+        /* 0x2B */ Opcode::new(Pld, Implied, 5),
+        /* 0x2C */ Opcode::new(Bit, Absolute, 4),
+        /* 0x2D */ Opcode::new(And, Absolute, 4),
+        /* 0x2E */ Opcode::new(Rol, Absolute, 6),
+        /* 0x2F */ Opcode::new(And, AbsoluteLong, 5),
+        /* 0x30 */ Opcode::new(Bmi, Relative, 2).with_page_penalty(),
+        /* 0x31 */ Opcode::new(And, IndirectY, 5).with_page_penalty(),
+        /* 0x32 */ Opcode::new(And, ZeroPageIndirect, 5),
+        /* 0x33 */ Opcode::new(And, StackRelativeIndirectY, 7),
+        /* 0x34 */ Opcode::new(Bit, ZeroPageX, 4),
+        /* 0x35 */ Opcode::new(And, ZeroPageX, 4),
+        /* 0x36 */ Opcode::new(Rol, ZeroPageX, 6),
+        /* 0x37 */ Opcode::new(And, DirectIndirectLongY, 6),
+        /* 0x38 */ Opcode::new(Sec, Implied, 2),
+        /* 0x39 */ Opcode::new(And, AbsoluteY, 4).with_page_penalty(),
+        /* 0x3A */ Opcode::new(Dec, Accumulator, 2),
+        /* 0x3B */ Opcode::new(Tsc, Implied, 2),
+        /* 0x3C */ Opcode::new(Bit, AbsoluteX, 4).with_page_penalty(),
+        /* 0x3D */ Opcode::new(And, AbsoluteX, 4).with_page_penalty(),
+        /* 0x3E */ Opcode::new(Rol, AbsoluteX, 7),
+        /* 0x3F */ Opcode::new(And, AbsoluteLongX, 5),
+        /* 0x40 */ Opcode::new(Rti, Implied, 6),
+        /* 0x41 */ Opcode::new(Eor, IndirectX, 6),
+        /* 0x42 */ Opcode::new(Wdm, Immediate, 2),
+        /* 0x43 */ Opcode::new(Eor, StackRelative, 4),
+        /* 0x44 */ Opcode::new(Mvp, BlockMove, 7),
+        /* 0x45 */ Opcode::new(Eor, ZeroPage, 3),
+        /* 0x46 */ Opcode::new(Lsr, ZeroPage, 5),
+        /* 0x47 */ Opcode::new(Eor, DirectIndirectLong, 6),
+        /* 0x48 */ Opcode::new(Pha, Implied, 3),
+        /* 0x49 */ Opcode::new(Eor, Immediate, 2),
+        /* 0x4A */ Opcode::new(Lsr, Accumulator, 2),
+        /* 0x4B */ Opcode::new(Phk, Implied, 3),
+        /* 0x4C */ Opcode::new(Jmp, Absolute, 3),
+        /* 0x4D */ Opcode::new(Eor, Absolute, 4),
+        /* 0x4E */ Opcode::new(Lsr, Absolute, 6),
+        /* 0x4F */ Opcode::new(Eor, AbsoluteLong, 5),
+        /* 0x50 */ Opcode::new(Bvc, Relative, 2).with_page_penalty(),
+        /* 0x51 */ Opcode::new(Eor, IndirectY, 5).with_page_penalty(),
+        /* 0x52 */ Opcode::new(Eor, ZeroPageIndirect, 5),
+        /* 0x53 */ Opcode::new(Eor, StackRelativeIndirectY, 7),
+        /* 0x54 */ Opcode::new(Mvn, BlockMove, 7),
+        /* 0x55 */ Opcode::new(Eor, ZeroPageX, 4),
+        /* 0x56 */ Opcode::new(Lsr, ZeroPageX, 6),
+        /* 0x57 */ Opcode::new(Eor, DirectIndirectLongY, 6),
+        /* 0x58 */ Opcode::new(Cli, Implied, 2),
+        /* 0x59 */ Opcode::new(Eor, AbsoluteY, 4).with_page_penalty(),
+        /* 0x5A */ Opcode::new(Phy, Implied, 3),
+        /* 0x5B */ Opcode::new(Tcd, Implied, 2),
+        /* 0x5C */ Opcode::new(Jml, AbsoluteLong, 4),
+        /* 0x5D */ Opcode::new(Eor, AbsoluteX, 4).with_page_penalty(),
+        /* 0x5E */ Opcode::new(Lsr, AbsoluteX, 7),
+        /* 0x5F */ Opcode::new(Eor, AbsoluteLongX, 5),
+        /* 0x60 */ Opcode::new(Rts, Implied, 6),
+        /* 0x61 */ Opcode::new(Adc, IndirectX, 6),
+        /* 0x62 */ Opcode::new(Per, RelativeLong, 6),
+        /* 0x63 */ Opcode::new(Adc, StackRelative, 4),
+        /* 0x64 */ Opcode::new(Stz, ZeroPage, 3),
+        /* 0x65 */ Opcode::new(Adc, ZeroPage, 3),
+        /* 0x66 */ Opcode::new(Ror, ZeroPage, 5),
+        /* 0x67 */ Opcode::new(Adc, DirectIndirectLong, 6),
+        /* 0x68 */ Opcode::new(Pla, Implied, 4),
+        /* 0x69 */ Opcode::new(Adc, Immediate, 2),
+        /* 0x6A */ Opcode::new(Ror, Accumulator, 2),
+        /* 0x6B */ Opcode::new(Rtl, Implied, 6),
+        /* 0x6C */ Opcode::new(Jmp, Indirect, 5),
+        /* 0x6D */ Opcode::new(Adc, Absolute, 4),
+        /* 0x6E */ Opcode::new(Ror, Absolute, 6),
+        /* 0x6F */ Opcode::new(Adc, AbsoluteLong, 5),
+        /* 0x70 */ Opcode::new(Bvs, Relative, 2).with_page_penalty(),
+        /* 0x71 */ Opcode::new(Adc, IndirectY, 5).with_page_penalty(),
+        /* 0x72 */ Opcode::new(Adc, ZeroPageIndirect, 5),
+        /* 0x73 */ Opcode::new(Adc, StackRelativeIndirectY, 7),
+        /* 0x74 */ Opcode::new(Stz, ZeroPageX, 4),
+        /* 0x75 */ Opcode::new(Adc, ZeroPageX, 4),
+        /* 0x76 */ Opcode::new(Ror, ZeroPageX, 6),
+        /* 0x77 */ Opcode::new(Adc, DirectIndirectLongY, 6),
+        /* 0x78 */ Opcode::new(Sei, Implied, 2),
+        /* 0x79 */ Opcode::new(Adc, AbsoluteY, 4).with_page_penalty(),
+        /* 0x7A */ Opcode::new(Ply, Implied, 4),
+        /* 0x7B */ Opcode::new(Tdc, Implied, 2),
+        /* 0x7C */ Opcode::new(Jmp, AbsoluteIndexedIndirect, 6),
+        /* 0x7D */ Opcode::new(Adc, AbsoluteX, 4).with_page_penalty(),
+        /* 0x7E */ Opcode::new(Ror, AbsoluteX, 7),
+        /* 0x7F */ Opcode::new(Adc, AbsoluteLongX, 5),
+        /* 0x80 */ Opcode::new(Bra, Relative, 2).with_page_penalty(),
+        /* 0x81 */ Opcode::new(Sta, IndirectX, 6),
+        /* 0x82 */ Opcode::new(Brl, RelativeLong, 4),
+        /* 0x83 */ Opcode::new(Sta, StackRelative, 4),
+        /* 0x84 */ Opcode::new(Sty, ZeroPage, 3),
+        /* 0x85 */ Opcode::new(Sta, ZeroPage, 3),
+        /* 0x86 */ Opcode::new(Stx, ZeroPage, 3),
+        /* 0x87 */ Opcode::new(Sta, DirectIndirectLong, 6),
+        /* 0x88 */ Opcode::new(Dey, Implied, 2),
+        /* 0x89 */ Opcode::new(Bit, Immediate, 2),
+        /* 0x8A */ Opcode::new(Txa, Implied, 2),
+        /* 0x8B */ Opcode::new(Phb, Implied, 3),
+        /* 0x8C */ Opcode::new(Sty, Absolute, 4),
+        /* 0x8D */ Opcode::new(Sta, Absolute, 4),
+        /* 0x8E */ Opcode::new(Stx, Absolute, 4),
+        /* 0x8F */ Opcode::new(Sta, AbsoluteLong, 5),
+        /* 0x90 */ Opcode::new(Bcc, Relative, 2).with_page_penalty(),
+        /* 0x91 */ Opcode::new(Sta, IndirectY, 6),
+        /* 0x92 */ Opcode::new(Sta, ZeroPageIndirect, 5),
+        /* 0x93 */ Opcode::new(Sta, StackRelativeIndirectY, 7),
+        /* 0x94 */ Opcode::new(Sty, ZeroPageX, 4),
+        /* 0x95 */ Opcode::new(Sta, ZeroPageX, 4),
+        /* 0x96 */ Opcode::new(Stx, ZeroPageY, 4),
+        /* 0x97 */ Opcode::new(Sta, DirectIndirectLongY, 6),
+        /* 0x98 */ Opcode::new(Tya, Implied, 2),
+        /* 0x99 */ Opcode::new(Sta, AbsoluteY, 5),
+        /* 0x9A */ Opcode::new(Txs, Implied, 2),
+        /* 0x9B */ Opcode::new(Txy, Implied, 2),
+        /* 0x9C */ Opcode::new(Stz, Absolute, 4),
+        /* 0x9D */ Opcode::new(Sta, AbsoluteX, 5),
+        /* 0x9E */ Opcode::new(Stz, AbsoluteX, 5),
+        /* 0x9F */ Opcode::new(Sta, AbsoluteLongX, 5),
+        /* 0xA0 */ Opcode::new(Ldy, Immediate, 2),
+        /* 0xA1 */ Opcode::new(Lda, IndirectX, 6),
+        /* 0xA2 */ Opcode::new(Ldx, Immediate, 2),
+        /* 0xA3 */ Opcode::new(Lda, StackRelative, 4),
+        /* 0xA4 */ Opcode::new(Ldy, ZeroPage, 3),
+        /* 0xA5 */ Opcode::new(Lda, ZeroPage, 3),
+        /* 0xA6 */ Opcode::new(Ldx, ZeroPage, 3),
+        /* 0xA7 */ Opcode::new(Lda, DirectIndirectLong, 6),
+        /* 0xA8 */ Opcode::new(Tay, Implied, 2),
+        /* 0xA9 */ Opcode::new(Lda, Immediate, 2),
+        /* 0xAA */ Opcode::new(Tax, Implied, 2),
+        /* 0xAB */ Opcode::new(Plb, Implied, 4),
+        /* 0xAC */ Opcode::new(Ldy, Absolute, 4),
+        /* 0xAD */ Opcode::new(Lda, Absolute, 4),
+        /* 0xAE */ Opcode::new(Ldx, Absolute, 4),
+        /* 0xAF */ Opcode::new(Lda, AbsoluteLong, 5),
+        /* 0xB0 */ Opcode::new(Bcs, Relative, 2).with_page_penalty(),
+        /* 0xB1 */ Opcode::new(Lda, IndirectY, 5).with_page_penalty(),
+        /* 0xB2 */ Opcode::new(Lda, ZeroPageIndirect, 5),
+        /* 0xB3 */ Opcode::new(Lda, StackRelativeIndirectY, 7),
+        /* 0xB4 */ Opcode::new(Ldy, ZeroPageX, 4),
+        /* 0xB5 */ Opcode::new(Lda, ZeroPageX, 4),
+        /* 0xB6 */ Opcode::new(Ldx, ZeroPageY, 4),
+        /* 0xB7 */ Opcode::new(Lda, DirectIndirectLongY, 6),
+        /* 0xB8 */ Opcode::new(Clv, Implied, 2),
+        /* 0xB9 */ Opcode::new(Lda, AbsoluteY, 4).with_page_penalty(),
+        /* 0xBA */ Opcode::new(Tsx, Implied, 2),
+        /* 0xBB */ Opcode::new(Tyx, Implied, 2),
+        /* 0xBC */ Opcode::new(Ldy, AbsoluteX, 4).with_page_penalty(),
+        /* 0xBD */ Opcode::new(Lda, AbsoluteX, 4).with_page_penalty(),
+        /* 0xBE */ Opcode::new(Ldx, AbsoluteY, 4).with_page_penalty(),
+        /* 0xBF */ Opcode::new(Lda, AbsoluteLongX, 5),
+        /* 0xC0 */ Opcode::new(Cpy, Immediate, 2),
+        /* 0xC1 */ Opcode::new(Cmp, IndirectX, 6),
+        /* 0xC2 */ Opcode::new(Rep, Immediate, 3),
+        /* 0xC3 */ Opcode::new(Cmp, StackRelative, 4),
+        /* 0xC4 */ Opcode::new(Cpy, ZeroPage, 3),
+        /* 0xC5 */ Opcode::new(Cmp, ZeroPage, 3),
+        /* 0xC6 */ Opcode::new(Dec, ZeroPage, 5),
+        /* 0xC7 */ Opcode::new(Cmp, DirectIndirectLong, 6),
+        /* 0xC8 */ Opcode::new(Iny, Implied, 2),
+        /* 0xC9 */ Opcode::new(Cmp, Immediate, 2),
+        /* 0xCA */ Opcode::new(Dex, Implied, 2),
+        /* 0xCB */ Opcode::new(Wai, Implied, 3),
+        /* 0xCC */ Opcode::new(Cpy, Absolute, 4),
+        /* 0xCD */ Opcode::new(Cmp, Absolute, 4),
+        /* 0xCE */ Opcode::new(Dec, Absolute, 6),
+        /* 0xCF */ Opcode::new(Cmp, AbsoluteLong, 5),
+        /* 0xD0 */ Opcode::new(Bne, Relative, 2).with_page_penalty(),
+        /* 0xD1 */ Opcode::new(Cmp, IndirectY, 5).with_page_penalty(),
+        /* 0xD2 */ Opcode::new(Cmp, ZeroPageIndirect, 5),
+        /* 0xD3 */ Opcode::new(Cmp, StackRelativeIndirectY, 7),
+        /* 0xD4 */ Opcode::new(Pei, ZeroPageIndirect, 6),
+        /* 0xD5 */ Opcode::new(Cmp, ZeroPageX, 4),
+        /* 0xD6 */ Opcode::new(Dec, ZeroPageX, 6),
+        /* 0xD7 */ Opcode::new(Cmp, DirectIndirectLongY, 6),
+        /* 0xD8 */ Opcode::new(Cld, Implied, 2),
+        /* 0xD9 */ Opcode::new(Cmp, AbsoluteY, 4).with_page_penalty(),
+        /* 0xDA */ Opcode::new(Phx, Implied, 3),
+        /* 0xDB */ Opcode::new(Stp, Implied, 3),
+        /* 0xDC */ Opcode::new(Jml, AbsoluteIndirectLong, 6),
+        /* 0xDD */ Opcode::new(Cmp, AbsoluteX, 4).with_page_penalty(),
+        /* 0xDE */ Opcode::new(Dec, AbsoluteX, 7),
+        /* 0xDF */ Opcode::new(Cmp, AbsoluteLongX, 5),
+        /* 0xE0 */ Opcode::new(Cpx, Immediate, 2),
+        /* 0xE1 */ Opcode::new(Sbc, IndirectX, 6),
+        /* 0xE2 */ Opcode::new(Sep, Immediate, 3),
+        /* 0xE3 */ Opcode::new(Sbc, StackRelative, 4),
+        /* 0xE4 */ Opcode::new(Cpx, ZeroPage, 3),
+        /* 0xE5 */ Opcode::new(Sbc, ZeroPage, 3),
+        /* 0xE6 */ Opcode::new(Inc, ZeroPage, 5),
+        /* 0xE7 */ Opcode::new(Sbc, DirectIndirectLong, 6),
+        /* 0xE8 */ Opcode::new(Inx, Implied, 2),
+        /* 0xE9 */ Opcode::new(Sbc, Immediate, 2),
+        /* 0xEA */ Opcode::new(Nop, Implied, 2),
+        /* 0xEB */ Opcode::new(Xba, Implied, 3),
+        /* 0xEC */ Opcode::new(Cpx, Absolute, 4),
+        /* 0xED */ Opcode::new(Sbc, Absolute, 4),
+        /* 0xEE */ Opcode::new(Inc, Absolute, 6),
+        /* 0xEF */ Opcode::new(Sbc, AbsoluteLong, 5),
+        /* 0xF0 */ Opcode::new(Beq, Relative, 2).with_page_penalty(),
+        /* 0xF1 */ Opcode::new(Sbc, IndirectY, 5).with_page_penalty(),
+        /* 0xF2 */ Opcode::new(Sbc, ZeroPageIndirect, 5),
+        /* 0xF3 */ Opcode::new(Sbc, StackRelativeIndirectY, 7),
+        /* 0xF4 */ Opcode::new(Pea, Absolute, 5),
+        /* 0xF5 */ Opcode::new(Sbc, ZeroPageX, 4),
+        /* 0xF6 */ Opcode::new(Inc, ZeroPageX, 6),
+        /* 0xF7 */ Opcode::new(Sbc, DirectIndirectLongY, 6),
+        /* 0xF8 */ Opcode::new(Sed, Implied, 2),
+        /* 0xF9 */ Opcode::new(Sbc, AbsoluteY, 4).with_page_penalty(),
+        /* 0xFA */ Opcode::new(Plx, Implied, 4),
+        /* 0xFB */ Opcode::new(Xce, Implied, 2),
+        /* 0xFC */ Opcode::new(Jsr, AbsoluteIndexedIndirect, 8),
+        /* 0xFD */ Opcode::new(Sbc, AbsoluteX, 4).with_page_penalty(),
+        /* 0xFE */ Opcode::new(Inc, AbsoluteX, 7),
+        /* 0xFF */ Opcode::new(Sbc, AbsoluteLongX, 5),
+    ]
+};
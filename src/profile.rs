@@ -0,0 +1,276 @@
+// Cycle profiler: the cycles each step takes are charged to the instruction
+// and, through the shadow call stack, to the routines it runs in.
+//
+// A step is charged to the call stack from before it, so a JSR costs the
+// caller and the RTS the subroutine. Entering an interrupt runs no
+// instruction, its cycles are charged to the handler it enters. Code outside of any frame, such as the
+// main loop a program jumps to, is the top level. The profile can be saved
+// for KCachegrind and other callgrind tools, or as folded stacks, one line
+// per distinct stack, for flame graph tools.
+use crate::callstack::{CallStack, Frame, FrameKind};
+use crate::cpu::Interrupt;
+use crate::symbols::Symbols;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ProfileFormat {
+    #[default]
+    Callgrind,
+    Folded,
+}
+
+impl FromStr for ProfileFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "callgrind" => Ok(ProfileFormat::Callgrind),
+            "folded" => Ok(ProfileFormat::Folded),
+            _ => Err(format!("unknown profile format '{}'", s)),
+        }
+    }
+}
+
+/// Code entered through a frame, None is the top level
+pub type Routine = Option<(FrameKind, u16)>;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Cost {
+    pub cycles: u64,
+    pub instructions: u64,
+}
+
+impl Cost {
+    fn add(&mut self, cycles: u32, instruction: bool) {
+        self.cycles += cycles as u64;
+        self.instructions += instruction as u64;
+    }
+}
+
+/// Calls from one place to a routine and everything they cost
+#[derive(Clone, Copy, Debug, Default)]
+struct Calls {
+    count: u64,
+    inclusive: Cost,
+}
+
+/// Cycles a routine spent itself and including the routines it called
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RoutineCost {
+    pub routine: Routine,
+    pub own: u64,
+    pub inclusive: u64,
+}
+
+#[derive(Default)]
+pub struct Profiler {
+    frames: Vec<Frame>,                 // Call stack the next step runs in
+    path: Vec<(FrameKind, u16)>,        // The routines of those frames
+    own: HashMap<(Routine, u16), Cost>, // By routine and instruction
+    calls: HashMap<(Routine, u16, (FrameKind, u16)), Calls>, // By caller, call site and callee
+    stacks: HashMap<Vec<(FrameKind, u16)>, u64>,
+    total: Cost,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Charges the step of the instruction at `pc` taking `cycles`, call
+    /// after the step with the call stack it left and the interrupt it
+    /// entered instead of running the instruction, if any
+    pub fn record(
+        &mut self,
+        pc: u16,
+        cycles: u32,
+        calls: &CallStack,
+        interrupted: Option<Interrupt>,
+    ) {
+        // The entry costs the handler's first instruction
+        let instruction = interrupted.is_none();
+        let pc = if instruction {
+            pc
+        } else {
+            self.follow(calls.frames());
+            self.frames.last().map_or(pc, |frame| frame.target)
+        };
+        self.total.add(cycles, instruction);
+        let routine = self.path.last().copied();
+        self.own
+            .entry((routine, pc))
+            .or_default()
+            .add(cycles, instruction);
+        for (i, frame) in self.frames.iter().enumerate() {
+            let caller = i.checked_sub(1).map(|i| self.path[i]);
+            let key = (caller, frame.caller, self.path[i]);
+            self.calls
+                .entry(key)
+                .or_default()
+                .inclusive
+                .add(cycles, instruction);
+        }
+        match self.stacks.get_mut(self.path.as_slice()) {
+            Some(stack) => *stack += cycles as u64,
+            None => {
+                self.stacks.insert(self.path.clone(), cycles as u64);
+            }
+        }
+        self.follow(calls.frames());
+    }
+
+    // Takes over the frames that changed
+    fn follow(&mut self, frames: &[Frame]) {
+        let kept = self
+            .frames
+            .iter()
+            .zip(frames)
+            .take_while(|(old, new)| old == new)
+            .count();
+        if kept == self.frames.len() && kept == frames.len() {
+            return;
+        }
+        self.frames.truncate(kept);
+        self.path.truncate(kept);
+        for frame in &frames[kept..] {
+            let caller = self.path.last().copied();
+            let routine = (frame.kind, frame.target);
+            self.calls
+                .entry((caller, frame.caller, routine))
+                .or_default()
+                .count += 1;
+            self.frames.push(*frame);
+            self.path.push(routine);
+        }
+    }
+
+    pub fn total(&self) -> Cost {
+        self.total
+    }
+
+    /// Starts over, e.g. once a program has been loaded
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Cost of every routine run, the most expensive ones first
+    pub fn routines(&self) -> Vec<RoutineCost> {
+        let mut costs: HashMap<Routine, RoutineCost> = HashMap::new();
+        for (path, &cycles) in &self.stacks {
+            let mut routines: Vec<Routine> = vec![None];
+            routines.extend(path.iter().map(|&r| Some(r)));
+            let innermost = routines[routines.len() - 1];
+            // Recursive routines count once per stack
+            routines.sort();
+            routines.dedup();
+            for routine in routines {
+                let cost = costs.entry(routine).or_insert(RoutineCost {
+                    routine,
+                    own: 0,
+                    inclusive: 0,
+                });
+                cost.inclusive += cycles;
+                if routine == innermost {
+                    cost.own += cycles;
+                }
+            }
+        }
+        let mut costs: Vec<RoutineCost> = costs.into_values().collect();
+        costs.sort_by(|a, b| b.own.cmp(&a.own).then(b.inclusive.cmp(&a.inclusive)));
+        costs
+    }
+
+    /// Name of `routine` in the saved profiles
+    pub fn name(symbols: &Symbols, routine: Routine) -> String {
+        match routine {
+            Some((kind, target)) => symbols.routine_name(kind, target),
+            None => "(top level)".to_string(),
+        }
+    }
+
+    /// Folded stacks, "outer;inner cycles" per line, for flamegraph.pl
+    /// and inferno
+    pub fn write_folded(&self, out: &mut impl Write, symbols: &Symbols) -> io::Result<()> {
+        let mut lines: Vec<(String, u64)> = self
+            .stacks
+            .iter()
+            .map(|(path, &cycles)| {
+                let mut names = vec![Self::name(symbols, None)];
+                names.extend(path.iter().map(|&r| Self::name(symbols, Some(r))));
+                (names.join(";"), cycles)
+            })
+            .collect();
+        lines.sort();
+        for (stack, cycles) in lines {
+            writeln!(out, "{} {}", stack, cycles)?;
+        }
+        Ok(())
+    }
+
+    /// Callgrind profile with the cycles and instructions of each
+    /// instruction and call
+    pub fn write_callgrind(&self, out: &mut impl Write, symbols: &Symbols) -> io::Result<()> {
+        writeln!(out, "# callgrind format")?;
+        writeln!(out, "version: 1")?;
+        writeln!(out, "creator: c64-rs")?;
+        writeln!(out, "positions: instr")?;
+        writeln!(out, "events: Cycles Instructions")?;
+        writeln!(
+            out,
+            "summary: {} {}",
+            self.total.cycles, self.total.instructions
+        )?;
+
+        // Each routine's own instructions, then the calls it made
+        let mut routines: Vec<Routine> = self.own.keys().map(|&(routine, _)| routine).collect();
+        routines.extend(self.calls.keys().map(|&(caller, _, _)| caller));
+        routines.sort();
+        routines.dedup();
+        for routine in routines {
+            writeln!(out)?;
+            writeln!(out, "fn={}", Self::name(symbols, routine))?;
+            let mut own: Vec<(u16, Cost)> = self
+                .own
+                .iter()
+                .filter(|((r, _), _)| *r == routine)
+                .map(|(&(_, pc), &cost)| (pc, cost))
+                .collect();
+            own.sort_by_key(|&(pc, _)| pc);
+            for (pc, cost) in own {
+                writeln!(out, "0x{:04x} {} {}", pc, cost.cycles, cost.instructions)?;
+            }
+            let mut calls: Vec<(u16, (FrameKind, u16), Calls)> = self
+                .calls
+                .iter()
+                .filter(|((caller, _, _), _)| *caller == routine)
+                .map(|(&(_, site, callee), &calls)| (site, callee, calls))
+                .collect();
+            calls.sort_by_key(|&(site, (_, target), _)| (site, target));
+            for (site, callee, calls) in calls {
+                writeln!(out, "cfn={}", Self::name(symbols, Some(callee)))?;
+                writeln!(out, "calls={} 0x{:04x}", calls.count, callee.1)?;
+                writeln!(
+                    out,
+                    "0x{:04x} {} {}",
+                    site, calls.inclusive.cycles, calls.inclusive.instructions
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the profile to `path` in `format`
+    pub fn save(&self, path: &str, format: ProfileFormat, symbols: &Symbols) -> Result<(), String> {
+        let file = File::create(path).map_err(|e| format!("{}: {}", path, e))?;
+        let mut out = BufWriter::new(file);
+        match format {
+            ProfileFormat::Callgrind => self.write_callgrind(&mut out, symbols),
+            ProfileFormat::Folded => self.write_folded(&mut out, symbols),
+        }
+        .and_then(|_| out.flush())
+        .map_err(|e| format!("{}: {}", path, e))
+    }
+}
@@ -0,0 +1,140 @@
+use c64_rs::bus::IoDevice;
+use std::fmt;
+
+// MOS 6581 (SID). The sound isn't emulated, the chip only keeps what was
+// written to its registers so the monitor can show it. The 32 registers
+// repeat through $D400-$D7FF. Nothing the SID updates itself, like the
+// paddles and the oscillator 3 and envelope 3 readings, exists: reading
+// any register gives what was last written to it.
+#[derive(Default)]
+pub struct Sid {
+    registers: [u8; Sid::REGISTERS],
+}
+
+impl Sid {
+    const REGISTERS: usize = 0x20;
+
+    pub fn new() -> Self {
+        Sid {
+            registers: [0; Self::REGISTERS],
+        }
+    }
+
+    // Decoded state for the monitor, reading it changes nothing
+    pub fn state(&self) -> SidState {
+        let reg = |r: usize| self.registers[r];
+        let word = |r: usize| reg(r) as u16 | (reg(r + 1) as u16) << 8;
+        let voice = |v: usize| Voice {
+            frequency: word(v * 7),
+            pulse_width: word(v * 7 + 2) & 0x0fff,
+            control: reg(v * 7 + 4),
+            attack_decay: reg(v * 7 + 5),
+            sustain_release: reg(v * 7 + 6),
+        };
+        SidState {
+            voices: [voice(0), voice(1), voice(2)],
+            cutoff: (reg(0x15) as u16 & 0x07) | (reg(0x16) as u16) << 3,
+            filter: reg(0x17),
+            volume: reg(0x18),
+        }
+    }
+}
+
+impl IoDevice for Sid {
+    fn read_register(&mut self, r: u8) -> u8 {
+        self.peek_register(r)
+    }
+
+    fn write_register(&mut self, r: u8, v: u8) {
+        self.registers[r as usize % Self::REGISTERS] = v;
+    }
+
+    fn peek_register(&self, r: u8) -> u8 {
+        self.registers[r as usize % Self::REGISTERS]
+    }
+}
+
+pub struct Voice {
+    pub frequency: u16,
+    pub pulse_width: u16,
+    pub control: u8,
+    pub attack_decay: u8,
+    pub sustain_release: u8,
+}
+
+pub struct SidState {
+    pub voices: [Voice; 3],
+    pub cutoff: u16,
+    pub filter: u8, // $D417, resonance and the filtered voices
+    pub volume: u8, // $D418, filter mode and volume
+}
+
+impl SidState {
+    // PAL clock, the frequency registers count in clock / 2^24 Hz
+    const CLOCK: f64 = 985_248.0;
+
+    fn names(bits: u8, names: &[&'static str]) -> Vec<&'static str> {
+        names
+            .iter()
+            .enumerate()
+            .filter(|(bit, _)| bits & (1 << bit) != 0)
+            .map(|(_, name)| *name)
+            .collect()
+    }
+}
+
+impl fmt::Display for SidState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, voice) in self.voices.iter().enumerate() {
+            let hz = voice.frequency as f64 * Self::CLOCK / (1 << 24) as f64;
+            let mut control = Self::names(
+                voice.control,
+                &[
+                    "gate", "sync", "ring", "test", "triangle", "sawtooth", "pulse", "noise",
+                ],
+            );
+            if control.is_empty() {
+                control.push("off");
+            }
+            writeln!(
+                f,
+                "Voice {}  freq ${:04X} ({:.1} Hz)  pulse ${:03X}  ADSR {:X}{:X}{:X}{:X}  {}",
+                i + 1,
+                voice.frequency,
+                hz,
+                voice.pulse_width,
+                voice.attack_decay >> 4,
+                voice.attack_decay & 0x0f,
+                voice.sustain_release >> 4,
+                voice.sustain_release & 0x0f,
+                control.join(" ")
+            )?;
+        }
+        let mut routed = Self::names(self.filter, &["1", "2", "3", "ext"]);
+        if routed.is_empty() {
+            routed.push("none");
+        }
+        let mut mode = Self::names(self.volume >> 4, &["low", "band", "high"]);
+        if mode.is_empty() {
+            mode.push("off");
+        }
+        writeln!(
+            f,
+            "Filter   cutoff ${:03X}  resonance {}  voices {}  mode {}",
+            self.cutoff,
+            self.filter >> 4,
+            routed.join(" "),
+            mode.join(" ")
+        )?;
+        write!(
+            f,
+            "Volume   {}{}",
+            self.volume & 0x0f,
+            if self.volume & 0x80 != 0 {
+                ", voice 3 off"
+            } else {
+                ""
+            }
+        )
+    }
+}
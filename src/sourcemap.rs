@@ -0,0 +1,67 @@
+// Which source line each piece of a program was assembled from, so that
+// debuggers can set breakpoints by line and show where the CPU is.
+//
+// Paths are kept canonical, editors and the command line name the same
+// file in different ways.
+use crate::asm::Assembler;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Default)]
+pub struct SourceMap {
+    files: Vec<PathBuf>,
+    lines: BTreeMap<u16, (usize, usize)>, // Address to file index and line
+}
+
+impl SourceMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Lines of a file assembled with Assembler::assemble
+    pub fn from_assembler(path: &Path, assembler: &Assembler) -> Self {
+        let mut map = Self::new();
+        for (&addr, &line) in assembler.lines() {
+            map.add(path, line, addr);
+        }
+        map
+    }
+
+    pub fn add(&mut self, path: &Path, line: usize, addr: u16) {
+        let path = canonical(path);
+        let file = match self.files.iter().position(|f| *f == path) {
+            Some(file) => file,
+            None => {
+                self.files.push(path);
+                self.files.len() - 1
+            }
+        };
+        self.lines.insert(addr, (file, line));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    /// Address of the code for `line`, or for the first line after it that
+    /// has code, with the line it came from
+    pub fn address(&self, path: &Path, line: usize) -> Option<(u16, usize)> {
+        let path = canonical(path);
+        let file = self.files.iter().position(|f| *f == path)?;
+        self.lines
+            .iter()
+            .filter(|(_, &(f, l))| f == file && l >= line)
+            .min_by_key(|(&addr, &(_, l))| (l, addr))
+            .map(|(&addr, &(_, l))| (addr, l))
+    }
+
+    /// File and line the code at `addr` starts
+    pub fn line(&self, addr: u16) -> Option<(&Path, usize)> {
+        let &(file, line) = self.lines.get(&addr)?;
+        Some((&self.files[file], line))
+    }
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
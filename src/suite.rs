@@ -0,0 +1,149 @@
+// What a test of Wolfgang Lorenz's C64 emulator test suite does through the
+// KERNAL, and the verdict on it. The runner driving the machine is in the
+// lorenz module of the emulator.
+//
+// Every test is a BASIC program that prints its name, runs, and on success
+// loads the next test of the suite through the KERNAL LOAD routine. On a
+// failure it prints what went wrong and waits for a key.
+use crate::bus::Bus;
+use crate::cpu::Cpu;
+use crate::d64::petscii_to_ascii;
+use std::fmt;
+
+// KERNAL entry points and variables the tests are followed through
+pub const ADDR_CHROUT: u16 = 0xffd2;
+pub const ADDR_GETIN: u16 = 0xffe4;
+pub const ADDR_LOAD: u16 = 0xffd5;
+const ADDR_FILENAME_LEN: u16 = 0x00b7;
+const ADDR_FILENAME_PTR: u16 = 0x00bb;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Verdict {
+    Passed,
+    Failed,
+    Timeout,
+    Missing,
+}
+
+impl fmt::Display for Verdict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let text = match self {
+            Verdict::Passed => "passed",
+            Verdict::Failed => "FAILED",
+            Verdict::Timeout => "timeout",
+            Verdict::Missing => "missing",
+        };
+        // Pad here so the summary table lines up
+        f.pad(text)
+    }
+}
+
+pub struct TestResult {
+    pub name: String,
+    pub verdict: Verdict,
+    pub cycles: u64,
+    pub output: String,
+}
+
+impl TestResult {
+    pub fn new(name: &str, verdict: Verdict) -> Self {
+        TestResult {
+            name: name.to_string(),
+            verdict,
+            cycles: 0,
+            output: String::new(),
+        }
+    }
+
+    /// Last line the test printed, usually the reason it failed
+    pub fn detail(&self) -> &str {
+        self.output
+            .lines()
+            .map(str::trim)
+            .rfind(|line| !line.is_empty())
+            .unwrap_or("")
+    }
+}
+
+/// What the runner has to do for a test
+#[derive(Debug, PartialEq, Eq)]
+pub enum Trap {
+    /// The test waits for a key, it only does so after an error
+    Key,
+    /// The test loads the named program, the next test
+    Load(String),
+}
+
+/// Follows one test through the KERNAL calls it makes
+pub struct Watch {
+    result: TestResult,
+    failed: bool,
+}
+
+impl Watch {
+    pub fn new(name: &str) -> Self {
+        Watch {
+            result: TestResult::new(name, Verdict::Timeout),
+            failed: false,
+        }
+    }
+
+    /// Call after each step with the pc before it and the cycles it took.
+    /// Steps entering an interrupt run nothing, the KERNAL call at `pc`
+    /// only runs after the handler returns.
+    pub fn step<B: Bus>(&mut self, cpu: &Cpu<B>, pc: u16, cycles: u32) -> Option<Trap> {
+        self.result.cycles += cycles as u64;
+        if cpu.interrupted().is_some() {
+            return None;
+        }
+        match pc {
+            ADDR_CHROUT => {
+                self.result.output.push(petscii_to_ascii(cpu.a));
+                None
+            }
+            ADDR_GETIN => {
+                self.failed = true;
+                Some(Trap::Key)
+            }
+            ADDR_LOAD => Some(Trap::Load(Self::requested_file(cpu))),
+            _ => None,
+        }
+    }
+
+    pub fn cycles(&self) -> u64 {
+        self.result.cycles
+    }
+
+    /// Result of a test that went on to load the next one
+    pub fn loaded(mut self) -> TestResult {
+        self.result.verdict = if self.failed {
+            Verdict::Failed
+        } else {
+            Verdict::Passed
+        };
+        self.result
+    }
+
+    /// Result of a test that didn't load the next one, because the CPU
+    /// `halted` or the cycle limit ran out. `screen` is the text on screen,
+    /// some tests report errors without calling CHROUT.
+    pub fn stopped(mut self, halted: bool, screen: &str) -> TestResult {
+        if self.failed || halted || screen.contains("ERROR") {
+            self.result.verdict = Verdict::Failed;
+        }
+        self.result
+    }
+
+    // File name set up by SETNAM for the pending LOAD
+    fn requested_file<B: Bus>(cpu: &Cpu<B>) -> String {
+        let len = cpu.peek_memory(ADDR_FILENAME_LEN);
+        let ptr = u16::from_le_bytes([
+            cpu.peek_memory(ADDR_FILENAME_PTR),
+            cpu.peek_memory(ADDR_FILENAME_PTR + 1),
+        ]);
+        (0..len as u16)
+            .map(|i| petscii_to_ascii(cpu.peek_memory(ptr.wrapping_add(i))))
+            .collect::<String>()
+            .to_lowercase()
+    }
+}
@@ -0,0 +1,410 @@
+// Names for addresses, so the debuggers can show and accept them in place
+// of numbers. They are read from the label files assemblers write:
+//
+//   VICE            "al C:c000 .main", also written by ACME and the asm command
+//   ACME, 64tass    "main = $c000"
+//   KickAssembler   .dbg, XML with the labels and the lines of each block
+//   ca65            .dbg from ld65 --dbgfile, symbols, segments and spans
+//
+// KickAssembler and ca65 files also tell which source line code came from.
+// The KERNAL and BASIC entry points are known without loading anything.
+use crate::callstack::{Frame, FrameKind};
+use crate::disasm::hex_addr;
+use crate::sourcemap::SourceMap;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Jump table and well known entry points of the ROMs Memory loads
+const ROM: &[(&str, u16)] = &[
+    ("COLDST", 0xa000),
+    ("WARMST", 0xa002),
+    ("READY", 0xa474),
+    ("MAIN", 0xa480),
+    ("LNKPRG", 0xa533),
+    ("INLIN", 0xa560),
+    ("NEW", 0xa642),
+    ("CLR", 0xa65e),
+    ("NEWSTT", 0xa7ae),
+    ("RUN", 0xa871),
+    ("FRMNUM", 0xad8a),
+    ("FRMEVL", 0xad9e),
+    ("STROUT", 0xab1e),
+    ("GIVAYF", 0xb391),
+    ("GETBYT", 0xb79e),
+    ("LINPRT", 0xbdcd),
+    ("FOUT", 0xbddd),
+    ("BASCOLD", 0xe394),
+    ("BASWARM", 0xe37b),
+    ("CLRSCR", 0xe544),
+    ("EDITOR", 0xe5cd),
+    ("IRQSTD", 0xea31),
+    ("IRQEND", 0xea81),
+    ("RESET", 0xfce2),
+    ("NMI", 0xfe43),
+    ("NMIEND", 0xfebc),
+    ("IRQ", 0xff48),
+    ("CINT", 0xff81),
+    ("IOINIT", 0xff84),
+    ("RAMTAS", 0xff87),
+    ("RESTOR", 0xff8a),
+    ("VECTOR", 0xff8d),
+    ("SETMSG", 0xff90),
+    ("SECOND", 0xff93),
+    ("TKSA", 0xff96),
+    ("MEMTOP", 0xff99),
+    ("MEMBOT", 0xff9c),
+    ("SCNKEY", 0xff9f),
+    ("SETTMO", 0xffa2),
+    ("ACPTR", 0xffa5),
+    ("CIOUT", 0xffa8),
+    ("UNTLK", 0xffab),
+    ("UNLSN", 0xffae),
+    ("LISTEN", 0xffb1),
+    ("TALK", 0xffb4),
+    ("READST", 0xffb7),
+    ("SETLFS", 0xffba),
+    ("SETNAM", 0xffbd),
+    ("OPEN", 0xffc0),
+    ("CLOSE", 0xffc3),
+    ("CHKIN", 0xffc6),
+    ("CHKOUT", 0xffc9),
+    ("CLRCHN", 0xffcc),
+    ("CHRIN", 0xffcf),
+    ("CHROUT", 0xffd2),
+    ("LOAD", 0xffd5),
+    ("SAVE", 0xffd8),
+    ("SETTIM", 0xffdb),
+    ("RDTIM", 0xffde),
+    ("STOP", 0xffe1),
+    ("GETIN", 0xffe4),
+    ("CLALL", 0xffe7),
+    ("UDTIM", 0xffea),
+    ("SCREEN", 0xffed),
+    ("PLOT", 0xfff0),
+    ("IOBASE", 0xfff3),
+];
+
+#[derive(Clone, Default)]
+pub struct Symbols {
+    names: HashMap<String, u16>,
+    labels: BTreeMap<u16, String>, // Name shown for each address
+    lines: SourceMap,
+}
+
+impl Symbols {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The KERNAL and BASIC entry points
+    pub fn rom() -> Self {
+        let mut symbols = Self::new();
+        for &(name, addr) in ROM {
+            symbols.add(name, addr);
+        }
+        symbols
+    }
+
+    /// Names `addr`, the latest name given to an address is the one shown
+    pub fn add(&mut self, name: &str, addr: u16) {
+        self.names.insert(name.to_string(), addr);
+        self.labels.insert(addr, name.to_string());
+    }
+
+    /// Loads a label or debug file, telling the format from its contents.
+    /// Returns the number of labels read.
+    pub fn load(&mut self, path: &Path) -> Result<usize, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let dir = path.parent().unwrap_or(Path::new(""));
+        let count = if text.starts_with("version\tmajor=") {
+            self.load_ca65(&text, dir)
+        } else if text.contains("<C64debugger") {
+            self.load_kickass(&text, dir)
+        } else {
+            self.load_labels(&text)
+        };
+        count.map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn addr(&self, name: &str) -> Option<u16> {
+        self.names.get(name).copied().or_else(|| {
+            self.names
+                .iter()
+                .find(|(n, _)| n.eq_ignore_ascii_case(name))
+                .map(|(_, &addr)| addr)
+        })
+    }
+
+    pub fn name(&self, addr: u16) -> Option<&str> {
+        self.labels.get(&addr).map(String::as_str)
+    }
+
+    /// Name of an operand address, or the address in hex, for
+    /// Instruction::operand_text
+    pub fn operand(&self, addr: u16, zero_page: bool) -> String {
+        match self.name(addr) {
+            Some(name) => name.to_string(),
+            None => hex_addr(addr, zero_page),
+        }
+    }
+
+    /// What a call stack frame runs, "sub" for a subroutine and
+    /// "IRQ (IRQ)" for an interrupt handler
+    pub fn frame_name(&self, frame: &Frame) -> String {
+        self.routine_name(frame.kind, frame.target)
+    }
+
+    /// Name of the code at `target` entered the way `kind` says
+    pub fn routine_name(&self, kind: FrameKind, target: u16) -> String {
+        let name = self.operand(target, false);
+        match kind {
+            FrameKind::Call => name,
+            kind => format!("{} ({})", name, kind),
+        }
+    }
+
+    /// Names and their addresses, sorted by address
+    pub fn iter(&self) -> impl Iterator<Item = (u16, &str)> {
+        let mut names: Vec<(u16, &str)> = self
+            .names
+            .iter()
+            .map(|(name, &addr)| (addr, name.as_str()))
+            .collect();
+        names.sort();
+        names.into_iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    /// Source lines from KickAssembler and ca65 debug files
+    pub fn lines(&self) -> &SourceMap {
+        &self.lines
+    }
+
+    /// Replaces ".name" with the address it names in hex, like labels in
+    /// VICE monitor commands. Quoted text is left alone.
+    pub fn resolve(&self, text: &str) -> Result<String, String> {
+        let mut resolved = String::new();
+        let mut quoted = false;
+        let mut chars = text.chars().peekable();
+        let mut previous = ' ';
+        while let Some(c) = chars.next() {
+            let starts_name = !quoted
+                && c == '.'
+                && !(previous.is_ascii_alphanumeric() || previous == '_' || previous == '.')
+                && chars.peek().is_some_and(|&c| is_name_char(c));
+            if starts_name {
+                let mut name = String::new();
+                while let Some(&c) = chars.peek().filter(|&&c| is_name_char(c) || c == '.') {
+                    name.push(c);
+                    chars.next();
+                }
+                let addr = self
+                    .addr(&name)
+                    .ok_or_else(|| format!("unknown label: {}", name))?;
+                resolved.push_str(&format!("${:04X}", addr));
+                previous = '0';
+                continue;
+            }
+            if c == '"' {
+                quoted = !quoted;
+            }
+            resolved.push(c);
+            previous = c;
+        }
+        Ok(resolved)
+    }
+
+    // VICE, ACME and 64tass label lists, one label per line
+    fn load_labels(&mut self, text: &str) -> Result<usize, String> {
+        let mut count = 0;
+        for (i, line) in text.lines().enumerate() {
+            let line = line.split(';').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (name, value) = if let Some(rest) = line.strip_prefix("al ") {
+                // "al C:c000 .main", the memory space is optional
+                let mut words = rest.split_whitespace();
+                let (Some(addr), Some(name)) = (words.next(), words.next()) else {
+                    return Err(format!("line {}: bad label '{}'", i + 1, line));
+                };
+                let addr = addr.rsplit(':').next().unwrap_or(addr);
+                (name.strip_prefix('.').unwrap_or(name), format!("${}", addr))
+            } else if let Some((name, value)) = line.split_once('=') {
+                let name = name.trim();
+                let name = name.strip_prefix("!addr").unwrap_or(name).trim();
+                (name, value.trim().to_string())
+            } else {
+                return Err(format!("line {}: bad label '{}'", i + 1, line));
+            };
+            // Constants too large for an address aren't labels
+            if let Some(addr) = parse_value(&value).and_then(|v| u16::try_from(v).ok()) {
+                self.add(name, addr);
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    // Sections hold comma separated rows, their "values" attribute names
+    // the columns
+    fn load_kickass(&mut self, text: &str, dir: &Path) -> Result<usize, String> {
+        let mut files: HashMap<String, PathBuf> = HashMap::new();
+        let mut section = "";
+        let mut columns: Vec<&str> = Vec::new();
+        let mut count = 0;
+        for line in text.lines().map(str::trim) {
+            if let Some(tag) = line.strip_prefix('<') {
+                let name = tag.split([' ', '>']).next().unwrap_or_default();
+                if let Some(values) = attribute(tag, "values") {
+                    columns = values.split(',').collect();
+                }
+                section = match name {
+                    "Sources" | "Block" | "Labels" => name,
+                    _ => "",
+                };
+                continue;
+            }
+            if line.is_empty() || section.is_empty() {
+                continue;
+            }
+            if section == "Sources" {
+                let (index, file) = line.split_once(',').ok_or("bad source entry")?;
+                files.insert(index.to_string(), dir.join(file));
+                continue;
+            }
+            let row: HashMap<&str, &str> = columns.iter().copied().zip(line.split(',')).collect();
+            let field = |name: &str| row.get(name).copied().ok_or(format!("missing {}", name));
+            if section == "Labels" {
+                let addr = parse_value(field("ADDRESS")?).ok_or("bad label address")?;
+                self.add(field("NAME")?, addr as u16);
+                count += 1;
+            } else {
+                let start = parse_value(field("START")?).ok_or("bad block start")?;
+                let line: usize = field("LINE1")?.parse().map_err(|_| "bad line")?;
+                if let Some(file) = files.get(field("FILE_IDX")?) {
+                    self.lines.add(file, line, start as u16);
+                }
+            }
+        }
+        Ok(count)
+    }
+
+    // Records of key=value pairs, lines refer to spans in segments
+    fn load_ca65(&mut self, text: &str, dir: &Path) -> Result<usize, String> {
+        let mut files = HashMap::new();
+        let mut segments = HashMap::new();
+        let mut spans = HashMap::new();
+        let mut lines = Vec::new();
+        let mut count = 0;
+        for line in text.lines() {
+            let Some((kind, fields)) = line.split_once('\t') else {
+                continue;
+            };
+            let fields = ca65_fields(fields);
+            let get = |key: &str| fields.get(key).map(String::as_str);
+            let number = |key: &str| get(key).and_then(parse_value);
+            match kind {
+                "file" => {
+                    if let (Some(id), Some(name)) = (get("id"), get("name")) {
+                        files.insert(id.to_string(), dir.join(name));
+                    }
+                }
+                "seg" => {
+                    if let (Some(id), Some(start)) = (get("id"), number("start")) {
+                        segments.insert(id.to_string(), start);
+                    }
+                }
+                "span" => {
+                    if let (Some(id), Some(seg), Some(start)) =
+                        (get("id"), get("seg"), number("start"))
+                    {
+                        spans.insert(id.to_string(), (seg.to_string(), start));
+                    }
+                }
+                // Lines from macro expansions would point into the macro
+                "line" if get("type") != Some("2") => {
+                    if let (Some(file), Some(number), Some(span)) =
+                        (get("file"), number("line"), get("span"))
+                    {
+                        lines.push((file.to_string(), number as usize, span.to_string()));
+                    }
+                }
+                "sym" if get("type") == Some("lab") => {
+                    if let (Some(name), Some(addr)) = (get("name"), number("val")) {
+                        self.add(name, addr as u16);
+                        count += 1;
+                    }
+                }
+                _ => {}
+            }
+        }
+        for (file, line, span_ids) in lines {
+            let Some(path) = files.get(&file) else {
+                continue;
+            };
+            for id in span_ids.split('+') {
+                let addr = spans
+                    .get(id)
+                    .and_then(|(seg, start)| Some(segments.get(seg)? + start));
+                if let Some(addr) = addr {
+                    self.lines.add(path, line, addr as u16);
+                }
+            }
+        }
+        Ok(count)
+    }
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '@'
+}
+
+// "$c000", "0xc000", "%1010" or decimal
+fn parse_value(text: &str) -> Option<u32> {
+    let text = text.trim();
+    if let Some(hex) = text.strip_prefix('$').or(text.strip_prefix("0x")) {
+        u32::from_str_radix(hex, 16).ok()
+    } else if let Some(binary) = text.strip_prefix('%') {
+        u32::from_str_radix(binary, 2).ok()
+    } else {
+        text.parse().ok()
+    }
+}
+
+// Value of `name="..."` inside a tag
+fn attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    let start = tag.find(&format!("{}=\"", name))? + name.len() + 2;
+    let len = tag[start..].find('"')?;
+    Some(&tag[start..start + len])
+}
+
+// "id=0,name=\"a,b.s\",size=12", quoted values may hold commas
+fn ca65_fields(text: &str) -> HashMap<&str, String> {
+    let mut fields = HashMap::new();
+    let mut rest = text;
+    while let Some((key, value)) = rest.split_once('=') {
+        let (value, next) = match value.strip_prefix('"') {
+            Some(quoted) => {
+                let end = quoted.find('"').unwrap_or(quoted.len());
+                let next = quoted[end..].trim_start_matches('"');
+                (quoted[..end].to_string(), next)
+            }
+            None => {
+                let end = value.find(',').unwrap_or(value.len());
+                (value[..end].to_string(), &value[end..])
+            }
+        };
+        fields.insert(key, value);
+        rest = next.strip_prefix(',').unwrap_or(next);
+    }
+    fields
+}
@@ -0,0 +1,78 @@
+// The machine as seen by the remote debugger protocols. The emulator
+// implements it with all of its chips, tests with a bare CPU.
+use crate::breakpoint::{Breakpoints, Hit};
+use crate::cpu::Cpu;
+use crate::memory::Memory;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// Outcome of a single step
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Step {
+    Done,
+    Hit(Hit),
+    /// The machine can't go on, e.g. the CPU ran into a JAM
+    Halted,
+}
+
+pub trait Target {
+    fn cpu(&self) -> Rc<RefCell<Cpu<Memory>>>;
+
+    /// Executes one instruction on the whole machine, false when it can't
+    /// go on
+    fn step(&mut self) -> bool;
+
+    /// Raster line and cycle, for breakpoint conditions
+    fn raster(&self) -> (u16, u16);
+
+    /// Breakpoints, shared with other debuggers of the machine
+    fn breakpoints(&mut self) -> &mut Breakpoints;
+
+    /// Steps and checks the breakpoints
+    fn step_checked(&mut self) -> Step {
+        let cpu = self.cpu();
+        let from = cpu.borrow().pc;
+        if !self.step() {
+            return Step::Halted;
+        }
+        let raster = self.raster();
+        let hit = self
+            .breakpoints()
+            .check(&mut cpu.borrow_mut(), from, raster);
+        match hit {
+            Some(hit) => Step::Hit(hit),
+            None => Step::Done,
+        }
+    }
+
+    /// Undoes the last instruction from the execution history, false when
+    /// there is no history left
+    fn step_back(&mut self) -> bool {
+        false
+    }
+
+    /// Steps back and checks the execution breakpoints, None when there
+    /// is no history left
+    fn step_back_checked(&mut self) -> Option<Step> {
+        if !self.step_back() {
+            return None;
+        }
+        let cpu = self.cpu();
+        let raster = self.raster();
+        let hit = self.breakpoints().check_exec(&cpu.borrow(), raster);
+        Some(hit.map_or(Step::Done, Step::Hit))
+    }
+
+    /// Loads a PRG, or file `index` of a D64 image, into a freshly booted
+    /// machine and with `run` starts it
+    fn autostart(&mut self, _path: &str, _index: u16, _run: bool) -> Result<(), String> {
+        Err("autostart is not supported".to_string())
+    }
+
+    /// Brings the watched addresses in memory in line with the breakpoints,
+    /// call after changing them
+    fn sync_breakpoints(&mut self) {
+        let cpu = self.cpu();
+        self.breakpoints().sync(&mut cpu.borrow_mut().bus);
+    }
+}
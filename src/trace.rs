@@ -0,0 +1,180 @@
+// Instruction trace laid out like the CPU history of the VICE monitor, so a
+// log can be diffed against one recorded with VICE:
+//
+// .C:e5cd  A5 C6     LDA $C6        - A:00 X:00 Y:0A SP:f3 NV-BDIZC 012 034    1234567
+//
+// The flags are printed with a '.' for a clear flag, followed by the raster
+// line and cycle and the CPU cycle count.
+use crate::cpu::Cpu;
+use crate::disasm::{hex_addr, Instruction};
+use crate::flags::Flags;
+use crate::memory::Memory;
+use crate::symbols::Symbols;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+
+/// Which instructions to log depending on whether they run from ROM
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RomFilter {
+    #[default]
+    Any,
+    Inside,
+    Outside,
+}
+
+impl FromStr for RomFilter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "any" => Ok(RomFilter::Any),
+            "inside" => Ok(RomFilter::Inside),
+            "outside" => Ok(RomFilter::Outside),
+            _ => Err(format!("unknown ROM filter '{}'", s)),
+        }
+    }
+}
+
+/// Parses an address range such as "c000-cfff", a single address
+/// traces just that one
+pub fn parse_range(s: &str) -> Result<RangeInclusive<u16>, String> {
+    let parse = |s: &str| {
+        u16::from_str_radix(s.trim().trim_start_matches('$'), 16)
+            .map_err(|_| format!("invalid address '{}'", s))
+    };
+    match s.split_once('-') {
+        Some((first, last)) => Ok(parse(first)?..=parse(last)?),
+        None => {
+            let addr = parse(s)?;
+            Ok(addr..=addr)
+        }
+    }
+}
+
+/// One line of the trace for the instruction the CPU is about to execute
+pub struct TraceLine {
+    pub instruction: Instruction,
+    pub a: u8,
+    pub x: u8,
+    pub y: u8,
+    pub sp: u8,
+    pub p: Flags,
+    pub raster: (u16, u16), // Raster line and cycle
+    pub cycles: u32,
+}
+
+impl TraceLine {
+    pub fn capture(cpu: &Cpu<Memory>, raster: (u16, u16)) -> Self {
+        TraceLine {
+            instruction: Instruction::decode(cpu.pc, |addr| cpu.peek_memory(addr)),
+            a: cpu.a,
+            x: cpu.x,
+            y: cpu.y,
+            sp: cpu.sp,
+            p: cpu.p,
+            raster,
+            cycles: cpu.cycles(),
+        }
+    }
+
+    /// The line with operands named by `name`, as for
+    /// Instruction::operand_text
+    pub fn to_string_with(&self, name: impl Fn(u16, bool) -> String) -> String {
+        format!(
+            ".C:{:04x}  {:<8}  {:<13}  - A:{:02X} X:{:02X} Y:{:02X} SP:{:02x} {} {:03} {:03} {:>10}",
+            self.instruction.addr,
+            self.instruction.hex(),
+            self.instruction.text(name),
+            self.a,
+            self.x,
+            self.y,
+            self.sp,
+            self.p,
+            self.raster.0,
+            self.raster.1,
+            self.cycles
+        )
+    }
+}
+
+impl fmt::Display for TraceLine {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.to_string_with(hex_addr))
+    }
+}
+
+pub struct Tracer {
+    out: Box<dyn Write>,
+    ranges: Vec<RangeInclusive<u16>>,
+    rom: RomFilter,
+    symbols: Option<Symbols>,
+}
+
+impl Tracer {
+    pub fn new(out: Box<dyn Write>) -> Self {
+        Tracer {
+            out,
+            ranges: Vec::new(),
+            rom: RomFilter::Any,
+            symbols: None,
+        }
+    }
+
+    /// Trace to a file, "-" traces to stdout
+    pub fn create(path: &str) -> Result<Self, String> {
+        let out: Box<dyn Write> = if path == "-" {
+            Box::new(io::stdout())
+        } else {
+            let file = File::create(path).map_err(|e| format!("{}: {}", path, e))?;
+            Box::new(BufWriter::new(file))
+        };
+        Ok(Tracer::new(out))
+    }
+
+    /// Only log instructions inside the given ranges, all are logged while
+    /// no range is set
+    pub fn add_range(&mut self, range: RangeInclusive<u16>) {
+        self.ranges.push(range);
+    }
+
+    pub fn set_rom_filter(&mut self, rom: RomFilter) {
+        self.rom = rom;
+    }
+
+    /// Show operands by name where `symbols` has one
+    pub fn set_symbols(&mut self, symbols: Symbols) {
+        self.symbols = Some(symbols);
+    }
+
+    fn wants(&self, cpu: &Cpu<Memory>) -> bool {
+        let in_range = self.ranges.is_empty() || self.ranges.iter().any(|r| r.contains(&cpu.pc));
+        let in_rom = cpu.bus.is_rom(cpu.pc);
+        in_range
+            && match self.rom {
+                RomFilter::Any => true,
+                RomFilter::Inside => in_rom,
+                RomFilter::Outside => !in_rom,
+            }
+    }
+
+    /// Logs the instruction at PC, call before stepping the CPU. Nothing is
+    /// logged when the step enters an interrupt instead.
+    pub fn trace(&mut self, cpu: &Cpu<Memory>, raster: (u16, u16)) -> Result<(), String> {
+        if cpu.interrupt_due().is_some() || !self.wants(cpu) {
+            return Ok(());
+        }
+        let line = TraceLine::capture(cpu, raster);
+        let line = match &self.symbols {
+            Some(symbols) => line.to_string_with(|addr, zp| symbols.operand(addr, zp)),
+            None => line.to_string(),
+        };
+        writeln!(self.out, "{}", line).map_err(|e| format!("trace: {}", e))
+    }
+
+    pub fn flush(&mut self) -> Result<(), String> {
+        self.out.flush().map_err(|e| format!("trace: {}", e))
+    }
+}
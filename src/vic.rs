@@ -0,0 +1,337 @@
+use c64_rs::bus::IoDevice;
+use c64_rs::cpu::{Cpu, IrqSource};
+use c64_rs::memory::Memory;
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+// MOS 6569 (PAL VIC-II). Only the registers, the raster counter and the
+// raster interrupt are emulated so far, nothing is drawn yet.
+pub struct Vic {
+    cpu: Rc<RefCell<Cpu<Memory>>>,
+    registers: [u8; 0x40],
+    raster_line: u16,
+    raster_irq_line: u16,
+    irq_enabled: u8,
+    irq_status: u8,
+    next_raster_at: u32,
+}
+
+impl Vic {
+    pub const CYCLES_PER_LINE: u32 = 63;
+    pub const LINES_PER_FRAME: u16 = 312;
+
+    // Interrupt sources in $D019/$D01A
+    const IRQ_RASTER: u8 = 1 << 0;
+
+    pub fn new(cpu: Rc<RefCell<Cpu<Memory>>>) -> Self {
+        Vic {
+            cpu,
+            registers: [0; 0x40],
+            raster_line: 0,
+            raster_irq_line: 0,
+            irq_enabled: 0,
+            irq_status: 0,
+            next_raster_at: Self::CYCLES_PER_LINE,
+        }
+    }
+
+    pub fn write_register(&mut self, r: u8, v: u8) {
+        match r {
+            // control register 1, bit 7 is bit 8 of the raster IRQ line
+            0x11 => {
+                self.raster_irq_line = (self.raster_irq_line & 0x00ff) | ((v as u16 & 0x80) << 1);
+                self.registers[r as usize] = v;
+            }
+            // raster IRQ line
+            0x12 => {
+                self.raster_irq_line = (self.raster_irq_line & 0xff00) | v as u16;
+            }
+            // interrupt status, writing a 1 acknowledges the interrupt
+            0x19 => {
+                self.irq_status &= !v;
+            }
+            // interrupt enable
+            0x1a => {
+                self.irq_enabled = v & 0x0f;
+            }
+            _ => {
+                if let Some(reg) = self.registers.get_mut(r as usize) {
+                    *reg = v;
+                }
+            }
+        }
+    }
+
+    pub fn read_register(&self, r: u8) -> u8 {
+        match r {
+            0x11 => (self.registers[0x11] & 0x7f) | ((self.raster_line >> 1) as u8 & 0x80),
+            0x12 => self.raster_line as u8,
+            // unused bits read as 1
+            0x19 => {
+                let irq = if self.irq_status & self.irq_enabled != 0 {
+                    0x80
+                } else {
+                    0
+                };
+                self.irq_status | irq | 0x70
+            }
+            0x1a => self.irq_enabled | 0xf0,
+            0x2f..=0x3f => 0xff,
+            _ => self.registers[r as usize],
+        }
+    }
+
+    /// Current raster line and the cycle within it
+    pub fn raster_position(&self) -> (u16, u16) {
+        let cycles = self.cpu.borrow().cycles();
+        let left = self
+            .next_raster_at
+            .wrapping_sub(cycles)
+            .min(Self::CYCLES_PER_LINE);
+        (self.raster_line, (Self::CYCLES_PER_LINE - left) as u16)
+    }
+
+    /// Decoded state for the monitor, reading it changes nothing. `bank`
+    /// is the 16K bank CIA 2 selects.
+    pub fn state(&self, bank: u8) -> VicState {
+        VicState {
+            bank,
+            raster: self.raster_position(),
+            raster_irq_line: self.raster_irq_line,
+            irq_enabled: self.irq_enabled,
+            irq_status: self.irq_status,
+            control1: self.registers[0x11],
+            control2: self.registers[0x16],
+            memory: self.registers[0x18],
+            border: self.registers[0x20] & 0x0f,
+            background: self.registers[0x21] & 0x0f,
+        }
+    }
+
+    pub fn step(&mut self) -> bool {
+        let cycles = self.cpu.borrow().cycles();
+        // Cycle the raster interrupt line was reached, if it was this step
+        let mut raster_at = None;
+        while cycles.wrapping_sub(self.next_raster_at) as i32 >= 0 {
+            let line_at = self.next_raster_at;
+            self.next_raster_at = self.next_raster_at.wrapping_add(Self::CYCLES_PER_LINE);
+            self.raster_line = (self.raster_line + 1) % Self::LINES_PER_FRAME;
+            if self.raster_line == self.raster_irq_line {
+                self.irq_status |= Self::IRQ_RASTER;
+                raster_at.get_or_insert(line_at);
+            }
+        }
+        let irq = self.irq_status & self.irq_enabled != 0;
+        self.cpu
+            .borrow_mut()
+            .set_irq_at(IrqSource::Vic, irq, raster_at.unwrap_or(cycles));
+        true
+    }
+}
+
+pub struct VicState {
+    pub bank: u8,
+    pub raster: (u16, u16),
+    pub raster_irq_line: u16,
+    pub irq_enabled: u8,
+    pub irq_status: u8,
+    pub control1: u8, // $D011
+    pub control2: u8, // $D016
+    pub memory: u8,   // $D018
+    pub border: u8,
+    pub background: u8,
+}
+
+impl VicState {
+    /// Graphics mode from the ECM, BMM and MCM bits
+    pub fn mode(&self) -> &'static str {
+        let ecm = self.control1 & 0x40 != 0;
+        let bmm = self.control1 & 0x20 != 0;
+        let mcm = self.control2 & 0x10 != 0;
+        match (ecm, bmm, mcm) {
+            (false, false, false) => "standard text",
+            (false, false, true) => "multicolor text",
+            (false, true, false) => "standard bitmap",
+            (false, true, true) => "multicolor bitmap",
+            (true, false, false) => "extended background color text",
+            _ => "invalid (black)",
+        }
+    }
+
+    fn base(&self) -> u16 {
+        (self.bank as u16) << 14
+    }
+
+    pub fn screen(&self) -> u16 {
+        self.base() | (self.memory as u16 >> 4) * 0x0400
+    }
+
+    pub fn charset(&self) -> u16 {
+        self.base() | ((self.memory as u16 >> 1) & 0x07) * 0x0800
+    }
+
+    pub fn bitmap(&self) -> u16 {
+        self.base() | (self.memory as u16 & 0x08) * 0x0400
+    }
+
+    fn sources(bits: u8) -> String {
+        let names: Vec<&str> = ["raster", "sprite-background", "sprite-sprite", "light pen"]
+            .iter()
+            .enumerate()
+            .filter(|(bit, _)| bits & (1 << bit) != 0)
+            .map(|(_, name)| *name)
+            .collect();
+        if names.is_empty() {
+            "none".to_string()
+        } else {
+            names.join(", ")
+        }
+    }
+}
+
+impl fmt::Display for VicState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "Mode     {}, display {}, {} rows, {} columns, scroll {},{}",
+            self.mode(),
+            if self.control1 & 0x10 != 0 {
+                "on"
+            } else {
+                "off"
+            },
+            if self.control1 & 0x08 != 0 { 25 } else { 24 },
+            if self.control2 & 0x08 != 0 { 40 } else { 38 },
+            self.control2 & 0x07,
+            self.control1 & 0x07
+        )?;
+        writeln!(
+            f,
+            "Raster   line {} cycle {}, interrupt at line {}",
+            self.raster.0, self.raster.1, self.raster_irq_line
+        )?;
+        writeln!(
+            f,
+            "IRQ      enabled: {}  pending: {}",
+            Self::sources(self.irq_enabled),
+            Self::sources(self.irq_status)
+        )?;
+        // The character ROM shows at $1000-$1FFF in banks 0 and 2
+        let rom = self.bank & 1 == 0 && self.charset() & 0x3000 == 0x1000;
+        writeln!(
+            f,
+            "Memory   bank {} ${:04X}-${:04X}  screen ${:04X}  charset ${:04X}{}  bitmap ${:04X}",
+            self.bank,
+            self.base(),
+            self.base() + 0x3fff,
+            self.screen(),
+            self.charset(),
+            if rom { " (ROM)" } else { "" },
+            self.bitmap()
+        )?;
+        write!(
+            f,
+            "Colors   border {}  background {}",
+            self.border, self.background
+        )
+    }
+}
+
+impl IoDevice for Vic {
+    fn read_register(&mut self, r: u8) -> u8 {
+        Vic::read_register(self, r)
+    }
+
+    fn write_register(&mut self, r: u8, v: u8) {
+        Vic::write_register(self, r, v);
+    }
+
+    // Reads have no side effects yet, the sprite collision registers that
+    // clear when read aren't emulated
+    fn peek_register(&self, r: u8) -> u8 {
+        Vic::read_register(self, r)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use c64_rs::cpu::Interrupt;
+    use c64_rs::flags::Flags;
+
+    // A CPU looping on JMP $C000 with interrupts enabled
+    fn cpu() -> Rc<RefCell<Cpu<Memory>>> {
+        let mut memory = Memory::new().unwrap();
+        for (addr, value) in (0xc000..).zip([0x4c, 0x00, 0xc0]) {
+            memory.write_byte(addr, value);
+        }
+        let mut cpu = Cpu::new(memory);
+        cpu.pc = 0xc000;
+        cpu.p.remove(Flags::INTERRUPT_DISABLE);
+        Rc::new(RefCell::new(cpu))
+    }
+
+    // Runs until the VIC reaches `line`
+    fn run_to_line(cpu: &Rc<RefCell<Cpu<Memory>>>, vic: &mut Vic, line: u16) {
+        while vic.raster_position().0 != line {
+            cpu.borrow_mut().step();
+            vic.step();
+        }
+    }
+
+    #[test]
+    fn raster_irq_at_the_compare_line() {
+        let cpu = cpu();
+        let mut vic = Vic::new(cpu.clone());
+        vic.write_register(0x12, 5);
+        vic.write_register(0x1a, 0x01);
+
+        run_to_line(&cpu, &mut vic, 4);
+        assert_eq!(vic.read_register(0x19), 0x70);
+        run_to_line(&cpu, &mut vic, 5);
+        assert_eq!(vic.read_register(0x19), 0xf1);
+        // Line 5 starts at cycle 315, as the 105th JMP ends, too late for
+        // its interrupt poll, so one more JMP runs first
+        cpu.borrow_mut().step();
+        assert_eq!(cpu.borrow().interrupted(), None);
+        cpu.borrow_mut().step();
+        assert_eq!(cpu.borrow().interrupted(), Some(Interrupt::Irq));
+
+        // Acknowledging releases the line
+        vic.write_register(0x19, 0x01);
+        vic.step();
+        assert_eq!(vic.read_register(0x19), 0x70);
+        cpu.borrow_mut().p.remove(Flags::INTERRUPT_DISABLE);
+        assert_eq!(cpu.borrow().interrupt_due(), None);
+    }
+
+    #[test]
+    fn raster_compare_line_bit_8() {
+        let cpu = cpu();
+        let mut vic = Vic::new(cpu.clone());
+        // Bit 7 of $D011 is bit 8 of the compare line, line 256
+        vic.write_register(0x11, 0x80);
+        vic.write_register(0x12, 0x00);
+        vic.write_register(0x1a, 0x01);
+
+        run_to_line(&cpu, &mut vic, 255);
+        assert_eq!(vic.read_register(0x19) & 0x01, 0);
+        run_to_line(&cpu, &mut vic, 256);
+        assert_eq!(vic.read_register(0x11) & 0x80, 0x80);
+        assert_eq!(vic.read_register(0x12), 0x00);
+        assert_eq!(vic.read_register(0x19), 0xf1);
+    }
+
+    #[test]
+    fn disabled_raster_irq_only_sets_the_status() {
+        let cpu = cpu();
+        let mut vic = Vic::new(cpu.clone());
+        vic.write_register(0x12, 3);
+
+        run_to_line(&cpu, &mut vic, 4);
+        assert_eq!(vic.read_register(0x19), 0x71);
+        assert_eq!(cpu.borrow().interrupt_due(), None);
+        assert_eq!(cpu.borrow().interrupted(), None);
+    }
+}
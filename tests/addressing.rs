@@ -0,0 +1,126 @@
+// Effective addresses at the edges of memory: indexing past $FFFF wraps to
+// the zero page, zero page pointers and indexes stay in the zero page, and
+// JMP ($xxFF) fetches its high byte from the start of the same page.
+use c64_rs::bus::FlatRam;
+use c64_rs::cpu::Cpu;
+
+// Runs the instruction `code` at $0200 with X and Y set, A holds $AA to
+// store
+fn run(code: &[u8], x: u8, y: u8, data: &[(u16, &[u8])]) -> Cpu<FlatRam> {
+    let mut ram = FlatRam::new();
+    ram.load(0x0200, code);
+    for &(addr, bytes) in data {
+        ram.load(addr, bytes);
+    }
+    let mut cpu = Cpu::new(ram);
+    cpu.pc = 0x0200;
+    cpu.a = 0xaa;
+    cpu.x = x;
+    cpu.y = y;
+    cpu.step();
+    cpu
+}
+
+#[test]
+fn absolute_indexed_wraps() {
+    // LDA $FFFF,X
+    let cpu = run(&[0xbd, 0xff, 0xff], 0x01, 0, &[(0x0000, &[0x42])]);
+    assert_eq!(cpu.a, 0x42);
+    assert_eq!(cpu.cycles(), 5);
+
+    // LDA $FFF0,Y
+    let cpu = run(&[0xb9, 0xf0, 0xff], 0, 0x20, &[(0x0010, &[0x43])]);
+    assert_eq!(cpu.a, 0x43);
+    assert_eq!(cpu.cycles(), 5);
+
+    // STA $FFFF,X
+    let cpu = run(&[0x9d, 0xff, 0xff], 0x02, 0, &[]);
+    assert_eq!(cpu.peek_memory(0x0001), 0xaa);
+}
+
+#[test]
+fn indirect_indexed_wraps() {
+    // LDA ($20),Y with the pointer at $FFFF
+    let cpu = run(
+        &[0xb1, 0x20],
+        0,
+        0x02,
+        &[(0x0020, &[0xff, 0xff]), (0x0001, &[0x45])],
+    );
+    assert_eq!(cpu.a, 0x45);
+    assert_eq!(cpu.cycles(), 6);
+}
+
+#[test]
+fn zero_page_pointer_wraps() {
+    // LDA ($FF),Y takes the high byte from $00, not $0100
+    let cpu = run(
+        &[0xb1, 0xff],
+        0,
+        0x01,
+        &[
+            (0x00ff, &[0x00]),
+            (0x0000, &[0x03]),
+            (0x0100, &[0x04]),
+            (0x0301, &[0x46]),
+        ],
+    );
+    assert_eq!(cpu.a, 0x46);
+
+    // LDA ($FE,X) reads the pointer from $FF and $00
+    let cpu = run(
+        &[0xa1, 0xfe],
+        0x01,
+        0,
+        &[
+            (0x00ff, &[0x10]),
+            (0x0000, &[0x03]),
+            (0x0100, &[0x04]),
+            (0x0310, &[0x47]),
+        ],
+    );
+    assert_eq!(cpu.a, 0x47);
+
+    // LDA ($F0,X) with the index carrying out of the zero page
+    let cpu = run(
+        &[0xa1, 0xf0],
+        0x20,
+        0,
+        &[(0x0010, &[0x20, 0x03]), (0x0320, &[0x48])],
+    );
+    assert_eq!(cpu.a, 0x48);
+}
+
+#[test]
+fn zero_page_indexed_wraps() {
+    // LDA $F0,X
+    let cpu = run(
+        &[0xb5, 0xf0],
+        0x20,
+        0,
+        &[(0x0010, &[0x49]), (0x0110, &[0x4a])],
+    );
+    assert_eq!(cpu.a, 0x49);
+
+    // LDX $F0,Y
+    let cpu = run(
+        &[0xb6, 0xf0],
+        0,
+        0x20,
+        &[(0x0010, &[0x4b]), (0x0110, &[0x4c])],
+    );
+    assert_eq!(cpu.x, 0x4b);
+}
+
+#[test]
+fn jmp_indirect_page_bug() {
+    // JMP ($02FF) takes the high byte from $0200, which is the opcode
+    let cpu = run(
+        &[0x6c, 0xff, 0x02],
+        0,
+        0,
+        &[(0x02ff, &[0x34]), (0x0300, &[0x12])],
+    );
+    assert_eq!(cpu.pc, 0x6c34);
+    assert_eq!(cpu.cycles(), 5);
+}
@@ -0,0 +1,50 @@
+// Assembles operands in parentheses and local labels around constants.
+use c64_rs::asm::Assembler;
+
+fn assemble(source: &str) -> Assembler {
+    let mut assembler = Assembler::new(0xc000);
+    assembler.assemble(source).unwrap();
+    assembler
+}
+
+fn bytes(assembler: &Assembler) -> Vec<u8> {
+    assembler.image().values().copied().collect()
+}
+
+#[test]
+fn parentheses() {
+    let assembler = assemble(
+        "lda (1+2)*3\n\
+         lda ($20),y\n\
+         lda ($20,x)\n\
+         jmp ($1234)\n\
+         lda ($10+2)*$100,x\n\
+         lda ((1+2)*4),y\n",
+    );
+    assert_eq!(
+        bytes(&assembler),
+        [
+            0xa5, 0x09, // LDA $09
+            0xb1, 0x20, // LDA ($20),Y
+            0xa1, 0x20, // LDA ($20,X)
+            0x6c, 0x34, 0x12, // JMP ($1234)
+            0xbd, 0x00, 0x12, // LDA $1200,X
+            0xb1, 0x0c, // LDA ($0C),Y
+        ]
+    );
+}
+
+#[test]
+fn constants_keep_the_scope() {
+    let assembler = assemble(
+        "main:\n\
+         count = 3\n\
+         .loop dex\n\
+         bne .loop\n\
+         rts\n",
+    );
+    let labels = assembler.labels();
+    assert_eq!(labels.get("count"), Some(&3));
+    assert_eq!(labels.get("main.loop"), Some(&0xc000));
+    assert_eq!(labels.get("count.loop"), None);
+}
@@ -0,0 +1,194 @@
+// Drives the binary monitor server over a loopback connection the way a
+// VICE client would, with a bare CPU on the C64 memory map as the target.
+mod common;
+
+use c64_rs::binmon::BinaryMonitor;
+use c64_rs::cpu::Cpu;
+use c64_rs::gdb::Session;
+use c64_rs::memory::Memory;
+use common::Bare;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+
+const EVENT: u32 = 0xffff_ffff;
+
+#[derive(Debug)]
+struct Response {
+    kind: u8,
+    error: u8,
+    id: u32,
+    body: Vec<u8>,
+}
+
+struct Client {
+    stream: TcpStream,
+    next_id: u32,
+}
+
+impl Client {
+    fn send(&mut self, command: u8, body: &[u8]) -> u32 {
+        self.next_id += 1;
+        let mut request = vec![0x02, 0x02];
+        request.extend((body.len() as u32).to_le_bytes());
+        request.extend(self.next_id.to_le_bytes());
+        request.push(command);
+        request.extend(body);
+        self.stream.write_all(&request).unwrap();
+        self.next_id
+    }
+
+    fn receive(&mut self) -> Response {
+        let mut header = [0; 12];
+        self.stream.read_exact(&mut header).unwrap();
+        assert_eq!(header[..2], [0x02, 0x02]);
+        let len = u32::from_le_bytes(header[2..6].try_into().unwrap());
+        let mut body = vec![0; len as usize];
+        self.stream.read_exact(&mut body).unwrap();
+        Response {
+            kind: header[6],
+            error: header[7],
+            id: u32::from_le_bytes(header[8..12].try_into().unwrap()),
+            body,
+        }
+    }
+
+    // Sends a command and returns its response, which must succeed
+    fn request(&mut self, command: u8, body: &[u8]) -> Response {
+        let id = self.send(command, body);
+        let response = self.receive();
+        assert_eq!(response.id, id, "{:?}", response);
+        assert_eq!(response.error, 0, "{:?}", response);
+        response
+    }
+
+    fn expect_event(&mut self, kind: u8) -> Response {
+        let event = self.receive();
+        assert_eq!((event.kind, event.id), (kind, EVENT), "{:?}", event);
+        event
+    }
+
+    fn memory_set(&mut self, addr: u16, data: &[u8]) {
+        let mut body = vec![0];
+        body.extend(addr.to_le_bytes());
+        body.extend((addr + data.len() as u16 - 1).to_le_bytes());
+        body.extend([0, 0, 0]);
+        body.extend(data);
+        self.request(0x02, &body);
+    }
+
+    fn checkpoint_set(&mut self, addr: u16, op: u8, temporary: bool) -> u32 {
+        let mut body = addr.to_le_bytes().to_vec();
+        body.extend(addr.to_le_bytes());
+        body.extend([1, 1, op, temporary as u8]);
+        let info = self.request(0x12, &body);
+        assert_eq!(info.kind, 0x11);
+        u32::from_le_bytes(info.body[0..4].try_into().unwrap())
+    }
+}
+
+fn register(registers: &[u8], id: u8) -> u16 {
+    registers[2..]
+        .chunks(4)
+        .find(|item| item[1] == id)
+        .map(|item| u16::from_le_bytes([item[2], item[3]]))
+        .unwrap()
+}
+
+#[test]
+fn loopback_session() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let client = thread::spawn(move || {
+        let mut vice = Client {
+            stream: TcpStream::connect(addr).unwrap(),
+            next_id: 0,
+        };
+        // The machine runs until the first command
+        vice.send(0x81, &[]);
+        vice.expect_event(0x62);
+        assert_eq!(vice.receive().kind, 0x81);
+
+        // A body longer than any command needs is refused
+        let id = vice.send(0x02, &vec![0; 0x20000]);
+        let response = vice.receive();
+        assert_eq!((response.id, response.error), (id, 0x80));
+
+        // LDA #$42, STA $0400, INX, JMP $C005
+        vice.memory_set(
+            0xc000,
+            &[0xa9, 0x42, 0x8d, 0x00, 0x04, 0xe8, 0x4c, 0x05, 0xc0],
+        );
+        let registers = vice.request(0x32, &[0, 1, 0, 3, 0x03, 0x00, 0xc0]).body;
+        assert_eq!(u16::from_le_bytes([registers[0], registers[1]]), 10);
+        assert_eq!(register(&registers, 0x03), 0xc000);
+
+        vice.request(0x71, &[0, 1, 0]);
+        assert_eq!(vice.expect_event(0x62).body, [0x02, 0xc0]);
+        let registers = vice.request(0x31, &[0]).body;
+        assert_eq!(register(&registers, 0x00), 0x42, "A after LDA");
+
+        // A store checkpoint stops after the STA
+        let store = vice.checkpoint_set(0x0400, 0x02, false);
+        vice.request(0xaa, &[]);
+        vice.expect_event(0x63);
+        let hit = vice.expect_event(0x11);
+        assert_eq!(hit.body[..5], [store as u8, 0, 0, 0, 1], "currently hit");
+        assert_eq!(vice.expect_event(0x62).body, [0x05, 0xc0]);
+        let memory = vice
+            .request(0x01, &[0, 0x00, 0x04, 0x00, 0x04, 0, 0, 0])
+            .body;
+        assert_eq!(memory, [1, 0, 0x42]);
+
+        // Temporary checkpoints go away once hit
+        vice.checkpoint_set(0xc006, 0x04, true);
+        vice.request(0xaa, &[]);
+        vice.expect_event(0x63);
+        vice.expect_event(0x11);
+        assert_eq!(vice.expect_event(0x62).body, [0x06, 0xc0]);
+        let id = vice.send(0x14, &[]);
+        let info = vice.receive();
+        assert_eq!((info.kind, info.id), (0x11, id));
+        assert_eq!(vice.receive().body, 1u32.to_le_bytes());
+
+        vice.request(0x13, &store.to_le_bytes());
+        vice.send(0x13, &store.to_le_bytes());
+        assert_eq!(vice.receive().error, 0x01, "already deleted");
+        vice.send(0xee, &[]);
+        assert_eq!(vice.receive().error, 0x83, "unknown command");
+
+        // An "A" in white on blue in the top left corner, red border
+        vice.memory_set(0x0400, &[0x01]);
+        vice.memory_set(0xd800, &[0x01]);
+        vice.memory_set(0xd011, &[0x1b]);
+        vice.memory_set(0xd018, &[0x14]);
+        vice.memory_set(0xd020, &[0x02, 0x06]);
+        vice.memory_set(0xdd00, &[0x03]);
+        let display = vice.request(0x84, &[1, 0]).body;
+        let field = |i: usize| u16::from_le_bytes([display[i], display[i + 1]]) as usize;
+        let (width, inner_x, inner_y) = (field(4), field(8), field(10));
+        assert_eq!((width, field(6)), (384, 272));
+        let pixels = &display[21..];
+        assert_eq!(pixels.len(), 384 * 272);
+        assert_eq!(pixels[0], 2, "border");
+        let top_left = inner_y * width + inner_x;
+        assert_eq!(pixels[top_left..top_left + 8], [6, 6, 6, 1, 1, 6, 6, 6]);
+
+        // Any command stops the running machine
+        vice.request(0xaa, &[]);
+        vice.expect_event(0x63);
+        vice.send(0xbb, &[]);
+        vice.expect_event(0x62);
+        assert_eq!(vice.receive().kind, 0xbb);
+    });
+
+    let mut server = BinaryMonitor::new(Bare::new(Cpu::new(Memory::new().unwrap())));
+    let (stream, _) = listener.accept().unwrap();
+    let session = server.serve(stream).unwrap();
+    client.join().unwrap();
+    assert_eq!(session, Session::Killed);
+
+    let cpu = server.into_target().cpu;
+    assert_eq!(cpu.borrow().peek_memory(0x0400), 0x01);
+}
@@ -0,0 +1,189 @@
+// Runs calls and stack tricks through the shadow call stack, checking the
+// backtraces and that the stack trap reports the tricks. Interrupts and BRK
+// open frames of their own that RTI closes.
+mod common;
+
+use c64_rs::breakpoint::{Hit, Trap};
+use c64_rs::callstack::{FrameKind, Imbalance};
+use c64_rs::cpu::{Cpu, IrqSource, NmiSource};
+use c64_rs::flags::Flags;
+use c64_rs::history::History;
+use c64_rs::memory::Memory;
+use c64_rs::target::{Step, Target};
+use common::{load, Bare};
+
+// $C000  JSR sub, LDA #$C0, PHA, LDA #$09, PHA, RTS, JSR deep, JAM
+// $C010  sub: NOP, RTS
+// $C014  deep: LDX #$FF, TXS, JSR sub, JAM
+const PROGRAM: &[(u16, &[u8])] = &[
+    (
+        0xc000,
+        &[
+            0x20, 0x10, 0xc0, 0xa9, 0xc0, 0x48, 0xa9, 0x09, 0x48, 0x60, 0x20, 0x14, 0xc0, 0x02,
+        ],
+    ),
+    (0xc010, &[0xea, 0x60]),
+    (0xc014, &[0xa2, 0xff, 0x9a, 0x20, 0x10, 0xc0, 0x02]),
+];
+
+fn run(target: &mut Bare) -> Step {
+    loop {
+        match target.step_checked() {
+            Step::Done => {}
+            step => return step,
+        }
+    }
+}
+
+#[test]
+fn calls_and_stack_tricks() {
+    let mut cpu = Cpu::new(Memory::new().unwrap());
+    load(&mut cpu, PROGRAM);
+    cpu.pc = 0xc000;
+    cpu.sp = 0xf0;
+    let mut target = Bare::new(cpu);
+    target.history = History::new(100);
+    target.breakpoints.set_trap(Trap::Stack, true);
+    let cpu = target.cpu();
+
+    // Inside sub, called from $C000
+    target.step_checked();
+    let trace = cpu.borrow().calls().backtrace(0xc010);
+    assert_eq!(trace.len(), 2);
+    let frame = trace[0].1.unwrap();
+    assert_eq!(
+        (frame.kind, frame.caller, frame.target, frame.sp),
+        (FrameKind::Call, 0xc000, 0xc010, 0xf0)
+    );
+    assert_eq!(trace[1], (0xc000, None));
+
+    // Its RTS balances the call, the pushed address used as a jump doesn't
+    assert_eq!(
+        run(&mut target),
+        Step::Hit(Hit::Stack {
+            pc: 0xc009,
+            imbalance: Imbalance::Return {
+                pc: 0xc009,
+                to: 0xc00a
+            },
+        })
+    );
+    assert_eq!(cpu.borrow().calls().depth(), 0);
+
+    // Resetting the stack inside deep leaves it, the next call notices
+    assert_eq!(
+        run(&mut target),
+        Step::Hit(Hit::Stack {
+            pc: 0xc017,
+            imbalance: Imbalance::Abandoned {
+                pc: 0xc017,
+                frames: 1
+            },
+        })
+    );
+    let frames: Vec<u16> = cpu
+        .borrow()
+        .calls()
+        .frames()
+        .iter()
+        .map(|f| f.target)
+        .collect();
+    assert_eq!(frames, [0xc010]);
+
+    // Going back restores the frame that was left
+    target.step_back();
+    let frames: Vec<u16> = cpu
+        .borrow()
+        .calls()
+        .frames()
+        .iter()
+        .map(|f| f.target)
+        .collect();
+    assert_eq!(frames, [0xc014]);
+
+    assert!(matches!(
+        target.step_checked(),
+        Step::Hit(Hit::Stack { pc: 0xc017, .. })
+    ));
+    assert_eq!(run(&mut target), Step::Halted);
+    assert_eq!(cpu.borrow().pc, 0xc01a);
+    assert_eq!(cpu.borrow().calls().depth(), 0);
+}
+
+// $C000  NOP, NOP, BRK, $00, JAM
+// $C020  IRQ and BRK handler: NOP, RTI
+// $C030  NMI handler: NOP, RTI
+const INTERRUPTS: &[(u16, &[u8])] = &[
+    (0xc000, &[0xea, 0xea, 0x00, 0x00, 0x02]),
+    (0xc020, &[0xea, 0x40]),
+    (0xc030, &[0xea, 0x40]),
+    (0xfffa, &[0x30, 0xc0]),
+    (0xfffe, &[0x20, 0xc0]),
+];
+
+// Kind, caller and handler of each open frame, outermost first
+fn frames(target: &Bare) -> Vec<(FrameKind, u16, u16)> {
+    let cpu = target.cpu.borrow();
+    let frames = cpu.calls().frames().iter();
+    frames.map(|f| (f.kind, f.caller, f.target)).collect()
+}
+
+#[test]
+fn interrupts_and_rti() {
+    let mut cpu = Cpu::new(Memory::new().unwrap());
+    // KERNAL out, so the vectors come from RAM
+    cpu.bus.write_byte(Memory::ADDR_MEMORY_LAYOUT, 0x35);
+    assert!(!cpu.bus.is_rom(0xfffe));
+    load(&mut cpu, INTERRUPTS);
+    cpu.pc = 0xc000;
+    cpu.p.remove(Flags::INTERRUPT_DISABLE);
+    let mut target = Bare::new(cpu);
+    target.breakpoints.set_trap(Trap::Stack, true);
+    let cpu = target.cpu();
+    // Asserted early enough to be serviced before the next instruction
+    let due = |cpu: &Cpu<Memory>| cpu.cycles() - Cpu::<Memory>::INTERRUPT_LATENCY;
+
+    // An IRQ after the first NOP comes before the NOP at $C001
+    assert_eq!(target.step_checked(), Step::Done);
+    let at = due(&cpu.borrow());
+    cpu.borrow_mut().set_irq_at(IrqSource::Cia1, true, at);
+    assert_eq!(target.step_checked(), Step::Done);
+    cpu.borrow_mut().set_irq(IrqSource::Cia1, false);
+    assert_eq!(frames(&target), [(FrameKind::Irq, 0xc001, 0xc020)]);
+    assert_eq!(
+        cpu.borrow().calls().backtrace(0xc020)[1],
+        (0xc001, None),
+        "the interrupted instruction is the caller"
+    );
+
+    // An NMI interrupts the IRQ handler before its NOP
+    let at = due(&cpu.borrow());
+    cpu.borrow_mut().set_nmi_at(NmiSource::Restore, true, at);
+    assert_eq!(target.step_checked(), Step::Done);
+    cpu.borrow_mut().set_nmi(NmiSource::Restore, false);
+    assert_eq!(
+        frames(&target),
+        [
+            (FrameKind::Irq, 0xc001, 0xc020),
+            (FrameKind::Nmi, 0xc020, 0xc030)
+        ]
+    );
+
+    // Each RTI closes its own frame
+    target.step_checked();
+    target.step_checked();
+    assert_eq!(cpu.borrow().pc, 0xc020);
+    assert_eq!(frames(&target), [(FrameKind::Irq, 0xc001, 0xc020)]);
+    target.step_checked();
+    target.step_checked();
+    assert_eq!(cpu.borrow().pc, 0xc001);
+    assert_eq!(frames(&target), []);
+
+    // BRK skips its signature byte on the way back
+    target.step_checked();
+    target.step_checked();
+    assert_eq!(frames(&target), [(FrameKind::Brk, 0xc002, 0xc020)]);
+    assert_eq!(run(&mut target), Step::Halted);
+    assert_eq!(cpu.borrow().pc, 0xc004);
+    assert_eq!(frames(&target), []);
+}
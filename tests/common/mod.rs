@@ -0,0 +1,72 @@
+// Shared by the integration tests: a bare CPU on the C64 memory map as the
+// target of the debugger front ends, and loading programs into it.
+#![allow(dead_code)] // Each test uses a part of it
+
+use c64_rs::breakpoint::Breakpoints;
+use c64_rs::cpu::Cpu;
+use c64_rs::history::History;
+use c64_rs::memory::Memory;
+use c64_rs::target::Target;
+use std::cell::RefCell;
+use std::fs;
+use std::rc::Rc;
+
+pub struct Bare {
+    pub cpu: Rc<RefCell<Cpu<Memory>>>,
+    pub breakpoints: Breakpoints,
+    // Steps go through it, it records nothing unless given a capacity
+    pub history: History,
+}
+
+impl Bare {
+    pub fn new(cpu: Cpu<Memory>) -> Self {
+        Bare {
+            cpu: Rc::new(RefCell::new(cpu)),
+            breakpoints: Breakpoints::new(),
+            history: History::new(0),
+        }
+    }
+}
+
+impl Target for Bare {
+    fn cpu(&self) -> Rc<RefCell<Cpu<Memory>>> {
+        self.cpu.clone()
+    }
+
+    fn step(&mut self) -> bool {
+        self.history.step(&mut self.cpu.borrow_mut(), (0, 0))
+    }
+
+    fn raster(&self) -> (u16, u16) {
+        (0, 0)
+    }
+
+    fn breakpoints(&mut self) -> &mut Breakpoints {
+        &mut self.breakpoints
+    }
+
+    fn step_back(&mut self) -> bool {
+        self.history.step_back(&mut self.cpu.borrow_mut()).is_some()
+    }
+
+    // Without a KERNAL to boot, the program starts at its load address
+    fn autostart(&mut self, path: &str, _index: u16, _run: bool) -> Result<(), String> {
+        let program = fs::read(path).map_err(|e| e.to_string())?;
+        let addr = u16::from_le_bytes([program[0], program[1]]);
+        let mut cpu = self.cpu.borrow_mut();
+        for (i, &byte) in program[2..].iter().enumerate() {
+            cpu.bus.write_byte(addr + i as u16, byte);
+        }
+        cpu.pc = addr;
+        Ok(())
+    }
+}
+
+// Writes each part of `program` to its address
+pub fn load(cpu: &mut Cpu<Memory>, program: &[(u16, &[u8])]) {
+    for &(addr, bytes) in program {
+        for (i, &byte) in bytes.iter().enumerate() {
+            cpu.bus.write_byte(addr + i as u16, byte);
+        }
+    }
+}
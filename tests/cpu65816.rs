@@ -0,0 +1,164 @@
+// Runs short programs using what the 65816 adds in emulation mode, on a
+// flat 64K RAM.
+use c64_rs::bus::FlatRam;
+use c64_rs::cpu::Processor;
+use c64_rs::cpu65816::Cpu65816;
+use c64_rs::flags::Flags;
+use c64_rs::harness::{Harness, Outcome};
+
+const START: u16 = 0x0200;
+
+fn harness(program: &[u8]) -> Harness<Cpu65816<FlatRam>> {
+    let mut harness = Harness::new(Cpu65816::new(FlatRam::new()));
+    harness.load(START, program);
+    harness
+}
+
+// Runs the loaded program up to its end
+fn run(harness: &mut Harness<Cpu65816<FlatRam>>, program_len: usize) {
+    let end = START + program_len as u16;
+    let outcome = harness.run(START, Some(end), 1000);
+    assert_eq!(outcome, Outcome::Reached(end));
+}
+
+#[test]
+fn accumulator_b_and_direct_page() {
+    let program = [
+        0xa9, 0x03, // LDA #$03
+        0xeb, // XBA
+        0xa9, 0x10, // LDA #$10
+        0x5b, // TCD, D = $0310
+        0xa5, 0x05, // LDA $05, from $0315
+        0xa2, 0x01, // LDX #1
+        0xb4, 0xff, // LDY $FF,X, from $0410 as D isn't page aligned
+        0x7b, // TDC
+        0xeb, // XBA
+    ];
+    let mut harness = harness(&program);
+    harness.load(0x0315, &[0x42]);
+    harness.load(0x0410, &[0x24]);
+    run(&mut harness, program.len());
+    let registers = harness.cpu.registers();
+    assert_eq!(harness.cpu.d, 0x0310);
+    assert_eq!((registers.a, harness.cpu.b), (0x03, 0x10));
+    assert_eq!(registers.y, 0x24);
+}
+
+#[test]
+fn direct_page_wraps_on_a_page_boundary() {
+    let program = [
+        0xf4, 0x00, 0x03, // PEA $0300
+        0x2b, // PLD
+        0xa2, 0x02, // LDX #2
+        0xb5, 0xff, // LDA $FF,X, from $0301
+    ];
+    let mut harness = harness(&program);
+    harness.load(0x0301, &[0x42]);
+    harness.load(0x0401, &[0x24]);
+    run(&mut harness, program.len());
+    assert_eq!(harness.cpu.registers().a, 0x42);
+}
+
+#[test]
+fn long_and_stack_relative_addressing() {
+    let program = [
+        0xaf, 0x00, 0x30, 0x00, // LDA $003000
+        0xa2, 0x01, // LDX #1
+        0x1f, 0x00, 0x30, 0x00, // ORA $003000,X
+        0x87, 0x10, // STA [$10]
+        0x48, // PHA
+        0xa3, 0x01, // LDA $01,S
+        0xa0, 0x01, // LDY #1
+        0xb7, 0x10, // LDA [$10],Y
+    ];
+    let mut harness = harness(&program);
+    harness.load(0x3000, &[0x0f, 0xf0]);
+    harness.load(0x0010, &[0x00, 0x40, 0x00]);
+    harness.load(0x4001, &[0x55]);
+    run(&mut harness, program.len());
+    assert_eq!(harness.cpu.peek_memory(0x4000), 0xff);
+    assert_eq!(harness.cpu.registers().a, 0x55);
+}
+
+#[test]
+fn long_calls() {
+    let program = [
+        0x22, 0x00, 0x03, 0x00, // JSL $000300
+        0x82, 0x01, 0x00, // BRL +1
+        0xea, // NOP, skipped
+        0x4b, // PHK
+        0x68, // PLA
+    ];
+    let mut harness = harness(&program);
+    harness.load(0x0300, &[0xa2, 0x42, 0x6b]); // LDX #$42, RTL
+    run(&mut harness, program.len());
+    let registers = harness.cpu.registers();
+    assert_eq!((registers.a, registers.x), (0, 0x42));
+    assert_eq!(registers.sp, 0xff);
+}
+
+#[test]
+fn block_move() {
+    let program = [
+        0xa9, 0x03, // LDA #3, moves four bytes
+        0xa2, 0x10, // LDX #$10
+        0xa0, 0x20, // LDY #$20
+        0x54, 0x00, 0x00, // MVN $00,$00
+    ];
+    let mut harness = harness(&program);
+    harness.load(0x0010, &[1, 2, 3, 4, 5]);
+    run(&mut harness, program.len());
+    let registers = harness.cpu.registers();
+    assert_eq!(
+        (0x20..0x25)
+            .map(|addr| harness.cpu.peek_memory(addr))
+            .collect::<Vec<_>>(),
+        [1, 2, 3, 4, 0]
+    );
+    assert_eq!((registers.x, registers.y), (0x14, 0x24));
+    assert_eq!((registers.a, harness.cpu.b), (0xff, 0xff));
+}
+
+#[test]
+fn rep_sep_keep_emulation_mode_bits() {
+    let program = [
+        0xe2, 0xcf, // SEP #$CF
+        0xc2, 0xff, // REP #$FF
+    ];
+    let mut harness = harness(&program);
+    run(&mut harness, 2);
+    let p = harness.cpu.registers().p;
+    assert!(p.contains(Flags::NEGATIVE | Flags::OVERFLOW | Flags::DECIMAL | Flags::CARRY));
+    run(&mut harness, program.len());
+    let p = harness.cpu.registers().p;
+    assert_eq!(p, Flags::from_stack(0));
+}
+
+#[test]
+fn cop_vector() {
+    let mut harness = harness(&[0x02, 0x12]);
+    harness.load(0xfff4, &[0x00, 0x03]);
+    harness.load(0x0300, &[0x40]); // RTI
+    assert_eq!(
+        harness.run(START, Some(0x0300), 10),
+        Outcome::Reached(0x0300)
+    );
+    assert_eq!(
+        harness.run(0x0300, Some(0x0202), 10),
+        Outcome::Reached(0x0202)
+    );
+}
+
+#[test]
+fn native_mode_stops() {
+    let program = [
+        0x38, // SEC
+        0xfb, // XCE, stays in emulation mode
+        0x18, // CLC
+        0xfb, // XCE
+    ];
+    let mut harness = harness(&program);
+    assert_eq!(harness.run(START, None, 10), Outcome::Stopped(0x0204));
+    assert!(harness.cpu.native());
+    assert!(harness.cpu.registers().p.contains(Flags::CARRY));
+}
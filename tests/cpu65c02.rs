@@ -0,0 +1,132 @@
+// Runs short programs using what the 65C02 adds to or changes from the
+// NMOS core, on a flat 64K RAM.
+use c64_rs::bus::FlatRam;
+use c64_rs::cpu::Processor;
+use c64_rs::cpu65c02::Cpu65C02;
+use c64_rs::flags::Flags;
+use c64_rs::harness::{Harness, Outcome};
+
+const START: u16 = 0x0200;
+
+fn harness(program: &[u8]) -> Harness<Cpu65C02<FlatRam>> {
+    let mut harness = Harness::new(Cpu65C02::new(FlatRam::new()));
+    harness.load(START, program);
+    harness
+}
+
+// Runs the loaded program up to its end
+fn run(harness: &mut Harness<Cpu65C02<FlatRam>>, program_len: usize) {
+    let end = START + program_len as u16;
+    let outcome = harness.run(START, Some(end), 1000);
+    assert_eq!(outcome, Outcome::Reached(end));
+}
+
+#[test]
+fn stz_and_index_register_stack() {
+    let program = [
+        0xa9, 0x55, // LDA #$55
+        0x85, 0x10, // STA $10
+        0x64, 0x10, // STZ $10
+        0xa2, 0x12, // LDX #$12
+        0xda, // PHX
+        0xa2, 0x00, // LDX #0
+        0xfa, // PLX
+        0xa0, 0x34, // LDY #$34
+        0x5a, // PHY
+        0xa0, 0x00, // LDY #0
+        0x7a, // PLY
+    ];
+    let mut harness = harness(&program);
+    run(&mut harness, program.len());
+    let registers = harness.cpu.registers();
+    assert_eq!(harness.cpu.peek_memory(0x10), 0);
+    assert_eq!((registers.x, registers.y), (0x12, 0x34));
+}
+
+#[test]
+fn bit_instructions() {
+    let program = [
+        0xa9, 0x05, // LDA #$05
+        0x85, 0x20, // STA $20
+        0xa9, 0x0a, // LDA #$0A
+        0x04, 0x20, // TSB $20, $0F
+        0xa9, 0x03, // LDA #$03
+        0x14, 0x20, // TRB $20, $0C
+        0xf7, 0x20, // SMB7 $20, $8C
+        0x27, 0x20, // RMB2 $20, $88
+        0xa9, 0xff, // LDA #$FF
+        0x1a, // INC A
+    ];
+    let mut harness = harness(&program);
+    run(&mut harness, program.len());
+    let registers = harness.cpu.registers();
+    assert_eq!(harness.cpu.peek_memory(0x20), 0x88);
+    assert_eq!(registers.a, 0);
+    assert!(registers.p.contains(Flags::ZERO));
+}
+
+#[test]
+fn branches() {
+    let program = [
+        0xa9, 0x01, // LDA #1
+        0x85, 0x30, // STA $30
+        0x0f, 0x30, 0x02, // BBR0 $30, not taken
+        0xa2, 0x01, // LDX #1
+        0x8f, 0x30, 0x02, // BBS0 $30, taken
+        0xa2, 0x02, // LDX #2
+        0x80, 0x02, // BRA
+        0xa0, 0x03, // LDY #3
+    ];
+    let mut harness = harness(&program);
+    run(&mut harness, program.len());
+    let registers = harness.cpu.registers();
+    assert_eq!((registers.x, registers.y), (1, 0));
+}
+
+#[test]
+fn jmp_indirect() {
+    // The pointer crosses a page, the NMOS core would take its high byte
+    // from $0200
+    let mut harness = harness(&[0x6c, 0xff, 0x02]);
+    harness.load(0x02ff, &[0x10, 0x03]);
+    harness.load(0x0310, &[0xa9, 0x42]);
+    assert_eq!(
+        harness.run(START, Some(0x0312), 10),
+        Outcome::Reached(0x0312)
+    );
+    assert_eq!(harness.cpu.registers().a, 0x42);
+
+    // JMP ($0320,X)
+    let mut harness = self::harness(&[0xa2, 0x02, 0x7c, 0x20, 0x03]);
+    harness.load(0x0322, &[0x30, 0x03]);
+    harness.load(0x0330, &[0xa9, 0x24]);
+    assert_eq!(
+        harness.run(START, Some(0x0332), 10),
+        Outcome::Reached(0x0332)
+    );
+    assert_eq!(harness.cpu.registers().a, 0x24);
+}
+
+#[test]
+fn decimal_flags() {
+    let program = [
+        0xf8, // SED
+        0x18, // CLC
+        0xa9, 0x99, // LDA #$99
+        0x69, 0x01, // ADC #$01
+    ];
+    let mut harness = harness(&program);
+    run(&mut harness, program.len());
+    let registers = harness.cpu.registers();
+    // Z comes from the BCD result, not the binary $9A
+    assert_eq!(registers.a, 0);
+    assert!(registers.p.contains(Flags::ZERO));
+    assert!(registers.p.contains(Flags::CARRY));
+    assert!(!registers.p.contains(Flags::NEGATIVE));
+}
+
+#[test]
+fn stp_stops() {
+    let mut harness = harness(&[0xea, 0xdb, 0xea]);
+    assert_eq!(harness.run(START, None, 10), Outcome::Stopped(0x0202));
+}
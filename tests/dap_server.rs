@@ -0,0 +1,301 @@
+// Drives the DAP server over a loopback connection the way an editor
+// would, launching an assembler source on a bare CPU with the C64 memory
+// map as the target.
+mod common;
+
+use c64_rs::cpu::Cpu;
+use c64_rs::dap::DapServer;
+use c64_rs::gdb::Session;
+use c64_rs::memory::Memory;
+use c64_rs::symbols::Symbols;
+use common::Bare;
+use serde_json::{json, Value};
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, Cursor, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::{fs, thread};
+
+const SOURCE: &str = "\
+*= $c000
+start   lda #$42
+        sta $0400
+        jsr sub
+loop    inx
+        jmp loop
+
+sub     ldy #1
+        rts
+";
+
+struct Client {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    seq: u64,
+    events: VecDeque<Value>,
+}
+
+impl Client {
+    fn read(&mut self) -> Value {
+        let mut len = 0;
+        loop {
+            let mut line = String::new();
+            self.reader.read_line(&mut line).unwrap();
+            match line.trim_end() {
+                "" => break,
+                header => len = header["Content-Length: ".len()..].parse().unwrap(),
+            }
+        }
+        let mut body = vec![0; len];
+        self.reader.read_exact(&mut body).unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    // Sends a request and returns the body of its response, which must
+    // succeed. Events on the way are kept for later.
+    fn request(&mut self, command: &str, arguments: Value) -> Value {
+        self.seq += 1;
+        let body = json!({
+            "seq": self.seq,
+            "type": "request",
+            "command": command,
+            "arguments": arguments,
+        })
+        .to_string();
+        write!(
+            self.writer,
+            "Content-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        )
+        .unwrap();
+        loop {
+            let message = self.read();
+            if message["type"] == "event" {
+                self.events.push_back(message);
+                continue;
+            }
+            assert_eq!(message["request_seq"], self.seq);
+            assert_eq!(message["success"], true, "{}", message);
+            return message["body"].clone();
+        }
+    }
+
+    fn event(&mut self, name: &str) -> Value {
+        let event = self.events.pop_front().unwrap_or_else(|| self.read());
+        assert_eq!(event["event"], name, "{}", event);
+        event["body"].clone()
+    }
+
+    fn stopped(&mut self, reason: &str) -> Value {
+        let body = self.event("stopped");
+        assert_eq!(body["reason"], reason, "{}", body);
+        body
+    }
+
+    fn line(&mut self) -> Value {
+        let trace = self.request("stackTrace", json!({ "threadId": 1 }));
+        trace["stackFrames"][0]["line"].clone()
+    }
+}
+
+#[test]
+fn loopback_session() {
+    let dir = std::env::temp_dir().join(format!("c64-rs-dap-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let source = dir.join("program.asm");
+    fs::write(&source, SOURCE).unwrap();
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let path = source.to_string_lossy().into_owned();
+
+    let client = thread::spawn(move || {
+        let stream = TcpStream::connect(addr).unwrap();
+        let mut editor = Client {
+            reader: BufReader::new(stream.try_clone().unwrap()),
+            writer: stream,
+            seq: 0,
+            events: VecDeque::new(),
+        };
+        let capabilities = editor.request("initialize", json!({ "adapterID": "c64-rs" }));
+        assert_eq!(capabilities["supportsDisassembleRequest"], true);
+        editor.request("launch", json!({ "program": path, "stopOnEntry": true }));
+        editor.event("initialized");
+
+        // Line 7 is empty, its breakpoint moves to the next line with code
+        let breakpoints = editor.request(
+            "setBreakpoints",
+            json!({ "source": { "path": path }, "breakpoints": [{ "line": 4 }, { "line": 7 }] }),
+        );
+        let lines: Vec<&Value> = breakpoints["breakpoints"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|b| &b["line"])
+            .collect();
+        assert_eq!(lines, [4, 8]);
+        editor.request("setExceptionBreakpoints", json!({ "filters": [] }));
+        editor.request("configurationDone", json!({}));
+        editor.stopped("entry");
+        assert_eq!(editor.line(), 2);
+
+        editor.request("continue", json!({ "threadId": 1 }));
+        let stop = editor.stopped("breakpoint");
+        assert_eq!(
+            stop["hitBreakpointIds"],
+            json!([breakpoints["breakpoints"][0]["id"]])
+        );
+        assert_eq!(editor.line(), 4);
+
+        // Stepping over the call stops at the breakpoint inside it
+        editor.request("next", json!({ "threadId": 1 }));
+        editor.stopped("breakpoint");
+        assert_eq!(editor.line(), 8);
+        let trace = editor.request("stackTrace", json!({ "threadId": 1 }));
+        assert_eq!(trace["totalFrames"], 2);
+        assert_eq!(trace["stackFrames"][0]["name"], "sub");
+        assert_eq!(trace["stackFrames"][1]["line"], 4, "{}", trace);
+        editor.request("stepIn", json!({ "threadId": 1 }));
+        editor.stopped("step");
+        editor.request("stepOut", json!({ "threadId": 1 }));
+        editor.stopped("step");
+        assert_eq!(editor.line(), 5);
+
+        let variables = editor.request("variables", json!({ "variablesReference": 1 }));
+        let value = |name: &str| {
+            variables["variables"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .find(|v| v["name"] == name)
+                .unwrap()["value"]
+                .clone()
+        };
+        assert_eq!((value("A"), value("Y")), (json!("$42"), json!("$01")));
+        let set = editor.request(
+            "setVariable",
+            json!({ "variablesReference": 1, "name": "A", "value": "0x10" }),
+        );
+        assert_eq!(set["value"], "$10");
+        let result = editor.request("evaluate", json!({ "expression": "a + 1" }));
+        assert_eq!(result["result"], "$11 (17)");
+
+        editor.request(
+            "writeMemory",
+            json!({ "memoryReference": "0x0401", "data": "AQI=" }),
+        );
+        let memory = editor.request(
+            "readMemory",
+            json!({ "memoryReference": "0x0400", "count": 3 }),
+        );
+        assert_eq!(memory["data"], "QgEC");
+        let disassembly = editor.request(
+            "disassemble",
+            json!({ "memoryReference": "0xC000", "instructionCount": 2 }),
+        );
+        let instructions = &disassembly["instructions"];
+        assert_eq!(instructions[0]["instruction"], "LDA #$42");
+        assert_eq!(instructions[1]["address"], "0xC002");
+        assert_eq!(instructions[1]["line"], 3);
+
+        // The loop runs until paused
+        editor.request("continue", json!({ "threadId": 1 }));
+        editor.request("pause", json!({ "threadId": 1 }));
+        editor.stopped("pause");
+        editor.request("disconnect", json!({ "terminateDebuggee": true }));
+    });
+
+    let mut server = DapServer::new(Bare::new(Cpu::new(Memory::new().unwrap())));
+    let (stream, _) = listener.accept().unwrap();
+    let session = server.serve(stream.try_clone().unwrap(), stream).unwrap();
+    client.join().unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(session, Session::Killed);
+
+    let cpu = server.into_target().cpu;
+    assert_eq!(cpu.borrow().peek_memory(0x0402), 0x02);
+}
+
+// Parses the messages the server wrote, failing on anything in between
+fn frames(mut output: &[u8]) -> Vec<Value> {
+    let mut messages = Vec::new();
+    while !output.is_empty() {
+        let text = std::str::from_utf8(output).unwrap();
+        let header = text.strip_prefix("Content-Length: ").expect(text);
+        let end = header.find("\r\n\r\n").unwrap();
+        let len: usize = header[..end].parse().unwrap();
+        let start = text.len() - header.len() + end + 4;
+        messages.push(serde_json::from_slice(&output[start..start + len]).unwrap());
+        output = &output[start + len..];
+    }
+    messages
+}
+
+#[test]
+fn stdio_session_with_labels() {
+    let dir = std::env::temp_dir().join(format!("c64-rs-dap-stdio-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    // JSR sub, loop: JMP loop, sub: RTS
+    let program = dir.join("program.prg");
+    fs::write(
+        &program,
+        [0x00, 0xc0, 0x20, 0x06, 0xc0, 0x4c, 0x03, 0xc0, 0x60],
+    )
+    .unwrap();
+    let labels = dir.join("program.vs");
+    fs::write(
+        &labels,
+        "al C:c000 .start\nal C:c003 .loop\nal C:c006 .sub\n",
+    )
+    .unwrap();
+
+    // Everything the editor sends, as it would come in on stdin
+    let requests = [
+        ("initialize", json!({ "adapterID": "c64-rs" })),
+        (
+            "launch",
+            json!({ "program": program.to_string_lossy(), "stopOnEntry": true }),
+        ),
+        ("configurationDone", json!({})),
+        (
+            "disassemble",
+            json!({ "memoryReference": "0xC000", "instructionCount": 2 }),
+        ),
+        ("disconnect", json!({ "terminateDebuggee": true })),
+    ];
+    let mut input = Vec::new();
+    for (seq, (command, arguments)) in requests.into_iter().enumerate() {
+        let body = json!({
+            "seq": seq + 1,
+            "type": "request",
+            "command": command,
+            "arguments": arguments,
+        })
+        .to_string();
+        write!(input, "Content-Length: {}\r\n\r\n{}", body.len(), body).unwrap();
+    }
+
+    let mut server = DapServer::new(Bare::new(Cpu::new(Memory::new().unwrap())));
+    let mut symbols = Symbols::rom();
+    symbols.load(&labels).unwrap();
+    server.set_symbols(symbols);
+    let mut output = Vec::new();
+    let session = server.serve(Cursor::new(input), &mut output).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(session, Session::Killed);
+
+    let messages = frames(&output);
+    assert!(
+        messages.iter().all(|m| m["success"] != false),
+        "{:?}",
+        messages
+    );
+    let disassembly = messages
+        .iter()
+        .find(|m| m["command"] == "disassemble")
+        .unwrap();
+    let instructions = &disassembly["body"]["instructions"];
+    assert_eq!(instructions[0]["symbol"], "start");
+    assert_eq!(instructions[0]["instruction"], "JSR sub");
+    assert_eq!(instructions[1]["instruction"], "JMP loop");
+}
@@ -0,0 +1,112 @@
+// Disassembler output for every addressing mode of the NMOS, 65C02 and
+// 65816 tables, undocumented opcodes and instructions at the top of memory.
+use c64_rs::bus::{Bus, FlatRam};
+use c64_rs::disasm::{disassemble, listing_line, Instruction};
+use c64_rs::opcode::{Opcode, OPCODES, OPCODES_65816, OPCODES_65C02};
+
+// Decodes `bytes` placed at `addr` with `table`
+fn decode_at(table: &'static [Opcode; 256], addr: u16, bytes: &[u8]) -> Instruction {
+    let mut ram = FlatRam::new();
+    ram.load(addr, bytes);
+    Instruction::decode_with(table, addr, |a| ram.peek(a))
+}
+
+fn text(table: &'static [Opcode; 256], bytes: &[u8]) -> String {
+    decode_at(table, 0xc000, bytes).to_string()
+}
+
+#[test]
+fn addressing_modes_nmos() {
+    let cases: &[(&[u8], &str)] = &[
+        (&[0xea], "NOP"),
+        (&[0x0a], "ASL A"),
+        (&[0xa9, 0x05], "LDA #$05"),
+        (&[0xa5, 0x12], "LDA $12"),
+        (&[0xb5, 0x12], "LDA $12,X"),
+        (&[0xb6, 0x12], "LDX $12,Y"),
+        (&[0xad, 0x34, 0x12], "LDA $1234"),
+        (&[0xbd, 0x34, 0x12], "LDA $1234,X"),
+        (&[0xb9, 0x34, 0x12], "LDA $1234,Y"),
+        (&[0x6c, 0x34, 0x12], "JMP ($1234)"),
+        (&[0xa1, 0x12], "LDA ($12,X)"),
+        (&[0xb1, 0x12], "LDA ($12),Y"),
+        // Relative to the next instruction at $C002
+        (&[0xd0, 0x10], "BNE $C012"),
+        (&[0xd0, 0xfe], "BNE $C000"),
+    ];
+    for &(bytes, expected) in cases {
+        assert_eq!(text(&OPCODES, bytes), expected, "{:02X?}", bytes);
+    }
+}
+
+#[test]
+fn addressing_modes_65c02() {
+    let cases: &[(&[u8], &str)] = &[
+        (&[0xb2, 0x12], "LDA ($12)"),
+        (&[0x7c, 0x34, 0x12], "JMP ($1234,X)"),
+        // BBR0 $12 to $C003 + $10
+        (&[0x0f, 0x12, 0x10], "BBR0 $12,$C013"),
+        (&[0x89, 0x80], "BIT #$80"),
+        (&[0x64, 0x12], "STZ $12"),
+    ];
+    for &(bytes, expected) in cases {
+        assert_eq!(text(&OPCODES_65C02, bytes), expected, "{:02X?}", bytes);
+    }
+}
+
+#[test]
+fn addressing_modes_65816() {
+    let cases: &[(&[u8], &str)] = &[
+        (&[0x03, 0x05], "ORA $05,S"),
+        (&[0x13, 0x05], "ORA ($05,S),Y"),
+        (&[0x07, 0x12], "ORA [$12]"),
+        (&[0x17, 0x12], "ORA [$12],Y"),
+        (&[0x0f, 0x56, 0x34, 0x12], "ORA $123456"),
+        (&[0x1f, 0x56, 0x34, 0x12], "ORA $123456,X"),
+        (&[0xdc, 0x34, 0x12], "JML [$1234]"),
+        // Relative to the next instruction at $C003
+        (&[0x82, 0xfd, 0xff], "BRL $C000"),
+        // Destination bank first in memory, source bank first in the text
+        (&[0x54, 0x01, 0x02], "MVN $02,$01"),
+    ];
+    for &(bytes, expected) in cases {
+        assert_eq!(text(&OPCODES_65816, bytes), expected, "{:02X?}", bytes);
+    }
+}
+
+#[test]
+fn undocumented_opcodes_are_marked() {
+    assert_eq!(text(&OPCODES, &[0xa7, 0x12]), "*LAX $12");
+    assert_eq!(text(&OPCODES, &[0x1a]), "*NOP");
+    assert_eq!(text(&OPCODES, &[0x0c, 0x34, 0x12]), "*NOP $1234");
+    assert_eq!(text(&OPCODES, &[0xeb, 0x01]), "*SBC #$01");
+    assert_eq!(text(&OPCODES, &[0x02]), "*JAM");
+    // $64 is STZ on the 65C02, $5C one of the NOPs WDC left undefined
+    assert_eq!(text(&OPCODES_65C02, &[0x64, 0x12]), "STZ $12");
+    assert_eq!(text(&OPCODES_65C02, &[0x5c, 0x34, 0x12]), "*NOP $1234");
+}
+
+#[test]
+fn operands_wrap_at_the_top_of_memory() {
+    // The operand of LDA $1234 at $FFFF continues at $0000
+    let mut ram = FlatRam::new();
+    ram.load(0xfffe, &[0xea, 0xad]);
+    ram.load(0x0000, &[0x34, 0x12]);
+    let lda = Instruction::decode(0xffff, |a| ram.peek(a));
+    assert_eq!(lda.to_string(), "LDA $1234");
+    assert_eq!(lda.next(), 0x0002);
+
+    // So do branch targets
+    let bne = decode_at(&OPCODES, 0xfff0, &[0xd0, 0x20]);
+    assert_eq!(bne.branch_target(), Some(0x0012));
+    assert_eq!(bne.to_string(), "BNE $0012");
+    let bpl = decode_at(&OPCODES, 0x0000, &[0x10, 0xfc]);
+    assert_eq!(bpl.to_string(), "BPL $FFFE");
+
+    // A listing stops at $FFFF instead of starting over
+    let lines: Vec<String> = disassemble(&ram, 0xfffe, 0xffff)
+        .iter()
+        .map(listing_line)
+        .collect();
+    assert_eq!(lines, ["FFFE  EA        NOP", "FFFF  AD 34 12  LDA $1234"]);
+}
@@ -0,0 +1,113 @@
+// Drives the GDB server over a loopback connection the way GDB would, with
+// a bare CPU on the C64 memory map as the target.
+mod common;
+
+use c64_rs::cpu::Cpu;
+use c64_rs::gdb::{GdbServer, Session};
+use c64_rs::memory::Memory;
+use common::Bare;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+
+struct Client {
+    stream: TcpStream,
+}
+
+impl Client {
+    fn send_raw(&mut self, data: &[u8]) {
+        self.stream.write_all(data).unwrap();
+    }
+
+    fn read_byte(&mut self) -> u8 {
+        let mut byte = [0];
+        self.stream.read_exact(&mut byte).unwrap();
+        byte[0]
+    }
+
+    // Sends a packet and returns the reply, acknowledging both ways
+    fn request(&mut self, packet: &str) -> String {
+        let checksum = packet.bytes().fold(0u8, |sum, b| sum.wrapping_add(b));
+        self.send_raw(format!("${}#{:02x}", packet, checksum).as_bytes());
+        assert_eq!(self.read_byte(), b'+', "no ack for {}", packet);
+        self.reply()
+    }
+
+    fn reply(&mut self) -> String {
+        assert_eq!(self.read_byte(), b'$');
+        let mut data = Vec::new();
+        loop {
+            match self.read_byte() {
+                b'#' => break,
+                b => data.push(b),
+            }
+        }
+        let checksum = [self.read_byte(), self.read_byte()];
+        let expected = data.iter().fold(0u8, |sum, &b| sum.wrapping_add(b));
+        assert_eq!(
+            u8::from_str_radix(std::str::from_utf8(&checksum).unwrap(), 16).unwrap(),
+            expected
+        );
+        self.send_raw(b"+");
+        String::from_utf8(data).unwrap()
+    }
+}
+
+#[test]
+fn loopback_session() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let client = thread::spawn(move || {
+        let mut gdb = Client {
+            stream: TcpStream::connect(addr).unwrap(),
+        };
+        assert!(gdb.request("qSupported:swbreak+").contains("PacketSize"));
+        assert!(gdb
+            .request("qXfer:features:read:target.xml:0,fff")
+            .contains(r#"<reg name="pc" bitsize="16""#));
+        assert_eq!(gdb.request("?"), "S05");
+
+        // LDA #$42, STA $0400, INX, JMP $C005
+        assert_eq!(gdb.request("Mc000,9:a9428d0004e84c05c0"), "OK");
+        assert_eq!(gdb.request("mc000,3"), "a9428d");
+        assert_eq!(gdb.request("P5=00c0"), "OK");
+        assert_eq!(gdb.request("p5"), "00c0");
+
+        assert_eq!(gdb.request("s"), "S05");
+        let registers = gdb.request("g");
+        assert_eq!(&registers[0..2], "42", "A after LDA");
+        assert_eq!(&registers[10..14], "02c0", "PC after one step");
+
+        assert_eq!(gdb.request("Z2,0400,1"), "OK");
+        assert_eq!(gdb.request("c"), "T05watch:0400;");
+        assert_eq!(gdb.request("m0400,1"), "42");
+        assert_eq!(gdb.request("z2,0400,1"), "OK");
+
+        assert_eq!(gdb.request("Z0,c006,1"), "OK");
+        assert_eq!(gdb.request("c"), "T05swbreak:;");
+        assert_eq!(gdb.request("p5"), "06c0");
+        assert_eq!(gdb.request("z0,c006,1"), "OK");
+        assert_eq!(gdb.request("z0,c006,1"), "E01", "already removed");
+
+        // Nothing stops the loop but an interrupt
+        gdb.send_raw(b"$c#63");
+        assert_eq!(gdb.read_byte(), b'+');
+        gdb.send_raw(&[0x03]);
+        assert_eq!(gdb.reply(), "S02");
+
+        assert_eq!(gdb.request("Hg0"), "OK");
+        assert_eq!(gdb.request("vMustReplyEmpty"), "");
+        gdb.send_raw(b"$k#6b");
+        assert_eq!(gdb.read_byte(), b'+');
+    });
+
+    let mut server = GdbServer::new(Bare::new(Cpu::new(Memory::new().unwrap())));
+    let (stream, _) = listener.accept().unwrap();
+    let session = server.serve(stream).unwrap();
+    client.join().unwrap();
+    assert_eq!(session, Session::Killed);
+
+    let cpu = server.into_target().cpu;
+    assert_eq!(cpu.borrow().peek_memory(0x0400), 0x42);
+}
@@ -0,0 +1,96 @@
+// Records a short program in the execution history on the C64 memory map,
+// then goes back through it undoing the registers and memory it changed.
+mod common;
+
+use c64_rs::breakpoint::{Hit, Kind};
+use c64_rs::cpu::Cpu;
+use c64_rs::history::History;
+use c64_rs::memory::Memory;
+use c64_rs::target::{Step, Target};
+use common::{load, Bare};
+
+// LDA #$42, STA $0400, INC $0400, LDX #$35, STX $01, JAM
+const PROGRAM: &[u8] = &[
+    0xa9, 0x42, 0x8d, 0x00, 0x04, 0xee, 0x00, 0x04, 0xa2, 0x35, 0x86, 0x01, 0x02,
+];
+
+fn machine(capacity: usize) -> Bare {
+    let mut cpu = Cpu::new(Memory::new().unwrap());
+    load(&mut cpu, &[(0xc000, PROGRAM)]);
+    cpu.pc = 0xc000;
+    let mut target = Bare::new(cpu);
+    target.history = History::new(capacity);
+    target
+}
+
+#[test]
+fn step_back_undoes_writes() {
+    let mut target = machine(100);
+    while target.step() {}
+    let cpu = target.cpu();
+    assert_eq!(cpu.borrow().pc, 0xc00c, "stopped at the JAM");
+    assert_eq!(cpu.borrow().peek_memory(0x0400), 0x43);
+    assert_eq!(cpu.borrow().peek_memory(0x0001), 0x35);
+    assert!(!cpu.borrow().bus.is_rom(0xa000), "BASIC banked out");
+
+    // The JAM is the last step
+    assert_eq!(target.history.len(), 6);
+    let last: Vec<u16> = target
+        .history
+        .last(2)
+        .map(|r| r.line.instruction.addr)
+        .collect();
+    assert_eq!(last, [0xc00a, 0xc00c]);
+    let inc = target.history.last(4).next().unwrap();
+    assert_eq!(inc.writes.len(), 1);
+    assert_eq!((inc.writes[0].old, inc.writes[0].value), (0x42, 0x43));
+
+    // Back to the STX, the banking comes back
+    target.step_back();
+    target.step_back();
+    assert_eq!(cpu.borrow().pc, 0xc00a);
+    assert_eq!(cpu.borrow().peek_memory(0x0001), 0x07);
+    assert!(cpu.borrow().bus.is_rom(0xa000));
+
+    // A breakpoint stops going backwards, the start of the history too
+    target.breakpoints.add(Kind::Exec, 0xc002..=0xc002, None);
+    let step = loop {
+        match target.step_back_checked() {
+            Some(Step::Done) => {}
+            step => break step,
+        }
+    };
+    assert!(matches!(
+        step,
+        Some(Step::Hit(Hit::Break { pc: 0xc002, .. }))
+    ));
+    assert_eq!(cpu.borrow().peek_memory(0x0400), 0x00);
+    assert_eq!(cpu.borrow().a, 0x42);
+    assert_eq!(target.step_back_checked(), Some(Step::Done));
+    assert_eq!(target.step_back_checked(), None);
+    assert_eq!(cpu.borrow().a, 0x00);
+    assert_eq!(cpu.borrow().pc, 0xc000);
+}
+
+#[test]
+fn capacity_limits_the_history() {
+    let mut target = machine(3);
+    while target.step() {}
+    assert_eq!(target.history.len(), 3);
+    assert_eq!(
+        target
+            .history
+            .last(10)
+            .next()
+            .unwrap()
+            .line
+            .instruction
+            .addr,
+        0xc008
+    );
+
+    let mut target = machine(0);
+    while target.step() {}
+    assert!(target.history.is_empty());
+    assert!(!target.step_back());
+}
@@ -0,0 +1,208 @@
+// Checks when an interrupt asserted during an instruction is serviced: the
+// CPU polls ahead of the last cycle, so a line that changed in the last
+// cycle waits for one more instruction. Also the I flag, with the delayed
+// poll after CLI/SEI/PLP, the edge triggered NMI and the wired-OR IRQ line.
+use c64_rs::bus::FlatRam;
+use c64_rs::cpu::{Cpu, Interrupt, IrqSource, NmiSource};
+use c64_rs::flags::Flags;
+
+// `code` and NOPs at $0200, the handlers at $0300 (IRQ) and $0400 (NMI)
+// are NOPs as well
+fn program(code: &[u8], interrupt_disable: bool) -> Cpu<FlatRam> {
+    let mut ram = FlatRam::new();
+    ram.load(0x0200, &[0xea; 8]);
+    ram.load(0x0200, code);
+    ram.load(0x0300, &[0xea; 8]);
+    ram.load(0x0400, &[0xea; 8]);
+    ram.load(0xfffa, &[0x00, 0x04, 0x00, 0x02, 0x00, 0x03]);
+    let mut cpu = Cpu::new(ram);
+    cpu.pc = 0x0200;
+    cpu.p.set(Flags::INTERRUPT_DISABLE, interrupt_disable);
+    cpu
+}
+
+// Past the first NOP with interrupts enabled
+fn cpu() -> Cpu<FlatRam> {
+    let mut cpu = program(&[], false);
+    cpu.step();
+    cpu
+}
+
+#[test]
+fn irq_before_the_last_cycle() {
+    let mut cpu = cpu();
+    let at = cpu.cycles() - Cpu::<FlatRam>::INTERRUPT_LATENCY;
+    cpu.set_irq_at(IrqSource::Cia1, true, at);
+    cpu.step();
+    assert_eq!(cpu.interrupted(), Some(Interrupt::Irq));
+    assert_eq!(cpu.pc, 0x0300);
+}
+
+#[test]
+fn irq_in_the_last_cycle() {
+    let mut cpu = cpu();
+    let at = cpu.cycles() - 1;
+    cpu.set_irq_at(IrqSource::Vic, true, at);
+    cpu.step();
+    assert_eq!(cpu.interrupted(), None);
+    assert_eq!(cpu.pc, 0x0202);
+    cpu.step();
+    assert_eq!(cpu.interrupted(), Some(Interrupt::Irq));
+    assert_eq!(cpu.pc, 0x0300);
+}
+
+#[test]
+fn irq_between_instructions() {
+    let mut cpu = cpu();
+    cpu.set_irq(IrqSource::Cia1, true);
+    cpu.step();
+    assert_eq!(cpu.pc, 0x0202);
+    cpu.step();
+    assert_eq!(cpu.interrupted(), Some(Interrupt::Irq));
+}
+
+#[test]
+fn held_irq_keeps_its_assertion_cycle() {
+    let mut cpu = cpu();
+    let at = cpu.cycles() - Cpu::<FlatRam>::INTERRUPT_LATENCY;
+    cpu.set_irq_at(IrqSource::Cia1, true, at);
+    // Another source joining later doesn't delay it
+    cpu.set_irq(IrqSource::Vic, true);
+    cpu.step();
+    assert_eq!(cpu.interrupted(), Some(Interrupt::Irq));
+}
+
+#[test]
+fn nmi_boundaries() {
+    let mut cpu = cpu();
+    let at = cpu.cycles() - Cpu::<FlatRam>::INTERRUPT_LATENCY;
+    cpu.set_nmi_at(NmiSource::Cia2, true, at);
+    cpu.step();
+    assert_eq!(cpu.interrupted(), Some(Interrupt::Nmi));
+    assert_eq!(cpu.pc, 0x0400);
+
+    let mut cpu = self::cpu();
+    let at = cpu.cycles() - 1;
+    cpu.set_nmi_at(NmiSource::Cia2, true, at);
+    cpu.step();
+    assert_eq!(cpu.pc, 0x0202);
+    cpu.step();
+    assert_eq!(cpu.interrupted(), Some(Interrupt::Nmi));
+}
+
+#[test]
+fn masked_irq() {
+    let mut cpu = program(&[], true);
+    cpu.set_irq_at(IrqSource::Cia1, true, 0);
+    for pc in 0x0201..0x0205 {
+        cpu.step();
+        assert_eq!(cpu.interrupted(), None);
+        assert_eq!(cpu.pc, pc);
+    }
+}
+
+#[test]
+fn held_nmi_fires_once() {
+    let mut cpu = cpu();
+    cpu.set_nmi_at(NmiSource::Cia2, true, 0);
+    cpu.step();
+    assert_eq!(cpu.interrupted(), Some(Interrupt::Nmi));
+    // Still low, and another source pulling it low isn't an edge either
+    cpu.set_nmi(NmiSource::Cia2, true);
+    cpu.set_nmi(NmiSource::Restore, true);
+    for _ in 0..4 {
+        cpu.step();
+        assert_eq!(cpu.interrupted(), None);
+    }
+
+    // Released by every source and pulled low again
+    cpu.set_nmi(NmiSource::Cia2, false);
+    cpu.set_nmi(NmiSource::Restore, false);
+    cpu.set_nmi_at(NmiSource::Restore, true, 0);
+    cpu.step();
+    assert_eq!(cpu.interrupted(), Some(Interrupt::Nmi));
+}
+
+#[test]
+fn cli_delays_the_irq() {
+    let mut cpu = program(&[0x58], true);
+    cpu.set_irq_at(IrqSource::Cia1, true, 0);
+    cpu.step();
+    // The instruction after CLI still runs
+    cpu.step();
+    assert_eq!(cpu.interrupted(), None);
+    assert_eq!(cpu.pc, 0x0202);
+    cpu.step();
+    assert_eq!(cpu.interrupted(), Some(Interrupt::Irq));
+}
+
+#[test]
+fn sei_lets_one_irq_through() {
+    let mut cpu = program(&[0x78], false);
+    cpu.step();
+    cpu.set_irq_at(IrqSource::Cia1, true, 0);
+    // Polled with the I flag from before SEI
+    cpu.step();
+    assert_eq!(cpu.interrupted(), Some(Interrupt::Irq));
+    assert_eq!(cpu.pc, 0x0300);
+}
+
+#[test]
+fn plp_delays_the_irq() {
+    // PLP pulls a status with the I flag clear
+    let mut cpu = program(&[0x28], true);
+    cpu.bus.load(0x01ff, &[0x20]);
+    cpu.sp = 0xfe;
+    cpu.set_irq_at(IrqSource::Cia1, true, 0);
+    cpu.step();
+    assert!(!cpu.p.contains(Flags::INTERRUPT_DISABLE));
+    cpu.step();
+    assert_eq!(cpu.interrupted(), None);
+    assert_eq!(cpu.pc, 0x0202);
+    cpu.step();
+    assert_eq!(cpu.interrupted(), Some(Interrupt::Irq));
+
+    // And setting it lets one more through
+    let mut cpu = program(&[0x28], false);
+    cpu.bus.load(0x01ff, &[0x24]);
+    cpu.sp = 0xfe;
+    cpu.step();
+    cpu.set_irq_at(IrqSource::Cia1, true, 0);
+    cpu.step();
+    assert_eq!(cpu.interrupted(), Some(Interrupt::Irq));
+}
+
+#[test]
+fn irq_line_is_wired_or() {
+    let mut cpu = cpu();
+    cpu.set_irq_at(IrqSource::Cia1, true, 0);
+    cpu.set_irq_at(IrqSource::Vic, true, 0);
+    cpu.set_irq(IrqSource::Vic, false);
+    // Still held by the CIA
+    cpu.step();
+    assert_eq!(cpu.interrupted(), Some(Interrupt::Irq));
+
+    let mut cpu = self::cpu();
+    cpu.set_irq_at(IrqSource::Cia1, true, 0);
+    cpu.set_irq_at(IrqSource::Vic, true, 0);
+    cpu.set_irq(IrqSource::Vic, false);
+    cpu.set_irq(IrqSource::Cia1, false);
+    cpu.step();
+    assert_eq!(cpu.interrupted(), None);
+
+    // The line stays low while the last source holds it, so the handler is
+    // entered again as soon as it clears the I flag
+    let mut cpu = self::cpu();
+    cpu.set_irq_at(IrqSource::Cia1, true, 0);
+    cpu.set_irq_at(IrqSource::Vic, true, 0);
+    cpu.step();
+    assert_eq!(cpu.interrupted(), Some(Interrupt::Irq));
+    cpu.set_irq(IrqSource::Cia1, false);
+    cpu.p.remove(Flags::INTERRUPT_DISABLE);
+    cpu.step();
+    assert_eq!(cpu.interrupted(), Some(Interrupt::Irq));
+    cpu.set_irq(IrqSource::Vic, false);
+    cpu.p.remove(Flags::INTERRUPT_DISABLE);
+    cpu.step();
+    assert_eq!(cpu.interrupted(), None);
+}
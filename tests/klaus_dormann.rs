@@ -0,0 +1,106 @@
+// Klaus Dormann's 6502 test suites, run headless on a flat 64K RAM.
+//
+// The functional test under assets/tests is Klaus Dormann's. The decimal,
+// interrupt and 65C02 extended opcodes tests there are our own programs
+// in the layout of his (same load address, labels, feedback port and
+// success trap), so his binaries and listings can replace them; see
+// assets/tests/README.md.
+//
+// The functional test only uses instructions the 65C02 and the 65816 share
+// with the NMOS part, so it runs on all three cores.
+use c64_rs::bus::FlatRam;
+use c64_rs::cpu::{Cpu, Processor};
+use c64_rs::cpu65816::Cpu65816;
+use c64_rs::cpu65c02::Cpu65C02;
+use c64_rs::harness::{FeedbackPort, Harness, Listing, Outcome};
+use std::path::Path;
+
+const LIMIT: u64 = 100_000_000;
+
+fn load(name: &str, addr: u16) -> (Harness<Cpu<FlatRam>>, Listing) {
+    load_on(Cpu::new(FlatRam::new()), name, addr)
+}
+
+fn load_on<P: Processor>(cpu: P, name: &str, addr: u16) -> (Harness<P>, Listing) {
+    let bin = format!("assets/tests/{}.bin", name);
+    let lst = format!("assets/tests/{}.lst", name);
+    assert!(
+        Path::new(&bin).exists() && Path::new(&lst).exists(),
+        "{} or {} not found",
+        bin,
+        lst
+    );
+    let mut harness = Harness::new(cpu);
+    harness.load_file(&bin, addr).unwrap();
+    (harness, Listing::load(&lst).unwrap())
+}
+
+fn check(outcome: Outcome, expected: Outcome, listing: &Listing) {
+    assert_eq!(
+        outcome,
+        expected,
+        "{}, {}",
+        outcome,
+        listing.describe(outcome.pc())
+    );
+}
+
+#[test]
+fn functional_test() {
+    let (mut harness, listing) = load("6502_functional_test", 0x0400);
+    let success = listing.find("test passed").unwrap();
+
+    let outcome = harness.run(0x0400, None, LIMIT);
+    check(outcome, Outcome::Trapped(success), &listing);
+}
+
+#[test]
+fn functional_test_65c02() {
+    let cpu = Cpu65C02::new(FlatRam::new());
+    let (mut harness, listing) = load_on(cpu, "6502_functional_test", 0x0400);
+    let success = listing.find("test passed").unwrap();
+
+    let outcome = harness.run(0x0400, None, LIMIT);
+    check(outcome, Outcome::Trapped(success), &listing);
+}
+
+#[test]
+fn functional_test_65816() {
+    let cpu = Cpu65816::new(FlatRam::new());
+    let (mut harness, listing) = load_on(cpu, "6502_functional_test", 0x0400);
+    let success = listing.find("test passed").unwrap();
+
+    let outcome = harness.run(0x0400, None, LIMIT);
+    check(outcome, Outcome::Trapped(success), &listing);
+}
+
+#[test]
+fn extended_opcodes_test_65c02() {
+    let cpu = Cpu65C02::new(FlatRam::new());
+    let (mut harness, listing) = load_on(cpu, "65C02_extended_opcodes_test", 0x0400);
+    let success = listing.find("test passed").unwrap();
+
+    let outcome = harness.run(0x0400, None, LIMIT);
+    check(outcome, Outcome::Trapped(success), &listing);
+}
+
+#[test]
+fn decimal_test() {
+    let (mut harness, listing) = load("6502_decimal_test", 0x0200);
+    let done = listing.label("DONE").unwrap();
+    let error = listing.label("ERROR").unwrap();
+
+    let outcome = harness.run(0x0200, Some(done), LIMIT);
+    check(outcome, Outcome::Reached(done), &listing);
+    assert_eq!(harness.cpu.peek_memory(error), 0, "ERROR flag set");
+}
+
+#[test]
+fn interrupt_test() {
+    let (mut harness, listing) = load("6502_interrupt_test", 0x0400);
+    harness.set_feedback(FeedbackPort::default());
+    let success = listing.find("test passed").unwrap();
+
+    let outcome = harness.run(0x0400, None, LIMIT);
+    check(outcome, Outcome::Trapped(success), &listing);
+}
@@ -0,0 +1,56 @@
+// Runs in lockstep with a reference trace that has an IRQ in it.
+use c64_rs::cpu::{Cpu, IrqSource};
+use c64_rs::flags::Flags;
+use c64_rs::lockstep::{Check, Lockstep};
+use c64_rs::memory::Memory;
+use std::fs;
+
+#[test]
+fn reference_with_an_interrupt() {
+    let mut cpu = Cpu::new(Memory::new().unwrap());
+    cpu.bus.write_byte(0xc000, 0xea);
+    cpu.bus.write_byte(0xc001, 0xea);
+    cpu.pc = 0xc000;
+    cpu.p.remove(Flags::INTERRUPT_DISABLE);
+
+    // The NOP, then PHA, TXA, PHA at the start of the KERNAL handler
+    let handler = cpu.bus.read_word(Memory::ADDR_IRQ_VECTOR);
+    let reference = format!(
+        ".C:c000  EA        NOP\n\
+         .C:{:04x}  48        PHA\n\
+         .C:{:04x}  8A        TXA\n\
+         .C:{:04x}  48        PHA\n",
+        handler,
+        handler + 1,
+        handler + 2
+    );
+    let path = std::env::temp_dir().join(format!("c64-rs-lockstep-{}.log", std::process::id()));
+    fs::write(&path, reference).unwrap();
+    let mut lockstep = Lockstep::open(path.to_str().unwrap(), 0).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    let mut checks = Vec::new();
+    for step in 0..5 {
+        checks.push(match lockstep.check(&cpu, (0, 0)).unwrap() {
+            Check::Waiting => "waiting".to_string(),
+            Check::Matched => "matched".to_string(),
+            Check::Interrupt => "interrupt".to_string(),
+            Check::Finished(count) => format!("finished {}", count),
+            Check::Diverged(divergence) => format!("diverged\n{}", divergence),
+        });
+        cpu.step();
+        if step == 0 {
+            // Raised early enough to be serviced before the next NOP
+            let at = cpu.cycles() - Cpu::<Memory>::INTERRUPT_LATENCY;
+            cpu.set_irq_at(IrqSource::Cia1, true, at);
+        }
+    }
+    assert_eq!(
+        checks,
+        ["matched", "interrupt", "matched", "matched", "matched"]
+    );
+    assert!(matches!(
+        lockstep.check(&cpu, (0, 0)).unwrap(),
+        Check::Finished(4)
+    ));
+}
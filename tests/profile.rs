@@ -0,0 +1,156 @@
+// Profiles a loop calling a subroutine and checks the cycles charged to
+// each routine and what the profile files say, then an interrupt coming in.
+mod common;
+
+use c64_rs::callstack::FrameKind;
+use c64_rs::cpu::{Cpu, IrqSource};
+use c64_rs::flags::Flags;
+use c64_rs::memory::Memory;
+use c64_rs::profile::{Profiler, RoutineCost};
+use c64_rs::symbols::Symbols;
+use common::load;
+
+// $C000  LDX #3, loop: JSR sub, DEX, BNE loop, JAM
+// $C010  sub: NOP, RTS
+const PROGRAM: &[(u16, &[u8])] = &[
+    (
+        0xc000,
+        &[0xa2, 0x03, 0x20, 0x10, 0xc0, 0xca, 0xd0, 0xfa, 0x02],
+    ),
+    (0xc010, &[0xea, 0x60]),
+];
+
+fn profile() -> Profiler {
+    let mut cpu = Cpu::new(Memory::new().unwrap());
+    load(&mut cpu, PROGRAM);
+    cpu.pc = 0xc000;
+    let mut profiler = Profiler::new();
+    // Up to the JAM
+    while cpu.pc != 0xc008 {
+        let (pc, cycles) = (cpu.pc, cpu.cycles());
+        cpu.step();
+        profiler.record(pc, cpu.cycles() - cycles, cpu.calls(), cpu.interrupted());
+    }
+    profiler
+}
+
+#[test]
+fn cycles_per_routine() {
+    let profiler = profile();
+    // LDX 2, JSR 6, DEX 2, BNE 3 taken and 2 not, NOP 2, RTS 6
+    let top = 2 + 3 * (6 + 2) + 2 * 3 + 2;
+    let sub = 3 * (2 + 6);
+    assert_eq!(profiler.total().cycles, top + sub);
+    assert_eq!(profiler.total().instructions, 1 + 3 * 5);
+    assert_eq!(
+        profiler.routines(),
+        [
+            RoutineCost {
+                routine: None,
+                own: top,
+                inclusive: top + sub,
+            },
+            RoutineCost {
+                routine: Some((FrameKind::Call, 0xc010)),
+                own: sub,
+                inclusive: sub,
+            },
+        ]
+    );
+}
+
+#[test]
+fn profile_files() {
+    let profiler = profile();
+    let mut symbols = Symbols::new();
+    symbols.add("sub", 0xc010);
+
+    let mut folded = Vec::new();
+    profiler.write_folded(&mut folded, &symbols).unwrap();
+    assert_eq!(
+        String::from_utf8(folded).unwrap(),
+        "(top level) 34\n(top level);sub 24\n"
+    );
+
+    let mut callgrind = Vec::new();
+    profiler.write_callgrind(&mut callgrind, &symbols).unwrap();
+    let callgrind = String::from_utf8(callgrind).unwrap();
+    assert!(callgrind.starts_with("# callgrind format\n"));
+    assert!(callgrind.contains("events: Cycles Instructions\nsummary: 58 16\n"));
+    // The call site costs the JSR itself, the call everything in sub
+    assert!(callgrind.contains("fn=(top level)\n0xc000 2 1\n0xc002 18 3\n"));
+    assert!(
+        callgrind.contains("cfn=sub\ncalls=3 0xc010\n0xc002 24 6\n"),
+        "{}",
+        callgrind
+    );
+    assert!(callgrind.contains("fn=sub\n0xc010 6 3\n0xc011 18 3\n"));
+}
+
+#[test]
+fn interrupt_entry() {
+    // $C000  NOP, NOP, JAM, the IRQ handler at $C020: NOP, RTI
+    let mut cpu = Cpu::new(Memory::new().unwrap());
+    // KERNAL out, so the vector comes from RAM
+    cpu.bus.write_byte(Memory::ADDR_MEMORY_LAYOUT, 0x35);
+    load(
+        &mut cpu,
+        &[
+            (0xc000, &[0xea, 0xea, 0x02]),
+            (0xc020, &[0xea, 0x40]),
+            (0xfffe, &[0x20, 0xc0]),
+        ],
+    );
+    cpu.pc = 0xc000;
+    cpu.p.remove(Flags::INTERRUPT_DISABLE);
+    let mut profiler = Profiler::new();
+    while cpu.pc != 0xc002 {
+        let (pc, cycles) = (cpu.pc, cpu.cycles());
+        cpu.step();
+        profiler.record(pc, cpu.cycles() - cycles, cpu.calls(), cpu.interrupted());
+        if pc == 0xc000 {
+            // Asserted early enough to come before the second NOP
+            let at = cpu.cycles() - Cpu::<Memory>::INTERRUPT_LATENCY;
+            cpu.set_irq_at(IrqSource::Cia1, true, at);
+        } else if cpu.interrupted().is_some() {
+            cpu.set_irq(IrqSource::Cia1, false);
+        }
+    }
+
+    // NOP 2 twice, the entry 7 in the handler, its NOP 2 and RTI 6
+    let handler = 7 + 2 + 6;
+    assert_eq!(profiler.total().cycles, 2 * 2 + handler);
+    assert_eq!(profiler.total().instructions, 4, "the entry is none");
+    assert_eq!(
+        profiler.routines(),
+        [
+            RoutineCost {
+                routine: Some((FrameKind::Irq, 0xc020)),
+                own: handler,
+                inclusive: handler,
+            },
+            RoutineCost {
+                routine: None,
+                own: 2 * 2,
+                inclusive: 2 * 2 + handler,
+            },
+        ]
+    );
+
+    // Nothing is charged to the NOP the interrupt came before
+    let mut callgrind = Vec::new();
+    profiler
+        .write_callgrind(&mut callgrind, &Symbols::new())
+        .unwrap();
+    let callgrind = String::from_utf8(callgrind).unwrap();
+    assert!(
+        callgrind.contains("0xc000 2 1\n0xc001 2 1\n"),
+        "{}",
+        callgrind
+    );
+    assert!(
+        callgrind.contains("0xc020 9 1\n0xc021 6 1\n"),
+        "{}",
+        callgrind
+    );
+}
@@ -0,0 +1,185 @@
+// Per-opcode conformance tests in the single step JSON format of
+// https://github.com/SingleStepTests/65x02 (6502 directory). Each file
+// holds thousands of tests for one opcode, every test gives the initial
+// and final CPU state, the RAM it touches and the bus activity per cycle.
+//
+// assets/tests/single_step holds a few tests for every opcode but JAM,
+// generated by generate.py there. The upstream files can be copied over
+// them unchanged.
+//
+// The core runs whole instructions, but makes the same bus accesses as
+// the hardware in the same order, dummy reads and writes included, so
+// they are compared with the cycles of the test one by one.
+use c64_rs::bus::{Bus, FlatRam};
+use c64_rs::cpu::{Cpu, Processor, Registers};
+use c64_rs::flags::Flags;
+use c64_rs::opcode::{Mnemonic, OPCODES};
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+
+const DIR: &str = "assets/tests/single_step";
+
+fn byte(value: &Value) -> u8 {
+    value.as_u64().expect("number") as u8
+}
+
+fn word(value: &Value) -> u16 {
+    value.as_u64().expect("number") as u16
+}
+
+fn registers(state: &Value) -> Registers {
+    Registers {
+        pc: word(&state["pc"]),
+        sp: byte(&state["s"]),
+        a: byte(&state["a"]),
+        x: byte(&state["x"]),
+        y: byte(&state["y"]),
+        // B and bit 5 only exist in the copy of P pushed on the stack
+        p: Flags::from_stack(byte(&state["p"])),
+    }
+}
+
+fn ram(state: &Value) -> impl Iterator<Item = (u16, u8)> + '_ {
+    state["ram"]
+        .as_array()
+        .expect("ram")
+        .iter()
+        .map(|entry| (word(&entry[0]), byte(&entry[1])))
+}
+
+type Access = (u16, u8, String);
+
+// Flat RAM recording the accesses the CPU makes
+struct Recorder {
+    ram: FlatRam,
+    accesses: Vec<Access>,
+}
+
+impl Bus for Recorder {
+    fn read(&mut self, addr: u16) -> u8 {
+        let value = self.ram.read(addr);
+        self.accesses.push((addr, value, "read".to_string()));
+        value
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        self.ram.write(addr, value);
+        self.accesses.push((addr, value, "write".to_string()));
+    }
+
+    fn peek(&self, addr: u16) -> u8 {
+        self.ram.peek(addr)
+    }
+}
+
+fn cycles(test: &Value) -> Vec<Access> {
+    test["cycles"]
+        .as_array()
+        .expect("cycles")
+        .iter()
+        .map(|cycle| {
+            let kind = cycle[2].as_str().expect("read or write");
+            (word(&cycle[0]), byte(&cycle[1]), kind.to_string())
+        })
+        .collect()
+}
+
+// Runs one test, returns a description of the first mismatch
+fn run(test: &Value) -> Result<(), String> {
+    let mut cpu = Cpu::new(Recorder {
+        ram: FlatRam::new(),
+        accesses: Vec::new(),
+    });
+    cpu.set_registers(&registers(&test["initial"]));
+    for (addr, value) in ram(&test["initial"]) {
+        cpu.bus.ram.write(addr, value);
+    }
+
+    let start = cpu.cycles();
+    cpu.step();
+    let taken = cpu.cycles() - start;
+
+    let expected = registers(&test["final"]);
+    let actual = cpu.registers();
+    if actual != expected {
+        return Err(format!("expected {:02X?}, got {:02X?}", expected, actual));
+    }
+    for (addr, value) in ram(&test["final"]) {
+        let actual = cpu.peek_memory(addr);
+        if actual != value {
+            return Err(format!(
+                "${:04X} expected {:02X}, got {:02X}",
+                addr, value, actual
+            ));
+        }
+    }
+    let expected_cycles = cycles(test);
+    if taken != expected_cycles.len() as u32 {
+        return Err(format!(
+            "expected {} cycles, got {}",
+            expected_cycles.len(),
+            taken
+        ));
+    }
+    let accesses = &cpu.bus.accesses;
+    for (i, (expected, actual)) in expected_cycles.iter().zip(accesses).enumerate() {
+        if actual != expected {
+            return Err(format!(
+                "cycle {}: expected {} of {:02X} at ${:04X}, got {} of {:02X} at ${:04X}",
+                i + 1,
+                expected.2,
+                expected.1,
+                expected.0,
+                actual.2,
+                actual.1,
+                actual.0
+            ));
+        }
+    }
+    if accesses.len() != expected_cycles.len() {
+        return Err(format!(
+            "expected {} bus accesses, got {}",
+            expected_cycles.len(),
+            accesses.len()
+        ));
+    }
+    Ok(())
+}
+
+#[test]
+fn single_step() {
+    assert!(Path::new(DIR).exists(), "{} not found", DIR);
+
+    let mut failures = Vec::new();
+    for (opcode, op) in OPCODES.iter().enumerate() {
+        // JAM halts the CPU, there is no next state to compare
+        if op.mnemonic == Mnemonic::Jam {
+            continue;
+        }
+        let path = format!("{}/{:02x}.json", DIR, opcode);
+        let text = fs::read_to_string(&path).unwrap_or_else(|e| panic!("{}: {}", path, e));
+        let tests: Value = serde_json::from_str(&text).expect(&path);
+
+        let tests = tests.as_array().expect("array of tests");
+        let failed: Vec<String> = tests
+            .iter()
+            .filter_map(|test| {
+                run(test)
+                    .err()
+                    .map(|e| format!("{}: {}", test["name"].as_str().unwrap_or("?"), e))
+            })
+            .collect();
+        if let Some(first) = failed.first() {
+            failures.push(format!(
+                "{:02X} {}: {} of {} failed, first {}",
+                opcode,
+                op.mnemonic,
+                failed.len(),
+                tests.len(),
+                first
+            ));
+        }
+    }
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
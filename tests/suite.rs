@@ -0,0 +1,107 @@
+// Follows fake Lorenz suite tests through their KERNAL calls on a flat RAM
+// and checks the verdicts.
+use c64_rs::bus::FlatRam;
+use c64_rs::cpu::{Cpu, IrqSource};
+use c64_rs::flags::Flags;
+use c64_rs::suite::{Trap, Verdict, Watch, ADDR_CHROUT, ADDR_GETIN, ADDR_LOAD};
+
+// KERNAL stand-ins at their entry points that return straight away, an IRQ
+// handler at $0300 that does the same and the file name "NEXT" at $0340
+fn cpu(program: &[u8]) -> Cpu<FlatRam> {
+    let mut ram = FlatRam::new();
+    ram.load(0x0200, program);
+    for addr in [ADDR_CHROUT, ADDR_GETIN, ADDR_LOAD] {
+        ram.load(addr, &[0x60]);
+    }
+    ram.load(0x0300, &[0x40]);
+    ram.load(0xfffe, &[0x00, 0x03]);
+    ram.load(0x0340, b"NEXT");
+    ram.load(0x00b7, &[4]);
+    ram.load(0x00bb, &[0x40, 0x03]);
+    let mut cpu = Cpu::new(ram);
+    cpu.pc = 0x0200;
+    cpu.p.remove(Flags::INTERRUPT_DISABLE);
+    cpu
+}
+
+// Runs until a trap or the CPU halts. With `irq` an IRQ is raised on each
+// JSR to CHROUT, so it is serviced before the routine runs.
+fn run(cpu: &mut Cpu<FlatRam>, watch: &mut Watch, irq: bool) -> Option<Trap> {
+    loop {
+        let (pc, cycles) = (cpu.pc, cpu.cycles());
+        if !cpu.step() {
+            return None;
+        }
+        if irq && cpu.pc == ADDR_CHROUT && pc != 0x0300 {
+            let at = cpu.cycles() - Cpu::<FlatRam>::INTERRUPT_LATENCY;
+            cpu.set_irq_at(IrqSource::Cia1, true, at);
+        }
+        if cpu.interrupted().is_some() {
+            cpu.set_irq(IrqSource::Cia1, false);
+        }
+        if let Some(trap) = watch.step(cpu, pc, cpu.cycles() - cycles) {
+            return Some(trap);
+        }
+    }
+}
+
+// LDA #"O", JSR CHROUT, LDA #"K", JSR CHROUT, JSR LOAD, JAM
+const PASSING: &[u8] = &[
+    0xa9, 0x4f, 0x20, 0xd2, 0xff, 0xa9, 0x4b, 0x20, 0xd2, 0xff, 0x20, 0xd5, 0xff, 0x02,
+];
+
+#[test]
+fn passing_test() {
+    let mut cpu = cpu(PASSING);
+    let mut watch = Watch::new("test");
+    assert_eq!(
+        run(&mut cpu, &mut watch, false),
+        Some(Trap::Load("next".to_string()))
+    );
+    let result = watch.loaded();
+    assert_eq!(result.verdict, Verdict::Passed);
+    assert_eq!(result.output, "OK");
+    assert_eq!(result.cycles, cpu.cycles() as u64);
+}
+
+#[test]
+fn output_during_an_interrupt() {
+    let mut cpu = cpu(PASSING);
+    let mut watch = Watch::new("test");
+    run(&mut cpu, &mut watch, true);
+    // CHROUT is only recorded once it runs, after the handler returned
+    assert_eq!(watch.loaded().output, "OK");
+}
+
+#[test]
+fn failing_test() {
+    // LDA #"X", JSR CHROUT, JSR GETIN, JSR LOAD, JAM
+    let program = [
+        0xa9, 0x58, 0x20, 0xd2, 0xff, 0x20, 0xe4, 0xff, 0x20, 0xd5, 0xff, 0x02,
+    ];
+    let mut cpu = cpu(&program);
+    let mut watch = Watch::new("test");
+    assert_eq!(run(&mut cpu, &mut watch, false), Some(Trap::Key));
+    assert_eq!(
+        run(&mut cpu, &mut watch, false),
+        Some(Trap::Load("next".to_string()))
+    );
+    let result = watch.loaded();
+    assert_eq!(result.verdict, Verdict::Failed);
+    assert_eq!(result.detail(), "X");
+}
+
+#[test]
+fn stopped_tests() {
+    let verdict = |halted, screen| Watch::new("test").stopped(halted, screen).verdict;
+    assert_eq!(verdict(false, "READY."), Verdict::Timeout);
+    assert_eq!(verdict(false, "ERROR IN LINE 10"), Verdict::Failed);
+    assert_eq!(verdict(true, "READY."), Verdict::Failed);
+
+    // A test that reported an error and never loaded the next one
+    let mut cpu = cpu(&[0x20, 0xe4, 0xff, 0x02]);
+    let mut watch = Watch::new("test");
+    assert_eq!(run(&mut cpu, &mut watch, false), Some(Trap::Key));
+    assert_eq!(run(&mut cpu, &mut watch, false), None);
+    assert_eq!(watch.stopped(true, "").verdict, Verdict::Failed);
+}
@@ -0,0 +1,113 @@
+// Loads label and debug files in each format the way assemblers write
+// them and looks names, addresses and source lines up.
+use c64_rs::symbols::Symbols;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const VICE: &str = "\
+al C:c000 .start
+al C:c010 .loop
+";
+
+const ACME: &str = "\
+	start	= $c000	; ?
+	count	= 49168
+	!addr	screen	= $0400
+	big	= $12345
+";
+
+const KICKASS: &str = r#"<C64debugger version="1.0">
+  <Sources values="INDEX,FILE">
+    0,KickAss.jar:/include/autoinclude.asm
+    1,main.asm
+  </Sources>
+  <Segment name="Default" dest="" values="START,END,FILE_IDX,LINE1,COL1,LINE2,COL2">
+    <Block name="Basic">
+      $c000,$c001,1,3,1,3,10
+      $c002,$c004,1,4,1,4,12
+    </Block>
+  </Segment>
+  <Labels values="SEGMENT,ADDRESS,NAME,START,END,FILE_IDX,LINE1,COL1,LINE2,COL2">
+    Default,$c000,start,3,1,3,6,1
+  </Labels>
+  <Breakpoints values="SEGMENT,ADDRESS,ARGUMENT">
+  </Breakpoints>
+</C64debugger>
+"#;
+
+const CA65: &str = "version\tmajor=2,minor=0
+info\tcsym=0,file=1,lib=0,line=3,mod=1,scope=1,seg=1,span=2,sym=2,type=1
+file\tid=0,name=\"main,1.s\",size=100,mtime=0x5F000000,mod=0
+seg\tid=0,name=\"CODE\",start=0x00C000,size=0x0005,addrsize=absolute,type=ro,oname=\"main.prg\",ooffs=2
+span\tid=0,seg=0,start=0,size=2
+span\tid=1,seg=0,start=2,size=3
+line\tid=0,file=0,line=5,span=0
+line\tid=1,file=0,line=6,span=1
+line\tid=2,file=0,line=40,type=2,span=1
+sym\tid=0,name=\"start\",addrsize=absolute,scope=0,def=0,val=0xC000,seg=0,type=lab
+sym\tid=1,name=\"WIDTH\",addrsize=zeropage,scope=0,def=1,val=0x28,type=equ
+";
+
+fn write(dir: &Path, name: &str, text: &str) -> PathBuf {
+    let path = dir.join(name);
+    fs::write(&path, text).unwrap();
+    path
+}
+
+fn line(symbols: &Symbols, addr: u16) -> Option<(String, usize)> {
+    symbols.lines().line(addr).map(|(path, line)| {
+        let name = path.file_name().unwrap().to_string_lossy().into_owned();
+        (name, line)
+    })
+}
+
+#[test]
+fn label_files() {
+    let dir = std::env::temp_dir().join(format!("c64-rs-symbols-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+
+    let mut vice = Symbols::new();
+    assert_eq!(vice.load(&write(&dir, "program.vs", VICE)).unwrap(), 2);
+    assert_eq!(vice.addr("loop"), Some(0xc010));
+    assert_eq!(vice.name(0xc000), Some("start"));
+
+    // Constants too large for an address are skipped
+    let mut acme = Symbols::new();
+    assert_eq!(acme.load(&write(&dir, "labels.txt", ACME)).unwrap(), 3);
+    assert_eq!(acme.addr("count"), Some(0xc010));
+    assert_eq!(acme.addr("screen"), Some(0x0400));
+
+    let mut kickass = Symbols::new();
+    assert_eq!(kickass.load(&write(&dir, "main.dbg", KICKASS)).unwrap(), 1);
+    assert_eq!(kickass.addr("start"), Some(0xc000));
+    assert_eq!(line(&kickass, 0xc002), Some(("main.asm".to_string(), 4)));
+
+    // Only labels are symbols, macro lines don't replace source lines
+    let mut ca65 = Symbols::new();
+    assert_eq!(ca65.load(&write(&dir, "program.dbg", CA65)).unwrap(), 1);
+    assert_eq!(ca65.addr("WIDTH"), None);
+    assert_eq!(line(&ca65, 0xc000), Some(("main,1.s".to_string(), 5)));
+    assert_eq!(line(&ca65, 0xc002), Some(("main,1.s".to_string(), 6)));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn rom_names() {
+    let mut symbols = Symbols::rom();
+    assert_eq!(symbols.name(0xffd2), Some("CHROUT"));
+    assert_eq!(symbols.operand(0xffd2, false), "CHROUT");
+    assert_eq!(symbols.operand(0xfb, true), "$FB");
+
+    // Names are replaced in commands, but not inside quotes or file names
+    symbols.add("main", 0xc000);
+    assert_eq!(
+        symbols.resolve("break .main if @.chrout == 0").unwrap(),
+        "break $C000 if @$FFD2 == 0"
+    );
+    assert_eq!(
+        symbols.resolve("l \".main\" game.prg").unwrap(),
+        "l \".main\" game.prg"
+    );
+    assert!(symbols.resolve("d .nowhere").is_err());
+}
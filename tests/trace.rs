@@ -0,0 +1,36 @@
+// Traces a few instructions with an IRQ coming in between them.
+use c64_rs::cpu::{Cpu, IrqSource};
+use c64_rs::flags::Flags;
+use c64_rs::memory::Memory;
+use c64_rs::trace::Tracer;
+use std::fs;
+
+#[test]
+fn interrupt_entry_is_not_traced() {
+    let path = std::env::temp_dir().join(format!("c64-rs-trace-{}.log", std::process::id()));
+    let mut tracer = Tracer::create(path.to_str().unwrap()).unwrap();
+
+    let mut cpu = Cpu::new(Memory::new().unwrap());
+    for (i, &byte) in [0xea, 0xea, 0xea].iter().enumerate() {
+        cpu.bus.write_byte(0xc000 + i as u16, byte);
+    }
+    cpu.pc = 0xc000;
+    cpu.p.remove(Flags::INTERRUPT_DISABLE);
+    let handler = cpu.bus.read_word(Memory::ADDR_IRQ_VECTOR);
+
+    tracer.trace(&cpu, (0, 0)).unwrap();
+    cpu.step();
+    // Raised early enough to be serviced before the next NOP
+    let at = cpu.cycles() - Cpu::<Memory>::INTERRUPT_LATENCY;
+    cpu.set_irq_at(IrqSource::Cia1, true, at);
+    for _ in 0..2 {
+        tracer.trace(&cpu, (0, 0)).unwrap();
+        cpu.step();
+    }
+    tracer.flush().unwrap();
+
+    let log = fs::read_to_string(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    let pcs: Vec<&str> = log.lines().map(|line| &line[3..7]).collect();
+    assert_eq!(pcs, ["c000", &format!("{:04x}", handler)], "{}", log);
+}